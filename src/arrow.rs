@@ -0,0 +1,307 @@
+//! Apache Arrow support, enabled with the `arrow` feature
+//!
+//! A Histogram converts to a record batch with one row per non-empty bucket,
+//! holding the `bucket_low`, `bucket_high`, and `count` as non-null UInt64
+//! columns. The config and counters are carried in the schema metadata under
+//! `histogram.` keys, so a batch written to Parquet converts back exactly.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+
+use super::{occupied_indices, DeserializeLimits, Histogram, HistogramConfig, HistogramCounters,
+            HistogramError, HistogramProperties, OverflowPolicy};
+
+const LOW: &str = "bucket_low";
+const HIGH: &str = "bucket_high";
+const COUNT: &str = "count";
+
+const PRECISION: &str = "histogram.precision";
+const RADIX: &str = "histogram.radix";
+const MAX_VALUE: &str = "histogram.max_value";
+const MIN_VALUE: &str = "histogram.min_value";
+const SCALE: &str = "histogram.scale";
+const OVERFLOW: &str = "histogram.overflow";
+const ENTRIES: &str = "histogram.entries";
+const MISSED_SMALL: &str = "histogram.missed_small";
+const MISSED_LARGE: &str = "histogram.missed_large";
+const MISSED_UNKNOWN: &str = "histogram.missed_unknown";
+
+impl Histogram {
+    /// convert to an Arrow record batch of the non-empty buckets
+    ///
+    /// Fails with `InvalidEncoding` should Arrow reject the columns as not
+    /// matching the schema.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    /// h.record(2_000, 1).unwrap();
+    ///
+    /// let batch = h.to_arrow().unwrap();
+    ///
+    /// assert_eq!(batch.num_rows(), 2);
+    /// assert_eq!(batch.schema().metadata()["histogram.entries"], "4");
+    /// assert_eq!(Histogram::from_arrow(&batch).unwrap().get(10).unwrap(), 3);
+    pub fn to_arrow(&self) -> Result<RecordBatch, HistogramError> {
+        let mut low = Vec::new();
+        let mut high = Vec::new();
+        let mut count = Vec::new();
+        for index in occupied_indices(self.data.occupied) {
+            let bucket_count = self.data.count(index);
+            if bucket_count > 0 {
+                let (bucket_low, bucket_high) = self.index_bounds(index);
+                low.push(bucket_low);
+                high.push(bucket_high);
+                count.push(bucket_count);
+            }
+        }
+
+        let counters = &self.data.counters;
+        let overflow = match self.config.overflow {
+            OverflowPolicy::Reject => "reject",
+            OverflowPolicy::Clamp => "clamp",
+        };
+        let mut metadata: HashMap<String, String> =
+            vec![(PRECISION, self.config.precision.to_string()),
+                 (RADIX, self.config.radix.to_string()),
+                 (MAX_VALUE, self.config.max_value.to_string()),
+                 (OVERFLOW, overflow.to_owned()),
+                 (ENTRIES, counters.entries_total.to_string()),
+                 (MISSED_SMALL, counters.missed_small.to_string()),
+                 (MISSED_LARGE, counters.missed_large.to_string()),
+                 (MISSED_UNKNOWN, counters.missed_unknown.to_string())]
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect();
+        // left out at the default, so other batches are written as before
+        if self.config.min_value > 1 {
+            metadata.insert(MIN_VALUE.to_owned(), self.config.min_value.to_string());
+        }
+        if self.config.scale != 1.0 {
+            metadata.insert(SCALE.to_owned(), self.config.scale.to_string());
+        }
+
+        let columns: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from(low)),
+                                          Arc::new(UInt64Array::from(high)),
+                                          Arc::new(UInt64Array::from(count))];
+        RecordBatch::try_new(Arc::new(schema().with_metadata(metadata)), columns)
+            .map_err(|_| HistogramError::InvalidEncoding)
+    }
+
+    /// convert a record batch written by `to_arrow()` back to a Histogram
+    ///
+    /// The batch may have passed through Parquet or another Arrow store, but
+    /// must keep the schema metadata. Each row must cover exactly one bucket
+    /// of the config, in increasing order, otherwise the batch is rejected as
+    /// `InvalidEncoding`.
+    pub fn from_arrow(batch: &RecordBatch) -> Result<Histogram, HistogramError> {
+        Histogram::from_arrow_with_limits(batch, &DeserializeLimits::new())
+    }
+
+    /// convert a record batch as `from_arrow()`, rejecting a config over the
+    /// limits before any buckets are allocated
+    pub fn from_arrow_with_limits(batch: &RecordBatch,
+                                  limits: &DeserializeLimits)
+                                  -> Result<Histogram, HistogramError> {
+        let schema = batch.schema();
+        let metadata = schema.metadata();
+
+        let mut config = HistogramConfig::new();
+        config.precision(number(metadata, PRECISION)? as u32)
+              .max_value(number(metadata, MAX_VALUE)?)
+              .min_value(match metadata.get(MIN_VALUE) {
+                  Some(_) => number(metadata, MIN_VALUE)?,
+                  None => 1,
+              })
+              .scale(match metadata.get(SCALE) {
+                  Some(value) => value.parse().map_err(|_| HistogramError::InvalidEncoding)?,
+                  None => 1.0,
+              })
+              .overflow(match metadata.get(OVERFLOW).map(|value| value.as_str()) {
+                  Some("reject") => OverflowPolicy::Reject,
+                  Some("clamp") => OverflowPolicy::Clamp,
+                  _ => return Err(HistogramError::InvalidEncoding),
+              });
+        config.radix = number(metadata, RADIX)? as u32;
+        if config.radix < 2 || config.radix as u64 != number(metadata, RADIX)? {
+            return Err(HistogramError::InvalidEncoding);
+        }
+        let properties =
+            HistogramProperties::new(&config).map_err(|_| HistogramError::InvalidEncoding)?;
+        limits.check(&properties)?;
+        let mut histogram =
+            Histogram::configured(config).map_err(|_| HistogramError::InvalidEncoding)?;
+
+        let low = column(batch, LOW)?;
+        let high = column(batch, HIGH)?;
+        let count = column(batch, COUNT)?;
+
+        let mut data = vec![0; histogram.data.data.len()];
+        let mut next = 0;
+        for row in 0..batch.num_rows() {
+            let index = match histogram.get_index(low.value(row)) {
+                Some(index) if index >= next && index < data.len() => index,
+                _ => return Err(HistogramError::InvalidEncoding),
+            };
+            if histogram.index_bounds(index) != (low.value(row), high.value(row)) {
+                return Err(HistogramError::InvalidEncoding);
+            }
+            if let Some(bucket) = data.get_mut(index) {
+                *bucket = count.value(row);
+            }
+            next = index + 1;
+        }
+
+        histogram.data.data = Arc::new(data);
+        histogram.data.find_occupied();
+        histogram.data.counters = HistogramCounters {
+            entries_total: number(metadata, ENTRIES)?,
+            missed_unknown: number(metadata, MISSED_UNKNOWN)?,
+            missed_small: number(metadata, MISSED_SMALL)?,
+            missed_large: number(metadata, MISSED_LARGE)?,
+        };
+        Ok(histogram)
+    }
+}
+
+// the columns of a record batch, without the metadata
+fn schema() -> Schema {
+    Schema::new(vec![Field::new(LOW, DataType::UInt64, false),
+                     Field::new(HIGH, DataType::UInt64, false),
+                     Field::new(COUNT, DataType::UInt64, false)])
+}
+
+// parse a metadata value as an integer
+fn number(metadata: &HashMap<String, String>, key: &str) -> Result<u64, HistogramError> {
+    metadata.get(key)
+            .and_then(|value| value.parse().ok())
+            .ok_or(HistogramError::InvalidEncoding)
+}
+
+// find a column of non-null integers by name
+fn column<'a>(batch: &'a RecordBatch, name: &str) -> Result<&'a UInt64Array, HistogramError> {
+    batch.column_by_name(name)
+         .and_then(|column| column.as_any().downcast_ref::<UInt64Array>())
+         .filter(|column| column.null_count() == 0)
+         .ok_or(HistogramError::InvalidEncoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use arrow_array::{ArrayRef, RecordBatch, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema};
+
+    use super::super::{DeserializeLimits, Histogram, HistogramConfig, HistogramError,
+                       OverflowPolicy};
+
+    fn populated() -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2).overflow(OverflowPolicy::Clamp);
+
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(0, 2).unwrap_err();
+        h.record(5, 3).unwrap();
+        h.record(1_234, 7).unwrap();
+        h.record(5_000_000, 1).unwrap();
+        h
+    }
+
+    // a batch with the given columns and the metadata of the histogram
+    fn batch(h: &Histogram, low: Vec<u64>, high: Vec<u64>, count: Vec<u64>) -> RecordBatch {
+        let columns: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from(low)),
+                                          Arc::new(UInt64Array::from(high)),
+                                          Arc::new(UInt64Array::from(count))];
+        RecordBatch::try_new(h.to_arrow().unwrap().schema(), columns).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).min_value(1_000);
+        let mut offset = Histogram::configured(c).unwrap();
+        offset.record(999, 2).unwrap_err();
+        offset.record(1_234, 7).unwrap();
+        let mut c = HistogramConfig::new();
+        c.scale(1_000.0);
+        let mut scaled = Histogram::configured(c).unwrap();
+        scaled.record_f64(0.25, 3).unwrap();
+
+        for h in &[Histogram::new().unwrap(), populated(), offset, scaled] {
+            let decoded = Histogram::from_arrow(&h.to_arrow().unwrap()).unwrap();
+            assert_eq!(decoded.data.data, h.data.data);
+            assert_eq!(decoded.data.occupied, h.data.occupied);
+            assert_eq!(decoded.counters(), h.counters());
+            assert_eq!(decoded.config.overflow, h.config.overflow);
+            assert!(decoded.config.matches(&h.config));
+
+            let mut limits = DeserializeLimits::new();
+            limits.max_buckets(h.buckets_total() as u32 - 1);
+            let batch = h.to_arrow().unwrap();
+            assert_eq!(Histogram::from_arrow_with_limits(&batch, &limits).unwrap_err(),
+                       HistogramError::LimitExceeded);
+        }
+    }
+
+    #[test]
+    fn test_schema_fixture() {
+        let batch = populated().to_arrow().unwrap();
+
+        let metadata: HashMap<String, String> =
+            vec![("histogram.precision", "2"),
+                 ("histogram.radix", "10"),
+                 ("histogram.max_value", "1000000"),
+                 ("histogram.overflow", "clamp"),
+                 ("histogram.entries", "13"),
+                 ("histogram.missed_small", "2"),
+                 ("histogram.missed_large", "0"),
+                 ("histogram.missed_unknown", "0")]
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value.to_owned()))
+                .collect();
+        let schema = Schema::new(vec![Field::new("bucket_low", DataType::UInt64, false),
+                                      Field::new("bucket_high", DataType::UInt64, false),
+                                      Field::new("count", DataType::UInt64, false)])
+            .with_metadata(metadata);
+        assert_eq!(*batch.schema(), schema);
+
+        let column = |index: usize| {
+            batch.column(index).as_any().downcast_ref::<UInt64Array>().unwrap().values().to_vec()
+        };
+        assert_eq!(column(0), vec![5, 1_229, 996_148]);
+        assert_eq!(column(1), vec![5, 1_239, 1_000_000]);
+        assert_eq!(column(2), vec![3, 7, 1]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let h = populated();
+        assert!(Histogram::from_arrow(&batch(&h, vec![5], vec![5], vec![3])).is_ok());
+
+        for (low, high) in [(vec![5], vec![6]),
+                            (vec![1_231], vec![1_239]),
+                            (vec![0], vec![0]),
+                            (vec![2_000_000], vec![2_000_000]),
+                            (vec![1_229, 5], vec![1_239, 5]),
+                            (vec![5, 5], vec![5, 5])] {
+            let count = vec![1; low.len()];
+            assert_eq!(Histogram::from_arrow(&batch(&h, low, high, count)).unwrap_err(),
+                       HistogramError::InvalidEncoding);
+        }
+
+        let columns: Vec<ArrayRef> = vec![Arc::new(UInt64Array::from(vec![5])),
+                                          Arc::new(UInt64Array::from(vec![5])),
+                                          Arc::new(UInt64Array::from(vec![3]))];
+        let plain = RecordBatch::try_new(Arc::new(super::schema()), columns).unwrap();
+        assert_eq!(Histogram::from_arrow(&plain).unwrap_err(),
+                   HistogramError::InvalidEncoding);
+    }
+}
@@ -0,0 +1,360 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+use super::{Clock, Histogram, HistogramConfig, HistogramCounters, HistogramError,
+            HistogramProperties, OverflowPolicy, SystemClock};
+
+/// a Histogram recorded into from many threads without locking
+///
+/// The buckets and counters are atomics, so `increment()` and `record()`
+/// take `&self` and the histogram can be shared through an `Arc`. Counts
+/// saturate at u64::MAX as in a Histogram. Statistics are computed on a
+/// plain Histogram copied out by `load()`.
+///
+/// # Example
+/// ```
+/// # use histogram::{AtomicHistogram, HistogramConfig};
+/// # use std::sync::Arc;
+/// # use std::thread;
+/// let h = Arc::new(AtomicHistogram::configured(HistogramConfig::new()).unwrap());
+///
+/// let writers: Vec<_> = (0..4)
+///     .map(|_| {
+///         let h = h.clone();
+///         thread::spawn(move || h.increment(100).unwrap())
+///     })
+///     .collect();
+/// for writer in writers {
+///     writer.join().unwrap();
+/// }
+///
+/// assert_eq!(h.entries(), 4);
+/// assert_eq!(h.load().percentile(50.0).unwrap(), 100);
+pub struct AtomicHistogram {
+    config: HistogramConfig,
+    properties: HistogramProperties,
+    data: Vec<AtomicU64>,
+    entries_total: AtomicU64,
+    missed_unknown: AtomicU64,
+    missed_small: AtomicU64,
+    missed_large: AtomicU64,
+    started: Mutex<Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+impl AtomicHistogram {
+    /// create an AtomicHistogram, failing as `Histogram::configured()` for
+    /// an invalid config
+    pub fn configured(config: HistogramConfig) -> Result<AtomicHistogram, HistogramError> {
+        AtomicHistogram::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// create an AtomicHistogram which takes its timestamps from the given
+    /// clock
+    pub fn with_clock(config: HistogramConfig,
+                      clock: Arc<dyn Clock>)
+                      -> Result<AtomicHistogram, HistogramError> {
+        let properties = HistogramProperties::new(&config)?;
+        Ok(AtomicHistogram {
+            config,
+            properties,
+            data: (0..properties.buckets_total).map(|_| AtomicU64::new(0)).collect(),
+            entries_total: AtomicU64::new(0),
+            missed_unknown: AtomicU64::new(0),
+            missed_small: AtomicU64::new(0),
+            missed_large: AtomicU64::new(0),
+            started: Mutex::new(clock.now()),
+            clock,
+        })
+    }
+
+    /// record a single sample, as `Histogram::increment()`
+    pub fn increment(&self, value: u64) -> Result<(), HistogramError> {
+        self.record(value, 1)
+    }
+
+    /// record additional counts for a value, as `Histogram::record()`
+    pub fn record(&self, value: u64, count: u64) -> Result<(), HistogramError> {
+        saturating_add(&self.entries_total, count);
+        if value < self.config.min_value {
+            saturating_add(&self.missed_small, count);
+            return Err(HistogramError::TooSmall);
+        }
+
+        let clamp = value > self.config.max_value;
+        if clamp && self.config.overflow == OverflowPolicy::Reject {
+            saturating_add(&self.missed_large, count);
+            return Err(HistogramError::TooLarge);
+        }
+
+        let stored = if clamp { self.config.max_value } else { value };
+        match self.properties.get_index(stored).and_then(|index| self.data.get(index)) {
+            Some(bucket) => {
+                saturating_add(bucket, count);
+                Ok(())
+            }
+            _ => {
+                saturating_add(&self.missed_unknown, count);
+                Err(HistogramError::Unknown)
+            }
+        }
+    }
+
+    /// return the number of entries recorded, including missed samples
+    pub fn entries(&self) -> u64 {
+        self.entries_total.load(Ordering::Relaxed)
+    }
+
+    /// zero every bucket and counter
+    ///
+    /// Samples recorded while clearing may be kept or cleared, in part.
+    pub fn clear(&self) {
+        for count in &self.data {
+            count.store(0, Ordering::Relaxed);
+        }
+        for counter in &[&self.entries_total,
+                         &self.missed_unknown,
+                         &self.missed_small,
+                         &self.missed_large] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        *self.lock_started() = self.clock.now();
+    }
+
+    /// return a Histogram holding a copy of the current counts
+    ///
+    /// The copy is consistent, see `snapshot()`, with samples still being
+    /// recorded left out.
+    pub fn load(&self) -> Histogram {
+        self.snapshot().0
+    }
+
+    /// return a consistent copy of the current counts, and the number of
+    /// samples in flight which it leaves out
+    ///
+    /// Writers are not stopped, so a sample may be counted in the entries
+    /// before its bucket or missed counter is incremented, or be stored in a
+    /// bucket the copy has already passed. Rather than copy such a torn
+    /// state, the entries of the copy are recomputed from the buckets and
+    /// missed counters it read, so every sample it counts is one it stores.
+    /// The entries recorded by the time the copy finished, less those in
+    /// the copy, are returned as in flight. They are recorded in full in
+    /// the AtomicHistogram, and appear in a later copy. Counts dropped by
+    /// saturated buckets are also reported as in flight, and a copy taken
+    /// during `clear()` may report none.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{AtomicHistogram, HistogramConfig};
+    /// let h = AtomicHistogram::configured(HistogramConfig::new()).unwrap();
+    ///
+    /// h.record(100, 3).unwrap();
+    ///
+    /// let (snapshot, in_flight) = h.snapshot();
+    ///
+    /// assert_eq!(snapshot.entries(), 3);
+    /// assert_eq!(in_flight, 0);
+    pub fn snapshot(&self) -> (Histogram, u64) {
+        let data: Vec<u64> = self.data.iter().map(|count| count.load(Ordering::Acquire)).collect();
+        let mut counters = HistogramCounters {
+            entries_total: 0,
+            missed_unknown: self.missed_unknown.load(Ordering::Acquire),
+            missed_small: self.missed_small.load(Ordering::Acquire),
+            missed_large: self.missed_large.load(Ordering::Acquire),
+        };
+        counters.entries_total = data.iter()
+                                     .fold(0_u64, |total, count| total.saturating_add(*count))
+                                     .saturating_add(counters.missed_unknown)
+                                     .saturating_add(counters.missed_small)
+                                     .saturating_add(counters.missed_large);
+
+        // writers count a sample in the entries before storing it, and each
+        // count read above was released after its entry, so the entries read
+        // now include every sample in the copy
+        let recorded = self.entries_total.load(Ordering::Relaxed);
+        let in_flight = recorded.saturating_sub(counters.entries_total);
+
+        let mut histogram = Histogram::assembled(self.config, self.properties, counters, data);
+        histogram.clock = self.clock.clone();
+        histogram.data.started = *self.lock_started();
+        (histogram, in_flight)
+    }
+
+    // a poisoned lock is taken anyway, as the time is always valid
+    fn lock_started(&self) -> MutexGuard<'_, Instant> {
+        match self.started.lock() {
+            Ok(started) => started,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Histogram {
+    /// convert to an AtomicHistogram holding the same counts
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    ///
+    /// let a = h.into_atomic();
+    /// a.increment(10).unwrap();
+    ///
+    /// assert_eq!(a.load().get(10).unwrap(), 4);
+    pub fn into_atomic(self) -> AtomicHistogram {
+        let counters = self.data.counters;
+        AtomicHistogram {
+            config: self.config,
+            properties: self.properties,
+            data: self.counts().map(AtomicU64::new).collect(),
+            entries_total: AtomicU64::new(counters.entries_total),
+            missed_unknown: AtomicU64::new(counters.missed_unknown),
+            missed_small: AtomicU64::new(counters.missed_small),
+            missed_large: AtomicU64::new(counters.missed_large),
+            started: Mutex::new(self.data.started),
+            clock: self.clock,
+        }
+    }
+}
+
+// add to the atomic, saturating at u64::MAX rather than wrapping, releasing
+// the entries counted before it to a reader acquiring the result
+fn saturating_add(atomic: &AtomicU64, count: u64) {
+    let _ = atomic.fetch_update(Ordering::Release, Ordering::Relaxed, |current| {
+        Some(current.saturating_add(count))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::AtomicHistogram;
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+
+    #[test]
+    fn test_concurrent() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000);
+        let h = Arc::new(AtomicHistogram::configured(c).unwrap());
+
+        let writers: Vec<_> = (0..8_u64)
+            .map(|thread| {
+                let h = h.clone();
+                thread::spawn(move || {
+                    for i in 0..10_000 {
+                        let _ = h.increment((thread * 10_000 + i) % 1_100_000);
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        // the same samples recorded into a plain histogram
+        let mut expected = Histogram::configured(c).unwrap();
+        for value in 0..80_000 {
+            let _ = expected.increment(value % 1_100_000);
+        }
+
+        assert_eq!(h.entries(), 80_000);
+        let loaded = h.load();
+        loaded.check_invariants().unwrap();
+        assert_eq!(loaded.data.data, expected.data.data);
+        assert_eq!(loaded.counters(), expected.counters());
+        assert_eq!(loaded.percentile(50.0), expected.percentile(50.0));
+
+        h.clear();
+        assert_eq!(h.entries(), 0);
+        assert_eq!(h.load().percentile(50.0), Err(HistogramError::Empty));
+    }
+
+    #[test]
+    fn test_snapshot_stress() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(2);
+        let h = Arc::new(AtomicHistogram::configured(c).unwrap());
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writers: Vec<_> = (0..4_u64)
+            .map(|thread| {
+                let h = h.clone();
+                thread::spawn(move || {
+                    for i in 0..200_000_u64 {
+                        // include samples missed at both ends
+                        let _ = h.record((thread * 7_919 + i * 31) % 110_000, 1 + i % 3);
+                    }
+                })
+            })
+            .collect();
+
+        let reader = {
+            let h = h.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut snapshots = 0;
+                while !done.load(Ordering::Relaxed) || snapshots == 0 {
+                    let (snapshot, _) = h.snapshot();
+                    snapshot.check_invariants().unwrap();
+                    if snapshot.entries() > 0 {
+                        for p in &[1.0, 50.0, 90.0, 99.0] {
+                            match snapshot.percentile(*p) {
+                                Ok(_) | Err(HistogramError::Underflow) |
+                                Err(HistogramError::Overflow) => {}
+                                Err(e) => panic!("percentile {} failed: {}", p, e),
+                            }
+                        }
+                    }
+                    snapshots += 1;
+                }
+                snapshots
+            })
+        };
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        assert!(reader.join().unwrap() > 0);
+
+        // once the writers are done nothing is in flight
+        let (snapshot, in_flight) = h.snapshot();
+        assert_eq!(in_flight, 0);
+        assert_eq!(snapshot.entries(), h.entries());
+        snapshot.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_record() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let h = AtomicHistogram::configured(c).unwrap();
+
+        assert_eq!(h.increment(0), Err(HistogramError::TooSmall));
+        assert_eq!(h.record(1_001, 2), Err(HistogramError::TooLarge));
+        h.record(10, u64::MAX).unwrap();
+        h.record(10, 5).unwrap();
+
+        let loaded = h.load();
+        assert_eq!(loaded.get(10), Some(u64::MAX));
+        assert_eq!(loaded.counters().missed_small(), 1);
+        assert_eq!(loaded.counters().missed_large(), 2);
+        assert_eq!(loaded.entries(), u64::MAX);
+
+        let mut plain = Histogram::configured(c).unwrap();
+        plain.record(5, 3).unwrap();
+        plain.increment(0).unwrap_err();
+        let atomic = plain.clone().into_atomic();
+        atomic.increment(5).unwrap();
+        assert_eq!(atomic.entries(), 5);
+        assert_eq!(atomic.load().get(5), Some(4));
+        assert_eq!(atomic.load().counters().missed_small(), 1);
+    }
+}
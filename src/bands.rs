@@ -0,0 +1,186 @@
+use std::iter;
+
+use super::{occupied_indices, Histogram, HistogramError};
+
+/// the samples of a Histogram within a range of values, see
+/// `Histogram::bands()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Band {
+    low: u64,
+    high: Option<u64>,
+    count: u64,
+    fraction: f64,
+}
+
+impl Band {
+    /// return the lowest value of the band, inclusive
+    pub fn low(&self) -> u64 {
+        self.low
+    }
+
+    /// return the boundary above the band, exclusive, or None for the
+    /// open-ended band above the last boundary
+    pub fn high(&self) -> Option<u64> {
+        self.high
+    }
+
+    /// return the number of samples in the band
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// return the fraction of all recorded samples in the band, including
+    /// missed samples, or 0.0 for an empty histogram
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+}
+
+impl Histogram {
+    /// return the counts in the bands between the given boundaries
+    ///
+    /// The first band holds the values below the first boundary, each
+    /// following band the values from one boundary up to the next, and the
+    /// last band is open-ended above the last boundary. Samples missed as
+    /// too small are counted in the first band, and samples missed as too
+    /// large in the last. A bucket straddling a boundary is counted whole in
+    /// the band holding its lowest value, so no sample is counted twice and
+    /// a boundary is only as precise as the bucket around it. The boundaries
+    /// must be strictly increasing, otherwise `InvalidBoundaries` is
+    /// returned.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(50, 90).unwrap();
+    /// h.record(150, 9).unwrap();
+    /// h.record(400, 1).unwrap();
+    ///
+    /// let bands = h.bands(&[100, 250]).unwrap();
+    ///
+    /// assert_eq!(bands.len(), 3);
+    /// assert_eq!((bands[0].low(), bands[0].high()), (0, Some(100)));
+    /// assert_eq!(bands[0].count(), 90);
+    /// assert_eq!(bands[1].fraction(), 0.09);
+    /// assert_eq!((bands[2].low(), bands[2].high()), (250, None));
+    pub fn bands(&self, boundaries: &[u64]) -> Result<Vec<Band>, HistogramError> {
+        if boundaries.iter().zip(boundaries.iter().skip(1)).any(|(low, high)| low >= high) {
+            return Err(HistogramError::InvalidBoundaries);
+        }
+
+        let counters = &self.data.counters;
+        let mut counts = vec![0_u64; boundaries.len() + 1];
+        if let Some(first) = counts.first_mut() {
+            *first = counters.missed_small;
+        }
+        for index in occupied_indices(self.data.occupied) {
+            let count = self.data.count(index);
+            if count == 0 {
+                continue;
+            }
+            let low = self.properties.index_low(index);
+            let band = boundaries.partition_point(|boundary| *boundary <= low);
+            if let Some(total) = counts.get_mut(band) {
+                *total = total.saturating_add(count);
+            }
+        }
+        if let Some(last) = counts.last_mut() {
+            *last = last.saturating_add(counters.missed_large);
+        }
+
+        let entries = counters.entries_total;
+        let lows = iter::once(0).chain(boundaries.iter().copied());
+        let highs = boundaries.iter().copied().map(Some).chain(iter::once(None));
+        Ok(lows.zip(highs)
+               .zip(counts)
+               .map(|((low, high), count)| {
+                   Band {
+                       low,
+                       high,
+                       count,
+                       fraction: if entries == 0 {
+                           0.0
+                       } else {
+                           count as f64 / entries as f64
+                       },
+                   }
+               })
+               .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError, OverflowPolicy};
+    use super::super::test_rng::Rng;
+
+    #[test]
+    fn test_exact() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(3);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 1..1_001 {
+            h.increment(value).unwrap();
+        }
+        h.increment(0).unwrap_err();
+        h.record(2_000, 4).unwrap_err();
+
+        let bands = h.bands(&[10, 100, 500]).unwrap();
+        let counts: Vec<u64> = bands.iter().map(|band| band.count()).collect();
+        assert_eq!(counts, vec![10, 90, 400, 505]);
+        assert_eq!(bands[3].low(), 500);
+        assert_eq!(bands[3].high(), None);
+        let total: f64 = bands.iter().map(|band| band.fraction()).sum();
+        assert!((total - 1.0).abs() < 1e-12);
+
+        let all = h.bands(&[]).unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!((all[0].low(), all[0].high(), all[0].count()), (0, None, 1_005));
+    }
+
+    #[test]
+    fn test_mid_bucket() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(1).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            let _ = h.record(rng.next() % 120_000, 1 + rng.next() % 3);
+        }
+
+        // boundaries within buckets, on bucket edges, and past max_value
+        let mut boundaries = vec![17, 18, 100, 1_000, 1_001, 1_025, 50_000, 200_000];
+        assert_eq!(h.bucket_bounds(17), Some((16, 17)));
+        assert_eq!(h.bucket_bounds(1_001), Some((973, 1_023)));
+        let bands = h.bands(&boundaries).unwrap();
+
+        // every sample lands in exactly one band
+        let total = bands.iter().fold(0, |total, band| total + band.count());
+        assert_eq!(total, h.entries());
+
+        // the bands below a boundary agree with the buckets holding values
+        // up to just below it
+        let mut below = 0;
+        for (band, boundary) in bands.iter().zip(&boundaries) {
+            below += band.count();
+            assert_eq!(below,
+                       h.count_between(0, boundary - 1) + h.counters().missed_small(),
+                       "boundary: {}",
+                       boundary);
+        }
+
+        boundaries.swap(1, 2);
+        assert_eq!(h.bands(&boundaries), Err(HistogramError::InvalidBoundaries));
+        assert_eq!(h.bands(&[10, 10]), Err(HistogramError::InvalidBoundaries));
+    }
+
+    #[test]
+    fn test_empty() {
+        let h = Histogram::new().unwrap();
+        let bands = h.bands(&[100]).unwrap();
+        assert_eq!(bands.len(), 2);
+        assert!(bands.iter().all(|band| band.count() == 0 && band.fraction() == 0.0));
+    }
+}
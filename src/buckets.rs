@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use super::{occupied_indices, Histogram, HistogramConfig, HistogramError, OverflowPolicy};
+
+impl Histogram {
+    /// create a Histogram from `(value, count)` pairs, as exported by another
+    /// histogram implementation, returning it and the number of pairs dropped
+    ///
+    /// Each count is added straight into the bucket counts, and the occupied
+    /// buckets found once at the end, so this is cheaper than recording each
+    /// pair. The pairs need not be sorted or distinct, and pairs of count 0
+    /// are skipped. A value outside the range
+    /// is handled as by `record()`: clamped under `OverflowPolicy::Clamp`,
+    /// otherwise its pair is dropped and its count added to the missed
+    /// counters, rather than failing the whole call. The exact `stats()` are
+    /// of the values given. Fails only for an invalid config.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000);
+    ///
+    /// let pairs = [(10, 3), (20, 2), (10, 1), (5_000, 4), (0, 1)];
+    /// let (h, dropped) = Histogram::from_buckets(c, &pairs).unwrap();
+    ///
+    /// assert_eq!(dropped, 2);
+    /// assert_eq!(h.get(10), Some(4));
+    /// assert_eq!(h.entries(), 11);
+    /// assert_eq!((h.missed_small(), h.missed_large()), (1, 4));
+    pub fn from_buckets(config: HistogramConfig,
+                        pairs: &[(u64, u64)])
+                        -> Result<(Histogram, u64), HistogramError> {
+        let mut histogram = Histogram::configured(config)?;
+        let config = histogram.config;
+        let properties = histogram.properties;
+        let mut counters = histogram.data.counters;
+        let mut stats = histogram.data.stats;
+        let mut dropped = 0;
+
+        let counts = Arc::make_mut(&mut histogram.data.data);
+        for &(value, count) in pairs {
+            if count == 0 {
+                continue;
+            }
+            counters.entries_total = counters.entries_total.saturating_add(count);
+
+            let clamp = value > config.max_value && config.overflow == OverflowPolicy::Clamp;
+            let stored = if clamp { config.max_value } else { value };
+            let bucket = if value < config.min_value || stored > config.max_value {
+                None
+            } else {
+                properties.get_index(stored).and_then(|index| counts.get_mut(index))
+            };
+            match bucket {
+                Some(bucket) => {
+                    let added = count.min(u64::MAX - *bucket);
+                    *bucket += added;
+                    stats = stats.and_then(|stats| stats.add(stored, added));
+                }
+                None => {
+                    dropped += 1;
+                    let missed = if value < config.min_value {
+                        &mut counters.missed_small
+                    } else if value > config.max_value {
+                        &mut counters.missed_large
+                    } else {
+                        &mut counters.missed_unknown
+                    };
+                    *missed = missed.saturating_add(count);
+                }
+            }
+        }
+
+        histogram.data.counters = counters;
+        histogram.data.stats = stats;
+        histogram.data.find_occupied();
+        Ok((histogram, dropped))
+    }
+
+    /// return the non-empty buckets as `(value, count)` pairs of the nominal
+    /// value of each bucket and its count, in ascending order of value
+    ///
+    /// These suit another histogram implementation, or `from_buckets()`,
+    /// which reproduces the same bucket counts for the same config. Missed
+    /// samples are left out.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.precision(2);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    /// h.record(1_234, 2).unwrap();
+    ///
+    /// assert_eq!(h.to_buckets(), vec![(10, 3), (1_229, 2)]);
+    pub fn to_buckets(&self) -> Vec<(u64, u64)> {
+        occupied_indices(self.data.occupied).filter_map(|index| match self.data.count(index) {
+                                                0 => None,
+                                                count => Some((self.index_value(index), count)),
+                                            })
+                                            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, OverflowPolicy};
+    use super::super::test_rng::Rng;
+
+    fn random_pairs(seed: u64, pairs: usize) -> Vec<(u64, u64)> {
+        let mut rng = Rng(seed);
+        (0..pairs).map(|_| {
+                      let exponent = (rng.next() >> 11) as f64 / (1_u64 << 53) as f64 * 30.0;
+                      (exponent.exp2() as u64, rng.next() % 100)
+                  })
+                  .collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &(precision, radix) in &[(1, 10), (3, 10), (4, 2), (5, 10)] {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).radix(radix).max_value(1 << 30);
+            let mut h = Histogram::configured(c).unwrap();
+            for &(value, count) in &random_pairs(precision as u64 + 1, 5_000) {
+                h.record(value, count).unwrap();
+            }
+
+            let buckets = h.to_buckets();
+            assert!(buckets.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert!(buckets.iter().all(|pair| pair.1 > 0));
+            let (copy, dropped) = Histogram::from_buckets(c, &buckets).unwrap();
+            assert_eq!(dropped, 0);
+            assert_eq!(copy.data.data, h.data.data);
+            assert_eq!(copy.data.occupied, h.data.occupied);
+            assert_eq!(copy.entries(), h.entries());
+            assert_eq!(copy.to_buckets(), buckets);
+
+            // the same counts as recording each pair
+            let pairs = random_pairs(precision as u64 + 1, 5_000);
+            let (loaded, _) = Histogram::from_buckets(c, &pairs).unwrap();
+            assert_eq!(loaded.data.data, h.data.data);
+            assert_eq!(loaded.stats(), h.stats());
+        }
+        assert!(Histogram::new().unwrap().to_buckets().is_empty());
+    }
+
+    #[test]
+    fn test_cross_precision() {
+        let mut fine = HistogramConfig::new();
+        fine.precision(4).max_value(1 << 30);
+        let mut coarse = HistogramConfig::new();
+        coarse.precision(1).radix(2).max_value(1 << 30);
+        let (h, dropped) = Histogram::from_buckets(fine, &random_pairs(7, 10_000)).unwrap();
+        assert_eq!(dropped, 0);
+
+        let buckets = h.to_buckets();
+        let (coarser, dropped) = Histogram::from_buckets(coarse, &buckets).unwrap();
+        assert_eq!(dropped, 0);
+        assert!(coarser.to_buckets().len() < buckets.len());
+        assert_eq!(coarser.entries(), h.entries());
+        assert_eq!(coarser.data.data.iter().sum::<u64>(), h.data.data.iter().sum::<u64>());
+
+        // each count lands in the coarse bucket holding its fine bucket
+        for &(value, count) in &buckets {
+            let (low, high) = coarser.bucket_bounds(value).unwrap();
+            assert!(coarser.count_between(low, high) >= count);
+        }
+    }
+
+    #[test]
+    fn test_dropped() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).min_value(10);
+        let pairs = [(5, 2), (10, 1), (1_000, 1), (1_001, 3), (u64::MAX, 1), (500, 0)];
+        let (h, dropped) = Histogram::from_buckets(c, &pairs).unwrap();
+        assert_eq!(dropped, 3);
+        assert_eq!(h.entries(), 8);
+        assert_eq!(h.recorded(), 2);
+        assert_eq!((h.missed_small(), h.missed_large(), h.missed_unknown()), (2, 4, 0));
+        assert_eq!(h.stats().unwrap().max(), Some(1_000));
+
+        // clamped values are kept at max_value
+        c.overflow(OverflowPolicy::Clamp);
+        let (h, dropped) = Histogram::from_buckets(c, &pairs).unwrap();
+        assert_eq!(dropped, 1);
+        assert_eq!(h.get(1_000), Some(5));
+        assert_eq!(h.maximum(), Ok(1_000));
+
+        // saturated buckets keep what fits
+        let (h, dropped) = Histogram::from_buckets(c, &[(10, u64::MAX), (10, 5)]).unwrap();
+        assert_eq!(dropped, 0);
+        assert_eq!(h.get(10), Some(u64::MAX));
+
+        c.precision(40);
+        assert!(Histogram::from_buckets(c, &pairs).is_err());
+    }
+}
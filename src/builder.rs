@@ -0,0 +1,264 @@
+use std::mem;
+
+use super::{Histogram, HistogramConfig, HistogramError, OverflowPolicy};
+
+// the state of a HistogramBuilder, which infers its config once the buffer
+// fills and then streams into the histogram, which is held inline as the
+// builder spends nearly all its life streaming
+#[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
+enum State {
+    Buffering(Vec<(u64, u64)>),
+    Streaming(Histogram),
+    // no config fits within max_memory
+    Failed,
+}
+
+/// builds a Histogram from a stream of samples, choosing the config from the
+/// first samples seen
+///
+/// The first `buffer()` samples are held back. Once the buffer fills, or at
+/// `finish()`, the builder picks the precision meeting `relative_error()` and
+/// a max_value covering the largest buffered value, lowering the precision
+/// if needed to fit `max_memory()`. The buffered samples are then replayed
+/// and later samples recorded directly, with values above the inferred
+/// max_value following the `overflow()` policy.
+///
+/// # Example
+/// ```
+/// # use histogram::HistogramBuilder;
+/// let mut b = HistogramBuilder::auto();
+/// b.buffer(100).relative_error(0.01);
+///
+/// for value in (1..1001).rev() {
+///     b.increment(value).unwrap();
+/// }
+///
+/// let (h, _config) = b.finish().unwrap();
+///
+/// assert_eq!(h.entries(), 1000);
+/// assert_eq!(h.percentile(0.0).unwrap(), 1);
+#[derive(Clone)]
+pub struct HistogramBuilder {
+    buffer: usize,
+    relative_error: f64,
+    max_memory: u32,
+    overflow: OverflowPolicy,
+    state: State,
+}
+
+impl HistogramBuilder {
+    /// create a HistogramBuilder inferring its config from the first 1024
+    /// samples, with a relative error of 0.1% and no memory limit
+    pub fn auto() -> HistogramBuilder {
+        HistogramBuilder {
+            buffer: 1024,
+            relative_error: 0.001,
+            max_memory: 0,
+            overflow: OverflowPolicy::Reject,
+            state: State::Buffering(Vec::new()),
+        }
+    }
+
+    /// set the number of samples buffered before the config is chosen
+    pub fn buffer(&mut self, samples: usize) -> &mut Self {
+        self.buffer = samples;
+        self
+    }
+
+    /// set the largest acceptable relative error of a recorded value
+    pub fn relative_error(&mut self, error: f64) -> &mut Self {
+        self.relative_error = error;
+        self
+    }
+
+    /// set the memory limit of the histogram, 0 for none
+    pub fn max_memory(&mut self, bytes: u32) -> &mut Self {
+        self.max_memory = bytes;
+        self
+    }
+
+    /// set the handling of values above the inferred max_value
+    pub fn overflow(&mut self, policy: OverflowPolicy) -> &mut Self {
+        self.overflow = policy;
+        self
+    }
+
+    /// record a single sample
+    pub fn increment(&mut self, value: u64) -> Result<(), HistogramError> {
+        self.record(value, 1)
+    }
+
+    /// record additional counts for a value
+    ///
+    /// While buffering, values too small to store are counted as missed and
+    /// reported immediately, and other values are accepted. Once streaming,
+    /// this behaves as `Histogram::record()`.
+    pub fn record(&mut self, value: u64, count: u64) -> Result<(), HistogramError> {
+        let full = match self.state {
+            State::Buffering(ref mut samples) => {
+                samples.push((value, count));
+                samples.len() >= self.buffer
+            }
+            State::Streaming(ref mut histogram) => return histogram.record(value, count),
+            State::Failed => return Err(HistogramError::MemoryLimitExceeded),
+        };
+
+        if full {
+            self.build();
+            if let State::Failed = self.state {
+                return Err(HistogramError::MemoryLimitExceeded);
+            }
+        }
+        if value < 1 {
+            return Err(HistogramError::TooSmall);
+        }
+        Ok(())
+    }
+
+    /// return the histogram and its config, choosing the config from the
+    /// buffered samples if the buffer has not filled
+    ///
+    /// Fails with `MemoryLimitExceeded` if no config fits within max_memory.
+    pub fn finish(mut self) -> Result<(Histogram, HistogramConfig), HistogramError> {
+        self.build();
+        match self.state {
+            State::Streaming(histogram) => {
+                let config = histogram.config;
+                Ok((histogram, config))
+            }
+            _ => Err(HistogramError::MemoryLimitExceeded),
+        }
+    }
+
+    // choose a config from the buffered samples and replay them into a new
+    // histogram
+    fn build(&mut self) {
+        let samples = match mem::replace(&mut self.state, State::Failed) {
+            State::Buffering(samples) => samples,
+            state => {
+                self.state = state;
+                return;
+            }
+        };
+
+        let largest = samples.iter().map(|sample| sample.0).max().unwrap_or(0);
+        let mut histogram = match self.infer(largest) {
+            Some(histogram) => histogram,
+            None => return,
+        };
+
+        for (value, count) in samples {
+            let _ = histogram.record(value, count);
+        }
+        self.state = State::Streaming(histogram);
+    }
+
+    // the histogram with the finest precision meeting the relative error and
+    // memory limit whose range covers the largest value
+    fn infer(&self, largest: u64) -> Option<Histogram> {
+        // the layout depends only on the bit length of max_value, so filling
+        // the low bits covers larger values for free
+        let max_value = u64::MAX >> largest.max(1).leading_zeros();
+
+        let error = self.relative_error.clamp(1e-9, 1.0);
+        // allow for rounding in the logarithm of exact powers of ten
+        let precision = ((-error.log10() - 1e-9).ceil() as u32).max(1);
+
+        let mut config = HistogramConfig::new();
+        config.max_value(max_value)
+              .max_memory(self.max_memory)
+              .overflow(self.overflow);
+
+        (1..(precision + 1))
+            .rev()
+            .filter_map(|precision| {
+                config.precision(precision);
+                Histogram::configured(config).ok()
+            })
+            .next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HistogramBuilder;
+    use super::super::{Histogram, HistogramError, OverflowPolicy};
+    use super::super::test_rng::Rng;
+
+    #[test]
+    fn test_heavy_tail() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let samples: Vec<u64> = (0..20_000).map(|_| rng.pareto()).collect();
+        let largest = *samples[..1_000].iter().max().unwrap();
+
+        let mut b = HistogramBuilder::auto();
+        b.buffer(1_000).relative_error(0.01).overflow(OverflowPolicy::Clamp);
+        for &value in &samples {
+            b.increment(value).unwrap();
+        }
+        let (h, config) = b.finish().unwrap();
+
+        // the buffered range is covered at the requested precision
+        assert_eq!(config.precision, 2);
+        assert!(config.max_value >= largest);
+        assert!(config.max_value < largest * 2);
+        assert_eq!(config.overflow, OverflowPolicy::Clamp);
+
+        // and matches recording everything into a histogram of that config
+        let mut expected = Histogram::configured(config).unwrap();
+        for &value in &samples {
+            expected.increment(value).unwrap();
+        }
+        assert_eq!(h.entries(), 20_000);
+        assert_eq!(h.data.data, expected.data.data);
+        for p in &[0.0, 50.0, 90.0, 99.0, 100.0] {
+            assert_eq!(h.percentile(*p), expected.percentile(*p));
+        }
+        let clamped = samples.iter().filter(|&&value| value > config.max_value).count();
+        assert!(clamped > 0);
+        assert_eq!(h.get(config.max_value).unwrap() as usize, clamped);
+    }
+
+    #[test]
+    fn test_reject_after_buffer() {
+        let mut b = HistogramBuilder::auto();
+        b.buffer(3);
+
+        b.increment(0).unwrap_err();
+        b.increment(100).unwrap();
+        b.increment(1_000).unwrap();
+
+        // the buffer is full, so max_value is 1023
+        b.increment(1_023).unwrap();
+        assert_eq!(b.increment(1_024), Err(HistogramError::TooLarge));
+
+        let (h, config) = b.finish().unwrap();
+        assert_eq!(config.max_value, 1_023);
+        assert_eq!(config.precision, 3);
+        assert_eq!(h.entries(), 5);
+        assert_eq!(h.data.counters.missed_small, 1);
+        assert_eq!(h.data.counters.missed_large, 1);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut unlimited = HistogramBuilder::auto();
+        unlimited.increment(1_000_000_000).unwrap();
+        let (h, _) = unlimited.finish().unwrap();
+        let memory = h.properties.memory_used as u32;
+
+        let mut b = HistogramBuilder::auto();
+        b.max_memory(memory - 1);
+        b.increment(1_000_000_000).unwrap();
+        let (h, config) = b.finish().unwrap();
+        assert!(config.precision < 3);
+        assert_eq!(h.get(1_000_000_000).unwrap(), 1);
+
+        let mut b = HistogramBuilder::auto();
+        b.buffer(1).max_memory(1);
+        assert_eq!(b.increment(10), Err(HistogramError::MemoryLimitExceeded));
+        assert_eq!(b.increment(10), Err(HistogramError::MemoryLimitExceeded));
+        assert_eq!(b.finish().unwrap_err(), HistogramError::MemoryLimitExceeded);
+    }
+}
@@ -0,0 +1,160 @@
+use super::{Histogram, HistogramError};
+
+/// the rate at which an error budget is spent, over a short and a long window
+///
+/// A sample above the latency threshold is an error. The burn rate of a
+/// window is its fraction of errors divided by the allowed error rate, one
+/// minus the objective, so a rate of 1.0 spends exactly the budget over the
+/// SLO period. Alerting on both windows exceeding a factor, such as 14.4 for
+/// 1h and 5m, pages quickly on a fast burn without firing on a brief spike.
+///
+/// # Example
+/// ```
+/// # use histogram::{BurnRate, Histogram};
+/// let mut short = Histogram::new().unwrap();
+/// let mut long = Histogram::new().unwrap();
+///
+/// short.record(10, 980).unwrap();
+/// short.record(500, 20).unwrap();
+/// long.record(10, 9_990).unwrap();
+/// long.record(500, 10).unwrap();
+///
+/// let rate = BurnRate::between(&short, &long, 100, 0.999).unwrap();
+///
+/// assert_eq!(rate.short().round(), 20.0);
+/// assert_eq!(rate.long().round(), 1.0);
+/// assert!(!rate.exceeds(14.4));
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BurnRate {
+    short: f64,
+    long: f64,
+}
+
+impl BurnRate {
+    /// compute the burn rate of histograms holding the samples of the short
+    /// and long windows, counting samples above the threshold as errors
+    ///
+    /// The objective is the fraction of samples which should be at or below
+    /// the threshold, such as 0.999, and must be between 0 and 1 exclusive,
+    /// otherwise `InvalidPercentile` is returned. Errors are counted to the
+    /// precision of the bucket holding the threshold, and samples missed as
+    /// too large are errors. A window without samples burns nothing.
+    pub fn between(short: &Histogram,
+                   long: &Histogram,
+                   threshold: u64,
+                   objective: f64)
+                   -> Result<BurnRate, HistogramError> {
+        if !(objective > 0.0 && objective < 1.0) {
+            return Err(HistogramError::InvalidPercentile);
+        }
+        let budget = 1.0 - objective;
+        Ok(BurnRate {
+            short: error_rate(short, threshold)? / budget,
+            long: error_rate(long, threshold)? / budget,
+        })
+    }
+
+    /// return the burn rate of the short window
+    pub fn short(&self) -> f64 {
+        self.short
+    }
+
+    /// return the burn rate of the long window
+    pub fn long(&self) -> f64 {
+        self.long
+    }
+
+    /// return whether both windows burn faster than the given factor, the
+    /// condition of a multi-window burn rate alert
+    pub fn exceeds(&self, factor: f64) -> bool {
+        self.short > factor && self.long > factor
+    }
+}
+
+// the fraction of the samples of a window above the threshold, counting
+// every recorded sample whether or not the config ranks missed samples
+fn error_rate(window: &Histogram, threshold: u64) -> Result<f64, HistogramError> {
+    match window.check_stored() {
+        Ok(()) => {}
+        Err(HistogramError::Empty) => return Ok(0.0),
+        Err(e) => return Err(e),
+    }
+    let counters = &window.data.counters;
+    let mut below = counters.missed_small.saturating_add(window.count_between(0, threshold));
+    if threshold > window.config.max_value {
+        below = below.saturating_add(counters.missed_large);
+    }
+    Ok((1.0 - below as f64 / counters.entries_total as f64).max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BurnRate;
+    use super::super::{Histogram, HistogramConfig, HistogramError, OverflowPolicy};
+
+    // a window of the given samples, a fraction of them above 100ms
+    fn window(samples: u64, errors: u64) -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000);
+        let mut h = Histogram::configured(c).unwrap();
+        let _ = h.record(20, samples - errors);
+        let _ = h.record(900, errors);
+        h
+    }
+
+    #[test]
+    fn test_fast_burn() {
+        // 2% of the budget of a 30 day 99.9% SLO spent in an hour, 14.4x
+        let long = window(360_000, 5_184);
+        let short = window(30_000, 432);
+        let rate = BurnRate::between(&short, &long, 100, 0.999).unwrap();
+        assert!((rate.short() - 14.4).abs() < 1e-6, "{:?}", rate);
+        assert!((rate.long() - 14.4).abs() < 1e-6, "{:?}", rate);
+        assert!(rate.exceeds(14.0));
+
+        // the burn has already stopped, so the short window keeps it quiet
+        let recovered = BurnRate::between(&window(30_000, 3), &long, 100, 0.999).unwrap();
+        assert!(recovered.long() > 14.0);
+        assert!(!recovered.exceeds(14.0));
+
+        // a brief spike alone does not page
+        let spike = BurnRate::between(&window(30_000, 3_000), &window(360_000, 3_360), 100, 0.999)
+            .unwrap();
+        assert!(spike.short() > 14.4);
+        assert!(!spike.exceeds(14.4));
+    }
+
+    #[test]
+    fn test_slow_burn() {
+        // 5% of the budget spent in 6 hours, 6x, a ticket rather than a page
+        let long = window(2_160_000, 12_960);
+        let short = window(180_000, 1_080);
+        let rate = BurnRate::between(&short, &long, 100, 0.999).unwrap();
+        assert!((rate.short() - 6.0).abs() < 1e-6, "{:?}", rate);
+        assert!((rate.long() - 6.0).abs() < 1e-6, "{:?}", rate);
+        assert!(!rate.exceeds(14.4));
+        assert!(rate.exceeds(5.9));
+    }
+
+    #[test]
+    fn test_edges() {
+        let empty = Histogram::new().unwrap();
+        let rate = BurnRate::between(&empty, &empty, 100, 0.99).unwrap();
+        assert_eq!((rate.short(), rate.long()), (0.0, 0.0));
+
+        for &objective in &[0.0, 1.0, -0.5, 99.9, f64::NAN] {
+            assert_eq!(BurnRate::between(&empty, &empty, 100, objective),
+                       Err(HistogramError::InvalidPercentile));
+        }
+
+        // samples missed as too large are errors, too small ones are not
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).overflow(OverflowPolicy::Reject);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(10, 97).unwrap();
+        h.record(0, 1).unwrap_err();
+        h.record(5_000, 2).unwrap_err();
+        let rate = BurnRate::between(&h, &h, 100, 0.9).unwrap();
+        assert!((rate.short() - 0.2).abs() < 1e-9, "{:?}", rate);
+    }
+}
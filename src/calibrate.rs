@@ -0,0 +1,183 @@
+use super::{Histogram, HistogramConfig, HistogramError};
+
+// the percentiles compared by a calibration, p50 through the maximum
+const PERCENTILES: [f64; 5] = [50.0, 90.0, 99.0, 99.9, 100.0];
+
+/// the observed error of a HistogramConfig on a reference dataset
+///
+/// Returned by `HistogramConfig::calibrate()`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalibrationReport {
+    points: Vec<CalibrationPoint>,
+    error_bound: f64,
+    memory_used: u64,
+}
+
+/// a percentile of the reference dataset, as reported by a histogram and as
+/// computed exactly
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationPoint {
+    percentile: f64,
+    exact: u64,
+    observed: u64,
+}
+
+impl HistogramConfig {
+    /// measure the error of this config on the samples
+    ///
+    /// The samples are recorded into a temporary histogram, and its p50,
+    /// p90, p99, p99.9 and maximum compared with the exact percentiles of
+    /// the samples, chosen by the same rank. The samples should lie within
+    /// min_value and max_value, as the histogram ranks missed samples, so a
+    /// percentile which falls among them returns its error, such as
+    /// `Overflow`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::HistogramConfig;
+    /// let mut c = HistogramConfig::new();
+    /// c.precision(2);
+    ///
+    /// let samples: Vec<u64> = (1..10_000).map(|i| i * 37).collect();
+    /// let report = c.calibrate(&samples).unwrap();
+    ///
+    /// assert_eq!(report.points().len(), 5);
+    /// assert!(report.max_relative_error() <= report.error_bound());
+    pub fn calibrate(&self, samples: &[u64]) -> Result<CalibrationReport, HistogramError> {
+        let mut config = *self;
+        config.rank_missed(true);
+        let mut histogram = Histogram::configured(config)?;
+        for &sample in samples {
+            let _ = histogram.increment(sample);
+        }
+
+        // the samples as sorted (value, count) pairs, so the exact
+        // percentiles do not each sort the whole slice
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let mut pairs: Vec<(u64, u64)> = Vec::new();
+        for value in sorted {
+            match pairs.last_mut() {
+                Some(last) if last.0 == value => last.1 += 1,
+                _ => pairs.push((value, 1)),
+            }
+        }
+
+        let mut points = Vec::with_capacity(PERCENTILES.len());
+        for &percentile in &PERCENTILES {
+            points.push(CalibrationPoint {
+                percentile,
+                exact: Histogram::percentile_of_pairs(&pairs, percentile)?,
+                observed: histogram.percentile(percentile)?,
+            });
+        }
+
+        Ok(CalibrationReport {
+            points,
+            error_bound: 1.0 / histogram.properties.buckets_inner as f64,
+            memory_used: histogram.properties.memory_used,
+        })
+    }
+}
+
+impl CalibrationReport {
+    /// return the comparison at each percentile, in ascending order
+    pub fn points(&self) -> &[CalibrationPoint] {
+        &self.points
+    }
+
+    /// return the largest relative error of any percentile
+    pub fn max_relative_error(&self) -> f64 {
+        self.points.iter().map(|point| point.relative_error()).fold(0.0, f64::max)
+    }
+
+    /// return the theoretical bound on the relative error of a value stored
+    /// by the config, one over the number of buckets in each power of two
+    pub fn error_bound(&self) -> f64 {
+        self.error_bound
+    }
+
+    /// return the memory used by the buckets of the config in bytes
+    pub fn memory_used(&self) -> u64 {
+        self.memory_used
+    }
+}
+
+impl CalibrationPoint {
+    /// return the percentile compared
+    pub fn percentile(&self) -> f64 {
+        self.percentile
+    }
+
+    /// return the exact percentile of the samples
+    pub fn exact(&self) -> u64 {
+        self.exact
+    }
+
+    /// return the percentile reported by the histogram
+    pub fn observed(&self) -> u64 {
+        self.observed
+    }
+
+    /// return the difference between the observed and exact percentile
+    pub fn absolute_error(&self) -> u64 {
+        self.observed.abs_diff(self.exact)
+    }
+
+    /// return the absolute error relative to the exact percentile
+    pub fn relative_error(&self) -> f64 {
+        self.absolute_error() as f64 / self.exact as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HistogramConfig, HistogramError, OverflowPolicy};
+    use super::super::test_rng::Rng;
+
+    #[test]
+    fn test_heavy_tail() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let samples: Vec<u64> = (0..50_000).map(|_| rng.pareto()).collect();
+
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000_000_000_000);
+        for precision in 1..4 {
+            c.precision(precision);
+            let report = c.calibrate(&samples).unwrap();
+
+            assert_eq!(report.error_bound(), 1.0 / 10_f64.powi(precision as i32));
+            let percentiles: Vec<f64> = report.points().iter().map(|p| p.percentile()).collect();
+            assert_eq!(percentiles, vec![50.0, 90.0, 99.0, 99.9, 100.0]);
+            assert_eq!(report.points()[4].exact(), *samples.iter().max().unwrap());
+            for point in report.points() {
+                assert!(point.exact() >= 1_000);
+                assert!(point.observed() > 0);
+                assert!(point.relative_error() <= report.error_bound(), "{:?}", point);
+            }
+            assert!(report.max_relative_error() > 0.0);
+            assert!(report.max_relative_error() <= report.error_bound());
+            assert_eq!(report.memory_used(),
+                       super::super::Histogram::configured(c).unwrap().buckets_total() * 8);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let c = HistogramConfig::new();
+        assert_eq!(c.calibrate(&[]).unwrap_err(), HistogramError::Empty);
+
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        assert_eq!(c.calibrate(&[10, 10, 10, 5_000]).unwrap_err(), HistogramError::Overflow);
+
+        // clamping reports the error of the clamped maximum
+        c.overflow(OverflowPolicy::Clamp);
+        let report = c.calibrate(&[10, 10, 10, 5_000]).unwrap();
+        assert_eq!(report.points()[4].absolute_error(), 4_000);
+        assert_eq!(report.points()[0].absolute_error(), 0);
+
+        c.precision(40);
+        assert_eq!(c.calibrate(&[10]).unwrap_err(), HistogramError::InvalidPrecision);
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+
+/// a source of time for the histogram timestamps
+///
+/// The default is `SystemClock`. Tests can substitute a `ManualClock` to
+/// control time explicitly.
+pub trait Clock: Send + Sync {
+    /// return the current time
+    fn now(&self) -> Instant;
+
+    /// return the current wall clock time, which timestamps taken from
+    /// `now()` are reported relative to
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// the clock backed by `Instant::now()`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// a clock which only moves when advanced
+///
+/// # Example
+/// ```
+/// # use histogram::{Clock, ManualClock};
+/// # use std::time::Duration;
+/// let clock = ManualClock::new();
+/// let start = clock.now();
+///
+/// clock.advance(Duration::from_secs(5));
+/// assert_eq!(clock.now() - start, Duration::from_secs(5));
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<Instant>,
+    // the instant the clock started at, and the wall clock time then
+    start: Instant,
+    wall: SystemTime,
+}
+
+impl ManualClock {
+    /// create a ManualClock starting at the current time
+    pub fn new() -> ManualClock {
+        let start = Instant::now();
+        ManualClock {
+            now: Mutex::new(start),
+            start,
+            wall: SystemTime::now(),
+        }
+    }
+
+    /// move the clock forward
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.lock();
+        if let Some(later) = now.checked_add(duration) {
+            *now = later;
+        }
+    }
+
+    // a poisoned lock is taken anyway, as the time is always valid
+    fn lock(&self) -> MutexGuard<'_, Instant> {
+        match self.now.lock() {
+            Ok(now) => now,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> ManualClock {
+        ManualClock::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.lock()
+    }
+
+    // the wall clock moves with the clock, so is as predictable
+    fn system_time(&self) -> SystemTime {
+        self.wall + (self.now() - self.start)
+    }
+}
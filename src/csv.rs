@@ -0,0 +1,245 @@
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+use super::{Histogram, HistogramConfig, HistogramError};
+
+/// the outcome of `Histogram::from_csv_reader()`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    rows: u64,
+    malformed: Vec<usize>,
+    too_small: u64,
+    too_large: u64,
+    unknown: u64,
+}
+
+impl ImportReport {
+    /// return the number of rows parsed and recorded
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    /// return the line numbers, counting from 1, of the rows which could
+    /// not be parsed and were skipped
+    pub fn malformed(&self) -> &[usize] {
+        &self.malformed
+    }
+
+    /// return the count of the rows below min_value
+    pub fn too_small(&self) -> u64 {
+        self.too_small
+    }
+
+    /// return the count of the rows above max_value rejected by the
+    /// overflow policy
+    pub fn too_large(&self) -> u64 {
+        self.too_large
+    }
+
+    /// return the count of the rows which failed to index
+    pub fn unknown(&self) -> u64 {
+        self.unknown
+    }
+}
+
+impl Histogram {
+    /// create a Histogram from CSV rows of `value,count`
+    ///
+    /// Blank lines are skipped and whitespace around each field ignored. A
+    /// first row which does not parse is taken as a header. Any later row
+    /// which does not parse is skipped and its line number reported, rather
+    /// than failing the import. Counts outside the histogram range are
+    /// recorded as missed, as by `record()`, and reported. Fails on an
+    /// invalid config or a failure to read.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let csv = "value,count\n10, 3\n20,2\noops\n";
+    ///
+    /// let (h, report) = Histogram::from_csv_reader(HistogramConfig::new(), csv.as_bytes())
+    ///     .unwrap();
+    ///
+    /// assert_eq!(h.entries(), 5);
+    /// assert_eq!(report.rows(), 2);
+    /// assert_eq!(report.malformed(), &[4]);
+    pub fn from_csv_reader<R: Read>(config: HistogramConfig,
+                                    reader: R)
+                                    -> Result<(Histogram, ImportReport), HistogramError> {
+        let mut histogram = Histogram::configured(config)?;
+        let mut report = ImportReport::default();
+
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        let mut number = 0;
+        let mut first = true;
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)
+                             .map_err(|e| HistogramError::Io(e.kind()))?;
+            if read == 0 {
+                break;
+            }
+            number += 1;
+
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let (value, count) = match parse_row(text) {
+                Some(row) => row,
+                None if first => {
+                    first = false;
+                    continue;
+                }
+                None => {
+                    report.malformed.push(number);
+                    continue;
+                }
+            };
+            first = false;
+
+            report.rows += 1;
+            match histogram.record(value, count) {
+                Err(HistogramError::TooSmall) => {
+                    report.too_small = report.too_small.saturating_add(count);
+                }
+                Err(HistogramError::TooLarge) => {
+                    report.too_large = report.too_large.saturating_add(count);
+                }
+                Err(_) => report.unknown = report.unknown.saturating_add(count),
+                Ok(()) => {}
+            }
+        }
+        Ok((histogram, report))
+    }
+
+    /// write the non-empty buckets as CSV rows of `value,count`, after a
+    /// `value,count` header
+    ///
+    /// Each value is that of its bucket, so `from_csv_reader()` with the same
+    /// config restores the buckets. Missed samples have no value, so are not
+    /// written.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    /// h.record(20, 2).unwrap();
+    ///
+    /// let mut csv = Vec::new();
+    /// h.to_csv_writer(&mut csv).unwrap();
+    ///
+    /// assert_eq!(csv, b"value,count\n10,3\n20,2\n");
+    pub fn to_csv_writer<W: Write>(&self, writer: W) -> Result<(), HistogramError> {
+        let mut writer = BufWriter::new(writer);
+        let write = |writer: &mut BufWriter<W>| -> io::Result<()> {
+            writeln!(writer, "value,count")?;
+            for bucket in self.iter_recorded() {
+                writeln!(writer, "{},{}", bucket.value(), bucket.count())?;
+            }
+            writer.flush()
+        };
+        write(&mut writer).map_err(|e| HistogramError::Io(e.kind()))
+    }
+}
+
+// parse a row of two unsigned integer fields
+fn parse_row(text: &str) -> Option<(u64, u64)> {
+    let mut fields = text.split(',');
+    let value = fields.next()?.trim().parse().ok()?;
+    let count = fields.next()?.trim().parse().ok()?;
+    match fields.next() {
+        Some(_) => None,
+        None => Some((value, count)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+
+    fn config() -> HistogramConfig {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).min_value(10);
+        c
+    }
+
+    #[test]
+    fn test_well_formed() {
+        let csv = "  value , count \r\n\
+                   \r\n\
+                   10,1\r\n\
+                   500 ,\t7\n\
+                   \n\
+                   999999, 2\n\
+                   500,3";
+        let (h, report) = Histogram::from_csv_reader(config(), csv.as_bytes()).unwrap();
+        assert_eq!(report.rows(), 4);
+        assert!(report.malformed().is_empty());
+        assert_eq!((report.too_small(), report.too_large(), report.unknown()), (0, 0, 0));
+        assert_eq!(h.entries(), 13);
+        assert_eq!(h.get(500), Some(10));
+
+        // without a header
+        let (h, report) = Histogram::from_csv_reader(config(), "10,1\n".as_bytes()).unwrap();
+        assert_eq!((h.entries(), report.rows()), (1, 1));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let csv = "value,count\n\
+                   10,1\n\
+                   ten,1\n\
+                   10\n\
+                   10,1,1\n\
+                   -5,2\n\
+                   20,18446744073709551616\n\
+                   20,\n\
+                   \u{fffd},1\n\
+                   20,4\n";
+        let (h, report) = Histogram::from_csv_reader(config(), csv.as_bytes()).unwrap();
+        assert_eq!(report.rows(), 2);
+        assert_eq!(report.malformed(), &[3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(h.entries(), 5);
+
+        // invalid utf-8 is malformed rather than fatal
+        let bytes = b"10,1\n\xff\xfe,2\n";
+        let (_, report) = Histogram::from_csv_reader(config(), &bytes[..]).unwrap();
+        assert_eq!(report.malformed(), &[2]);
+
+        let mut c = config();
+        c.precision(40);
+        assert_eq!(Histogram::from_csv_reader(c, "".as_bytes()).unwrap_err(),
+                   HistogramError::InvalidPrecision);
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let csv = "0,2\n5,3\n100,1\n2000000,4\n";
+        let (h, report) = Histogram::from_csv_reader(config(), csv.as_bytes()).unwrap();
+        assert_eq!(report.rows(), 4);
+        assert_eq!((report.too_small(), report.too_large()), (5, 4));
+        assert_eq!(h.entries(), 10);
+        assert_eq!(h.recorded(), 1);
+        assert_eq!((h.missed_small(), h.missed_large()), (5, 4));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut h = Histogram::configured(config()).unwrap();
+        for value in 0..5_000 {
+            let _ = h.record(value * 331, value % 5);
+        }
+
+        let mut csv = Vec::new();
+        h.to_csv_writer(&mut csv).unwrap();
+        let (r, report) = Histogram::from_csv_reader(config(), &csv[..]).unwrap();
+        assert!(report.malformed().is_empty());
+        assert_eq!(report.rows(), h.iter_recorded().count() as u64);
+        assert_eq!(r.data.data, h.data.data);
+        assert_eq!(r.recorded(), h.recorded());
+    }
+}
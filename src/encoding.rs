@@ -0,0 +1,1038 @@
+use std::io::{self, Read, Write};
+
+use super::{occupied_indices, DeserializeLimits, Histogram, HistogramConfig, HistogramCounters,
+            HistogramError, HistogramProperties, OverflowPolicy};
+
+// identifies an encoded Histogram
+const MAGIC: &[u8; 4] = b"HIST";
+
+// the layout version written by to_bytes
+const VERSION: u8 = 1;
+
+// the layout version written by serialize, with varints and zero runs
+const COMPACT_VERSION: u8 = 2;
+
+// the layout versions written for a config with a min_value above 1, which
+// follows max_value, so other histograms still encode as before
+const MIN_VALUE_VERSION: u8 = 3;
+const COMPACT_MIN_VALUE_VERSION: u8 = 4;
+
+// the layout versions written for a config with a scale other than 1, which
+// follows the min_value as the bits of the f64
+const SCALE_VERSION: u8 = 5;
+const COMPACT_SCALE_VERSION: u8 = 6;
+
+// magic, version, overflow policy, precision, radix, max_value, four
+// counters, and the bucket count
+const HEADER_LEN: usize = 4 + 1 + 1 + 4 + 4 + 8 + 4 * 8 + 4;
+
+// the magic and version which start every header
+const PREFIX_LEN: usize = 4 + 1;
+
+// the number of bucket counts streamed through the buffer at a time
+const CHUNK_COUNTS: usize = 512;
+
+// identifies encoded HistogramCounters
+const COUNTERS_MAGIC: &[u8; 4] = b"HCNT";
+
+// magic, version, and four counters
+const COUNTERS_LEN: usize = 4 + 1 + 4 * 8;
+
+impl Histogram {
+    /// encode the config, counters, and bucket counts
+    ///
+    /// The layout is little-endian: a `HIST` magic and version byte, the
+    /// overflow policy, precision, radix and max_value, the entry and missed
+    /// counters, then the number of buckets followed by each bucket count.
+    /// A config with a min_value above 1 is written as version 3, with the
+    /// min_value following max_value. A config with a scale other than 1 is
+    /// written as version 5, with the min_value followed by the bits of the
+    /// scale.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(1).unwrap();
+    ///
+    /// let bytes = h.to_bytes();
+    /// let r = Histogram::from_bytes(&bytes).unwrap();
+    ///
+    /// assert_eq!(r.get(1).unwrap(), 1);
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let buckets = self.properties.buckets_total as usize;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + 8 + buckets * 8);
+
+        self.write_header(&mut bytes);
+        for count in self.counts() {
+            bytes.extend_from_slice(&count.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// write the encoding of `to_bytes()` to the writer, returning the number
+    /// of bytes written
+    ///
+    /// The buckets are written through a small buffer, so no copy of the
+    /// whole encoding is made.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(1).unwrap();
+    ///
+    /// let mut bytes = Vec::new();
+    /// let written = h.serialize_into(&mut bytes).unwrap();
+    ///
+    /// assert_eq!(written, bytes.len());
+    /// assert_eq!(bytes, h.to_bytes());
+    pub fn serialize_into<W: Write>(&self, mut writer: W) -> io::Result<usize> {
+        let mut header = Vec::with_capacity(HEADER_LEN + 8);
+        self.write_header(&mut header);
+        writer.write_all(&header)?;
+
+        let mut buffer = [0_u8; CHUNK_COUNTS * 8];
+        let mut counts = self.counts();
+        loop {
+            let mut len = 0;
+            for (bytes, count) in buffer.chunks_mut(8).zip(&mut counts) {
+                bytes.copy_from_slice(&count.to_le_bytes());
+                len += 8;
+            }
+            if len == 0 {
+                break;
+            }
+            let bytes = buffer.get(..len).ok_or(io::ErrorKind::InvalidInput)?;
+            writer.write_all(bytes)?;
+        }
+        Ok(header.len() + self.properties.buckets_total as usize * 8)
+    }
+
+    /// read a Histogram encoded by `to_bytes()` or `serialize_into()` from
+    /// the reader, consuming only the bytes of the encoding
+    ///
+    /// A reader ending early gives `InvalidEncoding`, and other failures of
+    /// the reader are returned as `Io`. The config must fit the default
+    /// `DeserializeLimits`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(1, 3).unwrap();
+    ///
+    /// let bytes = h.to_bytes();
+    /// let r = Histogram::deserialize_from(&bytes[..]).unwrap();
+    ///
+    /// assert_eq!(r.get(1).unwrap(), 3);
+    pub fn deserialize_from<R: Read>(reader: R) -> Result<Histogram, HistogramError> {
+        Histogram::deserialize_from_with_limits(reader, &DeserializeLimits::new())
+    }
+
+    /// read a Histogram as `deserialize_from()`, rejecting a config over the
+    /// limits before any buckets are allocated
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{DeserializeLimits, Histogram};
+    /// let h = Histogram::new().unwrap();
+    ///
+    /// let mut limits = DeserializeLimits::new();
+    /// limits.max_memory(1 << 20);
+    ///
+    /// let bytes = h.to_bytes();
+    /// assert!(Histogram::deserialize_from_with_limits(&bytes[..], &limits).is_ok());
+    pub fn deserialize_from_with_limits<R: Read>(mut reader: R,
+                                                 limits: &DeserializeLimits)
+                                                 -> Result<Histogram, HistogramError> {
+        // the version read first gives the length of the rest of the header
+        let mut header = vec![0_u8; PREFIX_LEN];
+        reader.read_exact(&mut header).map_err(io_error)?;
+        header.resize(header_len(header.last().copied())?, 0);
+        let rest = header.get_mut(PREFIX_LEN..).ok_or(HistogramError::InvalidEncoding)?;
+        reader.read_exact(rest).map_err(io_error)?;
+        let (config, properties, counters) = read_header(&mut Reader { bytes: &header }, limits)?;
+        let buckets = properties.buckets_total as usize;
+
+        // the header is untrusted, so the buckets are only allocated as they
+        // arrive rather than all at once
+        let mut data = Vec::with_capacity(buckets.min(CHUNK_COUNTS * 64));
+        let mut buffer = [0_u8; CHUNK_COUNTS * 8];
+        while data.len() < buckets {
+            let len = (buckets - data.len()).min(CHUNK_COUNTS) * 8;
+            let bytes = buffer.get_mut(..len).ok_or(HistogramError::InvalidEncoding)?;
+            reader.read_exact(bytes).map_err(io_error)?;
+
+            let mut chunk = Reader { bytes };
+            while !chunk.bytes.is_empty() {
+                data.push(chunk.u64()?);
+            }
+        }
+
+        Ok(Histogram::assembled(config, properties, counters, data))
+    }
+
+    // append the header of the encoding, up to and including the bucket count
+    fn write_header(&self, bytes: &mut Vec<u8>) {
+        let scale = self.config.scale != 1.0;
+        let min_value = self.config.min_value > 1 || scale;
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(if scale {
+            SCALE_VERSION
+        } else if min_value {
+            MIN_VALUE_VERSION
+        } else {
+            VERSION
+        });
+        bytes.push(match self.config.overflow {
+            OverflowPolicy::Reject => 0,
+            OverflowPolicy::Clamp => 1,
+        });
+        bytes.extend_from_slice(&self.config.precision.to_le_bytes());
+        bytes.extend_from_slice(&self.config.radix.to_le_bytes());
+        bytes.extend_from_slice(&self.config.max_value.to_le_bytes());
+        if min_value {
+            bytes.extend_from_slice(&self.config.min_value.to_le_bytes());
+        }
+        if scale {
+            bytes.extend_from_slice(&self.config.scale.to_bits().to_le_bytes());
+        }
+
+        self.data.counters.write(bytes);
+
+        bytes.extend_from_slice(&(self.properties.buckets_total as u32).to_le_bytes());
+    }
+
+    /// decode a Histogram written by `to_bytes()`
+    ///
+    /// The header is validated against the layout its config derives, and
+    /// truncated or trailing input is rejected. The config must fit the
+    /// default `DeserializeLimits`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// assert_eq!(Histogram::from_bytes(b"HIST").unwrap_err(),
+    ///            HistogramError::InvalidEncoding);
+    pub fn from_bytes(bytes: &[u8]) -> Result<Histogram, HistogramError> {
+        Histogram::from_bytes_with_limits(bytes, &DeserializeLimits::new())
+    }
+
+    /// decode a Histogram as `from_bytes()`, rejecting a config over the
+    /// limits before any buckets are allocated
+    pub fn from_bytes_with_limits(bytes: &[u8],
+                                  limits: &DeserializeLimits)
+                                  -> Result<Histogram, HistogramError> {
+        let mut reader = Reader { bytes };
+
+        let (config, properties, counters) = read_header(&mut reader, limits)?;
+        let buckets = properties.buckets_total as usize;
+        if reader.bytes.len() != buckets * 8 {
+            return Err(HistogramError::InvalidEncoding);
+        }
+
+        let mut data = Vec::with_capacity(buckets);
+        for _ in 0..buckets {
+            data.push(reader.u64()?);
+        }
+
+        Ok(Histogram::assembled(config, properties, counters, data))
+    }
+
+    /// encode the config, counters, and bucket counts compactly
+    ///
+    /// The layout starts with a `HIST` magic, version byte 2, and the
+    /// overflow policy. The precision, radix, max_value, counters, number of
+    /// buckets, and number of non-empty buckets follow as LEB128 varints,
+    /// then each non-empty bucket as the varint number of empty buckets
+    /// skipped before it and its varint count. A config with a min_value
+    /// above 1 is written as version 4, with the min_value as a varint
+    /// following max_value. A config with a scale other than 1 is written as
+    /// version 6, with the min_value followed by the little-endian bits of
+    /// the scale. A histogram with few occupied
+    /// buckets encodes in a few hundred bytes, while `to_bytes()` always
+    /// takes 8 bytes per bucket.
+    ///
+    /// This shadows `serde::Serialize::serialize()` in method calls, which
+    /// remains reachable through the trait.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..100 {
+    ///     h.increment(value * 1_000).unwrap();
+    /// }
+    ///
+    /// let bytes = h.serialize();
+    /// let r = Histogram::deserialize(&bytes).unwrap();
+    ///
+    /// assert!(bytes.len() < 400);
+    /// assert_eq!(r.percentile(50.0).unwrap(), h.percentile(50.0).unwrap());
+    pub fn serialize(&self) -> Vec<u8> {
+        let scale = self.config.scale != 1.0;
+        let min_value = self.config.min_value > 1 || scale;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(if scale {
+            COMPACT_SCALE_VERSION
+        } else if min_value {
+            COMPACT_MIN_VALUE_VERSION
+        } else {
+            COMPACT_VERSION
+        });
+        bytes.push(match self.config.overflow {
+            OverflowPolicy::Reject => 0,
+            OverflowPolicy::Clamp => 1,
+        });
+
+        write_varint(&mut bytes, self.config.precision as u64);
+        write_varint(&mut bytes, self.config.radix as u64);
+        write_varint(&mut bytes, self.config.max_value);
+        if min_value {
+            write_varint(&mut bytes, self.config.min_value);
+        }
+        if scale {
+            bytes.extend_from_slice(&self.config.scale.to_bits().to_le_bytes());
+        }
+
+        let counters = &self.data.counters;
+        let occupied = occupied_indices(self.data.occupied).filter(|&i| self.data.count(i) > 0)
+                                                           .count();
+        for value in &[counters.entries_total,
+                       counters.missed_unknown,
+                       counters.missed_small,
+                       counters.missed_large,
+                       self.properties.buckets_total,
+                       occupied as u64] {
+            write_varint(&mut bytes, *value);
+        }
+
+        let mut next = 0;
+        for index in occupied_indices(self.data.occupied) {
+            let count = self.data.count(index);
+            if count > 0 {
+                write_varint(&mut bytes, (index - next) as u64);
+                write_varint(&mut bytes, count);
+                next = index + 1;
+            }
+        }
+        bytes
+    }
+
+    /// decode a Histogram written by `serialize()`
+    ///
+    /// The header is validated against the layout its config derives, and
+    /// truncated, corrupt, or trailing input is rejected. The config must
+    /// fit the default `DeserializeLimits`, as a few bytes describe any
+    /// number of empty buckets.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let bytes = Histogram::new().unwrap().serialize();
+    ///
+    /// assert!(Histogram::deserialize(&bytes).is_ok());
+    /// assert!(Histogram::deserialize(&bytes[..8]).is_err());
+    pub fn deserialize(bytes: &[u8]) -> Result<Histogram, HistogramError> {
+        Histogram::deserialize_with_limits(bytes, &DeserializeLimits::new())
+    }
+
+    /// decode a Histogram as `deserialize()`, rejecting a config over the
+    /// limits before any buckets are allocated
+    pub fn deserialize_with_limits(bytes: &[u8],
+                                   limits: &DeserializeLimits)
+                                   -> Result<Histogram, HistogramError> {
+        read_compact(&mut Reader { bytes }, limits)
+    }
+}
+
+// decode the layout written by serialize
+fn read_compact(reader: &mut Reader,
+                limits: &DeserializeLimits)
+                -> Result<Histogram, HistogramError> {
+    if reader.take(4)? != MAGIC {
+        return Err(HistogramError::InvalidEncoding);
+    }
+    let version = reader.u8()?;
+    if !matches!(version, COMPACT_VERSION | COMPACT_MIN_VALUE_VERSION | COMPACT_SCALE_VERSION) {
+        return Err(HistogramError::InvalidEncoding);
+    }
+    let overflow = reader.u8()?;
+    let precision = reader.varint_u32()?;
+    let radix = reader.varint_u32()?;
+    let max_value = reader.varint()?;
+    let min_value = match version {
+        COMPACT_MIN_VALUE_VERSION | COMPACT_SCALE_VERSION => reader.varint()?,
+        _ => 1,
+    };
+    let scale = match version {
+        COMPACT_SCALE_VERSION => f64::from_bits(reader.u64()?),
+        _ => 1.0,
+    };
+    let config = decoded_config(overflow, precision, radix, max_value, min_value, scale)?;
+
+    let counters = HistogramCounters {
+        entries_total: reader.varint()?,
+        missed_unknown: reader.varint()?,
+        missed_small: reader.varint()?,
+        missed_large: reader.varint()?,
+    };
+
+    let properties =
+        HistogramProperties::new(&config).map_err(|_| HistogramError::InvalidEncoding)?;
+    limits.check(&properties)?;
+    if reader.varint()? != properties.buckets_total {
+        return Err(HistogramError::InvalidEncoding);
+    }
+
+    let mut data = vec![0; properties.buckets_total as usize];
+    let mut next = 0_u64;
+    let occupied = reader.varint()?;
+    if occupied > data.len() as u64 {
+        return Err(HistogramError::InvalidEncoding);
+    }
+    for _ in 0..occupied {
+        let skipped = reader.varint()?;
+        let count = reader.varint()?;
+        let index = match next.checked_add(skipped) {
+            Some(index) if index < data.len() as u64 && count > 0 => index,
+            _ => return Err(HistogramError::InvalidEncoding),
+        };
+        if let Some(bucket) = data.get_mut(index as usize) {
+            *bucket = count;
+        }
+        next = index as u64 + 1;
+    }
+    if !reader.bytes.is_empty() {
+        return Err(HistogramError::InvalidEncoding);
+    }
+
+    Ok(Histogram::assembled(config, properties, counters, data))
+}
+
+// append a value as an unsigned LEB128 varint
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+// map a failed read, where ending early means the encoding was truncated
+fn io_error(error: io::Error) -> HistogramError {
+    match error.kind() {
+        io::ErrorKind::UnexpectedEof => HistogramError::InvalidEncoding,
+        kind => HistogramError::Io(kind),
+    }
+}
+
+// read and validate the header, returning the config, the layout which gives
+// the number of buckets to follow, and the counters
+fn read_header(reader: &mut Reader,
+               limits: &DeserializeLimits)
+               -> Result<(HistogramConfig, HistogramProperties, HistogramCounters),
+                         HistogramError> {
+    if reader.take(4)? != MAGIC {
+        return Err(HistogramError::InvalidEncoding);
+    }
+    let version = reader.u8()?;
+    header_len(Some(version))?;
+
+    let overflow = reader.u8()?;
+    let precision = reader.u32()?;
+    let radix = reader.u32()?;
+    let max_value = reader.u64()?;
+    let min_value = match version {
+        MIN_VALUE_VERSION | SCALE_VERSION => reader.u64()?,
+        _ => 1,
+    };
+    let scale = match version {
+        SCALE_VERSION => f64::from_bits(reader.u64()?),
+        _ => 1.0,
+    };
+    let config = decoded_config(overflow, precision, radix, max_value, min_value, scale)?;
+
+    let counters = HistogramCounters::read(reader)?;
+
+    let properties =
+        HistogramProperties::new(&config).map_err(|_| HistogramError::InvalidEncoding)?;
+    limits.check(&properties)?;
+    let buckets = reader.u32()?;
+    if buckets as u64 != properties.buckets_total {
+        return Err(HistogramError::InvalidEncoding);
+    }
+    Ok((config, properties, counters))
+}
+
+// the length of the header up to and including the bucket count for the
+// version, failing for an unknown version
+fn header_len(version: Option<u8>) -> Result<usize, HistogramError> {
+    match version {
+        Some(VERSION) => Ok(HEADER_LEN),
+        Some(MIN_VALUE_VERSION) => Ok(HEADER_LEN + 8),
+        Some(SCALE_VERSION) => Ok(HEADER_LEN + 16),
+        _ => Err(HistogramError::InvalidEncoding),
+    }
+}
+
+// build the config from its decoded fields
+fn decoded_config(overflow: u8,
+                  precision: u32,
+                  radix: u32,
+                  max_value: u64,
+                  min_value: u64,
+                  scale: f64)
+                  -> Result<HistogramConfig, HistogramError> {
+    let mut config = HistogramConfig::new();
+    config.overflow(match overflow {
+        0 => OverflowPolicy::Reject,
+        1 => OverflowPolicy::Clamp,
+        _ => return Err(HistogramError::InvalidEncoding),
+    });
+    config.precision(precision).max_value(max_value).min_value(min_value).scale(scale);
+    config.radix = radix;
+    if config.radix < 2 || config.min_value != min_value {
+        return Err(HistogramError::InvalidEncoding);
+    }
+    Ok(config)
+}
+
+impl HistogramCounters {
+    /// encode the counters alone, as a small message for heartbeats
+    ///
+    /// The layout is little-endian: a `HCNT` magic and version byte, then
+    /// the entry and missed counters in the order used by
+    /// `Histogram::to_bytes()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramCounters};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(1, 3).unwrap();
+    ///
+    /// let bytes = h.counters().to_bytes();
+    /// let counters = HistogramCounters::from_bytes(&bytes).unwrap();
+    ///
+    /// assert_eq!(bytes.len(), 37);
+    /// assert_eq!(counters.entries(), 3);
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(COUNTERS_LEN);
+        bytes.extend_from_slice(COUNTERS_MAGIC);
+        bytes.push(VERSION);
+        self.write(&mut bytes);
+        bytes
+    }
+
+    /// decode counters written by `to_bytes()`
+    pub fn from_bytes(bytes: &[u8]) -> Result<HistogramCounters, HistogramError> {
+        let mut reader = Reader { bytes };
+
+        if reader.take(4)? != COUNTERS_MAGIC || reader.u8()? != VERSION {
+            return Err(HistogramError::InvalidEncoding);
+        }
+        let counters = HistogramCounters::read(&mut reader)?;
+        if !reader.bytes.is_empty() {
+            return Err(HistogramError::InvalidEncoding);
+        }
+        Ok(counters)
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>) {
+        for counter in &[self.entries_total,
+                         self.missed_unknown,
+                         self.missed_small,
+                         self.missed_large] {
+            bytes.extend_from_slice(&counter.to_le_bytes());
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<HistogramCounters, HistogramError> {
+        Ok(HistogramCounters {
+            entries_total: reader.u64()?,
+            missed_unknown: reader.u64()?,
+            missed_small: reader.u64()?,
+            missed_large: reader.u64()?,
+        })
+    }
+}
+
+// reads little-endian values from the front of a slice
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], HistogramError> {
+        if self.bytes.len() < len {
+            return Err(HistogramError::InvalidEncoding);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, HistogramError> {
+        self.take(1)?.first().copied().ok_or(HistogramError::InvalidEncoding)
+    }
+
+    fn u32(&mut self) -> Result<u32, HistogramError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, HistogramError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    // an unsigned LEB128 varint of at most 10 bytes, rejecting overlong
+    // encodings and values past u64::MAX
+    fn varint(&mut self) -> Result<u64, HistogramError> {
+        let mut value = 0_u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            if (shift == 63 && byte > 1) || (shift > 0 && byte == 0) {
+                return Err(HistogramError::InvalidEncoding);
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(HistogramError::InvalidEncoding)
+    }
+
+    fn varint_u32(&mut self) -> Result<u32, HistogramError> {
+        let value = self.varint()?;
+        if value > u32::MAX as u64 {
+            return Err(HistogramError::InvalidEncoding);
+        }
+        Ok(value as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::super::{DeserializeLimits, Histogram, HistogramConfig, HistogramCounters,
+                       HistogramError, OverflowPolicy};
+
+    // returns a few bytes per read, interrupting every other read
+    struct Chunked<'a> {
+        bytes: &'a [u8],
+        reads: usize,
+    }
+
+    impl<'a> Read for Chunked<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            if self.reads.is_multiple_of(2) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+            }
+            let len = buf.len().min(self.bytes.len()).min(3);
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes = &self.bytes[len..];
+            Ok(len)
+        }
+    }
+
+    // fails every operation
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset"))
+        }
+    }
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn populated() -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 0..10_000 {
+            h.record(value * 97 % 2_000_000, value % 5).unwrap_or(());
+        }
+        h
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+
+        for value in 0..10_000 {
+            h.record(value * 97 % 2_000_000, value % 5).unwrap_or(());
+        }
+
+        let r = Histogram::from_bytes(&h.to_bytes()).unwrap();
+        r.check_invariants().unwrap();
+        assert_eq!(r.entries(), h.entries());
+        assert_eq!(r.buckets_total(), h.buckets_total());
+        assert_eq!(r.config.overflow, OverflowPolicy::Clamp);
+        assert_eq!(r.data.data, h.data.data);
+        for p in 0..101 {
+            assert_eq!(r.percentile(p as f64), h.percentile(p as f64));
+        }
+
+        let e = Histogram::new().unwrap();
+        let r = Histogram::from_bytes(&e.to_bytes()).unwrap();
+        assert_eq!(r.entries(), 0);
+        assert_eq!(r.buckets_total(), e.buckets_total());
+    }
+
+    #[test]
+    fn test_min_value() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2).min_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 0..10_000 {
+            h.record(value * 97 % 1_000_000, value % 5).unwrap_or(());
+        }
+
+        // the min_value is written after max_value under its own versions
+        let bytes = h.to_bytes();
+        assert_eq!(bytes[4], 3);
+        assert_eq!(&bytes[22..30], &1_000_u64.to_le_bytes());
+        let compact = h.serialize();
+        assert_eq!(compact[4], 4);
+
+        for r in &[Histogram::from_bytes(&bytes).unwrap(),
+                   Histogram::deserialize_from(Chunked { bytes: &bytes, reads: 0 }).unwrap(),
+                   Histogram::deserialize(&compact).unwrap()] {
+            r.check_invariants().unwrap();
+            assert!(r.config.matches(&h.config));
+            assert_eq!(r.counters(), h.counters());
+            assert_eq!(r.data.data, h.data.data);
+        }
+
+        for len in 0..bytes.len() {
+            assert!(Histogram::from_bytes(&bytes[..len]).is_err());
+            assert!(Histogram::deserialize_from(&bytes[..len]).is_err());
+        }
+        for len in 0..compact.len() {
+            assert!(Histogram::deserialize(&compact[..len]).is_err());
+        }
+
+        // a min_value above max_value
+        let mut forged = bytes.clone();
+        forged[22..30].copy_from_slice(&2_000_000_u64.to_le_bytes());
+        assert!(Histogram::from_bytes(&forged).is_err());
+
+        // other histograms keep the original versions
+        assert_eq!(populated().to_bytes()[4], 1);
+        assert_eq!(populated().serialize()[4], 2);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(1);
+        let mut h = Histogram::configured(c).unwrap();
+        h.increment(10).unwrap();
+        let bytes = h.to_bytes();
+
+        // every truncation and any trailing data
+        for len in 0..bytes.len() {
+            assert_eq!(Histogram::from_bytes(&bytes[..len]).unwrap_err(),
+                       HistogramError::InvalidEncoding);
+        }
+        let mut long = bytes.clone();
+        long.push(0);
+        assert!(Histogram::from_bytes(&long).is_err());
+
+        // a bucket count disagreeing with the config
+        let mut forged = bytes.clone();
+        forged[54..58].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Histogram::from_bytes(&forged).is_err());
+
+        // a config whose layout overflows
+        let mut forged = bytes.clone();
+        forged[6..10].copy_from_slice(&40_u32.to_le_bytes());
+        assert!(Histogram::from_bytes(&forged).is_err());
+
+        // bad magic, version, and overflow policy
+        for &offset in &[0, 4, 5] {
+            let mut forged = bytes.clone();
+            forged[offset] = 0xff;
+            assert!(Histogram::from_bytes(&forged).is_err());
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        for h in &[Histogram::new().unwrap(), populated()] {
+            let bytes = h.serialize();
+            let r = Histogram::deserialize(&bytes).unwrap();
+            r.check_invariants().unwrap();
+            assert_eq!(r.data.data, h.data.data);
+            assert_eq!(r.data.occupied, h.data.occupied);
+            assert_eq!(r.counters(), h.counters());
+            assert_eq!(r.config.overflow, h.config.overflow);
+            assert!(r.config.matches(&h.config));
+            for p in 0..101 {
+                assert_eq!(r.percentile(p as f64), h.percentile(p as f64));
+            }
+        }
+
+        // a few samples take a few bytes each, not 8 bytes per bucket
+        let mut h = Histogram::new().unwrap();
+        assert_eq!(h.serialize().len(), 22);
+        for value in 1..100 {
+            h.record(value * 1_000_003, value).unwrap();
+        }
+        h.record(u64::MAX, u64::MAX).unwrap_err();
+        let bytes = h.serialize();
+        assert!(bytes.len() < 450, "{}", bytes.len());
+        assert!(bytes.len() * 100 < h.to_bytes().len());
+        assert_eq!(Histogram::deserialize(&bytes).unwrap().to_bytes(), h.to_bytes());
+
+        // the layouts are not interchangeable
+        assert!(Histogram::from_bytes(&bytes).is_err());
+        assert!(Histogram::deserialize(&h.to_bytes()).is_err());
+
+        let mut limits = DeserializeLimits::new();
+        limits.max_buckets(h.buckets_total() as u32 - 1);
+        assert_eq!(Histogram::deserialize_with_limits(&bytes, &limits).unwrap_err(),
+                   HistogramError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_compact_invalid() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(1);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(10, 300).unwrap();
+        h.increment(500).unwrap();
+        let bytes = h.serialize();
+        assert_eq!(bytes,
+                   vec![b'H', b'I', b'S', b'T', 2, 0, 1, 10, 0xe8, 0x07, 0xad, 0x02, 0, 0, 0,
+                        75, 2, 9, 0xac, 0x02, 54, 1]);
+
+        // every truncation and any trailing data
+        for len in 0..bytes.len() {
+            assert_eq!(Histogram::deserialize(&bytes[..len]).unwrap_err(),
+                       HistogramError::InvalidEncoding);
+        }
+        let mut long = bytes.clone();
+        long.push(0);
+        assert!(Histogram::deserialize(&long).is_err());
+
+        let corrupt = |offset: usize, replacement: &[u8]| {
+            let mut forged = bytes.clone();
+            forged.splice(offset..(offset + 1), replacement.iter().cloned());
+            Histogram::deserialize(&forged)
+        };
+        assert!(corrupt(6, &[1]).is_ok());
+        for &(offset, replacement) in &[(0, &[0xff][..]),
+                                        (4, &[1]),
+                                        (5, &[2]),
+                                        (6, &[40]),
+                                        (7, &[1]),
+                                        (15, &[30]),
+                                        (15, &[0xff, 0xff, 0xff, 0xff, 0x7f]),
+                                        (16, &[1]),
+                                        (16, &[3]),
+                                        (16, &[0xff, 0xff, 0xff, 0xff, 0x7f]),
+                                        (17, &[40]),
+                                        (18, &[0]),
+                                        (20, &[0x80, 0x00]),
+                                        (21, &[0xff; 11])] {
+            assert!(corrupt(offset, replacement).is_err(), "{} {:?}", offset, replacement);
+        }
+
+        // the last varint byte of a u64 may only hold its top bit
+        let mut overlong = bytes[..16].to_vec();
+        overlong.extend_from_slice(&[1, 9, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        let mut maximal = overlong.clone();
+        maximal.push(0x01);
+        overlong.push(0x02);
+        assert_eq!(Histogram::deserialize(&maximal).unwrap().get(10), Some(u64::MAX));
+        assert!(Histogram::deserialize(&overlong).is_err());
+    }
+
+    #[test]
+    fn test_heartbeats() {
+        let mut source = Histogram::new().unwrap();
+        let mut central = Histogram::new().unwrap();
+        let mut sent = source.counters();
+
+        // heartbeats carry the counter deltas each second
+        for second in 1..6 {
+            for value in 0..(second * 100) {
+                let _ = source.record(value * 200_000_003, second);
+            }
+            let delta = source.counters().since(&sent);
+            sent = source.counters();
+
+            let bytes = delta.to_bytes();
+            assert_eq!(bytes.len(), 37);
+            central.apply_counter_delta(&HistogramCounters::from_bytes(&bytes).unwrap());
+            assert_eq!(central.counters(), source.counters());
+        }
+        assert!(central.counters().missed_large() > 0);
+        assert!(central.counters().missed_small() > 0);
+
+        // a full sync taken after the last heartbeat covers the deltas
+        source.increment(5).unwrap();
+        let full = Histogram::from_bytes(&source.to_bytes()).unwrap();
+        full.check_counters(&central.counters()).unwrap();
+        central.reset_to(&full).unwrap();
+        central.check_invariants().unwrap();
+        assert_eq!(central.percentile(50.0), source.percentile(50.0));
+
+        // but one older than the heartbeats is rejected
+        let mut stale = source.clone_empty();
+        stale.record(5, 10).unwrap();
+        assert_eq!(stale.check_counters(&central.counters()),
+                   Err(HistogramError::CountersMismatch));
+
+        let bytes = central.counters().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(HistogramCounters::from_bytes(&bytes[..len]).is_err());
+        }
+        let mut long = bytes.clone();
+        long.push(0);
+        assert!(HistogramCounters::from_bytes(&long).is_err());
+        assert!(HistogramCounters::from_bytes(&source.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_streaming() {
+        let h = populated();
+        let bytes = h.to_bytes();
+
+        // through gzip
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        assert_eq!(h.serialize_into(&mut encoder).unwrap(), bytes.len());
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < bytes.len());
+        let r = Histogram::deserialize_from(GzDecoder::new(&compressed[..])).unwrap();
+        r.check_invariants().unwrap();
+        assert_eq!(r.data.data, h.data.data);
+        assert_eq!(r.counters(), h.counters());
+
+        // through short reads, leaving what follows the encoding unread
+        let mut stream = bytes.clone();
+        stream.extend_from_slice(b"next");
+        let mut reader = Chunked {
+            bytes: &stream,
+            reads: 0,
+        };
+        let r = Histogram::deserialize_from(&mut reader).unwrap();
+        assert_eq!(r.to_bytes(), bytes);
+        assert_eq!(reader.bytes, b"next");
+
+        for len in &[0, 10, 57, 58, bytes.len() - 1] {
+            assert_eq!(Histogram::deserialize_from(&bytes[..*len]).unwrap_err(),
+                       HistogramError::InvalidEncoding);
+        }
+        assert_eq!(Histogram::deserialize_from(Broken).unwrap_err(),
+                   HistogramError::Io(io::ErrorKind::ConnectionReset));
+        assert_eq!(h.serialize_into(Broken).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    // the header of an encoding claiming the layout of precision and
+    // max_value with the given number of buckets
+    fn forged(precision: u32, max_value: u64, buckets: u32) -> Vec<u8> {
+        let mut bytes = Histogram::new().unwrap().to_bytes();
+        bytes.truncate(super::HEADER_LEN);
+        bytes[6..10].copy_from_slice(&precision.to_le_bytes());
+        bytes[14..22].copy_from_slice(&max_value.to_le_bytes());
+        bytes[54..58].copy_from_slice(&buckets.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut limits = DeserializeLimits::new();
+        limits.max_buckets(1_000_000).max_memory(64 << 20);
+
+        // a bucket count the config does not derive is rejected outright
+        let bytes = forged(3, 60_000_000_000, u32::MAX);
+        assert_eq!(Histogram::from_bytes(&bytes).unwrap_err(), HistogramError::InvalidEncoding);
+        assert_eq!(Histogram::from_bytes_with_limits(&bytes, &limits).unwrap_err(),
+                   HistogramError::InvalidEncoding);
+        assert_eq!(Histogram::deserialize_from(io::repeat(0)).unwrap_err(),
+                   HistogramError::InvalidEncoding);
+
+        // a config deriving about 45 million buckets is rejected before
+        // reading or allocating them, even from an endless reader
+        let bytes = forged(6, u64::MAX, 45_048_575);
+        let endless = (&bytes[..]).chain(io::repeat(0));
+        assert_eq!(Histogram::deserialize_from_with_limits(endless, &limits).unwrap_err(),
+                   HistogramError::LimitExceeded);
+        assert_eq!(Histogram::from_bytes_with_limits(&bytes, &limits).unwrap_err(),
+                   HistogramError::LimitExceeded);
+
+        // each limit applies alone, and a histogram within both decodes
+        let h = populated();
+        let bytes = h.to_bytes();
+        let buckets = h.buckets_total() as u32;
+        let fits = |limits: &DeserializeLimits| Histogram::from_bytes_with_limits(&bytes, limits);
+        assert!(fits(&limits).is_ok());
+        assert!(fits(DeserializeLimits::new().max_buckets(buckets)).is_ok());
+        assert_eq!(fits(DeserializeLimits::new().max_buckets(buckets - 1)).unwrap_err(),
+                   HistogramError::LimitExceeded);
+        assert!(fits(DeserializeLimits::new().max_memory(buckets as u64 * 8)).is_ok());
+        assert_eq!(fits(DeserializeLimits::new().max_memory(buckets as u64 * 8 - 1)).unwrap_err(),
+                   HistogramError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_default_limits() {
+        // a compact header of a few dozen bytes for about 45 million empty
+        // buckets is rejected by the calls without limits
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(super::MAGIC);
+        bytes.extend_from_slice(&[super::COMPACT_VERSION, 0]);
+        for value in &[6, 10, u64::MAX, 0, 0, 0, 0, 45_048_575, 0] {
+            super::write_varint(&mut bytes, *value);
+        }
+        assert!(bytes.len() < 32);
+        assert_eq!(Histogram::deserialize(&bytes).unwrap_err(), HistogramError::LimitExceeded);
+        let forged = forged(6, u64::MAX, 45_048_575);
+        assert_eq!(Histogram::from_bytes(&forged).unwrap_err(), HistogramError::LimitExceeded);
+        assert_eq!(Histogram::deserialize_from(&forged[..]).unwrap_err(),
+                   HistogramError::LimitExceeded);
+
+        // the default leaves room for precision 5, and a max_memory of 0
+        // lifts the limit
+        let mut c = HistogramConfig::new();
+        c.precision(5);
+        let h = Histogram::configured(c).unwrap();
+        assert!(Histogram::deserialize(&h.serialize()).is_ok());
+        let mut unlimited = DeserializeLimits::new();
+        unlimited.max_memory(0);
+        c.precision(6);
+        let h = Histogram::configured(c).unwrap();
+        assert_eq!(Histogram::deserialize(&h.serialize()).unwrap_err(),
+                   HistogramError::LimitExceeded);
+        assert!(Histogram::deserialize_with_limits(&h.serialize(), &unlimited).is_ok());
+    }
+}
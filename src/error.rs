@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// errors returned by Histogram operations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistogramError {
+    /// no samples have been recorded
+    Empty,
+    /// every recorded sample fell outside the histogram range, the counters
+    /// show how many were too small, too large, or failed to index
+    OnlyMissedSamples {
+        small: u64,
+        large: u64,
+        unknown: u64,
+    },
+    /// the requested percentile is not within 0.0 to 100.0
+    InvalidPercentile,
+    /// the requested percentile falls within the samples which were too small
+    Underflow,
+    /// the requested percentile falls within the samples which were too large
+    Overflow,
+    /// the requested count exceeds the samples stored
+    OutOfRange,
+    /// the histograms do not share the same bucket layout
+    IncompatibleConfig,
+    /// the bytes are not a valid encoded histogram
+    InvalidEncoding,
+    /// reading or writing the encoding failed
+    Io(io::ErrorKind),
+    /// the bucket id is not within the histogram
+    InvalidBucket,
+    /// the value is below the min_value of the config, 1 by default
+    TooSmall,
+    /// the value is above max_value and the overflow policy rejects it
+    TooLarge,
+    /// the bucket could not hold every count without overflowing
+    Saturated,
+    /// the histogram counts fewer samples than the counters it was checked
+    /// against
+    CountersMismatch,
+    /// the counters promise more samples than the buckets and missed
+    /// counters hold, so the rank needed cannot be found
+    InconsistentState {
+        needed: u64,
+        available: u64,
+    },
+    /// the encoded histogram is larger than the deserialize limits allow
+    LimitExceeded,
+    /// the config is invalid, as with a min_value above max_value or a scale
+    /// which is not positive
+    InvalidConfig,
+    /// the buckets of the config would use more than its max_memory
+    MemoryLimitExceeded,
+    /// the config derives more buckets than any histogram may have, as with
+    /// a precision too high for the max_value
+    TooManyBuckets {
+        buckets: u64,
+        limit: u64,
+    },
+    /// the precision is 0, or so high that the radix raised to it does not
+    /// fit in a u32, as with a precision above 9 for radix 10
+    InvalidPrecision,
+    /// the radix is below 2
+    InvalidRadix,
+    /// the max_value is 0
+    InvalidMaxValue,
+    /// the weight is NaN, infinite, or negative
+    InvalidWeight,
+    /// the f64 value is NaN, infinite, or negative
+    InvalidValue,
+    /// the band boundaries are not strictly increasing
+    InvalidBoundaries,
+    /// the number of bucket counts does not match the buckets of the config
+    BucketCountMismatch {
+        expected: usize,
+        found: usize,
+    },
+    /// the statistic could not be computed
+    Unknown,
+}
+
+impl fmt::Display for HistogramError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HistogramError::Empty => write!(f, "no data"),
+            HistogramError::OnlyMissedSamples { small, large, unknown } => {
+                write!(f,
+                       "only missed samples (small: {} large: {} unknown: {})",
+                       small,
+                       large,
+                       unknown)
+            }
+            HistogramError::InvalidPercentile => write!(f, "percentile out of range"),
+            HistogramError::Underflow => write!(f, "underflow"),
+            HistogramError::Overflow => write!(f, "overflow"),
+            HistogramError::OutOfRange => write!(f, "count out of range"),
+            HistogramError::IncompatibleConfig => write!(f, "incompatible config"),
+            HistogramError::InvalidEncoding => write!(f, "invalid encoding"),
+            HistogramError::Io(kind) => write!(f, "io error: {}", kind),
+            HistogramError::InvalidBucket => write!(f, "bucket id out of range"),
+            HistogramError::TooSmall => write!(f, "sample value too small"),
+            HistogramError::TooLarge => write!(f, "sample value too large"),
+            HistogramError::Saturated => write!(f, "bucket saturated"),
+            HistogramError::CountersMismatch => write!(f, "counters do not match the histogram"),
+            HistogramError::InconsistentState { needed, available } => {
+                write!(f,
+                       "inconsistent counters: rank needs {} samples but {} are held",
+                       needed,
+                       available)
+            }
+            HistogramError::LimitExceeded => write!(f, "encoding exceeds deserialize limits"),
+            HistogramError::InvalidConfig => write!(f, "invalid config"),
+            HistogramError::MemoryLimitExceeded => write!(f, "config exceeds max_memory"),
+            HistogramError::TooManyBuckets { buckets, limit } => {
+                write!(f, "config needs {} buckets, over the limit of {}", buckets, limit)
+            }
+            HistogramError::InvalidPrecision => write!(f, "precision out of range for the radix"),
+            HistogramError::InvalidRadix => write!(f, "radix below 2"),
+            HistogramError::InvalidMaxValue => write!(f, "max_value below 1"),
+            HistogramError::InvalidWeight => write!(f, "invalid weight"),
+            HistogramError::InvalidValue => write!(f, "invalid value"),
+            HistogramError::InvalidBoundaries => write!(f, "invalid band boundaries"),
+            HistogramError::BucketCountMismatch { expected, found } => {
+                write!(f, "expected {} bucket counts but found {}", expected, found)
+            }
+            HistogramError::Unknown => write!(f, "unknown failure"),
+        }
+    }
+}
+
+impl Error for HistogramError {}
@@ -0,0 +1,144 @@
+use super::Histogram;
+
+// levels each bar is quantized to, fitting in four bits
+const LEVELS: u128 = 15;
+
+// 64 bit FNV-1a, spelled out so the fingerprint does not depend on the
+// standard library hasher, which may change between releases
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+impl Histogram {
+    /// return a coarse hash of the shape of the distribution, for tagging
+    /// samples by latency profile with a bounded number of distinct tags
+    ///
+    /// The buckets are split into `buckets` bars of equal numbers of
+    /// buckets, each bar's share of the stored samples is rounded to one of
+    /// 16 levels, and the levels are hashed. Missed samples are left out.
+    /// Scaling every count by the same factor keeps the shares, so gives the
+    /// same fingerprint, while moving samples between bars changes it once a
+    /// share crosses a level. `buckets` is taken as at least 1 and at most
+    /// the number of buckets. An empty histogram has the fingerprint of all
+    /// bars at level 0.
+    ///
+    /// The fingerprint is stable across runs, platforms and releases for
+    /// histograms of the same precision, max_value, min_value and radix, and
+    /// the same `buckets`. Fingerprints of differing layouts are unrelated.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// for value in 100..200 {
+    ///     a.record(value, 1).unwrap();
+    ///     b.record(value, 5).unwrap();
+    /// }
+    ///
+    /// assert_eq!(a.shape_fingerprint(16), b.shape_fingerprint(16));
+    ///
+    /// b.record(1_000_000, 500).unwrap();
+    ///
+    /// assert!(a.shape_fingerprint(16) != b.shape_fingerprint(16));
+    pub fn shape_fingerprint(&self, buckets: usize) -> u64 {
+        let total_buckets = (self.properties.buckets_total as usize).max(1);
+        let bars = buckets.clamp(1, total_buckets);
+
+        let mut counts = vec![0_u128; bars];
+        for (index, count) in self.data.data.iter().enumerate() {
+            if let Some(bar) = counts.get_mut(index * bars / total_buckets) {
+                *bar += *count as u128;
+            }
+        }
+        let total: u128 = counts.iter().sum();
+
+        let mut hash = FNV_OFFSET;
+        let mut add = |byte: u8| {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        };
+        for byte in (bars as u64).to_le_bytes().iter() {
+            add(*byte);
+        }
+        for count in counts {
+            // the share rounded half up, exactly, so scaled counts agree
+            let level = match total {
+                0 => 0,
+                total => (count * 2 * LEVELS + total) / (2 * total),
+            };
+            add(level as u8);
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig};
+
+    fn filled(samples: &[(u64, u64)], scale: u64) -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        for &(value, count) in samples {
+            h.record(value, count * scale).unwrap();
+        }
+        h
+    }
+
+    #[test]
+    fn test_scaled() {
+        let shape = [(10, 3), (200, 40), (250, 57), (3_000, 11), (90_000, 1)];
+        let base = filled(&shape, 1);
+        for &scale in &[2, 3, 7, 1_000, 1 << 40] {
+            let scaled = filled(&shape, scale);
+            for &bars in &[1, 4, 16, 64] {
+                assert_eq!(scaled.shape_fingerprint(bars), base.shape_fingerprint(bars));
+            }
+        }
+
+        // missed samples are left out
+        let mut missed = filled(&shape, 2);
+        missed.record(0, 500).unwrap_err();
+        missed.record(5_000_000, 500).unwrap_err();
+        assert_eq!(missed.shape_fingerprint(16), base.shape_fingerprint(16));
+    }
+
+    #[test]
+    fn test_shifted() {
+        let shape = [(100, 10), (120, 30), (150, 10)];
+        let base = filled(&shape, 1);
+        for &factor in &[10, 100, 1_000] {
+            let shifted: Vec<(u64, u64)> =
+                shape.iter().map(|&(value, count)| (value * factor, count)).collect();
+            let shifted = filled(&shifted, 1);
+            assert!(shifted.shape_fingerprint(16) != base.shape_fingerprint(16),
+                    "factor: {}",
+                    factor);
+        }
+
+        // a change of proportions between bars
+        let reshaped = filled(&[(100, 10), (120, 30), (150, 10), (50_000, 20)], 1);
+        assert!(reshaped.shape_fingerprint(16) != base.shape_fingerprint(16));
+    }
+
+    #[test]
+    fn test_bars() {
+        let h = filled(&[(10, 1), (100_000, 1)], 1);
+        let empty = filled(&[], 1);
+
+        // out of range bar counts are clamped
+        assert_eq!(h.shape_fingerprint(0), h.shape_fingerprint(1));
+        assert_eq!(h.shape_fingerprint(usize::MAX), h.shape_fingerprint(h.data.data.len()));
+
+        // one bar holds everything, whatever the shape
+        assert_eq!(h.shape_fingerprint(1), filled(&[(500, 9)], 1).shape_fingerprint(1));
+        assert!(h.shape_fingerprint(1) != empty.shape_fingerprint(1));
+        assert!(h.shape_fingerprint(8) != h.shape_fingerprint(16));
+
+        // stable across releases, so pinned
+        assert_eq!(empty.shape_fingerprint(4), 0x7f59_a258_b1d7_81d1);
+        assert_eq!(filled(&[(500, 9)], 1).shape_fingerprint(1), 0x529a_33dc_8ff5_3f91);
+    }
+}
@@ -0,0 +1,244 @@
+use super::{Histogram, HistogramError};
+
+/// a read-only Histogram with a precomputed cumulative count index
+///
+/// Created by `Histogram::freeze()`. The running totals which
+/// `Histogram::percentile()` builds on first use are built when freezing,
+/// and serve every query as a binary search, so issuing many percentile or
+/// rank queries against a histogram which no longer changes costs
+/// O(log buckets) per query.
+#[derive(Clone)]
+pub struct FrozenHistogram {
+    histogram: Histogram,
+}
+
+impl FrozenHistogram {
+    /// create a FrozenHistogram from a copy of the given Histogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{FrozenHistogram, Histogram};
+    /// let h = Histogram::new().unwrap();
+    ///
+    /// let f = FrozenHistogram::new(&h);
+    ///
+    /// assert_eq!(f.entries(), 0);
+    pub fn new(histogram: &Histogram) -> FrozenHistogram {
+        let histogram = histogram.clone();
+        histogram.data.totals();
+        FrozenHistogram { histogram }
+    }
+
+    /// return the Histogram this was frozen from
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// return the number of entries in the FrozenHistogram
+    pub fn entries(&self) -> u64 {
+        self.histogram.entries()
+    }
+
+    /// return the value for the given percentile, see `Histogram::percentile`,
+    /// which searches the same running totals
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let f = h.freeze();
+    ///
+    /// assert_eq!(f.percentile(50.0).unwrap(), 500);
+    /// assert_eq!(f.percentile(90.0).unwrap(), 900);
+    pub fn percentile(&self, percentile: f64) -> Result<u64, HistogramError> {
+        self.histogram.percentile(percentile)
+    }
+
+    /// return the percentage of entries at or below the given value
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.freeze().percentile_below(25).unwrap(), 25.0);
+    pub fn percentile_below(&self, value: u64) -> Result<f64, HistogramError> {
+        self.histogram.check_stored()?;
+
+        let counters = self.histogram.ranked_counters();
+
+        let mut have = counters.missed_small.saturating_add(self.count_between(0, value));
+        if value > self.histogram.config.max_value {
+            have = have.saturating_add(counters.missed_large);
+        }
+
+        Ok(100.0 * have as f64 / counters.entries_total as f64)
+    }
+
+    /// return the value of the bucket holding the count-th stored sample,
+    /// see `Histogram::value_at_count`
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..11 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.freeze().value_at_count(5).unwrap(), 5);
+    pub fn value_at_count(&self, count: u64) -> Result<u64, HistogramError> {
+        self.histogram.check_stored()?;
+
+        if count < 1 {
+            return Err(HistogramError::OutOfRange);
+        }
+
+        match self.histogram.data.totals().index_of(count) {
+            Some(index) => Ok(self.histogram.index_value(index)),
+            None => Err(HistogramError::OutOfRange),
+        }
+    }
+
+    /// return the total count stored in the buckets holding values from low
+    /// to high, inclusive
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.freeze().count_between(100, 199), 100);
+    pub fn count_between(&self, low: u64, high: u64) -> u64 {
+        match self.histogram.index_range(low, high) {
+            Some((low, high)) => {
+                let totals = self.histogram.data.totals();
+                totals.through(high) - totals.below(low)
+            }
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrozenHistogram;
+    use super::super::{Histogram, HistogramConfig};
+    use super::super::test_rng::Rng;
+
+    fn random_histogram(seed: u64, max: u64, samples: usize) -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(max).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        let mut rng = Rng(seed);
+
+        for _ in 0..samples {
+            // include out of range values on both ends
+            let value = rng.next() % (max + max / 10);
+            let _ = h.record(value, 1 + rng.next() % 3);
+        }
+        h
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<FrozenHistogram>();
+    }
+
+    #[test]
+    fn test_prebuilt() {
+        let h = random_histogram(7, 100_000, 1_000);
+        assert!(h.data.totals.get().is_none());
+
+        // the totals are built once when freezing, and shared by clones
+        let f = h.freeze();
+        let totals = f.histogram.data.totals.get().unwrap();
+        let copy = f.clone();
+        let shared = copy.histogram.data.totals.get().unwrap();
+        assert!(std::sync::Arc::ptr_eq(&totals.totals, &shared.totals));
+    }
+
+    #[test]
+    fn test_empty() {
+        let h = Histogram::new().unwrap();
+        let f = h.freeze();
+
+        assert_eq!(f.percentile(50.0), h.percentile(50.0));
+        assert_eq!(f.percentile_below(10), h.percentile_below(10));
+        assert_eq!(f.value_at_count(1), h.value_at_count(1));
+        assert_eq!(f.count_between(1, 100), 0);
+    }
+
+    #[test]
+    fn test_saturated() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(5_000, 1).unwrap_err();
+        h.record(10, u64::MAX).unwrap();
+
+        let f = h.freeze();
+        for p in &[10.0, 50.0, 90.0, 99.9] {
+            assert_eq!(f.percentile(*p), Ok(10), "percentile {}", p);
+        }
+    }
+
+    #[test]
+    fn test_matches_histogram() {
+        for seed in 1..6 {
+            let h = random_histogram(seed, 100_000, 1_000);
+            let f = h.freeze();
+
+            let mut p = 0.0;
+            while p <= 100.0 {
+                assert_eq!(f.percentile(p), h.percentile(p), "percentile: {}", p);
+                p += 0.25;
+            }
+            assert_eq!(f.percentile(99.9), h.percentile(99.9));
+            assert_eq!(f.percentile(-1.0), h.percentile(-1.0));
+            assert_eq!(f.percentile(100.1), h.percentile(100.1));
+
+            let mut rng = Rng(seed + 100);
+            for _ in 0..1_000 {
+                let a = rng.next() % 120_000;
+                let b = rng.next() % 120_000;
+                assert_eq!(f.count_between(a, b), h.count_between(a, b));
+                assert_eq!(f.percentile_below(a), h.percentile_below(a));
+                assert_eq!(f.value_at_count(a / 20), h.value_at_count(a / 20));
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_histogram_missed() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+
+        let _ = h.record(0, 10);
+        let _ = h.record(5_000, 10);
+        let _ = h.record(500, 5);
+
+        let f = h.freeze();
+
+        for p in 0..101 {
+            let p = p as f64;
+            assert_eq!(f.percentile(p), h.percentile(p), "percentile: {}", p);
+        }
+    }
+}
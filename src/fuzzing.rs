@@ -0,0 +1,422 @@
+//! generators for valid, populated histograms
+//!
+//! With the `arbitrary` feature, `HistogramConfig` and `Histogram` implement
+//! `arbitrary::Arbitrary`. With the `proptest` feature, `config_strategy()` and
+//! `histogram_strategy()` provide the equivalent proptest strategies. Every
+//! generated Histogram passes `Histogram::check_invariants()`.
+//!
+//! With the `fuzz` feature, `fuzz_target()` applies a sequence of arbitrary
+//! `Op`s to a Histogram, for a fuzzer to search for panics and broken
+//! invariants.
+
+use super::{Histogram, HistogramConfig, OverflowPolicy};
+#[cfg(feature = "fuzz")]
+use super::{DeserializeLimits, MergedView};
+
+/// the most records applied to a generated Histogram
+pub const MAX_RECORDS: usize = 256;
+
+/// the largest count of a single generated record
+pub const MAX_COUNT: u64 = 1_000;
+
+// the highest power of two of a generated max_value, which keeps the bucket
+// allocation small at the highest generated precision
+const MAX_VALUE_POWER: u32 = 40;
+
+// build a config from generated parts, always producing a valid layout
+fn config(precision: u32, power: u32, bits: u64, clamp: bool, prefault: bool) -> HistogramConfig {
+    let power = power % (MAX_VALUE_POWER + 1);
+    let max_value = (1 << power) | (bits & ((1 << power) - 1));
+
+    let mut config = HistogramConfig::new();
+    config.precision(1 + precision % 3)
+          .max_value(max_value)
+          .prefault(prefault)
+          .overflow(if clamp {
+              OverflowPolicy::Clamp
+          } else {
+              OverflowPolicy::Reject
+          });
+    config
+}
+
+// the largest value generated for a config, leaving room to miss high
+fn value_limit(config: &HistogramConfig) -> u64 {
+    config.max_value.saturating_mul(2)
+}
+
+// create a Histogram and apply the records, ignoring rejected values
+fn populated<I>(config: HistogramConfig, records: I) -> Option<Histogram>
+    where I: IntoIterator<Item = (u64, u64)>
+{
+    let mut histogram = Histogram::configured(config).ok()?;
+    for (value, count) in records {
+        let _ = histogram.record(value, count);
+    }
+    Some(histogram)
+}
+
+// the most memory of the buckets of a histogram built by the fuzz target,
+// so that a fuzzer finds panics rather than exhausting memory
+#[cfg(feature = "fuzz")]
+const FUZZ_MEMORY: u32 = 16 << 20;
+
+/// an operation applied by `fuzz_target()`, covering the public API which
+/// reads or modifies a Histogram
+#[cfg(feature = "fuzz")]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    /// replace the histogram with an empty one of the config, if valid
+    Configure {
+        /// the precision of the config
+        precision: u32,
+        /// the max_value of the config
+        max_value: u64,
+        /// clamp rather than reject values above max_value
+        clamp: bool,
+    },
+    /// record a value with a count
+    Record(u64, u64),
+    /// remove counts of a value
+    Subtract(u64, u64),
+    /// overwrite the count of a bucket by id
+    SetBucketCount(usize, u64),
+    /// clear the counts
+    Clear,
+    /// merge in a copy of the histogram
+    MergeSelf,
+    /// raise the precision
+    Refine(u32),
+    /// multiply every value
+    ShiftValues(u64),
+    /// encode and decode in each format, replacing the histogram
+    RoundTrip,
+    /// decode untrusted bytes in each format
+    Decode(Vec<u8>),
+    /// read the value of percentiles by each method
+    Percentiles(Vec<f64>),
+    /// read the counts and values around a value
+    Value(u64),
+    /// read the counts between two values
+    Range(u64, u64),
+    /// read the value of the count-th sample
+    ValueAtCount(u64),
+    /// read the summary statistics and reports
+    Statistics,
+}
+
+/// apply the operations to a histogram with the default config, for
+/// external fuzzers
+///
+/// Every operation's result is ignored, as errors are expected for most
+/// inputs, but the histogram must stay consistent. Configs are limited to
+/// 16MB of buckets.
+///
+/// # Panics
+///
+/// If the histogram breaks one of its invariants, which is a bug, as is any
+/// other panic.
+///
+/// # Example
+/// ```
+/// # use histogram::{fuzz_target, Op};
+/// fuzz_target(&[Op::Record(100, 5), Op::Percentiles(vec![50.0, 150.0]), Op::Clear]);
+#[cfg(feature = "fuzz")]
+pub fn fuzz_target(ops: &[Op]) {
+    let mut config = HistogramConfig::new();
+    config.max_memory(FUZZ_MEMORY);
+    let mut histogram = match Histogram::configured(config) {
+        Ok(histogram) => histogram,
+        Err(_) => return,
+    };
+    let mut limits = DeserializeLimits::new();
+    limits.max_memory(FUZZ_MEMORY as u64);
+
+    for op in ops {
+        match *op {
+            Op::Configure { precision, max_value, clamp } => {
+                let mut config = HistogramConfig::new();
+                config.precision(precision)
+                      .max_value(max_value)
+                      .max_memory(FUZZ_MEMORY)
+                      .overflow(if clamp {
+                          OverflowPolicy::Clamp
+                      } else {
+                          OverflowPolicy::Reject
+                      });
+                if let Ok(configured) = Histogram::configured(config) {
+                    histogram = configured;
+                }
+            }
+            Op::Record(value, count) => {
+                let _ = histogram.record(value, count);
+                let _ = histogram.try_record(value, count);
+            }
+            Op::Subtract(value, count) => {
+                let _ = histogram.subtract(value, count);
+            }
+            Op::SetBucketCount(id, count) => {
+                let _ = histogram.set_bucket_count(id, count);
+            }
+            Op::Clear => {
+                histogram.clear();
+            }
+            Op::MergeSelf => {
+                let other = histogram.clone();
+                let _ = histogram.merge(&other);
+            }
+            Op::Refine(precision) => {
+                let _ = histogram.refine(precision);
+            }
+            Op::ShiftValues(multiplier) => {
+                if let Ok(shifted) = histogram.shift_values_exact(multiplier) {
+                    histogram = shifted;
+                }
+            }
+            Op::RoundTrip => {
+                if let Ok(decoded) = Histogram::from_bytes(&histogram.to_bytes()) {
+                    histogram = decoded;
+                }
+                if let Ok(decoded) = Histogram::deserialize(&histogram.serialize()) {
+                    histogram = decoded;
+                }
+                // the memory limit is not encoded, but still bounds refine()
+                histogram.config.max_memory(FUZZ_MEMORY);
+            }
+            Op::Decode(ref bytes) => {
+                let _ = Histogram::from_bytes_with_limits(bytes, &limits);
+                let _ = Histogram::deserialize_with_limits(bytes, &limits);
+            }
+            Op::Percentiles(ref percentiles) => {
+                let frozen = histogram.freeze();
+                let _ = histogram.percentiles(percentiles);
+                for &percentile in percentiles {
+                    let _ = histogram.percentile(percentile);
+                    let _ = histogram.percentile_bounds(percentile);
+                    let _ = histogram.above_percentile(percentile);
+                    let _ = frozen.percentile(percentile);
+                    let _ = histogram.percentile_bp(percentile as u32);
+                    let _ = histogram.quantile_ppm(percentile as u32);
+                }
+                if let Ok(view) = MergedView::new(&[&histogram, &histogram]) {
+                    for &percentile in percentiles {
+                        let _ = view.percentile(percentile);
+                    }
+                }
+            }
+            Op::Value(value) => {
+                let _ = histogram.get(value);
+                let _ = histogram.bucket_bounds(value);
+                let _ = histogram.percentile_below(value);
+                let _ = histogram.freeze().percentile_below(value);
+                let _ = histogram.iter_from(value).count();
+                let _ = histogram.percentile_with_pending(&[value], 50.0);
+            }
+            Op::Range(low, high) => {
+                let _ = histogram.count_between(low, high);
+                let _ = histogram.freeze().count_between(low, high);
+                let _ = histogram.iter_range(low, high).count();
+                let _ = histogram.values_between(low, high).count();
+                let _ = histogram.condensed((high % 64) as usize);
+            }
+            Op::ValueAtCount(count) => {
+                let _ = histogram.value_at_count(count);
+                let _ = histogram.freeze().value_at_count(count);
+                let _ = histogram.percentile_curve((count % 64) as usize);
+            }
+            Op::Statistics => {
+                let _ = histogram.summary();
+                let _ = histogram.minimum();
+                let _ = histogram.maximum();
+                let _ = histogram.mean();
+                let _ = histogram.stdvar();
+                let _ = histogram.stddev();
+                let _ = histogram.entropy();
+                let _ = histogram.emd(&histogram);
+                let _ = histogram.by_power_of_two();
+                let _ = histogram.power_of_two_chart();
+                let _ = histogram.describe();
+                let _ = histogram.report().to_string();
+            }
+        }
+        if let Err(broken) = histogram.check_invariants() {
+            panic!("{} after {:?}", broken, op);
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impls {
+    use arbitrary::{Arbitrary, Error, Result, Unstructured};
+
+    use super::{config, populated, value_limit, MAX_COUNT, MAX_RECORDS, MAX_VALUE_POWER};
+    use super::super::{Histogram, HistogramConfig};
+    #[cfg(feature = "fuzz")]
+    use super::Op;
+
+    impl<'a> Arbitrary<'a> for HistogramConfig {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<HistogramConfig> {
+            Ok(config(u.int_in_range(0..=2)?,
+                      u.int_in_range(0..=MAX_VALUE_POWER)?,
+                      u.arbitrary()?,
+                      u.arbitrary()?,
+                      u.arbitrary()?))
+        }
+    }
+
+    impl<'a> Arbitrary<'a> for Histogram {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Histogram> {
+            let config = HistogramConfig::arbitrary(u)?;
+            let limit = value_limit(&config);
+
+            let mut records = Vec::new();
+            for _ in 0..u.int_in_range(0..=MAX_RECORDS)? {
+                records.push((u.int_in_range(0..=limit)?, u.int_in_range(1..=MAX_COUNT)?));
+            }
+
+            populated(config, records).ok_or(Error::IncorrectFormat)
+        }
+    }
+
+    #[cfg(feature = "fuzz")]
+    impl<'a> Arbitrary<'a> for Op {
+        fn arbitrary(u: &mut Unstructured<'a>) -> Result<Op> {
+            Ok(match u.int_in_range(0..=14)? {
+                0 => Op::Configure {
+                    precision: u.arbitrary()?,
+                    max_value: u.arbitrary()?,
+                    clamp: u.arbitrary()?,
+                },
+                1 => Op::Record(u.arbitrary()?, u.arbitrary()?),
+                2 => Op::Subtract(u.arbitrary()?, u.arbitrary()?),
+                3 => Op::SetBucketCount(u.arbitrary()?, u.arbitrary()?),
+                4 => Op::Clear,
+                5 => Op::MergeSelf,
+                6 => Op::Refine(u.arbitrary()?),
+                7 => Op::ShiftValues(u.arbitrary()?),
+                8 => Op::RoundTrip,
+                9 => Op::Decode(u.arbitrary()?),
+                10 => Op::Percentiles(u.arbitrary()?),
+                11 => Op::Value(u.arbitrary()?),
+                12 => Op::Range(u.arbitrary()?, u.arbitrary()?),
+                13 => Op::ValueAtCount(u.arbitrary()?),
+                _ => Op::Statistics,
+            })
+        }
+    }
+}
+
+#[cfg(feature = "proptest")]
+mod strategies {
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::{config, populated, value_limit, MAX_COUNT, MAX_RECORDS, MAX_VALUE_POWER};
+    use super::super::{Histogram, HistogramConfig};
+
+    /// a strategy generating valid HistogramConfigs
+    pub fn config_strategy() -> impl Strategy<Value = HistogramConfig> {
+        (0_u32..3, 0..MAX_VALUE_POWER + 1, any::<u64>(), any::<bool>(), any::<bool>())
+            .prop_map(|(precision, power, bits, clamp, prefault)| {
+                config(precision, power, bits, clamp, prefault)
+            })
+    }
+
+    /// a strategy generating Histograms with a valid config and a bounded
+    /// number of records
+    pub fn histogram_strategy() -> impl Strategy<Value = Histogram> {
+        config_strategy()
+            .prop_flat_map(|config| {
+                let records = vec((0..=value_limit(&config), 1..=MAX_COUNT), 0..=MAX_RECORDS);
+                (Just(config), records)
+            })
+            .prop_map(|(config, records)| populated(config, records).unwrap())
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub use self::strategies::{config_strategy, histogram_strategy};
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        use super::super::Histogram;
+        use super::super::test_rng::Rng;
+
+        let mut rng = Rng(1);
+        let bytes: Vec<u8> = (0..64 * 1024).map(|_| rng.next() as u8).collect();
+
+        for start in 0..64 {
+            let mut u = Unstructured::new(&bytes[start * 1024..]);
+            let h = Histogram::arbitrary(&mut u).unwrap();
+            h.check_invariants().unwrap();
+        }
+
+        // running out of input still produces a valid Histogram
+        let h = Histogram::arbitrary(&mut Unstructured::new(&[])).unwrap();
+        h.check_invariants().unwrap();
+    }
+
+    #[cfg(feature = "fuzz")]
+    #[test]
+    fn test_fuzz_target() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        use super::{fuzz_target, Op};
+        use super::super::test_rng::Rng;
+
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..2_000 {
+            let bytes: Vec<u8> = (0..256).map(|_| rng.next() as u8).collect();
+            let ops = Vec::<Op>::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            fuzz_target(&ops);
+        }
+    }
+
+    // operation sequences which panicked before the checked refactor
+    #[cfg(feature = "fuzz")]
+    #[test]
+    fn test_fuzz_regressions() {
+        use super::{fuzz_target, Op};
+
+        let corpus = vec![
+            // the walk from the top of a frozen histogram overflowed adding
+            // the missed samples to a saturated bucket total
+            vec![Op::Configure { precision: 3, max_value: 1_000, clamp: false },
+                 Op::Record(5_000, 1),
+                 Op::Record(10, u64::MAX),
+                 Op::Percentiles(vec![90.0])],
+            // the chart label of the top power of two overflowed
+            vec![Op::Configure { precision: 1, max_value: u64::MAX, clamp: true },
+                 Op::Record(u64::MAX, 1),
+                 Op::Statistics],
+        ];
+        for ops in &corpus {
+            fuzz_target(ops);
+        }
+    }
+
+    #[cfg(feature = "proptest")]
+    mod strategies {
+        use proptest::prelude::*;
+
+        use super::super::{config_strategy, histogram_strategy};
+        use super::super::super::Histogram;
+
+        proptest! {
+            #[test]
+            fn test_config_strategy(config in config_strategy()) {
+                prop_assert!(Histogram::configured(config).is_ok());
+            }
+
+            #[test]
+            fn test_histogram_strategy(h in histogram_strategy()) {
+                prop_assert_eq!(h.check_invariants(), Ok(()));
+            }
+        }
+    }
+}
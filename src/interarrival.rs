@@ -0,0 +1,130 @@
+use std::time::Instant;
+
+use super::{Histogram, HistogramError};
+
+/// records the time between successive events into a Histogram
+///
+/// Each call to `record()` after the first stores the gap since the previous
+/// call in nanoseconds, timed by the clock of the wrapped Histogram.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, HistogramConfig, InterarrivalRecorder, ManualClock};
+/// # use std::sync::Arc;
+/// # use std::time::Duration;
+/// let clock = Arc::new(ManualClock::new());
+/// let h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+/// let mut r = InterarrivalRecorder::new(h);
+///
+/// r.record().unwrap();
+/// clock.advance(Duration::from_micros(100));
+/// r.record().unwrap();
+///
+/// assert_eq!(r.histogram().entries(), 1);
+/// assert_eq!(r.histogram().get(100_000).unwrap(), 1);
+#[derive(Clone)]
+pub struct InterarrivalRecorder {
+    histogram: Histogram,
+    previous: Option<Instant>,
+}
+
+impl InterarrivalRecorder {
+    /// create an InterarrivalRecorder storing gaps into the histogram
+    pub fn new(histogram: Histogram) -> InterarrivalRecorder {
+        InterarrivalRecorder {
+            histogram,
+            previous: None,
+        }
+    }
+
+    /// mark an event, recording the gap since the previous one
+    ///
+    /// Returns the gap in nanoseconds, or None for the first event after
+    /// creation or `reset_clock()`.
+    pub fn record(&mut self) -> Result<Option<u64>, HistogramError> {
+        let now = self.histogram.clock.now();
+        let previous = self.previous.replace(now);
+
+        match previous {
+            Some(previous) => {
+                let gap = if now > previous {
+                    (now - previous).as_nanos().min(u64::MAX as u128) as u64
+                } else {
+                    0
+                };
+                self.histogram.increment(gap).map(|_| Some(gap))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// forget the previous event, so the gap up to the next is not recorded
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, InterarrivalRecorder};
+    /// let mut r = InterarrivalRecorder::new(Histogram::new().unwrap());
+    ///
+    /// r.record().unwrap();
+    /// r.reset_clock();
+    ///
+    /// assert_eq!(r.record().unwrap(), None);
+    /// assert_eq!(r.histogram().entries(), 0);
+    pub fn reset_clock(&mut self) {
+        self.previous = None;
+    }
+
+    /// return the histogram of gaps
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// return the histogram of gaps for modification, eg to clear it
+    pub fn histogram_mut(&mut self) -> &mut Histogram {
+        &mut self.histogram
+    }
+
+    /// consume the recorder, returning the histogram of gaps
+    pub fn into_inner(self) -> Histogram {
+        self.histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::InterarrivalRecorder;
+    use super::super::{Histogram, HistogramConfig, ManualClock};
+
+    #[test]
+    fn test_gaps() {
+        let clock = Arc::new(ManualClock::new());
+        let h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+        let mut r = InterarrivalRecorder::new(h);
+
+        assert_eq!(r.record(), Ok(None));
+        for &gap in &[10, 500, 10, 7_000] {
+            clock.advance(Duration::from_nanos(gap));
+            assert_eq!(r.record(), Ok(Some(gap)));
+        }
+
+        // events on the same instant record a gap of zero, which is too small
+        assert!(r.record().is_err());
+
+        // the gap across a reset is not recorded
+        r.reset_clock();
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(r.record(), Ok(None));
+        clock.advance(Duration::from_nanos(20));
+        assert_eq!(r.record(), Ok(Some(20)));
+
+        let h = r.into_inner();
+        assert_eq!(h.entries(), 6);
+        assert_eq!(h.get(10).unwrap(), 2);
+        assert_eq!(h.get(20).unwrap(), 1);
+        assert_eq!(h.get(500).unwrap(), 1);
+        assert_eq!(h.get(7_000).unwrap(), 1);
+    }
+}
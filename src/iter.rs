@@ -0,0 +1,864 @@
+use std::ops;
+
+use super::{occupied_indices, Histogram, HistogramBucket};
+
+/// an iterator over the buckets of a Histogram which doesn't modify it
+///
+/// Created by `Histogram::iter()`, `Histogram::iter_recorded()`,
+/// `Histogram::iter_from()`, and `Histogram::iter_range()`, and adapted with
+/// `recorded()` to skip empty buckets or `cumulative()` to carry a running
+/// total.
+#[derive(Clone)]
+pub struct HistogramIter<'a> {
+    histogram: &'a Histogram,
+    index: usize,
+    end: usize,
+    recorded: bool,
+}
+
+impl<'a> HistogramIter<'a> {
+    // iterate the buckets with index from start up to, not including, end
+    pub(crate) fn new(histogram: &'a Histogram, start: usize, end: usize) -> HistogramIter<'a> {
+        let end = end.min(histogram.properties.buckets_total as usize);
+        HistogramIter {
+            histogram,
+            index: start.min(end),
+            end,
+            recorded: false,
+        }
+    }
+
+    /// skip buckets which have a count of zero
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(5).unwrap();
+    /// h.increment(10).unwrap();
+    ///
+    /// let values: Vec<u64> = h.iter_from(1).recorded().map(|b| b.value()).collect();
+    /// assert_eq!(values, vec![5, 10]);
+    pub fn recorded(mut self) -> HistogramIter<'a> {
+        self.recorded = true;
+        self
+    }
+
+    /// yield each bucket with the total count of the buckets iterated so far,
+    /// including itself
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(5, 2).unwrap();
+    /// h.record(10, 3).unwrap();
+    ///
+    /// let totals: Vec<u64> = h.iter_from(1).recorded().cumulative().map(|(_, t)| t).collect();
+    /// assert_eq!(totals, vec![2, 5]);
+    pub fn cumulative(self) -> Cumulative<'a> {
+        Cumulative {
+            inner: self,
+            total: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for HistogramIter<'a> {
+    type Item = HistogramBucket;
+
+    fn next(&mut self) -> Option<HistogramBucket> {
+        while self.index < self.end {
+            let index = self.index;
+            self.index += 1;
+
+            let bucket = self.histogram.bucket(index);
+            if self.recorded && bucket.count == 0 {
+                continue;
+            }
+            return Some(bucket);
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end - self.index;
+        if self.recorded {
+            (0, Some(remaining))
+        } else {
+            (remaining, Some(remaining))
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Histogram {
+    type Item = HistogramBucket;
+    type IntoIter = HistogramIter<'a>;
+
+    fn into_iter(self) -> HistogramIter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Histogram {
+    type Item = HistogramBucket;
+    type IntoIter = HistogramIter<'a>;
+
+    fn into_iter(self) -> HistogramIter<'a> {
+        self.iter()
+    }
+}
+
+/// an iterator over every bucket of a Histogram it owns
+///
+/// Created by `Histogram::into_iter()`, starting from the first bucket.
+///
+/// # Example
+/// ```
+/// # use histogram::Histogram;
+/// let mut h = Histogram::new().unwrap();
+///
+/// h.record(3, 2).unwrap();
+///
+/// let buckets: Vec<_> = h.into_iter().filter(|b| b.count() > 0).collect();
+/// assert_eq!(buckets.len(), 1);
+/// assert_eq!(buckets[0].value(), 3);
+#[derive(Clone)]
+pub struct IntoIter {
+    histogram: Histogram,
+    index: usize,
+}
+
+impl Iterator for IntoIter {
+    type Item = HistogramBucket;
+
+    fn next(&mut self) -> Option<HistogramBucket> {
+        if self.index >= self.histogram.properties.buckets_total as usize {
+            return None;
+        }
+        let bucket = self.histogram.bucket(self.index);
+        self.index += 1;
+        Some(bucket)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buckets = self.histogram.properties.buckets_total as usize;
+        let remaining = buckets.saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+impl IntoIterator for Histogram {
+    type Item = HistogramBucket;
+    type IntoIter = IntoIter;
+
+    fn into_iter(self) -> IntoIter {
+        IntoIter {
+            histogram: self,
+            index: 0,
+        }
+    }
+}
+
+/// an iterator yielding buckets with a running total of their counts
+///
+/// Created by `HistogramIter::cumulative()`.
+#[derive(Clone)]
+pub struct Cumulative<'a> {
+    inner: HistogramIter<'a>,
+    total: u64,
+}
+
+impl<'a> Iterator for Cumulative<'a> {
+    type Item = (HistogramBucket, u64);
+
+    fn next(&mut self) -> Option<(HistogramBucket, u64)> {
+        self.inner.next().map(|bucket| {
+            self.total = self.total.saturating_add(bucket.count);
+            (bucket, self.total)
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// a resumable position in the buckets of a Histogram, for walking them a
+/// chunk at a time
+///
+/// Created by `Histogram::cursor()`. Buckets are always visited in ascending
+/// order of their id, which is also ascending order of value, and the id of
+/// a bucket is fixed by the config. So `position()` may be kept while the
+/// cursor is dropped, the histogram records more samples, and a new cursor
+/// is `resume()`d from it. Samples recorded in between into buckets already
+/// passed are not seen until the walk starts over.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram,HistogramConfig};
+/// let mut c = HistogramConfig::new();
+/// c.max_value(100).precision(1);
+/// let mut h = Histogram::configured(c).unwrap();
+///
+/// h.increment(2).unwrap();
+///
+/// let mut cursor = h.cursor();
+/// assert_eq!(cursor.next_chunk(10).len(), 10);
+/// let position = cursor.position();
+///
+/// h.increment(50).unwrap();
+///
+/// let mut cursor = h.cursor();
+/// cursor.resume(position);
+/// let rest: u64 = cursor.next_chunk(1_000).iter().map(|b| b.count()).sum();
+/// assert_eq!(rest, 1);
+/// assert!(cursor.next_chunk(10).is_empty());
+#[derive(Clone)]
+pub struct BucketCursor<'a> {
+    histogram: &'a Histogram,
+    index: usize,
+}
+
+impl<'a> BucketCursor<'a> {
+    pub(crate) fn new(histogram: &'a Histogram) -> BucketCursor<'a> {
+        BucketCursor {
+            histogram,
+            index: 0,
+        }
+    }
+
+    /// return up to n buckets from the cursor, advancing past them, or none
+    /// once past the last bucket
+    pub fn next_chunk(&mut self, n: usize) -> Vec<HistogramBucket> {
+        let buckets = self.histogram.properties.buckets_total as usize;
+        let end = self.index.saturating_add(n).min(buckets);
+        let chunk = (self.index..end).map(|index| self.histogram.bucket(index)).collect();
+        self.index = end;
+        chunk
+    }
+
+    /// move the cursor to the bucket holding the value, as
+    /// `Histogram::iter_from()`
+    pub fn seek_to_value(&mut self, value: u64) {
+        self.index = self.histogram.iter_from(value).index;
+    }
+
+    /// return the id of the next bucket the cursor yields, which equals the
+    /// number of buckets once past the last
+    pub fn position(&self) -> u64 {
+        self.index as u64
+    }
+
+    /// move the cursor to a position returned by `position()`, for the same
+    /// config, clamping positions past the last bucket
+    pub fn resume(&mut self, position: u64) {
+        let len = self.histogram.properties.buckets_total as usize;
+        self.index = position.min(len as u64) as usize;
+    }
+}
+
+/// a range of values with the stored count in it, yielded by `LinearIter`
+/// and `LogIter`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValueRange {
+    low: u64,
+    high: u64,
+    count: u64,
+    cumulative: u64,
+}
+
+impl ValueRange {
+    /// return the lowest value of the range
+    pub fn low(self) -> u64 {
+        self.low
+    }
+
+    /// return the highest value of the range, inclusive
+    pub fn high(self) -> u64 {
+        self.high
+    }
+
+    /// return the count of the buckets assigned to the range
+    pub fn count(self) -> u64 {
+        self.count
+    }
+
+    /// return the total count of this range and all before it
+    pub fn cumulative(self) -> u64 {
+        self.cumulative
+    }
+}
+
+// a walk over the occupied buckets, assigning each to the first range whose
+// high is at least the lowest value of the bucket, so that counts are never
+// split or lost however the ranges fall across the buckets
+#[derive(Clone)]
+struct RangeWalk<'a> {
+    histogram: &'a Histogram,
+    indices: ops::Range<usize>,
+    low: u64,
+    cumulative: u64,
+    done: bool,
+}
+
+impl<'a> RangeWalk<'a> {
+    fn new(histogram: &'a Histogram) -> RangeWalk<'a> {
+        RangeWalk {
+            histogram,
+            indices: occupied_indices(histogram.data.occupied),
+            low: histogram.config.min_value,
+            cumulative: 0,
+            done: false,
+        }
+    }
+
+    // the range from the end of the last up to high, clamped to max_value,
+    // None once max_value has been passed
+    fn step(&mut self, high: u64) -> Option<ValueRange> {
+        if self.done {
+            return None;
+        }
+        let max = self.histogram.config.max_value;
+        let high = high.clamp(self.low, max);
+        let mut count = 0_u64;
+        for index in self.indices.clone() {
+            if self.histogram.properties.index_low(index) > high {
+                break;
+            }
+            count = count.saturating_add(self.histogram.data.count(index));
+            self.indices.start = index + 1;
+        }
+        self.cumulative = self.cumulative.saturating_add(count);
+
+        let range = ValueRange {
+            low: self.low,
+            high,
+            count,
+            cumulative: self.cumulative,
+        };
+        self.done = high >= max;
+        self.low = high.saturating_add(1);
+        Some(range)
+    }
+}
+
+/// an iterator over fixed width ranges of values from min_value to
+/// max_value, with the stored count in each
+///
+/// Created by `Histogram::iter_linear()`.
+#[derive(Clone)]
+pub struct LinearIter<'a> {
+    walk: RangeWalk<'a>,
+    step: u64,
+}
+
+impl<'a> LinearIter<'a> {
+    pub(crate) fn new(histogram: &'a Histogram, step: u64) -> LinearIter<'a> {
+        LinearIter {
+            walk: RangeWalk::new(histogram),
+            step: step.max(1),
+        }
+    }
+}
+
+impl<'a> Iterator for LinearIter<'a> {
+    type Item = ValueRange;
+
+    fn next(&mut self) -> Option<ValueRange> {
+        let high = self.walk.low.saturating_add(self.step - 1);
+        self.walk.step(high)
+    }
+}
+
+/// an iterator over exponentially growing ranges of values from min_value
+/// to max_value, with the stored count in each
+///
+/// Created by `Histogram::iter_log()`.
+#[derive(Clone)]
+pub struct LogIter<'a> {
+    walk: RangeWalk<'a>,
+    base: f64,
+    high: u64,
+}
+
+impl<'a> LogIter<'a> {
+    pub(crate) fn new(histogram: &'a Histogram, base: f64, start: u64) -> LogIter<'a> {
+        LogIter {
+            walk: RangeWalk::new(histogram),
+            base,
+            high: start,
+        }
+    }
+}
+
+impl<'a> Iterator for LogIter<'a> {
+    type Item = ValueRange;
+
+    fn next(&mut self) -> Option<ValueRange> {
+        let range = self.walk.step(self.high)?;
+        // always grow by at least one, whatever the base
+        let next = (range.high as f64 * self.base).ceil() as u64;
+        self.high = next.max(range.high.saturating_add(1));
+        Some(range)
+    }
+}
+
+/// a step towards the highest stored sample, yielded by `QuantileIter`
+///
+/// The low and high values bound the values passed since the previous step,
+/// starting from the lowest bucket stored, and the count is of the samples
+/// between them. A quantile held by the same bucket as the previous one
+/// passes no values, so its step has the bounds of that bucket and a count
+/// of 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuantileStep {
+    quantile: f64,
+    value: u64,
+    low: u64,
+    high: u64,
+    count: u64,
+    cumulative: u64,
+}
+
+impl QuantileStep {
+    /// return the quantile reached, from 0.0 to 1.0
+    pub fn quantile(self) -> f64 {
+        self.quantile
+    }
+
+    /// return the value of the bucket holding the quantile
+    pub fn value(self) -> u64 {
+        self.value
+    }
+
+    /// return the lowest value of the step
+    pub fn low(self) -> u64 {
+        self.low
+    }
+
+    /// return the highest value of the step, inclusive
+    pub fn high(self) -> u64 {
+        self.high
+    }
+
+    /// return the count of the buckets passed in the step
+    pub fn count(self) -> u64 {
+        self.count
+    }
+
+    /// return the total count of this step and all before it
+    pub fn cumulative(self) -> u64 {
+        self.cumulative
+    }
+}
+
+/// an iterator over quantiles which close in on the highest stored sample,
+/// as for a percentile plot with a logarithmic axis
+///
+/// Created by `Histogram::iter_quantiles()`. As HdrHistogram's percentile
+/// iterator, each halving of the distance to 1.0 is split into the given
+/// number of ticks, so the quantiles run 0.0, 0.5, 0.75, 0.875 and so on for
+/// one tick. They stop once the next would fall on the highest sample,
+/// which is yielded as quantile 1.0.
+#[derive(Clone)]
+pub struct QuantileIter<'a> {
+    histogram: &'a Histogram,
+    indices: ops::Range<usize>,
+    ticks: u32,
+    total: u64,
+    cumulative: u64,
+    reported: u64,
+    level: f64,
+    low: Option<u64>,
+    last: Option<usize>,
+    done: bool,
+}
+
+impl<'a> QuantileIter<'a> {
+    pub(crate) fn new(histogram: &'a Histogram, ticks: u32) -> QuantileIter<'a> {
+        let indices = occupied_indices(histogram.data.occupied);
+        let total = indices.clone()
+                           .fold(0_u64, |total, index| {
+                               total.saturating_add(histogram.data.count(index))
+                           });
+        QuantileIter {
+            histogram,
+            indices,
+            ticks: ticks.max(1),
+            total,
+            cumulative: 0,
+            reported: 0,
+            level: 0.0,
+            low: histogram.data.occupied.map(|(first, _)| histogram.index_bounds(first).0),
+            last: None,
+            done: false,
+        }
+    }
+
+    // the distance to 1.0 split into ticks, for each halving of it
+    fn advance(&mut self) {
+        let halvings = (1.0 / (1.0 - self.level)).log2().floor() + 1.0;
+        let level = self.level + 1.0 / (halvings.exp2() * self.ticks as f64);
+        // past the precision of f64, the next tick is the end
+        self.level = if level > self.level { level } else { 1.0 };
+    }
+}
+
+impl<'a> Iterator for QuantileIter<'a> {
+    type Item = QuantileStep;
+
+    fn next(&mut self) -> Option<QuantileStep> {
+        if self.done || self.total == 0 {
+            return None;
+        }
+        let total = self.total as f64;
+        loop {
+            if let Some(last) = self.last {
+                if self.cumulative as f64 >= self.level * total {
+                    let (low, high) = self.histogram.index_bounds(last);
+                    // quantiles whose rank is the highest sample end the walk
+                    let last_rank = (self.level * total).ceil() >= total;
+                    let count = self.cumulative - self.reported;
+                    let step = QuantileStep {
+                        quantile: if last_rank { 1.0 } else { self.level },
+                        value: self.histogram.index_value(last),
+                        low: if count > 0 { self.low.unwrap_or(low) } else { low },
+                        high,
+                        count,
+                        cumulative: self.cumulative,
+                    };
+                    if count > 0 {
+                        self.low = Some(high.saturating_add(1));
+                    }
+                    self.reported = self.cumulative;
+                    self.done = last_rank;
+                    self.advance();
+                    return Some(step);
+                }
+            }
+
+            let index = self.indices.next()?;
+            let count = self.histogram.data.count(index);
+            if count > 0 {
+                self.cumulative = self.cumulative.saturating_add(count);
+                self.last = Some(index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig};
+
+    fn histogram() -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 1..1000 {
+            h.record(value * 37 % 100_000 + 1, value % 7).unwrap();
+        }
+        h
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let h = histogram();
+
+        let first = h.iter_from(5_000).next().unwrap();
+        assert_eq!(first.id() as usize, h.get_index(5_000).unwrap());
+        assert!(first.value() <= 5_000);
+
+        let first = h.iter_from(5_000).recorded().next().unwrap();
+        assert!(first.count() > 0);
+        assert!(first.value() >= h.bucket_bounds(5_000).unwrap().0);
+
+        assert_eq!(h.iter_from(0).next().unwrap().id(), 0);
+        assert!(h.iter_from(100_001).next().is_none());
+        assert_eq!(h.iter_from(1).count() as u64, h.buckets_total());
+    }
+
+    #[test]
+    fn test_iter_range() {
+        let h = histogram();
+
+        for &(low, high) in &[(1, 100), (50, 5_000), (1_000, 100_000), (0, u64::MAX)] {
+            let total: u64 = h.iter_range(low, high).map(|b| b.count()).sum();
+            assert_eq!(total, h.count_between(low, high));
+
+            let recorded: u64 = h.iter_range(low, high).recorded().map(|b| b.count()).sum();
+            assert_eq!(recorded, total);
+
+            let last = h.iter_range(low, high).cumulative().last().unwrap();
+            assert_eq!(last.1, total);
+        }
+
+        assert!(h.iter_range(5_000, 50).next().is_none());
+    }
+
+    #[test]
+    fn test_cumulative() {
+        let h = histogram();
+
+        let mut previous = 0;
+        for (bucket, total) in h.iter_from(1).recorded().cumulative() {
+            assert_eq!(total, previous + bucket.count());
+            previous = total;
+        }
+        assert_eq!(previous, h.count_between(1, 100_000));
+    }
+
+    #[test]
+    fn test_iter_shared() {
+        let h = histogram();
+        let shared = &h;
+
+        // iterating twice, and nested, over the same reference
+        let first: Vec<(u64, u64)> = shared.iter().map(|b| (b.id(), b.count())).collect();
+        let second: Vec<(u64, u64)> = shared.into_iter().map(|b| (b.id(), b.count())).collect();
+        assert_eq!(first, second);
+        assert_eq!(first.len() as u64, h.buckets_total());
+        for (id, &(expected, count)) in first.iter().enumerate() {
+            assert_eq!(id as u64, expected);
+            assert_eq!(Some(count), h.count_in_bucket(expected as usize));
+        }
+        let nested = shared.iter().take(3).flat_map(|_| shared.iter()).count();
+        assert_eq!(nested as u64, 3 * h.buckets_total());
+
+        // an early break leaves nothing behind
+        for bucket in shared {
+            if bucket.count() > 0 {
+                break;
+            }
+        }
+        assert_eq!(shared.iter().next().unwrap().id(), 0);
+
+        let owned: Vec<(u64, u64)> = h.clone().into_iter().map(|b| (b.id(), b.count())).collect();
+        assert_eq!(owned, first);
+        assert_eq!(h.clone().into_iter().size_hint(), (first.len(), Some(first.len())));
+    }
+
+    #[test]
+    fn test_iter_recorded() {
+        let h = histogram();
+
+        let recorded: Vec<u64> = h.iter_recorded().map(|b| b.id()).collect();
+        let expected: Vec<u64> = h.iter()
+                                  .filter(|b| b.count() > 0)
+                                  .map(|b| b.id())
+                                  .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(recorded, expected);
+        assert!(h.iter_recorded().all(|b| b.count() > 0));
+
+        assert_eq!(Histogram::new().unwrap().iter_recorded().count(), 0);
+    }
+
+    #[test]
+    fn test_iter_linear() {
+        let mut h = histogram();
+        h.increment(0).unwrap_err();
+        h.increment(200_000).unwrap_err();
+        let stored = h.count_between(0, u64::MAX);
+
+        for &step in &[1, 7, 100, 999, 25_000, 100_000, u64::MAX] {
+            let ranges: Vec<_> = h.iter_linear(step).collect();
+            assert_eq!(ranges.iter().map(|r| r.count()).sum::<u64>(), stored, "{}", step);
+            assert_eq!(ranges.last().unwrap().cumulative(), stored);
+
+            // the ranges cover 1..=max_value without gaps or overlaps
+            assert_eq!(ranges[0].low(), 1);
+            assert_eq!(ranges.last().unwrap().high(), 100_000);
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].high() + 1, pair[1].low());
+                assert_eq!(pair[0].high() - pair[0].low() + 1, step.min(100_000));
+                assert_eq!(pair[1].cumulative(), pair[0].cumulative() + pair[1].count());
+            }
+        }
+        assert_eq!(h.iter_linear(0).count(), 100_000);
+        assert_eq!(h.iter_linear(30_000).count(), 4);
+
+        // ranges as wide as the buckets match them
+        let mut c = HistogramConfig::new();
+        c.max_value(100);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(42, 3).unwrap();
+        let counts: Vec<u64> = h.iter_linear(1).map(|r| r.count()).collect();
+        assert_eq!(counts.len(), 100);
+        assert_eq!(counts[41], 3);
+        assert_eq!(Histogram::configured(c).unwrap().iter_linear(10).count(), 10);
+    }
+
+    #[test]
+    fn test_iter_log() {
+        let h = histogram();
+        let stored = h.count_between(0, u64::MAX);
+
+        for &(base, start) in &[(2.0, 1), (10.0, 1), (1.5, 100), (2.0, 1_000_000), (0.5, 99_990)] {
+            let ranges: Vec<_> = h.iter_log(base, start).collect();
+            assert_eq!(ranges.iter().map(|r| r.count()).sum::<u64>(), stored);
+            assert_eq!(ranges[0].low(), 1);
+            assert_eq!(ranges[0].high(), start.min(100_000));
+            assert_eq!(ranges.last().unwrap().high(), 100_000);
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].high() + 1, pair[1].low());
+            }
+        }
+
+        let highs: Vec<u64> = h.iter_log(2.0, 1).map(|r| r.high()).take(5).collect();
+        assert_eq!(highs, vec![1, 2, 4, 8, 16]);
+        assert_eq!(h.iter_log(2.0, 1).count(), 18);
+        assert_eq!(h.iter_log(f64::NAN, 99_990).count(), 11);
+        assert_eq!(h.iter_log(f64::INFINITY, 1).count(), 2);
+    }
+
+    #[test]
+    fn test_iter_quantiles() {
+        let mut h = histogram();
+        h.increment(200_000).unwrap_err();
+        let stored = h.count_between(0, u64::MAX);
+
+        for &ticks in &[0, 1, 5, 20] {
+            let steps: Vec<_> = h.iter_quantiles(ticks).collect();
+            assert_eq!(steps.iter().map(|s| s.count()).sum::<u64>(), stored);
+            let last = steps.last().unwrap();
+            assert_eq!((last.quantile(), last.cumulative()), (1.0, stored));
+            assert_eq!(last.value(), h.maximum_approx().unwrap());
+            assert_eq!(steps[0].quantile(), 0.0);
+            assert_eq!(steps[0].value(), h.minimum_approx().unwrap());
+
+            for pair in steps.windows(2) {
+                assert!(pair[0].quantile() < pair[1].quantile());
+                assert!(pair[0].value() <= pair[1].value());
+                assert_eq!(pair[1].cumulative(), pair[0].cumulative() + pair[1].count());
+                if pair[1].count() > 0 {
+                    assert_eq!(pair[0].high() + 1, pair[1].low());
+                } else {
+                    assert_eq!(pair[0].high(), pair[1].high());
+                }
+            }
+
+            // each value is that of the percentile
+            for step in &steps[1..steps.len() - 1] {
+                let percentile = h.percentile(step.quantile() * 100.0).unwrap();
+                assert_eq!(step.value(), percentile, "{}", step.quantile());
+            }
+        }
+
+        // more ticks, more steps, ending once the tail holds a single sample
+        let count = |ticks| h.iter_quantiles(ticks).count();
+        assert!(count(1) < count(5) && count(5) < count(20));
+        assert!(count(1) < 2 + (stored as f64).log2().ceil() as usize);
+
+        assert_eq!(Histogram::new().unwrap().iter_quantiles(5).count(), 0);
+        let mut single = Histogram::new().unwrap();
+        single.increment(7).unwrap();
+        let steps: Vec<_> = single.iter_quantiles(1).map(|s| (s.quantile(), s.count())).collect();
+        assert_eq!(steps, vec![(0.0, 1), (1.0, 0)]);
+
+        // a huge total still ends
+        let mut c = HistogramConfig::new();
+        c.max_value(100);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(1, u64::MAX / 2).unwrap();
+        h.record(100, u64::MAX / 2).unwrap();
+        let steps: Vec<_> = h.iter_quantiles(1).collect();
+        assert!(steps.len() < 100);
+        assert_eq!(steps.last().unwrap().quantile(), 1.0);
+        assert_eq!(steps.last().unwrap().cumulative(), u64::MAX - 1);
+    }
+
+    #[test]
+    fn test_iter_mut() {
+        let mut h = histogram();
+
+        // a loop over a mutable reference starts from the first bucket
+        // however the last one ended
+        for bucket in &mut h {
+            if bucket.id() == 2 {
+                break;
+            }
+        }
+        assert_eq!((&mut h).into_iter().next().unwrap().id(), 0);
+        let walked: Vec<u64> = (&mut h).into_iter().map(|b| b.id()).collect();
+        assert_eq!(walked.len() as u64, h.buckets_total());
+        assert_eq!(walked[0], 0);
+
+        h.increment(1).unwrap();
+        let counts: u64 = (&mut h).into_iter().map(|b| b.count()).sum();
+        assert_eq!(counts, h.iter().map(|b| b.count()).sum::<u64>());
+    }
+
+    #[test]
+    fn test_cursor_chunks() {
+        let h = histogram();
+
+        let mut cursor = h.cursor();
+        let mut walked = Vec::new();
+        loop {
+            let chunk = cursor.next_chunk(7);
+            if chunk.is_empty() {
+                break;
+            }
+            assert!(chunk.len() <= 7);
+            walked.extend(chunk.iter().map(|b| (b.id(), b.count())));
+            assert_eq!(cursor.position(), walked.len() as u64);
+        }
+        let expected: Vec<(u64, u64)> = h.iter().map(|b| (b.id(), b.count())).collect();
+        assert_eq!(walked, expected);
+        assert!(cursor.next_chunk(0).is_empty());
+
+        cursor.seek_to_value(5_000);
+        assert_eq!(cursor.position() as usize, h.get_index(5_000).unwrap());
+        assert_eq!(cursor.next_chunk(1)[0].id(), h.iter_from(5_000).next().unwrap().id());
+        cursor.seek_to_value(0);
+        assert_eq!(cursor.position(), 0);
+        cursor.seek_to_value(u64::MAX);
+        assert!(cursor.next_chunk(10).is_empty());
+
+        cursor.resume(u64::MAX);
+        assert_eq!(cursor.position(), h.buckets_total());
+    }
+
+    #[test]
+    fn test_cursor_resume() {
+        let mut h = histogram();
+        let total = h.count_between(0, u64::MAX);
+
+        let mut position = 0;
+        let mut seen = 0;
+        let mut ahead = 0;
+        let mut missed = 0;
+        let mut value = 1;
+        loop {
+            let mut cursor = h.cursor();
+            cursor.resume(position);
+            let chunk = cursor.next_chunk(50);
+            if chunk.is_empty() {
+                break;
+            }
+            seen += chunk.iter().map(|b| b.count()).sum::<u64>();
+            position = cursor.position();
+
+            // record into a bucket already passed, which is missed, and one
+            // which may still be ahead, which is seen
+            let (passed, _) = h.index_bounds(position as usize - 1);
+            h.increment(passed.min(100_000)).unwrap();
+            missed += 1;
+            value = (value * 7 + 13) % 100_000 + 1;
+            h.increment(value).unwrap();
+            if h.get_index(value).unwrap() as u64 >= position {
+                ahead += 1;
+            } else {
+                missed += 1;
+            }
+        }
+        assert_eq!(position, h.buckets_total());
+        assert!(ahead > 0);
+        assert_eq!(seen, total + ahead);
+        assert_eq!(h.count_between(0, u64::MAX), total + ahead + missed);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::hash_map::{self, HashMap};
+use std::hash::Hash;
+
+use super::{Histogram, HistogramConfig, HistogramError};
+
+/// histograms per label, such as a status class, with an overall rollup
+///
+/// Each label's histogram is created with the shared config when first
+/// recorded to. Every sample is also recorded into the rollup as it arrives,
+/// so `overall()` needs no merge to read. At most `max_labels` labels are
+/// kept, and samples of further labels go into the `other()` histogram, so
+/// the memory used stays bounded however many distinct labels are seen.
+///
+/// # Example
+/// ```
+/// # use histogram::LabeledHistogram;
+/// let mut h = LabeledHistogram::new(2).unwrap();
+///
+/// h.increment("2xx", 10).unwrap();
+/// h.increment("5xx", 900).unwrap();
+/// h.increment("4xx", 20).unwrap();
+///
+/// assert_eq!(h.label(&"5xx").unwrap().maximum().unwrap(), 900);
+/// assert_eq!(h.other().entries(), 1);
+/// assert_eq!(h.overall().entries(), 3);
+#[derive(Clone, Debug)]
+pub struct LabeledHistogram<K> {
+    labels: HashMap<K, Histogram>,
+    max_labels: usize,
+    other: Histogram,
+    overall: Histogram,
+}
+
+impl<K: Eq + Hash> LabeledHistogram<K> {
+    /// create a LabeledHistogram of the default config, keeping at most
+    /// max_labels labels
+    pub fn new(max_labels: usize) -> Result<LabeledHistogram<K>, HistogramError> {
+        LabeledHistogram::configured(HistogramConfig::new(), max_labels)
+    }
+
+    /// create a LabeledHistogram whose histograms use the config, failing
+    /// as `Histogram::configured()` if the config is invalid
+    pub fn configured(config: HistogramConfig,
+                      max_labels: usize)
+                      -> Result<LabeledHistogram<K>, HistogramError> {
+        let overall = Histogram::configured(config)?;
+        Ok(LabeledHistogram {
+            labels: HashMap::new(),
+            max_labels,
+            other: overall.clone_empty(),
+            overall,
+        })
+    }
+
+    /// record a single sample for the label
+    pub fn increment(&mut self, label: K, value: u64) -> Result<(), HistogramError> {
+        self.record(label, value, 1)
+    }
+
+    /// record counts of a value for the label, as `Histogram::record()`,
+    /// into the label's histogram, or `other()` once max_labels labels are
+    /// kept, and into the rollup
+    pub fn record(&mut self, label: K, value: u64, count: u64) -> Result<(), HistogramError> {
+        // the rollup shares the config, so gives the same result
+        let _ = self.overall.record(value, count);
+        if !self.labels.contains_key(&label) && self.labels.len() >= self.max_labels {
+            return self.other.record(value, count);
+        }
+        let overall = &self.overall;
+        self.labels
+            .entry(label)
+            .or_insert_with(|| overall.clone_empty())
+            .record(value, count)
+    }
+
+    /// return the histogram of the label, None if nothing was recorded for
+    /// it or it was counted in `other()`
+    pub fn label(&self, label: &K) -> Option<&Histogram> {
+        self.labels.get(label)
+    }
+
+    /// return the histogram of the samples of labels beyond max_labels
+    pub fn other(&self) -> &Histogram {
+        &self.other
+    }
+
+    /// return the histogram of every sample, of all labels and `other()`
+    pub fn overall(&self) -> &Histogram {
+        &self.overall
+    }
+
+    /// return an iterator over the labels and their histograms, in no
+    /// particular order, for export
+    ///
+    /// The samples of `other()` are not included.
+    pub fn iter_labels(&self) -> hash_map::Iter<'_, K, Histogram> {
+        self.labels.iter()
+    }
+
+    /// return the number of labels kept
+    pub fn labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// clear every histogram and forget the labels
+    pub fn clear(&mut self) {
+        self.labels.clear();
+        self.other.clear();
+        self.overall.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+    use super::LabeledHistogram;
+    use super::super::test_rng::Rng;
+
+    // the merge of every label's histogram and other
+    fn merged(h: &LabeledHistogram<u64>) -> Histogram {
+        let mut merged = h.other().clone();
+        for (_, histogram) in h.iter_labels() {
+            merged.merge(histogram);
+        }
+        merged
+    }
+
+    #[test]
+    fn test_rollup() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000);
+        let mut h = LabeledHistogram::configured(c, 4).unwrap();
+        let mut rng = Rng(0x0123_4567_89ab_cdef);
+        for _ in 0..10_000 {
+            let label = rng.next() % 8;
+            let value = rng.next() % (label * 500_000 + 1);
+            let _ = h.increment(label, value);
+        }
+        assert_eq!(h.labels(), 4);
+        assert!(h.other().entries() > 0);
+        assert_eq!(h.overall().entries(), 10_000);
+        assert!(h.overall().missed_small() > 0 && h.overall().missed_large() > 0);
+        assert_eq!(*h.overall(), merged(&h));
+
+        h.clear();
+        assert_eq!(h.labels(), 0);
+        assert_eq!(h.overall().entries(), 0);
+    }
+
+    #[test]
+    fn test_cap() {
+        let mut h = LabeledHistogram::new(3).unwrap();
+        for label in 0..10_u64 {
+            h.record(label, 100 + label, 2).unwrap();
+        }
+
+        // the first labels seen are kept, and the rest share other
+        assert_eq!(h.labels(), 3);
+        for label in 0..3 {
+            assert_eq!(h.label(&label).unwrap().entries(), 2);
+        }
+        assert!(h.label(&3).is_none());
+        assert_eq!(h.other().entries(), 14);
+        assert_eq!(h.other().minimum().unwrap(), 103);
+
+        // kept labels still record once the cap is reached
+        h.increment(1, 50).unwrap();
+        assert_eq!(h.label(&1).unwrap().entries(), 3);
+        assert_eq!(h.labels(), 3);
+        assert_eq!(*h.overall(), merged(&h));
+
+        // a cap of 0 puts everything in other
+        let mut h = LabeledHistogram::new(0).unwrap();
+        h.increment("a", 1).unwrap();
+        assert_eq!((h.labels(), h.other().entries()), (0, 1));
+
+        let mut c = HistogramConfig::new();
+        c.precision(0);
+        assert_eq!(LabeledHistogram::<u64>::configured(c, 1).unwrap_err(),
+                   HistogramError::InvalidPrecision);
+    }
+}
@@ -0,0 +1,209 @@
+//! tracing integration, enabled with the `tracing` feature
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+use tracing::span::{Attributes, Id};
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use super::{Clock, Histogram, HistogramConfig, HistogramError, SystemClock};
+
+// when a measured span was opened, kept in the span extensions
+struct Opened(Instant);
+
+/// a tracing Layer recording the duration of each span, in nanoseconds, into
+/// a Histogram per span name
+///
+/// The layer is a cheap handle to a shared registry, so keep a clone to read
+/// the histograms after installing it in a subscriber.
+///
+/// # Example
+/// ```
+/// # extern crate histogram;
+/// # extern crate tracing;
+/// # extern crate tracing_subscriber;
+/// # use histogram::{HistogramConfig, HistogramLayer};
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let layer = HistogramLayer::new(HistogramConfig::new()).unwrap().target("my_app");
+/// let subscriber = tracing_subscriber::registry().with(layer.clone());
+///
+/// tracing::subscriber::with_default(subscriber, || {
+///     let _span = tracing::info_span!(target: "my_app", "request").entered();
+/// });
+///
+/// assert_eq!(layer.histogram("request").unwrap().entries(), 1);
+#[derive(Clone)]
+pub struct HistogramLayer {
+    empty: Histogram,
+    targets: Vec<String>,
+    names: Vec<String>,
+    clock: Arc<dyn Clock>,
+    histograms: Arc<Mutex<HashMap<&'static str, Histogram>>>,
+}
+
+impl HistogramLayer {
+    /// create a HistogramLayer whose histograms use the config, failing as
+    /// `Histogram::configured()` if the config is invalid
+    pub fn new(config: HistogramConfig) -> Result<HistogramLayer, HistogramError> {
+        HistogramLayer::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// create a HistogramLayer which times spans with the given clock
+    pub fn with_clock(config: HistogramConfig,
+                      clock: Arc<dyn Clock>)
+                      -> Result<HistogramLayer, HistogramError> {
+        Ok(HistogramLayer {
+            empty: Histogram::with_clock(config, clock.clone())?,
+            targets: Vec::new(),
+            names: Vec::new(),
+            clock,
+            histograms: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// only measure spans whose target starts with the prefix, may be given
+    /// more than once
+    pub fn target(mut self, prefix: &str) -> HistogramLayer {
+        self.targets.push(prefix.to_owned());
+        self
+    }
+
+    /// only measure spans with the name, may be given more than once
+    pub fn name(mut self, name: &str) -> HistogramLayer {
+        self.names.push(name.to_owned());
+        self
+    }
+
+    /// return a snapshot of the histogram for the span name, None if no span
+    /// with the name has closed
+    pub fn histogram(&self, name: &str) -> Option<Histogram> {
+        self.lock().get(name).cloned()
+    }
+
+    /// return the names of the spans which have been measured
+    pub fn names(&self) -> Vec<&'static str> {
+        let histograms = self.lock();
+        let mut names: Vec<&'static str> = histograms.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    // a poisoned lock is taken anyway, as a panicking writer leaves the
+    // histograms valid
+    fn lock(&self) -> MutexGuard<'_, HashMap<&'static str, Histogram>> {
+        match self.histograms.lock() {
+            Ok(histograms) => histograms,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn matches(&self, metadata: &Metadata) -> bool {
+        (self.targets.is_empty() ||
+         self.targets.iter().any(|prefix| metadata.target().starts_with(prefix.as_str()))) &&
+        (self.names.is_empty() || self.names.iter().any(|name| name == metadata.name()))
+    }
+}
+
+impl<S> Layer<S> for HistogramLayer
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    fn on_new_span(&self, attrs: &Attributes, id: &Id, ctx: Context<S>) {
+        if !self.matches(attrs.metadata()) {
+            return;
+        }
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(Opened(self.clock.now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let opened = match span.extensions().get::<Opened>() {
+            Some(opened) => opened.0,
+            None => return,
+        };
+
+        let now = self.clock.now();
+        let nanoseconds = if now > opened {
+            (now - opened).as_nanos().min(u64::MAX as u128) as u64
+        } else {
+            0
+        };
+
+        let mut histograms = self.lock();
+        let histogram = histograms.entry(span.name()).or_insert_with(|| self.empty.clone_empty());
+        let _ = histogram.increment(nanoseconds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::registry;
+
+    use super::HistogramLayer;
+    use super::super::{HistogramConfig, ManualClock};
+
+    #[test]
+    fn test_sleeps() {
+        let layer = HistogramLayer::new(HistogramConfig::new()).unwrap().target("histogram");
+        let subscriber = registry().with(layer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..3 {
+                let _span = tracing::info_span!("sleep").entered();
+                thread::sleep(Duration::from_millis(5));
+            }
+            let _span = tracing::info_span!(target: "other", "sleep").entered();
+        });
+
+        let h = layer.histogram("sleep").unwrap();
+        assert_eq!(h.entries(), 3);
+        assert!(h.minimum().unwrap() >= 4_990_000, "{}", h.minimum().unwrap());
+        assert!(h.maximum().unwrap() < 60_000_000_000);
+        assert_eq!(layer.names(), vec!["sleep"]);
+    }
+
+    #[test]
+    fn test_names() {
+        let clock = Arc::new(ManualClock::new());
+        let layer = HistogramLayer::with_clock(HistogramConfig::new(), clock.clone())
+                        .unwrap()
+                        .name("outer")
+                        .name("inner");
+        let subscriber = registry().with(layer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = tracing::info_span!("outer").entered();
+            for _ in 0..4 {
+                let _inner = tracing::info_span!("inner").entered();
+                let _ignored = tracing::info_span!("ignored").entered();
+                clock.advance(Duration::from_micros(250));
+            }
+            clock.advance(Duration::from_millis(9));
+            outer.exit();
+        });
+
+        let inner = layer.histogram("inner").unwrap();
+        assert_eq!(inner.entries(), 4);
+        assert_eq!(inner.get(250_000), Some(4));
+
+        let outer = layer.histogram("outer").unwrap();
+        assert_eq!(outer.entries(), 1);
+        assert_eq!(outer.get(10_000_000), Some(1));
+
+        assert!(layer.histogram("ignored").is_none());
+        assert_eq!(layer.names(), vec!["inner", "outer"]);
+    }
+}
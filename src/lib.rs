@@ -11,6 +11,22 @@
 //! # Future work
 //! * unknown
 //!
+//! # Bucket stability
+//!
+//! The mapping between values and bucket indices uses only integer
+//! arithmetic, so a config assigns every value to the same bucket on every
+//! target. The assignment is part of the stable interface: it will not change
+//! without a major version bump, so encoded histograms and bucket deltas can
+//! be merged across platforms and crate versions.
+//!
+//! # Panics
+//!
+//! No public API panics for any input, apart from the assertions of the
+//! `testing` module. Buckets are only accessed with checked indexing, which
+//! the crate enforces with the `clippy::indexing_slicing` lint, and counts
+//! saturate rather than overflow. With the `fuzz` feature, `fuzz_target()`
+//! drives the API with arbitrary operations to check this.
+//!
 //! # Usage
 //!
 //! Create a new histogram, call increment for every value, retrieve percentile
@@ -53,15 +69,173 @@
 
 #![crate_name = "histogram"]
 
+// no public API may panic, so buckets are read with checked indexing
+#![cfg_attr(not(test), deny(clippy::indexing_slicing))]
+
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+#[cfg(feature = "arrow")]
+extern crate arrow_array;
+#[cfg(feature = "arrow")]
+extern crate arrow_schema;
+#[cfg(test)]
+extern crate flate2;
+#[cfg(feature = "python")]
+extern crate numpy;
+#[cfg(feature = "proptest")]
+extern crate proptest;
+#[cfg(feature = "python")]
+extern crate pyo3;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "tracing")]
+extern crate tracing_subscriber;
+
+use std::convert::TryFrom;
 use std::fmt;
+use std::iter::{repeat, FromIterator};
 use std::mem;
+use std::ops;
+use std::ptr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use schedule::Schedule;
+
+#[cfg(feature = "arrow")]
+mod arrow;
+mod atomic;
+mod bands;
+mod buckets;
+mod builder;
+mod burn;
+mod calibrate;
+mod clock;
+mod csv;
+mod encoding;
+mod error;
+mod fingerprint;
+mod frozen;
+mod interarrival;
+#[cfg(any(feature = "arbitrary", feature = "proptest", feature = "fuzz"))]
+pub mod fuzzing;
+mod iter;
+mod labeled;
+#[cfg(feature = "tracing")]
+mod layer;
+mod limits;
+mod merged;
+mod outcome;
+mod peaks;
+mod prometheus;
+#[cfg(feature = "python")]
+pub mod python;
+mod raw;
+mod report;
+mod resolution;
+#[cfg(feature = "tokio")]
+mod reporter;
+mod save;
+mod scale;
+mod schedule;
+#[cfg(feature = "serde")]
+mod serialization;
+mod shadow;
+mod shared;
+mod significance;
+mod sketch;
+mod stats;
+mod summary;
+#[cfg(test)]
+mod test_rng;
+pub mod testing;
+mod trend;
+mod weighted;
+mod windowed;
+
+pub use atomic::AtomicHistogram;
+pub use bands::Band;
+pub use builder::HistogramBuilder;
+pub use burn::BurnRate;
+pub use calibrate::{CalibrationPoint, CalibrationReport};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use csv::ImportReport;
+pub use error::HistogramError;
+pub use frozen::FrozenHistogram;
+#[cfg(feature = "fuzz")]
+pub use fuzzing::{fuzz_target, Op};
+pub use interarrival::InterarrivalRecorder;
+pub use iter::{BucketCursor, Cumulative, HistogramIter, IntoIter, LinearIter, LogIter, QuantileIter,
+               QuantileStep, ValueRange};
+pub use labeled::LabeledHistogram;
+#[cfg(feature = "tracing")]
+pub use layer::HistogramLayer;
+pub use limits::DeserializeLimits;
+pub use merged::{MergedView, SpreadReport};
+pub use outcome::OutcomeHistogram;
+pub use report::{ReportBuilder, ReportStyle};
+pub use resolution::Resolution;
+#[cfg(feature = "tokio")]
+pub use reporter::{Reporter, ReporterHandle};
+pub use save::SaveOptions;
+pub use shadow::{Discrepancy, ShadowHistogram};
+pub use shared::SharedHistogram;
+pub use significance::{DeltaAssessment, DeltaVerdict};
+pub use sketch::QuantileSketch;
+pub use stats::HistogramStats;
+pub use summary::Summary;
+pub use trend::PercentileTrend;
+pub use weighted::WeightedHistogram;
+pub use windowed::{WindowedBuilder, WindowedHistogram};
+
+/// what to do with values larger than the configured max_value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OverflowPolicy {
+    /// count the value as missed_large and return an error
+    Reject,
+    /// store the value in the bucket holding max_value
+    Clamp,
+}
 
-#[derive(Clone, Copy)]
+/// how the value reported for a percentile is chosen within its bucket
+///
+/// Each method first finds the rank of the percentile, the sample at or
+/// below which the percentile of all samples lie, and the bucket holding it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PercentileMethod {
+    /// the nominal value of the bucket, its lowest value
+    NearestRank,
+    /// the middle of the bucket, rounded down
+    BucketMidpoint,
+    /// a value interpolated across the bucket, assuming its samples are
+    /// spread evenly from lowest to highest value: the rank being the k-th
+    /// of n samples in the bucket, the value (2k - 1) / 2n of the way from
+    /// the lowest to the highest, rounded down
+    LinearInterpolation,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct HistogramConfig {
     precision: u32,
     max_memory: u32,
     max_value: u64,
+    min_value: u64,
     radix: u32,
+    overflow: OverflowPolicy,
+    prefault: bool,
+    percentile_method: PercentileMethod,
+    track_peaks: bool,
+    scale: f64,
+    rank_missed: bool,
 }
 
 impl Default for HistogramConfig {
@@ -70,7 +244,14 @@ impl Default for HistogramConfig {
             precision: 3,
             max_memory: 0,
             max_value: 60_000_000_000,
+            min_value: 1,
             radix: 10,
+            overflow: OverflowPolicy::Reject,
+            prefault: false,
+            percentile_method: PercentileMethod::NearestRank,
+            track_peaks: false,
+            scale: 1.0,
+            rank_missed: false,
         }
     }
 }
@@ -100,8 +281,36 @@ impl HistogramConfig {
         self
     }
 
+    /// set HistogramConfig radix, the base in which precision is counted
+    ///
+    /// Each power of two above the linear buckets is split into radix to the
+    /// power of precision buckets, so the default radix of 10 gives precision
+    /// in significant decimal figures, while radix 2 gives it in bits, for
+    /// coarser and smaller histograms. The radix must be at least 2, and the
+    /// radix to the power of precision must fit in a u32.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig,HistogramError};
+    ///
+    /// let mut c = HistogramConfig::new();
+    /// c.radix(2).precision(4); // 16 buckets per power of two
+    ///
+    /// let h = Histogram::configured(c).unwrap();
+    /// assert_eq!(h.bucket_bounds(1_000).unwrap(), (992, 1_023));
+    ///
+    /// c.radix(1);
+    /// assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::InvalidRadix);
+    pub fn radix(&mut self, radix: u32) -> &mut Self {
+        self.radix = radix;
+        self
+    }
+
     /// set HistogramConfig memory limit
     ///
+    /// The limit applies to the bucket counts, allocated as 8 bytes for each
+    /// bucket, so 1MB allows at most 131,072 buckets.
+    ///
     /// # Example
     /// ```
     /// # use histogram::{Histogram,HistogramConfig};
@@ -125,953 +334,6963 @@ impl HistogramConfig {
         self.max_value = max;
         self
     }
-}
-
-#[derive(Clone, Copy)]
-pub struct HistogramCounters {
-    entries_total: u64,
-    missed_unknown: u64,
-    missed_small: u64,
-    missed_large: u64,
-}
-
-impl Default for HistogramCounters {
-    fn default() -> HistogramCounters {
-        HistogramCounters {
-            entries_total: 0,
-            missed_unknown: 0,
-            missed_small: 0,
-            missed_large: 0,
-        }
-    }
-}
-
-impl HistogramCounters {
-    fn new() -> HistogramCounters {
-        Default::default()
-    }
-
-    fn clear(&mut self) -> &mut Self {
-        self.entries_total = 0;
-        self.missed_unknown = 0;
-        self.missed_small = 0;
-        self.missed_large = 0;
-        self
-    }
-}
-
-#[derive(Clone)]
-pub struct HistogramData {
-    data: Vec<u64>,
-    counters: HistogramCounters,
-    iterator: usize,
-}
-
-#[derive(Clone, Copy)]
-pub struct HistogramProperties {
-    buckets_inner: u32,
-    buckets_outer: u32,
-    buckets_total: u32,
-    memory_used: u32,
-    linear_max: u64,
-    linear_power: u32,
-}
-
-#[derive(Clone)]
-pub struct Histogram {
-    config: HistogramConfig,
-    data: HistogramData,
-    properties: HistogramProperties,
-}
-
-#[derive(Clone, Copy)]
-pub struct HistogramBucket {
-    value: u64,
-    count: u64,
-    id: u64,
-}
 
-impl HistogramBucket {
-    /// return the sample value for the bucket
+    /// set HistogramConfig lowest stored value
+    ///
+    /// The buckets start at min_value rather than 1, so a histogram of
+    /// values known to be large, such as latencies of at least a few
+    /// microseconds in nanoseconds, does not allocate buckets it will never
+    /// use. Values below min_value are counted as too small. A min_value of
+    /// 0 is taken as 1.
     ///
     /// # Example
     /// ```
-    /// use histogram::*;
+    /// # use histogram::{Histogram,HistogramConfig,HistogramError};
     ///
-    /// let mut h = Histogram::new().unwrap();
-    /// let b = h.next().unwrap();
-    ///
-    /// assert_eq!(b.value(), 1);
-    pub fn value(self) -> u64 {
-        self.value
-    }
-
-    /// return the sample counts for the bucket
+    /// let mut c = HistogramConfig::new();
+    /// c.min_value(4096); // values below 4096 will not be stored
     ///
-    /// # Example
-    /// ```
-    /// use histogram::*;
+    /// let mut h = Histogram::configured(c).unwrap();
     ///
-    /// let mut h = Histogram::new().unwrap();
-    /// let b = h.next().unwrap();
+    /// assert!(h.buckets_total() < Histogram::new().unwrap().buckets_total());
+    /// assert_eq!(h.increment(100), Err(HistogramError::TooSmall));
+    /// h.increment(8_192).unwrap();
     ///
-    /// assert_eq!(b.count(), 0);
-    pub fn count(self) -> u64 {
-        self.count
+    /// assert_eq!(h.percentile(100.0).unwrap(), 8_192);
+    pub fn min_value(&mut self, min: u64) -> &mut Self {
+        self.min_value = min.max(1);
+        self
     }
 
-    /// return the bucket id
+    /// set HistogramConfig handling of values above max_value
     ///
     /// # Example
     /// ```
-    /// use histogram::*;
+    /// # use histogram::{Histogram,HistogramConfig,OverflowPolicy};
     ///
-    /// let mut h = Histogram::new().unwrap();
-    /// let b = h.next().unwrap();
-    ///
-    /// assert_eq!(b.id(), 0);
-    pub fn id(self) -> u64 {
-        self.id
-    }
-}
-
-impl Iterator for Histogram {
-    type Item = HistogramBucket;
-
-    fn next(&mut self) -> Option<HistogramBucket> {
-        let current = self.data.iterator;
-        self.data.iterator += 1;
-
-        if current == (self.properties.buckets_total as usize) {
-            self.data.iterator = 0;
-            None
-        } else {
-            Some(HistogramBucket {
-                id: current as u64,
-                value: self.index_value(current),
-                count: self.data.data[current],
-            })
-        }
-    }
-}
-
-impl fmt::Debug for Histogram {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({} total)", self.data.counters.entries_total)
+    /// let mut c = HistogramConfig::new();
+    /// c.overflow(OverflowPolicy::Clamp); // store large values as max_value
+    pub fn overflow(&mut self, policy: OverflowPolicy) -> &mut Self {
+        self.overflow = policy;
+        self
     }
-}
 
-impl Histogram {
-    /// create a new Histogram
+    /// set HistogramConfig to touch every page of the buckets at creation
+    ///
+    /// This moves the page faults for a large histogram to construction time
+    /// instead of the first samples recorded into each page.
     ///
     /// # Example
     /// ```
     /// # use histogram::{Histogram,HistogramConfig};
     ///
-    /// let mut h = Histogram::new().unwrap();
-    pub fn new() -> Option<Histogram> {
-        let config = HistogramConfig::new();
-        Histogram::configured(config)
+    /// let mut c = HistogramConfig::new();
+    /// c.prefault(true);
+    pub fn prefault(&mut self, enabled: bool) -> &mut Self {
+        self.prefault = enabled;
+        self
     }
 
-    /// create a new Histogram
+    /// set HistogramConfig method of reporting a percentile within its bucket
+    ///
+    /// This applies to every percentile query, including the minimum and
+    /// maximum when not known exactly, summaries, and frozen or merged views. It is not kept by the
+    /// encodings, so a decoded Histogram uses the default of NearestRank.
     ///
     /// # Example
     /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
+    /// # use histogram::{Histogram,HistogramConfig,PercentileMethod};
     ///
     /// let mut c = HistogramConfig::new();
+    /// c.max_value(10_000).precision(2);
+    /// c.percentile_method(PercentileMethod::BucketMidpoint);
     ///
     /// let mut h = Histogram::configured(c).unwrap();
-    pub fn configured(config: HistogramConfig) -> Option<Histogram> {
-
-        let buckets_inner: u32 = config.radix.pow(config.precision);
-        let linear_power: u32 = 32 - buckets_inner.leading_zeros();
-        let linear_max: u64 = 2.0_f64.powi(linear_power as i32) as u64 - 1;
-        let max_value_power: u32 = 64 - config.max_value.leading_zeros();
-
-        let mut buckets_outer = 0;
-
-        if max_value_power > linear_power {
-            buckets_outer = max_value_power - linear_power;
-        }
-
-        let buckets_total = buckets_inner * buckets_outer + linear_max as u32;
-        let memory_used = buckets_total * mem::size_of::<HistogramBucket>() as u32;
-
-        if config.max_memory > 0 && config.max_memory < memory_used {
-            return None;
-        }
-
-        let data = vec![0; buckets_total as usize];
-
-        let counters = HistogramCounters::new();
-
-        Some(Histogram {
-            config: config,
-            data: HistogramData {
-                data: data,
-                counters: counters,
-                iterator: 0,
-            },
-            properties: HistogramProperties {
-                buckets_inner: buckets_inner,
-                buckets_outer: buckets_outer,
-                buckets_total: buckets_total,
-                memory_used: memory_used,
-                linear_max: linear_max,
-                linear_power: linear_power,
-            },
-        })
+    /// h.increment(1_500).unwrap();
+    ///
+    /// assert_eq!(h.percentile_bounds(50.0).unwrap(), (1_496, 1_505));
+    /// assert_eq!(h.percentile(50.0).unwrap(), 1_500);
+    pub fn percentile_method(&mut self, method: PercentileMethod) -> &mut Self {
+        self.percentile_method = method;
+        self
     }
 
-    /// clear the histogram data
+    /// set HistogramConfig to record when each group of buckets last had a
+    /// sample recorded, see `Histogram::peak_times()`
+    ///
+    /// This keeps one timestamp for each power of two of the range. Like the
+    /// percentile method, it is not kept by the encodings.
     ///
     /// # Example
     /// ```
     /// # use histogram::{Histogram,HistogramConfig};
     ///
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// h.increment(1);
-    /// assert_eq!(h.entries(), 1);
-    /// h.clear().unwrap();
-    /// assert_eq!(h.entries(), 0);
-    pub fn clear(&mut self) -> Result<(), &'static str> {
-        // clear everything manually, weird results in practice?
-        self.data.counters.clear();
-
-        for i in 0..self.data.data.len() {
-            self.data.data[i] = 0;
-        }
-
-        Ok(())
+    /// let mut c = HistogramConfig::new();
+    /// c.track_peaks(true);
+    pub fn track_peaks(&mut self, enabled: bool) -> &mut Self {
+        self.track_peaks = enabled;
+        self
     }
 
-    /// increment the count for a value
+    /// set HistogramConfig factor applied to f64 values, see
+    /// `Histogram::record_f64()`
+    ///
+    /// Each f64 value is multiplied by the scale to give the integer value
+    /// stored, and results are divided by it, so a scale of 1000.0 stores
+    /// milliseconds as microseconds. The scale must be finite and above 0.
+    /// It is kept by the encodings, and histograms only merge or compare
+    /// equal with the same scale, as their counts stand for other values.
     ///
     /// # Example
     /// ```
     /// # use histogram::{Histogram,HistogramConfig};
     ///
-    /// let mut h = Histogram::new().unwrap();
+    /// let mut c = HistogramConfig::new();
+    /// c.scale(1_000.0);
     ///
-    /// h.increment(1);
-    /// assert_eq!(h.get(1).unwrap(), 1);
-    pub fn increment(&mut self, value: u64) -> Result<(), &'static str> {
-        self.record(value, 1_u64)
+    /// let mut h = Histogram::configured(c).unwrap();
+    /// h.record_f64(0.25, 1).unwrap();
+    ///
+    /// assert_eq!(h.percentile(50.0).unwrap(), 250);
+    /// assert_eq!(h.percentile_f64(50.0).unwrap(), 0.25);
+    pub fn scale(&mut self, scale: f64) -> &mut Self {
+        self.scale = scale;
+        self
     }
 
-    /// record additional counts for value
+    /// set HistogramConfig to rank percentiles among every sample recorded,
+    /// including those missed as out of range, rather than only the stored
+    /// samples
+    ///
+    /// By default the missed samples are left out, so percentiles, the
+    /// minimum and the maximum describe the data actually stored. Ranking
+    /// them instead keeps the percentiles true to everything recorded: a
+    /// rank among the samples missed as too small is `Underflow`, one among
+    /// those too large `Overflow`, and the minimum and maximum fail the
+    /// same way once any sample was missed at that end. Like the percentile
+    /// method, it is not kept by the encodings.
     ///
     /// # Example
     /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
+    /// # use histogram::{Histogram,HistogramConfig,HistogramError};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000);
     ///
-    /// let mut h = Histogram::new().unwrap();
+    /// let mut h = Histogram::configured(c).unwrap();
+    /// h.record(10, 9).unwrap();
+    /// h.record(5_000, 1).unwrap_err();
     ///
-    /// h.record(1, 1);
-    /// assert_eq!(h.get(1).unwrap(), 1);
+    /// assert_eq!(h.percentile(100.0).unwrap(), 10);
     ///
-    /// h.record(2, 2);
-    /// assert_eq!(h.get(2).unwrap(), 2);
+    /// c.rank_missed(true);
     ///
-    /// h.record(10, 10);
-    /// assert_eq!(h.get(10).unwrap(), 10);
-    pub fn record(&mut self, value: u64, count: u64) -> Result<(), &'static str> {
-        self.data.counters.entries_total = self.data.counters.entries_total.saturating_add(count);
-        if value < 1 {
-            self.data.counters.missed_small = self.data.counters.missed_small.saturating_add(count);
-            Err("sample value too small")
-        } else if value > self.config.max_value {
-            self.data.counters.missed_large = self.data.counters.missed_large.saturating_add(count);
-            Err("sample value too large")
-        } else {
-            match self.get_index(value) {
-                Some(index) => {
-                    self.data.data[index] = self.data.data[index].saturating_add(count);
-                    Ok(())
-                }
-                None => {
-                    self.data.counters.missed_unknown = self.data
-                                                            .counters
-                                                            .missed_unknown
-                                                            .saturating_add(count);
-                    Err("sample unknown error")
-                }
-            }
-        }
+    /// let mut h = Histogram::configured(c).unwrap();
+    /// h.record(10, 9).unwrap();
+    /// h.record(5_000, 1).unwrap_err();
+    ///
+    /// assert_eq!(h.percentile(100.0), Err(HistogramError::Overflow));
+    pub fn rank_missed(&mut self, enabled: bool) -> &mut Self {
+        self.rank_missed = enabled;
+        self
     }
 
-    /// get the count for a value
+    /// return the precision
     ///
     /// # Example
     /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
-    ///
-    /// let mut h = Histogram::new().unwrap();
+    /// # use histogram::HistogramConfig;
+    /// let mut c = HistogramConfig::new();
+    /// c.precision(4);
     ///
-    /// assert_eq!(h.get(1).unwrap(), 0);
-    pub fn get(&self, value: u64) -> Option<u64> {
-        match self.get_index(value) {
-            Some(index) => Some(self.data.data[index]),
-            None => None,
-        }
+    /// assert_eq!(c.get_precision(), 4);
+    pub fn get_precision(&self) -> u32 {
+        self.precision
     }
 
-    // calculate the index for a given value
-    fn get_index(&self, value: u64) -> Option<usize> {
-        let result: Option<usize> = None;
-
-        if value >= 1 {
-
-            if value <= self.properties.linear_max {
-                return Some((value - 1) as usize);
-            }
-
-            let l_max = self.properties.linear_max as u32;
+    /// return the radix
+    pub fn get_radix(&self) -> u32 {
+        self.radix
+    }
 
-            let outer = 63 - value.leading_zeros();
+    /// return the memory limit in bytes, 0 for none
+    pub fn get_max_memory(&self) -> u32 {
+        self.max_memory
+    }
 
-            let l_power = 64 - self.properties.linear_max.leading_zeros();
+    /// return the max_value
+    pub fn get_max_value(&self) -> u64 {
+        self.max_value
+    }
 
-            let remain = value as f64 - 2.0_f64.powi(outer as i32);
+    /// return the min_value
+    pub fn get_min_value(&self) -> u64 {
+        self.min_value
+    }
 
-            let inner = (self.properties.buckets_inner as f64 * remain as f64 /
-                         2.0_f64.powi((outer) as i32))
-                            .floor() as u32;
+    /// return the overflow policy
+    pub fn get_overflow(&self) -> OverflowPolicy {
+        self.overflow
+    }
 
-            // this gives the shifted outer index
-            let outer = outer as u32 - l_power;
+    /// return true if the buckets are prefaulted
+    pub fn get_prefault(&self) -> bool {
+        self.prefault
+    }
 
-            let index = l_max + self.properties.buckets_inner * outer + inner;
+    /// return the percentile method
+    pub fn get_percentile_method(&self) -> PercentileMethod {
+        self.percentile_method
+    }
 
-            return Some(index as usize);
-        }
-        result
+    /// return true if peaks are tracked
+    pub fn get_track_peaks(&self) -> bool {
+        self.track_peaks
     }
 
-    // calculate the nominal value of the given index
-    fn index_value(&self, index: usize) -> u64 {
+    /// return the scale of f64 values
+    pub fn get_scale(&self) -> f64 {
+        self.scale
+    }
 
-        // in this case, the index is linear
-        let index = index as u32;
+    /// return true if missed samples are ranked by percentiles
+    pub fn get_rank_missed(&self) -> bool {
+        self.rank_missed
+    }
 
-        let linear_max = self.properties.linear_max as u32;
+    // true if both configs produce the same bucket layout, with the same
+    // values standing for the f64 values recorded
+    fn matches(&self, other: &HistogramConfig) -> bool {
+        self.precision == other.precision && self.max_value == other.max_value &&
+        self.min_value == other.min_value && self.radix == other.radix &&
+        self.scale == other.scale
+    }
+}
 
-        if index < linear_max {
-            return (index + 1) as u64;
-        }
+/// the number of samples recorded and missed by a Histogram
+///
+/// Returned by `Histogram::counters()`, these can be sent on their own as a
+/// small heartbeat message, see `HistogramCounters::to_bytes()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistogramCounters {
+    entries_total: u64,
+    missed_unknown: u64,
+    missed_small: u64,
+    missed_large: u64,
+}
 
-        let log_index = index - linear_max;
+impl HistogramCounters {
+    fn new() -> HistogramCounters {
+        Default::default()
+    }
 
-        let outer = (log_index as f64 / self.properties.buckets_inner as f64).floor() as u32;
+    /// return the number of samples recorded, including missed samples
+    pub fn entries(&self) -> u64 {
+        self.entries_total
+    }
 
-        let inner = log_index - outer * self.properties.buckets_inner as u32;
+    /// return the number of samples below the smallest storable value
+    pub fn missed_small(&self) -> u64 {
+        self.missed_small
+    }
 
-        let mut value = 2.0_f64.powi((outer as u32 + self.properties.linear_power) as i32);
-        value += inner as f64 * (value as f64 / self.properties.buckets_inner as f64);
+    /// return the number of samples above max_value which were rejected
+    pub fn missed_large(&self) -> u64 {
+        self.missed_large
+    }
 
-        value.ceil() as u64
+    /// return the number of samples which could not be indexed
+    pub fn missed_unknown(&self) -> u64 {
+        self.missed_unknown
     }
 
-    /// return the value for the given percentile
+    /// return the growth of each counter since an earlier reading, as a delta
+    /// for `Histogram::apply_counter_delta()`
     ///
     /// # Example
     /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
+    /// # use histogram::Histogram;
     /// let mut h = Histogram::new().unwrap();
     ///
-    /// for value in 1..1000 {
-    ///     h.increment(value).unwrap();
-    /// }
+    /// h.increment(1).unwrap();
+    /// let earlier = h.counters();
+    /// h.increment(0).unwrap_err();
     ///
-    /// assert_eq!(h.percentile(50.0).unwrap(), 501);
-    /// assert_eq!(h.percentile(90.0).unwrap(), 901);
-    /// assert_eq!(h.percentile(99.0).unwrap(), 991);
-    /// assert_eq!(h.percentile(99.9).unwrap(), 999);
-    pub fn percentile(&self, percentile: f64) -> Result<u64, &'static str> {
+    /// let delta = h.counters().since(&earlier);
+    ///
+    /// assert_eq!(delta.entries(), 1);
+    /// assert_eq!(delta.missed_small(), 1);
+    pub fn since(&self, earlier: &HistogramCounters) -> HistogramCounters {
+        HistogramCounters {
+            entries_total: self.entries_total.saturating_sub(earlier.entries_total),
+            missed_unknown: self.missed_unknown.saturating_sub(earlier.missed_unknown),
+            missed_small: self.missed_small.saturating_sub(earlier.missed_small),
+            missed_large: self.missed_large.saturating_sub(earlier.missed_large),
+        }
+    }
+
+    // add each counter of the other, saturating
+    fn add(&mut self, other: &HistogramCounters) {
+        self.entries_total = self.entries_total.saturating_add(other.entries_total);
+        self.missed_unknown = self.missed_unknown.saturating_add(other.missed_unknown);
+        self.missed_small = self.missed_small.saturating_add(other.missed_small);
+        self.missed_large = self.missed_large.saturating_add(other.missed_large);
+    }
+
+    fn clear(&mut self) -> &mut Self {
+        self.entries_total = 0;
+        self.missed_unknown = 0;
+        self.missed_small = 0;
+        self.missed_large = 0;
+        self
+    }
+
+    // the number of samples not stored in the buckets
+    fn missed(&self) -> u64 {
+        self.missed_small.saturating_add(self.missed_large).saturating_add(self.missed_unknown)
+    }
+
+    // the counters percentiles are ranked among: every sample when missed
+    // samples are ranked, otherwise only the stored samples, unless none
+    // were stored so the error still tells which were missed
+    fn ranked(&self, missed: bool) -> HistogramCounters {
+        let stored = self.entries_total.saturating_sub(self.missed());
+        if missed || stored == 0 {
+            return *self;
+        }
+        HistogramCounters {
+            entries_total: stored,
+            ..HistogramCounters::new()
+        }
+    }
+
+    // error unless some recorded samples landed in the buckets
+    fn check_stored(&self) -> Result<(), HistogramError> {
+        if self.entries_total < 1 {
+            return Err(HistogramError::Empty);
+        }
+
+        if self.missed() >= self.entries_total {
+            return Err(HistogramError::OnlyMissedSamples {
+                small: self.missed_small,
+                large: self.missed_large,
+                unknown: self.missed_unknown,
+            });
+        }
+        Ok(())
+    }
+}
+
+// the counts are shared between clones until the first mutation, which makes
+// cloning cheap when most clones are only read
+#[derive(Clone)]
+pub struct HistogramData {
+    // empty once released by clear_shrink(), until the next write
+    data: Arc<Vec<u64>>,
+    counters: HistogramCounters,
+    // when the histogram was created or last cleared
+    started: Instant,
+    // the most recent successfully recorded value and when it was recorded
+    last: Option<(u64, Instant)>,
+    // the lowest and highest non-empty buckets, None when all are empty, so
+    // scans can skip the empty ends
+    occupied: Option<(usize, usize)>,
+    // when each group of buckets last had a sample recorded, empty unless
+    // the config tracks peaks
+    peaks: Box<[Option<Instant>]>,
+    // the summary last computed by cached_summary() and when, dropped by any
+    // change to the counts
+    summary: Option<Box<(Summary, Instant)>>,
+    // the running totals of the counts, built by the first percentile query
+    // after a change to the counts and dropped by the next
+    totals: OnceLock<RunningTotals>,
+    // the exact aggregates of the stored samples, None once a change loses
+    // them, and ignored unless they count every stored sample, as after
+    // decoding
+    stats: Option<HistogramStats>,
+}
+
+impl HistogramData {
+    // drop state derived from the counts, before they change
+    fn invalidate(&mut self) {
+        self.summary = None;
+        self.totals = OnceLock::new();
+    }
+
+    // prepare to write the counts, allocating the buckets if released
+    fn modify(&mut self, buckets: u64) {
+        self.invalidate();
+        if (self.data.len() as u64) < buckets {
+            Arc::make_mut(&mut self.data).resize(buckets as usize, 0);
+        }
+    }
+
+    // the running totals of the counts, built if dropped since last used
+    fn totals(&self) -> &RunningTotals {
+        self.totals.get_or_init(|| RunningTotals::new(self.occupied, |index| self.count(index)))
+    }
+
+    // widen the occupied range to include the bucket
+    fn occupy(&mut self, index: usize) {
+        self.occupied = match self.occupied {
+            Some((low, high)) => Some((low.min(index), high.max(index))),
+            None => Some((index, index)),
+        };
+    }
+
+    // the count of a bucket, 0 for an index past the buckets or released
+    // buckets
+    fn count(&self, index: usize) -> u64 {
+        self.data.get(index).copied().unwrap_or(0)
+    }
+
+    // set the count of a bucket, ignoring an index past the buckets
+    fn set_count(&mut self, index: usize, count: u64) {
+        if let Some(bucket) = Arc::make_mut(&mut self.data).get_mut(index) {
+            *bucket = count;
+        }
+    }
+
+    // find the occupied range by scanning, after the counts were replaced
+    fn find_occupied(&mut self) {
+        let data = &self.data;
+        self.occupied = data.iter().position(|count| *count > 0).map(|low| {
+            (low, data.iter().rposition(|count| *count > 0).unwrap_or(low))
+        });
+    }
+}
+
+// the most buckets a layout may have, 2 GiB of counts: past any precision
+// worth having, and short of a config which exhausts memory by mistake
+const MAX_BUCKETS: u64 = 1 << 28;
+
+#[derive(Clone, Copy)]
+pub struct HistogramProperties {
+    buckets_inner: u64,
+    buckets_outer: u64,
+    buckets_total: u64,
+    memory_used: u64,
+    linear_max: u64,
+    linear_power: u32,
+    // the lowest stored value, and the index it has in a layout starting at
+    // 1, which is the number of buckets left out below it
+    min_value: u64,
+    offset: u64,
+}
+
+impl HistogramProperties {
+    // derive the bucket layout for a config, failing if too large to
+    // represent, over the most buckets allowed, or over the memory limit
+    fn new(config: &HistogramConfig) -> Result<HistogramProperties, HistogramError> {
+        if config.radix < 2 {
+            return Err(HistogramError::InvalidRadix);
+        }
+        if config.max_value < 1 {
+            return Err(HistogramError::InvalidMaxValue);
+        }
+        let buckets_inner = match config.radix.checked_pow(config.precision) {
+            Some(inner) if config.precision >= 1 => inner as u64,
+            _ => return Err(HistogramError::InvalidPrecision),
+        };
+        let linear_power: u32 = 64 - buckets_inner.leading_zeros();
+        let linear_max: u64 = (1 << linear_power) - 1;
+        let max_value_power: u32 = 64 - config.max_value.leading_zeros();
+
+        let mut buckets_outer = 0;
+
+        if max_value_power > linear_power {
+            buckets_outer = (max_value_power - linear_power) as u64;
+        }
+
+        // an untrusted config, as when decoding, must not overflow the layout
+        let buckets_total = buckets_inner.checked_mul(buckets_outer)
+                                         .and_then(|buckets| buckets.checked_add(linear_max))
+                                         .unwrap_or(u64::MAX);
+
+        let min_value = config.min_value.max(1);
+        if min_value > 1 && min_value > config.max_value {
+            return Err(HistogramError::InvalidConfig);
+        }
+        if !(config.scale.is_finite() && config.scale > 0.0) {
+            return Err(HistogramError::InvalidConfig);
+        }
+        let mut properties = HistogramProperties {
+            buckets_inner,
+            buckets_outer,
+            buckets_total,
+            memory_used: 0,
+            linear_max,
+            linear_power,
+            min_value,
+            offset: 0,
+        };
+        // leave out the buckets below min_value
+        let offset = properties.raw_index(min_value).ok_or(HistogramError::InvalidConfig)? as u64;
+        let buckets_total = buckets_total.checked_sub(offset).ok_or(HistogramError::InvalidConfig)?;
+        if buckets_total > MAX_BUCKETS {
+            return Err(HistogramError::TooManyBuckets {
+                buckets: buckets_total,
+                limit: MAX_BUCKETS,
+            });
+        }
+        // the counts are allocated as one u64 for each bucket
+        let memory_used = buckets_total * mem::size_of::<u64>() as u64;
+
+        if config.max_memory > 0 && (config.max_memory as u64) < memory_used {
+            return Err(HistogramError::MemoryLimitExceeded);
+        }
+
+        properties.buckets_total = buckets_total;
+        properties.memory_used = memory_used;
+        properties.offset = offset;
+        Ok(properties)
+    }
+
+    // calculate the index for a given value, with the buckets below
+    // min_value left out
+    fn get_index(&self, value: u64) -> Option<usize> {
+        if value < self.min_value {
+            return None;
+        }
+        self.raw_index(value).map(|index| index - self.offset as usize)
+    }
+
+    // calculate the lowest value which maps to the given index
+    fn index_low(&self, index: usize) -> u64 {
+        self.raw_low(index + self.offset as usize).max(self.min_value)
+    }
+
+    // calculate the nominal value of the given index
+    fn index_value(&self, index: usize) -> u64 {
+        self.raw_value(index + self.offset as usize).max(self.min_value)
+    }
+
+    // calculate the index for a given value in the layout starting at 1
+    fn raw_index(&self, value: u64) -> Option<usize> {
+        let result: Option<usize> = None;
+
+        if value >= 1 {
+
+            if value <= self.linear_max {
+                return Some((value - 1) as usize);
+            }
+
+            let l_max = self.linear_max;
+
+            let outer = 63 - value.leading_zeros();
+
+            let l_power = 64 - self.linear_max.leading_zeros();
+
+            // the fraction of the way through the power of two, in integers so
+            // every target assigns the same bucket
+            let remain = value - (1 << outer);
+
+            let inner = ((self.buckets_inner as u128 * remain as u128) >> outer) as u64;
+
+            // this gives the shifted outer index
+            let outer = (outer - l_power) as u64;
+
+            let index = l_max + self.buckets_inner * outer + inner;
+
+            return usize::try_from(index).ok();
+        }
+        result
+    }
+
+    // calculate the lowest value which maps to the given index in the
+    // layout starting at 1
+    fn raw_low(&self, index: usize) -> u64 {
+        if index < self.linear_max as usize {
+            return (index + 1) as u64;
+        }
+
+        // raw_index is monotonic, so search for the first value at the index
+        let mut low = self.linear_max;
+        let mut high = u64::MAX;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.raw_index(mid) {
+                Some(i) if i >= index => high = mid,
+                _ => low = mid + 1,
+            }
+        }
+        low
+    }
+
+    // calculate the nominal value of the given index in the layout starting
+    // at 1
+    fn raw_value(&self, index: usize) -> u64 {
+
+        // in this case, the index is linear
+        let index = index as u64;
+
+        let linear_max = self.linear_max;
+
+        if index < linear_max {
+            return index + 1;
+        }
+
+        let log_index = index - linear_max;
+
+        let outer = log_index / self.buckets_inner;
 
-        if self.entries() < 1 {
-            return Err("no data");
+        let inner = log_index % self.buckets_inner;
+
+        let power = outer + self.linear_power as u64;
+        if power >= 64 {
+            return u64::MAX;
+        }
+
+        // the start of the power of two plus the inner fraction, rounded up
+        let base = 1_u128 << power;
+        let buckets_inner = self.buckets_inner as u128;
+        let value = base + (inner as u128 * base).div_ceil(buckets_inner);
+
+        value.min(u64::MAX as u128) as u64
+    }
+}
+
+// the size of the pages touched when prefaulting buckets
+const PAGE_SIZE: usize = 4096;
+
+// the bytes of the shared allocation holding the counts besides the counts,
+// its reference counts and the Vec
+fn shared_overhead() -> usize {
+    2 * mem::size_of::<usize>() + mem::size_of::<Vec<u64>>()
+}
+
+// allocate zeroed buckets for the layout, prefaulting them if configured
+fn new_buckets(config: &HistogramConfig, properties: &HistogramProperties) -> Vec<u64> {
+    let mut data = vec![0; properties.buckets_total as usize];
+
+    if config.prefault {
+        // a zeroed allocation may be backed by untouched pages, so write a
+        // zero into each page to fault it in now
+        let step = PAGE_SIZE / mem::size_of::<u64>();
+        for count in data.iter_mut().step_by(step) {
+            unsafe {
+                ptr::write_volatile(count, 0);
+            }
+        }
+    }
+    data
+}
+
+// a requested position within the entries, either as a float percentage or
+// as an exact fraction for the integer percentile APIs
+#[derive(Clone, Copy)]
+enum Rank {
+    Percent(f64),
+    Fraction(u64, u64),
+}
+
+impl Rank {
+    fn is_valid(&self) -> bool {
+        match *self {
+            Rank::Percent(percentile) => (0.0..=100.0).contains(&percentile),
+            Rank::Fraction(numerator, denominator) => denominator > 0 && numerator <= denominator,
+        }
+    }
+
+    // the number of entries at or below the rank, rounded up
+    fn entries(&self, total: u64) -> u64 {
+        match *self {
+            Rank::Percent(percentile) => (total as f64 * (percentile / 100.0_f64)).ceil() as u64,
+            Rank::Fraction(numerator, denominator) => {
+                let scaled = total as u128 * numerator as u128;
+                scaled.div_ceil(denominator as u128) as u64
+            }
+        }
+    }
+
+    // Some(true) for the lowest sample, Some(false) for the highest, which
+    // are found directly rather than by rank
+    fn extreme(&self) -> Option<bool> {
+        match *self {
+            Rank::Percent(0.0) => Some(true),
+            Rank::Percent(100.0) => Some(false),
+            Rank::Fraction(0, _) => Some(true),
+            Rank::Fraction(numerator, denominator) if numerator == denominator => Some(false),
+            _ => None,
+        }
+    }
+}
+
+// find the index of the bucket holding the rank, reading the bucket counts
+// through the accessor so merged views can share the walk
+fn percentile_index<F>(counters: &HistogramCounters,
+                       occupied: Option<(usize, usize)>,
+                       count: F,
+                       rank: Rank)
+                       -> Result<usize, HistogramError>
+    where F: Fn(usize) -> u64
+{
+    percentile_position(counters, occupied, count, rank).map(|position| position.index)
+}
+
+// where a rank falls within the buckets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Position {
+    index: usize,
+    // the rank is the within-th sample of the count in the bucket, counting
+    // from 1 at its low end
+    within: u64,
+    count: u64,
+}
+
+// p0 and p100 are the first and last sample, which lie among the missed
+// samples at that end if there are any, or else in the end bucket, so are
+// found without the rank arithmetic. None if the end bucket is not known.
+fn extreme_position<F>(counters: &HistogramCounters,
+                       occupied: Option<(usize, usize)>,
+                       count: F,
+                       lower: bool)
+                       -> Option<Result<Position, HistogramError>>
+    where F: Fn(usize) -> u64
+{
+    match occupied {
+        _ if lower && counters.missed_small > 0 => Some(Err(HistogramError::Underflow)),
+        _ if !lower && counters.missed_large > 0 => Some(Err(HistogramError::Overflow)),
+        Some((low, high)) => {
+            let index = if lower { low } else { high };
+            let bucket = count(index);
+            if bucket == 0 {
+                return None;
+            }
+            Some(Ok(Position {
+                index,
+                within: if lower { 1 } else { bucket },
+                count: bucket,
+            }))
+        }
+        None => None,
+    }
+}
+
+// find the bucket holding the rank and the rank's place within it
+//
+// The rank is the lowest sample whose cumulative count reaches
+// ceil(q * total), found with one upward walk from the smallest sample, so
+// every percentile uses the same convention as `RunningTotals::position()`.
+fn percentile_position<F>(counters: &HistogramCounters,
+                          occupied: Option<(usize, usize)>,
+                          count: F,
+                          rank: Rank)
+                          -> Result<Position, HistogramError>
+    where F: Fn(usize) -> u64
+{
+    counters.check_stored()?;
+
+    if !rank.is_valid() {
+        return Err(HistogramError::InvalidPercentile);
+    }
+
+    if let Some(position) = rank.extreme().and_then(|lower| {
+        extreme_position(counters, occupied, &count, lower)
+    }) {
+        return position;
+    }
+
+    let need = rank.entries(counters.entries_total).clamp(1, counters.entries_total);
+
+    let mut have = counters.missed_small;
+    if have >= need {
+        return Err(HistogramError::Underflow);
+    }
+    for index in occupied_indices(occupied) {
+        let before = have;
+        let bucket = count(index);
+        have = have.saturating_add(bucket);
+
+        if have >= need {
+            return Ok(Position {
+                index,
+                within: need - before,
+                count: bucket,
+            });
+        }
+    }
+    Err(beyond_buckets(counters, need, have))
+}
+
+// the running totals of the bucket counts across an occupied range, so the
+// bucket holding a rank is found by binary search rather than a walk
+#[derive(Clone)]
+struct RunningTotals {
+    // the index of the bucket of the first total
+    first: usize,
+    // shared, so clones of a histogram share them as they do the counts
+    totals: Arc<[u64]>,
+}
+
+impl RunningTotals {
+    fn new<F>(occupied: Option<(usize, usize)>, count: F) -> RunningTotals
+        where F: Fn(usize) -> u64
+    {
+        let mut total = 0_u64;
+        let totals = occupied_indices(occupied).map(|index| {
+                                                   total = total.saturating_add(count(index));
+                                                   total
+                                               })
+                                               .collect();
+        RunningTotals {
+            first: occupied.map_or(0, |(low, _)| low),
+            totals,
+        }
+    }
+
+    // the memory held by the totals, in bytes
+    fn memory_used(&self) -> usize {
+        self.totals.len() * mem::size_of::<u64>()
+    }
+
+    // the total count of the buckets up to and including the index
+    fn through(&self, index: usize) -> u64 {
+        match index.checked_sub(self.first) {
+            Some(offset) => {
+                self.totals.get(offset).or(self.totals.last()).copied().unwrap_or(0)
+            }
+            None => 0,
+        }
+    }
+
+    // the total count of the buckets before the index
+    fn below(&self, index: usize) -> u64 {
+        match index {
+            0 => 0,
+            _ => self.through(index - 1),
+        }
+    }
+
+    // the index of the bucket holding the count-th stored sample, None if
+    // fewer are stored
+    fn index_of(&self, count: u64) -> Option<usize> {
+        let offset = self.totals.partition_point(|total| *total < count);
+        if offset < self.totals.len() {
+            Some(self.first + offset)
+        } else {
+            None
+        }
+    }
+
+    // find the bucket holding the rank and the rank's place within it, as
+    // `percentile_position()` does for the counts the totals were built from
+    fn position<F>(&self,
+                   counters: &HistogramCounters,
+                   occupied: Option<(usize, usize)>,
+                   count: F,
+                   rank: Rank)
+                   -> Result<Position, HistogramError>
+        where F: Fn(usize) -> u64
+    {
+        counters.check_stored()?;
+
+        if !rank.is_valid() {
+            return Err(HistogramError::InvalidPercentile);
+        }
+
+        if let Some(position) = rank.extreme().and_then(|lower| {
+            extreme_position(counters, occupied, &count, lower)
+        }) {
+            return position;
+        }
+
+        let need = rank.entries(counters.entries_total).clamp(1, counters.entries_total);
+
+        let have = counters.missed_small;
+        if have >= need {
+            return Err(HistogramError::Underflow);
+        }
+
+        // the first bucket where the running total reaches the rank
+        let offset = self.totals.partition_point(|total| have.saturating_add(*total) < need);
+        if offset < self.totals.len() {
+            let below = match offset {
+                0 => 0,
+                _ => self.totals.get(offset - 1).copied().unwrap_or(0),
+            };
+            let index = self.first + offset;
+            return Ok(Position {
+                index,
+                within: need - have.saturating_add(below),
+                count: count(index),
+            });
+        }
+        let stored = self.totals.last().copied().unwrap_or(0);
+        Err(beyond_buckets(counters, need, have.saturating_add(stored)))
+    }
+}
+
+// the error for a rank not reached by walking every bucket, having counted
+// have of the need samples from the low end
+fn beyond_buckets(counters: &HistogramCounters, need: u64, have: u64) -> HistogramError {
+    // the rank may lie among the samples missed as too large, or those which
+    // failed to index, otherwise the counters promise more samples than
+    // were stored
+    let have = have.saturating_add(counters.missed_large);
+    if have >= need {
+        return HistogramError::Overflow;
+    }
+
+    let available = have.saturating_add(counters.missed_unknown);
+    if available >= need {
+        return HistogramError::Unknown;
+    }
+    HistogramError::InconsistentState {
+        needed: need,
+        available,
+    }
+}
+
+
+// the indices of an occupied range, empty for None
+fn occupied_indices(occupied: Option<(usize, usize)>) -> ops::Range<usize> {
+    match occupied {
+        Some((low, high)) => low..(high + 1),
+        None => 0..0,
+    }
+}
+
+// arithmetic mean of the bucket values, reading counts through the accessor
+fn mean<F>(properties: &HistogramProperties,
+           counters: &HistogramCounters,
+           occupied: Option<(usize, usize)>,
+           count: F)
+           -> Result<u64, HistogramError>
+    where F: Fn(usize) -> u64
+{
+    counters.check_stored()?;
+
+    // missed samples have no value to include, so the mean is of the
+    // samples stored in the buckets
+    let mut total = 0.0_f64;
+    let mut sum = 0.0_f64;
+
+    for index in occupied_indices(occupied) {
+        let c = count(index) as f64;
+        total += c;
+        sum += properties.index_value(index) as f64 * c;
+    }
+    if total == 0.0 {
+        return Err(HistogramError::Empty);
+    }
+    Ok((sum / total).ceil() as u64)
+}
+
+// variance of the bucket values, reading counts through the accessor
+fn stdvar<F>(properties: &HistogramProperties,
+             counters: &HistogramCounters,
+             occupied: Option<(usize, usize)>,
+             count: F)
+             -> Result<u64, HistogramError>
+    where F: Fn(usize) -> u64
+{
+    let m = mean(properties, counters, occupied, &count)? as f64;
+
+    let mut total = 0.0_f64;
+    let mut stdvar = 0.0_f64;
+
+    for index in occupied_indices(occupied) {
+        let v = properties.index_value(index) as f64;
+        let c = count(index) as f64;
+        total += c;
+        stdvar += (c * v * v) - (2_f64 * c * m * v) + (c * m * m);
+    }
+
+    stdvar /= total;
+
+    Ok(stdvar.ceil() as u64)
+}
+
+// format a power of two with a binary unit suffix, as 4K for 4096
+fn power_label(value: u128) -> String {
+    let mut value = value;
+    for suffix in &["", "K", "M", "G", "T", "P"] {
+        if value < 1024 || !value.is_multiple_of(1024) {
+            return format!("{}{}", value, suffix);
+        }
+        value /= 1024;
+    }
+    format!("{}E", value)
+}
+
+// the more recent of two last recorded values
+fn newer(a: Option<(u64, Instant)>, b: Option<(u64, Instant)>) -> Option<(u64, Instant)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b.1 > a.1 { b } else { a }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[derive(Clone)]
+pub struct Histogram {
+    config: HistogramConfig,
+    data: HistogramData,
+    properties: HistogramProperties,
+    clock: Arc<dyn Clock>,
+    schedule: Schedule,
+}
+
+#[derive(Clone, Copy)]
+pub struct HistogramBucket {
+    value: u64,
+    count: u64,
+    id: u64,
+    low: u64,
+    high: u64,
+}
+
+/// the outcome of `Histogram::record_n`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Recorded {
+    stored: u64,
+    clamped: u64,
+    rejected: u64,
+}
+
+impl Recorded {
+    /// return the counts stored in the bucket for the value
+    pub fn stored(self) -> u64 {
+        self.stored
+    }
+
+    /// return the counts stored in the top bucket in place of a larger value
+    pub fn clamped(self) -> u64 {
+        self.clamped
+    }
+
+    /// return the counts which were not stored
+    pub fn rejected(self) -> u64 {
+        self.rejected
+    }
+}
+
+/// how `Histogram::try_record` stored a value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordOutcome {
+    /// the value has a bucket of its own in the linear region
+    StoredExact,
+    /// the value shares a bucket, whose value differs from it by bucket_error
+    StoredApproximate { bucket_error: u64 },
+    /// the value was above max_value and stored in the top bucket
+    Clamped,
+}
+
+/// the outcome of `Histogram::merge`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MergeReport {
+    lossy: bool,
+    transferred: u64,
+    dropped_small: u64,
+    dropped_large: u64,
+    dropped_saturated: u64,
+    dropped_unknown: u64,
+    max_relative_error: f64,
+    mean_relative_error: f64,
+}
+
+impl MergeReport {
+    /// true if the configs differed, so the source buckets were recorded by
+    /// value into the destination buckets
+    pub fn lossy(&self) -> bool {
+        self.lossy
+    }
+
+    /// return the counts stored into the destination
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    /// return the counts dropped for every reason
+    pub fn dropped(&self) -> u64 {
+        self.dropped_small
+            .saturating_add(self.dropped_large)
+            .saturating_add(self.dropped_saturated)
+            .saturating_add(self.dropped_unknown)
+    }
+
+    /// return the counts dropped as smaller than the destination min_value
+    pub fn dropped_small(&self) -> u64 {
+        self.dropped_small
+    }
+
+    /// return the counts dropped as larger than the destination max_value
+    pub fn dropped_large(&self) -> u64 {
+        self.dropped_large
+    }
+
+    /// return the counts dropped because a destination bucket was saturated
+    pub fn dropped_saturated(&self) -> u64 {
+        self.dropped_saturated
+    }
+
+    /// return the counts dropped because they could not be indexed
+    pub fn dropped_unknown(&self) -> u64 {
+        self.dropped_unknown
+    }
+
+    /// return the largest relative difference between a source bucket value
+    /// and the destination bucket value it was stored as
+    pub fn max_relative_error(&self) -> f64 {
+        self.max_relative_error
+    }
+
+    /// return the relative value error averaged over the transferred counts
+    pub fn mean_relative_error(&self) -> f64 {
+        self.mean_relative_error
+    }
+}
+
+/// the per-bucket differences between two histograms, from
+/// `Histogram::diff_buckets`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BucketDiff {
+    buckets: Vec<(u64, i64)>,
+    saturated: bool,
+}
+
+impl BucketDiff {
+    /// return the (bucket value, count difference) of every bucket which
+    /// differs, in ascending order
+    pub fn buckets(&self) -> &[(u64, i64)] {
+        &self.buckets
+    }
+
+    /// true if any difference was beyond the range of i64 and saturated
+    pub fn saturated(&self) -> bool {
+        self.saturated
+    }
+}
+
+impl HistogramBucket {
+    /// return the sample value for the bucket
+    ///
+    /// # Example
+    /// ```
+    /// use histogram::*;
+    ///
+    /// let h = Histogram::new().unwrap();
+    /// let b = h.iter().next().unwrap();
+    ///
+    /// assert_eq!(b.value(), 1);
+    pub fn value(self) -> u64 {
+        self.value
+    }
+
+    /// return the sample counts for the bucket
+    ///
+    /// # Example
+    /// ```
+    /// use histogram::*;
+    ///
+    /// let h = Histogram::new().unwrap();
+    /// let b = h.iter().next().unwrap();
+    ///
+    /// assert_eq!(b.count(), 0);
+    pub fn count(self) -> u64 {
+        self.count
+    }
+
+    /// return the bucket id
+    ///
+    /// # Example
+    /// ```
+    /// use histogram::*;
+    ///
+    /// let h = Histogram::new().unwrap();
+    /// let b = h.iter().next().unwrap();
+    ///
+    /// assert_eq!(b.id(), 0);
+    pub fn id(self) -> u64 {
+        self.id
+    }
+
+    /// return the lowest value which maps to the bucket, inclusive
+    ///
+    /// # Example
+    /// ```
+    /// use histogram::*;
+    ///
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(100).precision(1);
+    /// let h = Histogram::configured(c).unwrap();
+    /// let b = h.iter().nth(15).unwrap();
+    ///
+    /// assert_eq!((b.low(), b.high(), b.width()), (16, 18, 2));
+    pub fn low(self) -> u64 {
+        self.low
+    }
+
+    /// return the value above the bucket, exclusive, so every value from
+    /// `low()` up to but not including `high()` maps to the bucket
+    ///
+    /// The range ends after max_value, so the bucket holding max_value ends
+    /// at max_value + 1, saturating at u64::MAX, and buckets wholly above
+    /// max_value, which no value maps to, are empty with a high equal to
+    /// their low.
+    pub fn high(self) -> u64 {
+        self.high
+    }
+
+    /// return the number of values which map to the bucket
+    pub fn width(self) -> u64 {
+        self.high - self.low
+    }
+}
+
+/// an iterator over the nominal value of each bucket, in ascending order
+///
+/// Created by `Histogram::values()` and `Histogram::values_between()`.
+#[derive(Clone)]
+pub struct HistogramValues {
+    properties: HistogramProperties,
+    index: usize,
+    end: usize,
+}
+
+impl Iterator for HistogramValues {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.index >= self.end {
+            return None;
+        }
+        let value = self.properties.index_value(self.index);
+        self.index += 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end.saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+// a summary rather than every bucket, so failed assertions stay readable
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Histogram")
+         .field("entries", &self.data.counters.entries_total)
+         .field("missed", &self.data.counters.missed())
+         .field("min", &self.minimum().ok())
+         .field("max", &self.maximum().ok())
+         .field("p50", &self.percentile(50.0).ok())
+         .field("p99", &self.percentile(99.0).ok())
+         .finish()
+    }
+}
+
+/// histograms are equal when they have the same bucket layout, counters and
+/// bucket counts
+///
+/// Settings which do not change the buckets, such as the overflow policy or
+/// percentile method, and state such as the clock and `stats()`, are not
+/// compared, so a histogram equals its decoded copy. Histograms of differing
+/// layouts are unequal.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, HistogramConfig};
+/// let mut a = Histogram::new().unwrap();
+/// let mut b = Histogram::new().unwrap();
+///
+/// a.record(10, 2).unwrap();
+/// b.record(10, 2).unwrap();
+///
+/// assert_eq!(a, b);
+/// assert_eq!(a, Histogram::from_bytes(&a.to_bytes()).unwrap());
+///
+/// b.increment(10).unwrap();
+///
+/// assert!(a != b);
+impl PartialEq for Histogram {
+    fn eq(&self, other: &Histogram) -> bool {
+        self.config.matches(&other.config) && self.data.counters == other.data.counters &&
+        self.counts().eq(other.counts())
+    }
+}
+
+impl Eq for Histogram {}
+
+/// record each value, as by `increment()`, counting values out of range as
+/// missed rather than stopping at the first
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, HistogramConfig};
+/// let mut c = HistogramConfig::new();
+/// c.max_value(1_000);
+/// let mut h = Histogram::configured(c).unwrap();
+///
+/// h.extend(vec![10, 5_000, 20]);
+///
+/// assert_eq!(h.entries(), 3);
+/// assert_eq!(h.missed_large(), 1);
+impl Extend<u64> for Histogram {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, values: I) {
+        for value in values {
+            let _ = self.increment(value);
+        }
+    }
+}
+
+/// record each (value, count) pair, as by `record()`, counting values out of
+/// range as missed rather than stopping at the first
+///
+/// # Example
+/// ```
+/// # use histogram::Histogram;
+/// let mut h = Histogram::new().unwrap();
+///
+/// h.extend(vec![(10, 3), (0, 1), (20, 2)]);
+///
+/// assert_eq!(h.get(10), Some(3));
+/// assert_eq!(h.missed_small(), 1);
+impl Extend<(u64, u64)> for Histogram {
+    fn extend<I: IntoIterator<Item = (u64, u64)>>(&mut self, pairs: I) {
+        for (value, count) in pairs {
+            let _ = self.record(value, count);
+        }
+    }
+}
+
+/// collect values into a Histogram of the default config, as by `extend()`
+///
+/// # Example
+/// ```
+/// # use histogram::Histogram;
+/// let h: Histogram = (1..1001).collect();
+///
+/// assert_eq!(h.entries(), 1_000);
+/// assert_eq!(h.percentile(50.0).unwrap(), 500);
+impl FromIterator<u64> for Histogram {
+    fn from_iter<I: IntoIterator<Item = u64>>(values: I) -> Histogram {
+        let mut histogram = Histogram::new().expect("the default config is valid");
+        histogram.extend(values);
+        histogram
+    }
+}
+
+impl Histogram {
+    /// create a new Histogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    pub fn new() -> Result<Histogram, HistogramError> {
+        let config = HistogramConfig::new();
+        Histogram::configured(config)
+    }
+
+    /// create a new Histogram
+    ///
+    /// Fails with `InvalidPrecision`, `InvalidRadix` or `InvalidMaxValue` for
+    /// a setting out of range, `TooManyBuckets` if the config derives a
+    /// bucket layout of more than 2^28 buckets, 2 GiB of counts, as with
+    /// precision 8 at the default max_value, `InvalidConfig` for another
+    /// invalid combination, or `MemoryLimitExceeded` if its buckets would
+    /// use more than max_memory.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig,HistogramError};
+    ///
+    /// let mut c = HistogramConfig::new();
+    ///
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// c.max_memory(1024);
+    /// assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::MemoryLimitExceeded);
+    ///
+    /// c.max_memory(0).precision(40);
+    /// assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::InvalidPrecision);
+    ///
+    /// c.precision(8);
+    /// assert!(matches!(Histogram::configured(c), Err(HistogramError::TooManyBuckets { .. })));
+    pub fn configured(config: HistogramConfig) -> Result<Histogram, HistogramError> {
+        Histogram::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// create a Histogram with the finest precision whose buckets fit within
+    /// the memory budget in bytes, covering values up to max_value
+    ///
+    /// Fails with `MemoryLimitExceeded` if even precision 1 does not fit,
+    /// which is always the case for a budget of 0.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// let h = Histogram::with_memory_budget(64 * 1024, 60_000_000_000).unwrap();
+    ///
+    /// assert_eq!(h.precision(), 2);
+    /// assert!(h.buckets_total() * 8 <= 64 * 1024);
+    ///
+    /// assert_eq!(Histogram::with_memory_budget(64, 60_000_000_000).unwrap_err(),
+    ///            HistogramError::MemoryLimitExceeded);
+    pub fn with_memory_budget(bytes: u32, max_value: u64) -> Result<Histogram, HistogramError> {
+        if bytes == 0 {
+            return Err(HistogramError::MemoryLimitExceeded);
+        }
+        let mut config = HistogramConfig::new();
+        config.max_value(max_value).max_memory(bytes);
+
+        // the finest precision whose radix power fits the layout arithmetic
+        let mut precision = 1;
+        while config.radix.checked_pow(precision + 1).is_some() {
+            precision += 1;
+        }
+        (1..(precision + 1))
+            .rev()
+            .filter_map(|precision| {
+                config.precision(precision);
+                Histogram::configured(config).ok()
+            })
+            .next()
+            .ok_or(HistogramError::MemoryLimitExceeded)
+    }
+
+    /// create a new Histogram which takes its timestamps from the given clock
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig, ManualClock};
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// let clock = Arc::new(ManualClock::new());
+    /// let mut h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+    ///
+    /// clock.advance(Duration::from_secs(2));
+    /// assert_eq!(h.elapsed(), Duration::from_secs(2));
+    pub fn with_clock(config: HistogramConfig,
+                      clock: Arc<dyn Clock>)
+                      -> Result<Histogram, HistogramError> {
+        let properties = HistogramProperties::new(&config)?;
+
+        let data = Arc::new(new_buckets(&config, &properties));
+
+        let counters = HistogramCounters::new();
+
+        let started = clock.now();
+
+        Ok(Histogram {
+            config,
+            data: HistogramData {
+                data,
+                counters,
+                started,
+                last: None,
+                occupied: None,
+                peaks: peaks::new_peaks(&config, &properties),
+                summary: None,
+                totals: OnceLock::new(),
+                stats: Some(HistogramStats::default()),
+            },
+            properties,
+            clock,
+            schedule: Schedule::default(),
+        })
+    }
+
+    /// create an empty Histogram with the same config
+    ///
+    /// This reuses the bucket layout of the source rather than deriving it
+    /// again, and does not copy any counts.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    /// h.increment(1).unwrap();
+    ///
+    /// let e = h.clone_empty();
+    ///
+    /// assert_eq!(e.entries(), 0);
+    /// assert_eq!(e.buckets_total(), h.buckets_total());
+    pub fn clone_empty(&self) -> Histogram {
+        Histogram {
+            config: self.config,
+            data: HistogramData {
+                data: Arc::new(new_buckets(&self.config, &self.properties)),
+                counters: HistogramCounters::new(),
+                started: self.clock.now(),
+                last: None,
+                occupied: None,
+                peaks: peaks::new_peaks(&self.config, &self.properties),
+                summary: None,
+                totals: OnceLock::new(),
+                stats: Some(HistogramStats::default()),
+            },
+            properties: self.properties,
+            clock: self.clock.clone(),
+            schedule: Schedule::default(),
+        }
+    }
+
+    /// clear the histogram data, keeping the allocation of the buckets
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(1);
+    /// assert_eq!(h.entries(), 1);
+    /// h.clear();
+    /// assert_eq!(h.entries(), 0);
+    pub fn clear(&mut self) {
+        // a shared copy is simply released rather than copied and zeroed
+        match Arc::get_mut(&mut self.data.data) {
+            Some(data) => data.fill(0),
+            None => {
+                self.data.data = Arc::new(vec![0; self.properties.buckets_total as usize]);
+            }
+        }
+        self.reset_data();
+    }
+
+    /// clear the histogram data, releasing the allocation of the buckets
+    /// until the next sample is recorded
+    ///
+    /// For a long-lived pool of mostly idle histograms. The buckets are
+    /// allocated again, zeroed, by the next write to the counts. Reads work
+    /// as on any cleared histogram, and `memory_used()` leaves out the
+    /// released buckets.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    /// h.clear_shrink();
+    ///
+    /// assert_eq!(h.entries(), 0);
+    /// assert_eq!(h.get(10), Some(0));
+    ///
+    /// h.increment(10).unwrap();
+    ///
+    /// assert_eq!(h.get(10), Some(1));
+    pub fn clear_shrink(&mut self) {
+        self.data.data = Arc::new(Vec::new());
+        self.reset_data();
+    }
+
+    // reset everything but the bucket counts, as when cleared
+    fn reset_data(&mut self) {
+        self.data.invalidate();
+        self.data.counters.clear();
+        self.data.started = self.clock.now();
+        self.data.last = None;
+        self.data.occupied = None;
+        self.data.peaks.fill(None);
+        self.data.stats = Some(HistogramStats::default());
+    }
+
+    /// return a Histogram holding the counts recorded so far, leaving this
+    /// one cleared
+    ///
+    /// The counts are moved rather than copied, and this histogram is given
+    /// freshly zeroed buckets. Both keep the config and bucket layout. To
+    /// reuse the buckets of an earlier snapshot instead, see `take_into()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    ///
+    /// let interval = h.take();
+    ///
+    /// assert_eq!(interval.entries(), 3);
+    /// assert_eq!(h.entries(), 0);
+    pub fn take(&mut self) -> Histogram {
+        let mut snapshot = self.clone_empty();
+        mem::swap(&mut self.data, &mut snapshot.data);
+        snapshot
+    }
+
+    /// move the counts recorded so far into the snapshot, leaving this
+    /// histogram cleared
+    ///
+    /// The buckets of the two are swapped, and the old buckets of the
+    /// snapshot zeroed for reuse here, so a reporting loop which takes into
+    /// the same snapshot each interval does not allocate. The snapshot must
+    /// have the same bucket layout, and is given this histogram's config.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    /// let mut interval = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    /// h.take_into(&mut interval).unwrap();
+    ///
+    /// assert_eq!(interval.entries(), 3);
+    /// assert_eq!(h.entries(), 0);
+    pub fn take_into(&mut self, snapshot: &mut Histogram) -> Result<(), HistogramError> {
+        if !self.config.matches(&snapshot.config) {
+            return Err(HistogramError::IncompatibleConfig);
+        }
+        mem::swap(&mut self.data, &mut snapshot.data);
+        if self.config.track_peaks != snapshot.config.track_peaks {
+            self.data.peaks = peaks::new_peaks(&self.config, &self.properties);
+        }
+        snapshot.config = self.config;
+        self.clear();
+        Ok(())
+    }
+
+    /// overwrite this histogram with the contents of another
+    ///
+    /// Both histograms must share the same config. The existing allocation is
+    /// reused, so this does not allocate.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    ///
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// a.increment(1).unwrap();
+    /// b.reset_to(&a).unwrap();
+    ///
+    /// assert_eq!(b.get(1).unwrap(), 1);
+    /// assert_eq!(b.entries(), 1);
+    pub fn reset_to(&mut self, source: &Histogram) -> Result<(), HistogramError> {
+        if !self.config.matches(&source.config) {
+            return Err(HistogramError::IncompatibleConfig);
+        }
+        self.data.invalidate();
+
+        // a shared or released copy is replaced by sharing the source
+        match Arc::get_mut(&mut self.data.data) {
+            Some(data) if data.len() == source.data.data.len() => {
+                data.copy_from_slice(&source.data.data)
+            }
+            _ => self.data.data = source.data.data.clone(),
+        }
+        self.data.counters = source.data.counters;
+        self.data.started = source.data.started;
+        self.data.last = source.data.last;
+        self.data.occupied = source.data.occupied;
+        for (index, peak) in self.data.peaks.iter_mut().enumerate() {
+            *peak = source.data.peaks.get(index).copied().flatten();
+        }
+        self.data.stats = source.data.stats;
+
+        Ok(())
+    }
+
+    /// increment the count for a value
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(1);
+    /// assert_eq!(h.get(1).unwrap(), 1);
+    pub fn increment(&mut self, value: u64) -> Result<(), HistogramError> {
+        self.record(value, 1_u64)
+    }
+
+    /// increment the count for a duration, recorded in nanoseconds
+    ///
+    /// A duration of more than u64::MAX nanoseconds, about 584 years, is
+    /// taken as u64::MAX. Otherwise this records as `increment()`, so a
+    /// duration above max_value is counted as missed, or clamped.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::time::Duration;
+    /// # use histogram::{Histogram,HistogramError};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment_duration(Duration::from_micros(1_500)).unwrap();
+    ///
+    /// assert_eq!(h.maximum().unwrap(), 1_500_000);
+    /// assert_eq!(h.increment_duration(Duration::MAX), Err(HistogramError::TooLarge));
+    pub fn increment_duration(&mut self, duration: Duration) -> Result<(), HistogramError> {
+        self.increment(duration.as_nanos().min(u64::MAX as u128) as u64)
+    }
+
+    /// record additional counts for value
+    ///
+    /// A value below min_value fails with `TooSmall`, and one above
+    /// max_value with `TooLarge` unless the overflow policy clamps it. Either
+    /// is still counted, as missed.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig,HistogramError};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(1, 1);
+    /// assert_eq!(h.get(1).unwrap(), 1);
+    ///
+    /// h.record(2, 2);
+    /// assert_eq!(h.get(2).unwrap(), 2);
+    ///
+    /// h.record(10, 10);
+    /// assert_eq!(h.get(10).unwrap(), 10);
+    ///
+    /// assert_eq!(h.record(0, 1), Err(HistogramError::TooSmall));
+    /// assert_eq!(h.record(u64::MAX, 1), Err(HistogramError::TooLarge));
+    pub fn record(&mut self, value: u64, count: u64) -> Result<(), HistogramError> {
+        self.record_detail(value, count).1
+    }
+
+    /// record additional counts for value, reporting how the value was
+    /// stored or why it was not
+    ///
+    /// Values in the linear region are stored exactly, while larger values
+    /// share a bucket with their neighbours. A bucket which cannot hold all
+    /// the counts stores what it can and returns `HistogramError::Saturated`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError, RecordOutcome};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// assert_eq!(h.try_record(10, 1), Ok(RecordOutcome::StoredExact));
+    /// assert_eq!(h.try_record(1_000_001, 1),
+    ///            Ok(RecordOutcome::StoredApproximate { bucket_error: 183 }));
+    /// assert_eq!(h.try_record(0, 1), Err(HistogramError::TooSmall));
+    pub fn try_record(&mut self, value: u64, count: u64) -> Result<RecordOutcome, HistogramError> {
+        let (recorded, result) = self.record_detail(value, count);
+        result?;
+
+        if recorded.rejected > 0 {
+            return Err(HistogramError::Saturated);
+        }
+        if value > self.config.max_value {
+            return Ok(RecordOutcome::Clamped);
+        }
+        if value <= self.properties.linear_max {
+            return Ok(RecordOutcome::StoredExact);
+        }
+
+        match self.get_index(value) {
+            Some(index) => {
+                let bucket_error = self.index_value(index).abs_diff(value);
+                Ok(RecordOutcome::StoredApproximate { bucket_error })
+            }
+            None => Err(HistogramError::Unknown),
+        }
+    }
+
+    /// record additional counts for value, reporting where they landed
+    ///
+    /// The stored, clamped, and rejected counts always sum to the count
+    /// requested. Counts are clamped when a large value is folded into the
+    /// top bucket by `OverflowPolicy::Clamp`, and rejected when the value is
+    /// out of range or the bucket is saturated.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig,OverflowPolicy};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(100).overflow(OverflowPolicy::Clamp);
+    ///
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// let r = h.record_n(10, 5);
+    /// assert_eq!(r.stored(), 5);
+    ///
+    /// let r = h.record_n(1_000, 2);
+    /// assert_eq!(r.clamped(), 2);
+    ///
+    /// let r = h.record_n(0, 3);
+    /// assert_eq!(r.rejected(), 3);
+    pub fn record_n(&mut self, value: u64, count: u64) -> Recorded {
+        self.record_detail(value, count).0
+    }
+
+    // record counts for a value, returning both the accounting and the
+    // reason any were not stored
+    fn record_detail(&mut self, value: u64, count: u64) -> (Recorded, Result<(), HistogramError>) {
+        if self.schedule.is_active() {
+            self.run_schedule();
+        }
+        self.record_unscheduled(value, count)
+    }
+
+    // record counts for a value as record_detail(), without checking the
+    // auto clear schedule
+    fn record_unscheduled(&mut self,
+                          value: u64,
+                          count: u64)
+                          -> (Recorded, Result<(), HistogramError>) {
+        let mut recorded = Recorded {
+            stored: 0,
+            clamped: 0,
+            rejected: count,
+        };
+        self.data.modify(self.properties.buckets_total);
+
+        self.data.counters.entries_total = self.data.counters.entries_total.saturating_add(count);
+        if value < self.config.min_value {
+            self.data.counters.missed_small = self.data.counters.missed_small.saturating_add(count);
+            return (recorded, Err(HistogramError::TooSmall));
+        }
+
+        let clamp = value > self.config.max_value;
+        if clamp && self.config.overflow == OverflowPolicy::Reject {
+            self.data.counters.missed_large = self.data.counters.missed_large.saturating_add(count);
+            return (recorded, Err(HistogramError::TooLarge));
+        }
+
+        let stored = if clamp { self.config.max_value } else { value };
+        let index = self.get_index(stored);
+        let bucket = index.and_then(|index| Arc::make_mut(&mut self.data.data).get_mut(index));
+        match (index, bucket) {
+            (Some(index), Some(bucket)) => {
+                let added = count.min(u64::MAX - *bucket);
+                *bucket += added;
+                if *bucket > 0 {
+                    self.data.occupy(index);
+                }
+                if clamp {
+                    recorded.clamped = added;
+                } else {
+                    recorded.stored = added;
+                }
+                recorded.rejected = count - added;
+                self.data.stats = self.data.stats.and_then(|stats| stats.add(stored, added));
+                let now = self.clock.now();
+                self.data.last = Some((value, now));
+                if self.config.track_peaks {
+                    self.note_peak(index, now);
+                }
+                (recorded, Ok(()))
+            }
+            _ => {
+                self.data.counters.missed_unknown = self.data
+                                                        .counters
+                                                        .missed_unknown
+                                                        .saturating_add(count);
+                (recorded, Err(HistogramError::Unknown))
+            }
+        }
+    }
+
+    /// decrement the count for a value, removing one sample recorded earlier
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(10).unwrap();
+    /// h.increment(20).unwrap();
+    /// h.decrement(10).unwrap();
+    ///
+    /// assert_eq!(h.entries(), 1);
+    /// assert_eq!(h.percentile(0.0).unwrap(), 20);
+    pub fn decrement(&mut self, value: u64) -> Result<(), HistogramError> {
+        self.subtract(value, 1)
+    }
+
+    /// remove counts for a value recorded earlier, as when samples expire
+    /// from a sliding window
+    ///
+    /// The counts are taken from wherever `record()` placed them: the bucket
+    /// holding the value, or holding max_value for a clamped value, or else
+    /// the missed_small, missed_large, or missed_unknown counter. If that or
+    /// the entries hold fewer than count, nothing changes and `OutOfRange` is
+    /// returned.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 5).unwrap();
+    /// h.record(0, 1).unwrap_err();
+    ///
+    /// h.subtract(10, 3).unwrap();
+    /// h.subtract(0, 1).unwrap();
+    ///
+    /// assert_eq!(h.get(10).unwrap(), 2);
+    /// assert_eq!(h.entries(), 2);
+    /// assert_eq!(h.subtract(10, 3), Err(HistogramError::OutOfRange));
+    pub fn subtract(&mut self, value: u64, count: u64) -> Result<(), HistogramError> {
+        let too_small = value < self.config.min_value;
+        let rejected = too_small ||
+                       (value > self.config.max_value &&
+                        self.config.overflow == OverflowPolicy::Reject);
+        let index = if rejected {
+            None
+        } else {
+            self.get_index(value.min(self.config.max_value))
+                .filter(|index| (*index as u64) < self.properties.buckets_total)
+        };
+
+        let counters = &self.data.counters;
+        let held = match index {
+            Some(index) => self.data.count(index),
+            None if too_small => counters.missed_small,
+            None if rejected => counters.missed_large,
+            None => counters.missed_unknown,
+        };
+        if held < count || counters.entries_total < count {
+            return Err(HistogramError::OutOfRange);
+        }
+        self.data.modify(self.properties.buckets_total);
+
+        let counters = &mut self.data.counters;
+        // a saturated total has lost count, so may still be above u64::MAX
+        if counters.entries_total < u64::MAX {
+            counters.entries_total -= count;
+        }
+        match index {
+            Some(index) => {
+                self.data.set_count(index, held - count);
+                // which values were removed is not known
+                self.data.stats = None;
+                match self.data.occupied {
+                    Some((low, high)) if held == count && (index == low || index == high) => {
+                        self.data.find_occupied()
+                    }
+                    _ => {}
+                }
+            }
+            None if too_small => counters.missed_small = held - count,
+            None if rejected => counters.missed_large = held - count,
+            None => counters.missed_unknown = held - count,
+        }
+        Ok(())
+    }
+
+    /// get the count of the bucket holding a value, None if the value is
+    /// out of range
+    ///
+    /// This is the count of every sample stored in the bucket, not of the
+    /// value alone: values sharing a bucket give the same count. See
+    /// `count_for_equivalent()` for the range of values counted.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// assert_eq!(h.get(1).unwrap(), 0);
+    /// assert_eq!(h.get(0), None);
+    /// assert_eq!(h.get(u64::max_value()), None);
+    ///
+    /// h.record(1_000_001, 2).unwrap();
+    ///
+    /// assert_eq!(h.get(1_000_001), Some(2));
+    /// assert_eq!(h.get(1_000_000), Some(2));
+    pub fn get(&self, value: u64) -> Option<u64> {
+        self.count_for_equivalent(value).map(|(_, _, count)| count)
+    }
+
+    /// return the lowest and highest values of the bucket holding a value,
+    /// and its count, None if the value is out of range
+    ///
+    /// Every value from the lowest to the highest is stored in the same
+    /// bucket, so is equivalent to the histogram, and the count is of all
+    /// of them. The bounds are those of `bucket_bounds()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(100).precision(1);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.increment(16).unwrap();
+    /// h.increment(18).unwrap();
+    ///
+    /// // up to 15 each value has a bucket of its own
+    /// assert_eq!(h.count_for_equivalent(15), Some((15, 15, 0)));
+    ///
+    /// // above, 16 and 17 share a bucket, so both see the sample of 16
+    /// assert_eq!(h.count_for_equivalent(16), Some((16, 17, 1)));
+    /// assert_eq!(h.count_for_equivalent(17), Some((16, 17, 1)));
+    ///
+    /// // while 18 starts the next bucket
+    /// assert_eq!(h.count_for_equivalent(18), Some((18, 19, 1)));
+    /// assert_eq!(h.get(17), Some(1));
+    ///
+    /// assert_eq!(h.count_for_equivalent(101), None);
+    pub fn count_for_equivalent(&self, value: u64) -> Option<(u64, u64, u64)> {
+        if value > self.config.max_value {
+            return None;
+        }
+        match self.get_index(value) {
+            Some(index) if (index as u64) < self.properties.buckets_total => {
+                let (low, high) = self.index_bounds(index);
+                Some((low, high, self.data.count(index)))
+            }
+            _ => None,
+        }
+    }
+
+    // the count of every bucket, including buckets released by
+    // clear_shrink()
+    fn counts(&self) -> impl Iterator<Item = u64> + '_ {
+        let buckets = self.properties.buckets_total as usize;
+        self.data.data.iter().copied().chain(repeat(0)).take(buckets)
+    }
+
+    // calculate the index for a given value
+    fn get_index(&self, value: u64) -> Option<usize> {
+        self.properties.get_index(value)
+    }
+
+    // calculate the nominal value of the given index
+    fn index_value(&self, index: usize) -> u64 {
+        self.properties.index_value(index)
+    }
+
+    // the bucket at the index, as yielded when iterating
+    fn bucket(&self, index: usize) -> HistogramBucket {
+        let low = self.properties.index_low(index);
+        let next = self.properties.index_low(index + 1);
+        HistogramBucket {
+            id: index as u64,
+            value: self.index_value(index),
+            count: self.data.count(index),
+            low,
+            high: next.min(self.config.max_value.saturating_add(1)).max(low),
+        }
+    }
+
+    /// get the count stored in the bucket with the given id
+    ///
+    /// Bucket ids are those of `HistogramBucket::id()`, and unlike `get()`
+    /// this reads each bucket of the logarithmic region exactly.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(1_000_000, 3).unwrap();
+    /// let id = h.iter_from(1_000_000).next().unwrap().id();
+    ///
+    /// assert_eq!(h.count_in_bucket(id as usize), Some(3));
+    /// assert_eq!(h.count_in_bucket(h.buckets_total() as usize), None);
+    pub fn count_in_bucket(&self, id: usize) -> Option<u64> {
+        match id as u64 {
+            id if id < self.properties.buckets_total => Some(self.data.count(id as usize)),
+            _ => None,
+        }
+    }
+
+    /// overwrite the count stored in the bucket with the given id, adjusting
+    /// the entries by the difference
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    /// h.set_bucket_count(9, 5).unwrap();
+    ///
+    /// assert_eq!(h.get(10), Some(5));
+    /// assert_eq!(h.entries(), 5);
+    /// assert_eq!(h.set_bucket_count(h.buckets_total() as usize, 1),
+    ///            Err(HistogramError::InvalidBucket));
+    pub fn set_bucket_count(&mut self, id: usize, count: u64) -> Result<(), HistogramError> {
+        let previous = match self.count_in_bucket(id) {
+            Some(previous) => previous,
+            None => return Err(HistogramError::InvalidBucket),
+        };
+        self.data.modify(self.properties.buckets_total);
+
+        let counters = &mut self.data.counters;
+        if count > previous {
+            counters.entries_total = counters.entries_total.saturating_add(count - previous);
+        } else if counters.entries_total < u64::MAX {
+            // a saturated total has lost count, so may still be above u64::MAX
+            counters.entries_total = counters.entries_total.saturating_sub(previous - count);
+        }
+        self.data.set_count(id, count);
+        if count != previous {
+            self.data.stats = None;
+        }
+        match self.data.occupied {
+            _ if count > 0 => self.data.occupy(id),
+            Some((low, high)) if id == low || id == high => self.data.find_occupied(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// return the value for the given percentile
+    ///
+    /// The result is the value of the bucket holding the lowest sample whose
+    /// cumulative count reaches ceil(percentile / 100 * n) of the n stored
+    /// samples, so it lies within one bucket of the exact nearest-rank
+    /// percentile. The 0th percentile is the smallest stored value and the
+    /// 100th the largest, read from the lowest and highest non-empty
+    /// buckets. With `HistogramConfig::rank_missed()` the ranks count every
+    /// recorded sample, so a rank among the samples missed at either end is
+    /// `Underflow` or `Overflow`.
+    ///
+    /// The first query after the counts change builds their running totals
+    /// with one walk over the occupied buckets, and each query is then a
+    /// binary search, so repeated queries of an unchanged histogram take
+    /// O(log buckets). See `memory_used()` for the cost of the totals.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.percentile(50.0).unwrap(), 500);
+    /// assert_eq!(h.percentile(90.0).unwrap(), 900);
+    /// assert_eq!(h.percentile(99.0).unwrap(), 990);
+    /// assert_eq!(h.percentile(99.9).unwrap(), 999);
+    pub fn percentile(&self, percentile: f64) -> Result<u64, HistogramError> {
+        self.percentile_value(Rank::Percent(percentile))
+    }
+
+    /// return the values for several percentiles, in the order given
+    ///
+    /// Each value is that of the bucket holding the sample at the rank as by
+    /// `percentile()`, so values never decrease as the percentile increases.
+    /// Fails with the error of the lowest percentile without a value, or
+    /// `InvalidPercentile` if any is invalid.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1001 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let values = h.percentiles(&[99.0, 50.0, 90.0, 100.0, 50.0]).unwrap();
+    ///
+    /// assert_eq!(values, vec![990, 500, 900, 1000, 500]);
+    pub fn percentiles(&self, percentiles: &[f64]) -> Result<Vec<u64>, HistogramError> {
+        self.ranked_counters().check_stored()?;
+        if percentiles.iter().any(|&p| !Rank::Percent(p).is_valid()) {
+            return Err(HistogramError::InvalidPercentile);
+        }
+
+        let values: Vec<Result<u64, HistogramError>> =
+            percentiles.iter().map(|&p| self.percentile(p)).collect();
+        let lowest_error = percentiles.iter()
+                                      .zip(values.iter())
+                                      .filter_map(|(p, value)| value.as_ref().err().map(|e| (p, e)))
+                                      .min_by(|a, b| a.0.total_cmp(b.0));
+        match lowest_error {
+            Some((_, error)) => Err(*error),
+            None => values.into_iter().collect(),
+        }
+    }
+
+    /// return the value for the given percentile of sparse `(value, count)`
+    /// pairs, without building a histogram
+    ///
+    /// The pairs need not be sorted or distinct, as a sorted copy is made.
+    /// The rank is chosen as by `percentile()` with missed samples ranked,
+    /// see `HistogramConfig::rank_missed()`, and zero values counted as too
+    /// small to store, but the result is the exact value rather than the
+    /// value of its bucket.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// let pairs = [(1000, 1), (10, 9)];
+    ///
+    /// assert_eq!(Histogram::percentile_of_pairs(&pairs, 50.0), Ok(10));
+    /// assert_eq!(Histogram::percentile_of_pairs(&pairs, 100.0), Ok(1000));
+    /// assert_eq!(Histogram::percentile_of_pairs(&[], 50.0), Err(HistogramError::Empty));
+    pub fn percentile_of_pairs(pairs: &[(u64, u64)],
+                               percentile: f64)
+                               -> Result<u64, HistogramError> {
+        let mut counters = HistogramCounters::new();
+        let mut sorted = Vec::with_capacity(pairs.len());
+        for &(value, count) in pairs {
+            counters.entries_total = counters.entries_total.saturating_add(count);
+            if value < 1 {
+                counters.missed_small = counters.missed_small.saturating_add(count);
+            } else if count > 0 {
+                sorted.push((value, count));
+            }
+        }
+        sorted.sort_unstable();
+
+        let occupied = if sorted.is_empty() {
+            None
+        } else {
+            Some((0, sorted.len() - 1))
+        };
+
+        let index = percentile_index(&counters,
+                                     occupied,
+                                     |index| sorted.get(index).map_or(0, |pair| pair.1),
+                                     Rank::Percent(percentile))?;
+        sorted.get(index).map(|pair| pair.0).ok_or(HistogramError::Unknown)
+    }
+
+    /// return the value for the given percentile in basis points, where 9990
+    /// is p99.9 and 10000 is p100
+    ///
+    /// This avoids passing percentiles as floats, and agrees with
+    /// `percentile()` wherever the float is exact.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.percentile_bp(5000).unwrap(), 500);
+    /// assert_eq!(h.percentile_bp(9990).unwrap(), 999);
+    /// assert_eq!(h.percentile_bp(10001), Err(HistogramError::InvalidPercentile));
+    pub fn percentile_bp(&self, basis_points: u32) -> Result<u64, HistogramError> {
+        self.percentile_value(Rank::Fraction(basis_points as u64, 10_000))
+    }
+
+    /// return the value for the given quantile in parts per million, where
+    /// 999_999 is p99.9999 and 1_000_000 is p100
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.quantile_ppm(500_000).unwrap(), 500);
+    /// assert_eq!(h.quantile_ppm(999_000).unwrap(), 999);
+    pub fn quantile_ppm(&self, ppm: u32) -> Result<u64, HistogramError> {
+        self.percentile_value(Rank::Fraction(ppm as u64, 1_000_000))
+    }
+
+    /// return the number and approximate sum of the stored samples strictly
+    /// above the percentile
+    ///
+    /// The samples are ranked as by `percentile()`, but among the stored
+    /// samples only. Where the boundary falls within a bucket, only the share
+    /// of its count above the boundary is included. Dividing the sum by the
+    /// count gives the mean of the tail.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.above_percentile(99.0).unwrap(), (1, 100));
+    /// assert_eq!(h.above_percentile(90.0).unwrap(), (10, 955));
+    /// assert_eq!(h.above_percentile(100.0).unwrap(), (0, 0));
+    pub fn above_percentile(&self, percentile: f64) -> Result<(u64, u128), HistogramError> {
+        self.check_stored()?;
+
+        let rank = Rank::Percent(percentile);
+        if !rank.is_valid() {
+            return Err(HistogramError::InvalidPercentile);
+        }
+
+        let data = &self.data.data;
+        let stored = data.iter().fold(0_u64, |total, count| total.saturating_add(*count));
+        let above = stored - rank.entries(stored).min(stored);
+
+        let mut remaining = above;
+        let mut sum = 0_u128;
+        for (index, count) in data.iter().enumerate().rev() {
+            if remaining == 0 {
+                break;
+            }
+            let count = (*count).min(remaining);
+            sum += count as u128 * self.index_value(index) as u128;
+            remaining -= count;
+        }
+        Ok((above, sum))
+    }
+
+    /// return the given number of (percentile, value) pairs describing the
+    /// whole distribution
+    ///
+    /// Half the points are spaced evenly from p0 up to p99, and half are
+    /// spaced logarithmically between p99 and p100, reaching further into the
+    /// tail the more entries there are. The final pair is always the maximum
+    /// at p100. The counts are indexed once, so this is much cheaper than
+    /// calling `percentile()` for each point.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1001 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let curve = h.percentile_curve(6).unwrap();
+    ///
+    /// assert_eq!(curve[0], (0.0, 1));
+    /// assert_eq!(curve[3], (99.0, 990));
+    /// assert_eq!(curve[5], (100.0, 1000));
+    pub fn percentile_curve(&self, points: usize) -> Result<Vec<(f64, u64)>, HistogramError> {
+        self.check_stored()?;
+
+        let linear = points / 2;
+        let log = points - linear;
+
+        // the tail reaches to about a single entry, and at least to p99.9
+        let decades = ((self.entries() as f64).log10().ceil() - 2.0).max(1.0);
+
+        let mut percentiles = Vec::with_capacity(points);
+        for i in 0..linear {
+            percentiles.push(99.0 * i as f64 / linear as f64);
+        }
+        for j in 0..log.saturating_sub(1) {
+            let distance = 10.0_f64.powf(-decades * j as f64 / (log - 1) as f64);
+            percentiles.push(100.0 - distance);
+        }
+        if points > 0 {
+            percentiles.push(100.0);
+        }
+
+        let frozen = self.freeze();
+        percentiles.into_iter()
+                   .map(|percentile| frozen.percentile(percentile).map(|value| (percentile, value)))
+                   .collect()
+    }
+
+    /// return the lowest and highest values held by the bucket containing
+    /// the given percentile
+    ///
+    /// The value returned by `percentile()` always lies within these bounds.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    /// h.increment(1_000_000).unwrap();
+    ///
+    /// assert_eq!(h.percentile_bounds(50.0).unwrap(), (500, 500));
+    /// assert_eq!(h.percentile_bounds(100.0).unwrap(), (999818, 1000341));
+    pub fn percentile_bounds(&self, percentile: f64) -> Result<(u64, u64), HistogramError> {
+        self.percentile_index(Rank::Percent(percentile)).map(|index| self.index_bounds(index))
+    }
+
+    /// return the lowest and highest values held by the bucket containing
+    /// the given value, None if the value is out of range
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(100).precision(1);
+    ///
+    /// let h = Histogram::configured(c).unwrap();
+    ///
+    /// assert_eq!(h.bucket_bounds(15), Some((15, 15)));
+    /// assert_eq!(h.bucket_bounds(17), Some((16, 17)));
+    /// assert_eq!(h.bucket_bounds(100), Some((96, 100)));
+    /// assert_eq!(h.bucket_bounds(101), None);
+    pub fn bucket_bounds(&self, value: u64) -> Option<(u64, u64)> {
+        if value < self.config.min_value || value > self.config.max_value {
+            return None;
+        }
+        self.get_index(value).map(|index| self.index_bounds(index))
+    }
+
+    // the lowest and highest values which map to the given index
+    fn index_bounds(&self, index: usize) -> (u64, u64) {
+        let low = self.properties.index_low(index);
+        let high = self.properties.index_low(index + 1).saturating_sub(1);
+        let high = if high > self.config.max_value || high < low {
+            self.config.max_value
+        } else {
+            high
+        };
+        (low, high)
+    }
+
+    // find the index of the bucket holding the given percentile
+    fn percentile_index(&self, rank: Rank) -> Result<usize, HistogramError> {
+        self.percentile_position(rank).map(|position| position.index)
+    }
+
+    // find the bucket holding the given percentile and its place within it
+    //
+    // This searches the running totals, which the first query after a change
+    // to the counts builds with one walk over the occupied buckets.
+    fn percentile_position(&self, rank: Rank) -> Result<Position, HistogramError> {
+        self.data.totals().position(&self.ranked_counters(),
+                                    self.data.occupied,
+                                    |index| self.data.count(index),
+                                    rank)
+    }
+
+    // the counters percentiles are ranked among, by the config
+    fn ranked_counters(&self) -> HistogramCounters {
+        self.data.counters.ranked(self.config.rank_missed)
+    }
+
+    // the value reported for the given percentile
+    fn percentile_value(&self, rank: Rank) -> Result<u64, HistogramError> {
+        self.percentile_position(rank).map(|position| self.position_value(position))
+    }
+
+    // the value reported for a rank at the position, by the percentile method
+    fn position_value(&self, position: Position) -> u64 {
+        let (low, high) = self.index_bounds(position.index);
+        match self.config.percentile_method {
+            PercentileMethod::NearestRank => self.index_value(position.index),
+            PercentileMethod::BucketMidpoint => low + (high - low) / 2,
+            PercentileMethod::LinearInterpolation => {
+                let numerator = (high - low) as u128 * (2 * position.within as u128 - 1);
+                low + (numerator / (2 * position.count as u128)) as u64
+            }
+        }
+    }
+
+    /// convenience function for min
+    ///
+    /// This is the exact smallest value stored while the exact aggregates of
+    /// `stats()` are known, otherwise as `minimum_approx()`. Samples missed
+    /// as too small are left out, unless the config ranks missed samples,
+    /// when any such sample makes this `Underflow`.
+    ///
+    /// # Example
+    /// # use histogram::*;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value);
+    /// }
+    ///
+    /// assert_eq!(h.minimum().unwrap(), 1);
+    pub fn minimum(&self) -> Result<u64, HistogramError> {
+        match self.exact_minimum() {
+            Some(minimum) => Ok(minimum),
+            None => self.minimum_approx(),
+        }
+    }
+
+    /// the minimum from the buckets, the value of the lowest non-empty
+    /// bucket, as the 0th percentile
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.precision(1);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.increment(1_000).unwrap();
+    ///
+    /// assert_eq!(h.minimum().unwrap(), 1_000);
+    /// assert_eq!(h.minimum_approx().unwrap(), 973);
+    pub fn minimum_approx(&self) -> Result<u64, HistogramError> {
+        self.extreme_value(true)
+    }
+
+    /// convenience function for max
+    ///
+    /// This is the exact largest value stored while the exact aggregates of
+    /// `stats()` are known, otherwise as `maximum_approx()`. Samples missed
+    /// as too large are left out, unless the config ranks missed samples,
+    /// when any such sample, or one which failed to index, makes this
+    /// `Overflow`.
+    ///
+    /// # Example
+    /// # use histogram::*;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value);
+    /// }
+    ///
+    /// assert_eq!(h.maximum().unwrap(), 999);
+    pub fn maximum(&self) -> Result<u64, HistogramError> {
+        match self.exact_maximum() {
+            Some(maximum) => Ok(maximum),
+            None => self.maximum_approx(),
+        }
+    }
+
+    /// the maximum from the buckets, the value of the highest non-empty
+    /// bucket, as the 100th percentile
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.precision(1);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.increment(1_000).unwrap();
+    ///
+    /// assert_eq!(h.maximum().unwrap(), 1_000);
+    /// assert_eq!(h.maximum_approx().unwrap(), 973);
+    pub fn maximum_approx(&self) -> Result<u64, HistogramError> {
+        self.extreme_value(false)
+    }
+
+    // the value of the lowest or highest non-empty bucket, found directly
+    // from the occupied range rather than by walking the ranks
+    fn extreme_value(&self, lower: bool) -> Result<u64, HistogramError> {
+        let counters = self.ranked_counters();
+        counters.check_stored()?;
+        if lower && counters.missed_small > 0 {
+            return Err(HistogramError::Underflow);
+        }
+        if !lower && counters.missed_large > 0 {
+            return Err(HistogramError::Overflow);
+        }
+
+        let mut indices = occupied_indices(self.data.occupied);
+        let index = if lower {
+            indices.find(|&index| self.data.count(index) > 0)
+        } else {
+            indices.rev().find(|&index| self.data.count(index) > 0)
+        };
+        match index {
+            Some(index) => {
+                let count = self.data.count(index);
+                let within = if lower { 1 } else { count };
+                Ok(self.position_value(Position { index, within, count }))
+            }
+            None => Err(beyond_buckets(&counters, 1, 0)),
+        }
+    }
+
+    /// return the total count stored in the buckets holding values from low
+    /// to high, inclusive
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.count_between(100, 199), 100);
+    /// assert_eq!(h.count_between(200, 100), 0);
+    pub fn count_between(&self, low: u64, high: u64) -> u64 {
+        match self.index_range(low, high) {
+            Some((low, high)) => {
+                let mut count = 0_u64;
+                for index in low..(high + 1) {
+                    count = count.saturating_add(self.data.count(index));
+                }
+                count
+            }
+            None => 0,
+        }
+    }
+
+    /// return the percentage of entries at or below the given value
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.percentile_below(50).unwrap(), 50.0);
+    /// assert_eq!(h.percentile_below(100).unwrap(), 100.0);
+    pub fn percentile_below(&self, value: u64) -> Result<f64, HistogramError> {
+        self.check_stored()?;
+
+        let counters = self.ranked_counters();
+        let mut have = counters.missed_small.saturating_add(self.count_between(0, value));
+        if value > self.config.max_value {
+            have = have.saturating_add(counters.missed_large);
+        }
+
+        Ok(100.0 * have as f64 / counters.entries_total as f64)
+    }
+
+    /// return the value of the bucket holding the count-th stored sample,
+    /// counting upwards from the smallest value
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..11 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.value_at_count(1).unwrap(), 1);
+    /// assert_eq!(h.value_at_count(10).unwrap(), 10);
+    /// assert!(h.value_at_count(11).is_err());
+    pub fn value_at_count(&self, count: u64) -> Result<u64, HistogramError> {
+        self.check_stored()?;
+
+        if count < 1 {
+            return Err(HistogramError::OutOfRange);
+        }
+
+        match self.data.totals().index_of(count) {
+            Some(index) => Ok(self.index_value(index)),
+            None => Err(HistogramError::OutOfRange),
+        }
+    }
+
+    // error unless some recorded samples landed in the buckets
+    fn check_stored(&self) -> Result<(), HistogramError> {
+        self.data.counters.check_stored()
+    }
+
+    // calculate the inclusive index range covering values from low to high
+    fn index_range(&self, low: u64, high: u64) -> Option<(usize, usize)> {
+        let low = low.max(self.config.min_value);
+        let high = if high > self.config.max_value {
+            self.config.max_value
+        } else {
+            high
+        };
+
+        if low > high {
+            return None;
+        }
+
+        let last = self.buckets_total() as usize - 1;
+        match (self.get_index(low), self.get_index(high)) {
+            (Some(low), Some(high)) => {
+                let high = if high > last { last } else { high };
+                if low > high {
+                    None
+                } else {
+                    Some((low, high))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// arithmetic mean across the histogram, rounded up
+    ///
+    /// The mean is of the samples stored in the buckets, as counted by
+    /// `recorded()` rather than `entries()`, since missed samples have no
+    /// value to include. To count samples above max_value at max_value
+    /// instead, record with `OverflowPolicy::Clamp`. It is exact while the
+    /// exact aggregates of `stats()` are known, otherwise as `mean_approx()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value);
+    /// }
+    ///
+    /// assert_eq!(h.mean().unwrap(), 500);
+    pub fn mean(&self) -> Result<u64, HistogramError> {
+        self.data.counters.check_stored()?;
+        match self.exact_mean() {
+            Some(mean) => Ok(mean),
+            None => self.mean_approx(),
+        }
+    }
+
+    /// arithmetic mean approximation across the histogram, taking each
+    /// sample as the nominal value of its bucket
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.precision(1);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.increment(1_000).unwrap();
+    ///
+    /// assert_eq!(h.mean().unwrap(), 1_000);
+    /// assert_eq!(h.mean_approx().unwrap(), 973);
+    pub fn mean_approx(&self) -> Result<u64, HistogramError> {
+        mean(&self.properties,
+             &self.data.counters,
+             self.data.occupied,
+             |index| self.data.count(index))
+    }
+
+    /// population variance across the histogram, rounded up
+    ///
+    /// Like `mean()`, this is of the samples stored in the buckets, and is
+    /// exact while the exact aggregates are known, otherwise as
+    /// `stdvar_approx()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..11 {
+    ///     h.increment(value);
+    /// }
+    ///
+    /// assert_eq!(h.stdvar().unwrap(), 9);
+    pub fn stdvar(&self) -> Result<u64, HistogramError> {
+        self.data.counters.check_stored()?;
+        match self.exact_stdvar() {
+            Some(stdvar) => Ok(stdvar),
+            None => self.stdvar_approx(),
+        }
+    }
+
+    /// standard variance approximation across the histogram, taking each
+    /// sample as the nominal value of its bucket
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..11 {
+    ///     h.increment(value);
+    /// }
+    ///
+    /// assert_eq!(h.stdvar_approx().unwrap(), 9);
+    pub fn stdvar_approx(&self) -> Result<u64, HistogramError> {
+        stdvar(&self.properties,
+               &self.data.counters,
+               self.data.occupied,
+               |index| self.data.count(index))
+    }
+
+    /// standard deviation across the histogram, the square root of
+    /// `stdvar()` rounded up
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..11 {
+    ///     h.increment(value);
+    /// }
+    ///
+    /// assert_eq!(h.stddev().unwrap(), 3);
+    ///
+    /// h.clear();
+    ///
+    /// for value in 1..4 {
+    ///     h.increment(value);
+    /// }
+    /// for _ in 0..26 {
+    ///     h.increment(1);
+    /// }
+    ///
+    /// assert_eq!(h.stddev().unwrap(), 1);
+    pub fn stddev(&self) -> Result<u64, HistogramError> {
+
+        let stdvar = self.stdvar()? as f64;
+
+        let stddev = stdvar.sqrt();
+
+        Ok(stddev.ceil() as u64)
+    }
+
+    /// standard deviation approximation across the histogram, the square
+    /// root of `stdvar_approx()` rounded up
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..11 {
+    ///     h.increment(value);
+    /// }
+    ///
+    /// assert_eq!(h.stddev_approx().unwrap(), 3);
+    pub fn stddev_approx(&self) -> Result<u64, HistogramError> {
+        let stdvar = self.stdvar_approx()? as f64;
+        Ok(stdvar.sqrt().ceil() as u64)
+    }
+
+    /// Shannon entropy, in bits, of the distribution of counts across buckets
+    ///
+    /// Samples in a single bucket give 0, while samples spread evenly over n
+    /// buckets give log2(n), so a rising entropy shows a distribution becoming
+    /// more diffuse. Missed samples are not included.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 5).unwrap();
+    /// assert_eq!(h.entropy().unwrap(), 0.0);
+    ///
+    /// for value in 1..5 {
+    ///     h.record(value, 5).unwrap();
+    /// }
+    /// assert!((h.entropy().unwrap() - 5.0_f64.log2()).abs() < 1e-9);
+    pub fn entropy(&self) -> Result<f64, HistogramError> {
+        self.data.counters.check_stored()?;
+
+        let counts = occupied_indices(self.data.occupied).map(|index| self.data.count(index));
+        let total = counts.clone().map(|count| count as f64).sum::<f64>();
+
+        let entropy = counts.filter(|count| *count > 0)
+                            .map(|count| {
+                                let share = count as f64 / total;
+                                -share * share.log2()
+                            })
+                            .sum::<f64>();
+        Ok(entropy.max(0.0))
+    }
+
+    /// merge one Histogram into another Histogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    ///
+    /// let mut a = Histogram::new().unwrap();
+    ///
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// assert_eq!(a.entries(), 0);
+    /// assert_eq!(b.entries(), 0);
+    ///
+    /// a.increment(1);
+    /// b.increment(2);
+    ///
+    /// assert_eq!(a.entries(), 1);
+    /// assert_eq!(b.entries(), 1);
+    ///
+    /// a.merge(&b);
+    ///
+    /// assert_eq!(a.entries(), 2);
+    /// assert_eq!(a.get(1).unwrap(), 1);
+    /// assert_eq!(a.get(2).unwrap(), 1);
+    /// ```
+    ///
+    /// When both share the same bucket layout, the count of each bucket is
+    /// added to the matching bucket, and the entry and missed counters are
+    /// summed, so the result is identical to recording both streams into one
+    /// histogram. Otherwise each source bucket is recorded by its value,
+    /// which may move counts to a different bucket, and only the bucket
+    /// counts are transferred. The returned MergeReport accounts for every
+    /// count in the source buckets, whether the merge was lossy, and the
+    /// value error of re-bucketing.
+    ///
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000).precision(1);
+    ///
+    /// let mut a = Histogram::configured(c).unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// b.increment(100).unwrap();
+    /// b.increment(5_000).unwrap();
+    ///
+    /// let report = a.merge(&b);
+    ///
+    /// assert!(report.lossy());
+    /// assert_eq!(report.transferred(), 1);
+    /// assert_eq!(report.dropped_large(), 1);
+    /// assert!(report.max_relative_error() <= 0.1);
+    pub fn merge(&mut self, other: &Histogram) -> MergeReport {
+        // once for the whole merge, so no interval ends part way through it
+        if self.schedule.is_active() {
+            self.run_schedule();
+        }
+        if self.config.matches(&other.config) {
+            return self.merge_buckets(other);
+        }
+
+        let mut report = MergeReport {
+            lossy: true,
+            ..Default::default()
+        };
+        let mut error_total = 0.0_f64;
+
+        // the transfers below would otherwise stamp the bucket values as new,
+        // and add the bucket values to the stats
+        let theirs = other.data
+                          .last
+                          .map(|(value, at)| (self.config.rescale(value, &other.config), at));
+        let last = newer(self.data.last, theirs);
+        let peaks = self.data.peaks.clone();
+        let stats = self.data.stats;
+
+        for index in occupied_indices(other.data.occupied) {
+            let count = other.data.count(index);
+            if count == 0 {
+                continue;
+            }
+            // a value stored under another scale stands for another value
+            let value = self.config.rescale(other.index_value(index), &other.config);
+
+            let recorded = self.record_unscheduled(value, count).0;
+            let transferred = recorded.stored + recorded.clamped;
+            report.transferred = report.transferred.saturating_add(transferred);
+
+            if recorded.rejected > 0 {
+                if value < self.config.min_value {
+                    report.dropped_small = report.dropped_small.saturating_add(recorded.rejected);
+                } else if value > self.config.max_value && self.config.overflow == OverflowPolicy::Reject {
+                    report.dropped_large = report.dropped_large.saturating_add(recorded.rejected);
+                } else if transferred > 0 || self.get(value).is_some() {
+                    report.dropped_saturated =
+                        report.dropped_saturated.saturating_add(recorded.rejected);
+                } else {
+                    report.dropped_unknown =
+                        report.dropped_unknown.saturating_add(recorded.rejected);
+                }
+            }
+
+            if transferred > 0 {
+                if let Some(index) = self.get_index(value.min(self.config.max_value)) {
+                    let stored = self.index_value(index) as f64;
+                    let error = (stored - value as f64).abs() / value as f64;
+                    if error > report.max_relative_error {
+                        report.max_relative_error = error;
+                    }
+                    error_total += error * transferred as f64;
+                }
+            }
+        }
+
+        if report.transferred > 0 {
+            report.mean_relative_error = error_total / report.transferred as f64;
+        }
+        self.data.last = last;
+        self.data.peaks = peaks;
+        self.merge_peaks(other);
+        // the values are kept if every one was stored without clamping
+        let within = |theirs: &HistogramStats| {
+            self.config.scale == other.config.scale &&
+            theirs.min().is_none_or(|min| min >= self.config.min_value) &&
+            theirs.max().is_none_or(|max| max <= self.config.max_value)
+        };
+        self.data.stats = match (stats, other.exact_stats()) {
+            (Some(mine), Some(theirs)) if report.dropped() == 0 && within(theirs) => {
+                mine.combine(theirs)
+            }
+            _ => None,
+        };
+        report
+    }
+
+    // merge a histogram with the same bucket layout by adding the counts of
+    // each bucket and the counters
+    fn merge_buckets(&mut self, other: &Histogram) -> MergeReport {
+        let mut report = MergeReport::default();
+        self.data.modify(self.properties.buckets_total);
+
+        if let Some((low, high)) = other.data.occupied {
+            let data = Arc::make_mut(&mut self.data.data);
+            let counts = data.iter_mut().zip(other.data.data.iter()).skip(low).take(high + 1 - low);
+            for (mine, &count) in counts {
+                let added = count.min(u64::MAX - *mine);
+                *mine += added;
+                report.transferred = report.transferred.saturating_add(added);
+                report.dropped_saturated = report.dropped_saturated.saturating_add(count - added);
+            }
+            self.data.occupy(low);
+            self.data.occupy(high);
+        }
+
+        let counters = &mut self.data.counters;
+        let theirs = &other.data.counters;
+        counters.entries_total = counters.entries_total.saturating_add(theirs.entries_total);
+        counters.missed_small = counters.missed_small.saturating_add(theirs.missed_small);
+        counters.missed_large = counters.missed_large.saturating_add(theirs.missed_large);
+        counters.missed_unknown = counters.missed_unknown.saturating_add(theirs.missed_unknown);
+
+        self.data.last = newer(self.data.last, other.data.last);
+        self.merge_peaks(other);
+        self.data.stats = match (self.data.stats, other.exact_stats()) {
+            (Some(mine), Some(theirs)) if report.dropped() == 0 => mine.combine(theirs),
+            _ => None,
+        };
+        report
+    }
+
+    /// return the signed difference of this histogram's counts from the
+    /// other's for each bucket where they differ, as for a diverging heatmap
+    ///
+    /// Both histograms must share the same config.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// a.record(10, 5).unwrap();
+    /// b.record(10, 2).unwrap();
+    /// b.record(20, 1).unwrap();
+    ///
+    /// let diff = a.diff_buckets(&b).unwrap();
+    ///
+    /// assert_eq!(diff.buckets(), &[(10, 3), (20, -1)]);
+    /// assert!(!diff.saturated());
+    pub fn diff_buckets(&self, other: &Histogram) -> Result<BucketDiff, HistogramError> {
+        if !self.config.matches(&other.config) {
+            return Err(HistogramError::IncompatibleConfig);
+        }
+
+        let occupied = match (self.data.occupied, other.data.occupied) {
+            (Some((low, high)), Some((l, h))) => Some((low.min(l), high.max(h))),
+            (occupied, None) | (None, occupied) => occupied,
+        };
+
+        let mut diff = BucketDiff::default();
+        for index in occupied_indices(occupied) {
+            let (mine, theirs) = (self.data.count(index), other.data.count(index));
+            if mine == theirs {
+                continue;
+            }
+
+            let difference = mine as i128 - theirs as i128;
+            if difference > i64::MAX as i128 || difference < i64::MIN as i128 {
+                diff.saturated = true;
+            }
+            let difference = difference.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+            diff.buckets.push((self.index_value(index), difference));
+        }
+        Ok(diff)
+    }
+
+    /// return true if both have the same bucket layout and each bucket's
+    /// counts differ by at most the tolerance relative to the larger count,
+    /// as when comparing a histogram of sampled data against the full data
+    ///
+    /// A tolerance of 0.0 requires equal counts, and 0.01 allows counts 1%
+    /// apart. The counters are not compared. Histograms of differing layouts
+    /// are never approximately equal.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// a.record(10, 100).unwrap();
+    /// b.record(10, 99).unwrap();
+    ///
+    /// assert!(a.approx_eq(&b, 0.01));
+    /// assert!(!a.approx_eq(&b, 0.001));
+    pub fn approx_eq(&self, other: &Histogram, tolerance: f64) -> bool {
+        if !self.config.matches(&other.config) {
+            return false;
+        }
+        self.counts().zip(other.counts()).all(|(mine, theirs)| {
+            let difference = mine.abs_diff(theirs) as f64;
+            mine == theirs || difference <= tolerance * mine.max(theirs) as f64
+        })
+    }
+
+    /// return a copy with every value multiplied exactly by the multiplier,
+    /// as when migrating recorded durations to a finer unit
+    ///
+    /// Each bucket's count moves to the bucket holding its value times the
+    /// multiplier, using integer arithmetic, so every count and counter is
+    /// preserved exactly. Fails with `TooLarge` if a product exceeds
+    /// max_value, or `TooSmall` for a multiplier of 0. See
+    /// `shift_values_exact_into()` to raise max_value for the larger values.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig, HistogramError};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(250, 10).unwrap();
+    ///
+    /// let shifted = h.shift_values_exact(1_000).unwrap();
+    ///
+    /// assert_eq!(shifted.entries(), 10);
+    /// assert_eq!(shifted.get(250_000).unwrap(), 10);
+    /// assert_eq!(h.shift_values_exact(u64::MAX).unwrap_err(), HistogramError::TooLarge);
+    pub fn shift_values_exact(&self, multiplier: u64) -> Result<Histogram, HistogramError> {
+        self.shift_values_exact_into(multiplier, self.config)
+    }
+
+    /// return a copy as `shift_values_exact()`, with the buckets of the given
+    /// config
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut micros = HistogramConfig::new();
+    /// micros.max_value(60_000_000);
+    /// let mut nanos = HistogramConfig::new();
+    /// nanos.max_value(60_000_000_000);
+    ///
+    /// let mut h = Histogram::configured(micros).unwrap();
+    /// h.record(60_000_000, 1).unwrap();
+    ///
+    /// let shifted = h.shift_values_exact_into(1_000, nanos).unwrap();
+    ///
+    /// assert_eq!(shifted.get(60_000_000_000).unwrap(), 1);
+    pub fn shift_values_exact_into(&self,
+                                   multiplier: u64,
+                                   config: HistogramConfig)
+                                   -> Result<Histogram, HistogramError> {
+        let mut shifted = Histogram::with_clock(config, self.clock.clone())?;
+
+        let mut data = vec![0_u64; shifted.data.data.len()];
+        for index in occupied_indices(self.data.occupied) {
+            let count = self.data.count(index);
+            if count == 0 {
+                continue;
+            }
+            let value = self.index_value(index)
+                            .checked_mul(multiplier)
+                            .ok_or(HistogramError::TooLarge)?;
+            if value < config.min_value {
+                return Err(HistogramError::TooSmall);
+            } else if value > config.max_value {
+                return Err(HistogramError::TooLarge);
+            }
+            let target = shifted.get_index(value)
+                                .and_then(|target| data.get_mut(target))
+                                .ok_or(HistogramError::Unknown)?;
+            *target = target.checked_add(count).ok_or(HistogramError::Saturated)?;
+        }
+
+        shifted.data.data = Arc::new(data);
+        shifted.data.find_occupied();
+        shifted.data.counters = self.data.counters;
+        shifted.data.started = self.data.started;
+        shifted.data.last = self.data
+                                .last
+                                .and_then(|(value, at)| Some((value.checked_mul(multiplier)?, at)));
+        Ok(shifted)
+    }
+
+    /// rebuild the buckets at a higher precision, keeping the recorded counts
+    ///
+    /// Each count moves to the finer bucket holding the value of its old
+    /// bucket, so counts recorded before refining stay approximate to the old
+    /// precision, while later samples use the finer buckets. Fails with
+    /// `InvalidConfig` for a precision lower than the current one, or as
+    /// `Histogram::configured()` if the finer layout is invalid or over
+    /// max_memory, leaving the histogram unchanged.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000_000).precision(2);
+    ///
+    /// let mut h = Histogram::configured(c).unwrap();
+    /// h.increment(1_234).unwrap();
+    /// assert_eq!(h.bucket_bounds(1_234).unwrap(), (1_229, 1_239));
+    ///
+    /// h.refine(3).unwrap();
+    ///
+    /// assert_eq!(h.entries(), 1);
+    /// assert_eq!(h.bucket_bounds(1_234).unwrap(), (1_234, 1_234));
+    pub fn refine(&mut self, precision: u32) -> Result<(), HistogramError> {
+        if precision < self.config.precision {
+            return Err(HistogramError::InvalidConfig);
+        } else if precision == self.config.precision {
+            return Ok(());
+        }
+        let mut config = self.config;
+        config.precision(precision);
+        let properties = HistogramProperties::new(&config)?;
+        self.data.invalidate();
+
+        let mut data = new_buckets(&config, &properties);
+        for index in occupied_indices(self.data.occupied) {
+            let count = self.data.count(index);
+            if count > 0 {
+                let target = properties.get_index(self.index_value(index))
+                                       .and_then(|target| data.get_mut(target));
+                if let Some(target) = target {
+                    *target = target.saturating_add(count);
+                }
+            }
+        }
+
+        // the groups of peaks follow the layout, so are mapped to the new one
+        let peaks = mem::replace(&mut self.data.peaks, peaks::new_peaks(&config, &properties));
+        let old_config = mem::replace(&mut self.config, config);
+        let old = mem::replace(&mut self.properties, properties);
+        self.merge_group_peaks(&peaks, &old_config, &old);
+        self.data.data = Arc::new(data);
+        self.data.find_occupied();
+        Ok(())
+    }
+
+    /// return the stored counts grouped into power of two ranges, as
+    /// (low, high, count) with high inclusive
+    ///
+    /// The ranges run from the lowest to the highest containing a recorded
+    /// sample, including any empty ranges between them.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(3).unwrap();
+    /// h.record(20, 2).unwrap();
+    ///
+    /// assert_eq!(h.by_power_of_two(), vec![(2, 3, 1), (4, 7, 0), (8, 15, 0), (16, 31, 2)]);
+    pub fn by_power_of_two(&self) -> Vec<(u64, u64, u64)> {
+        // the buckets never straddle a power of two, so each is grouped by
+        // the lowest value it holds
+        let mut counts = [0_u64; 64];
+        for (index, count) in self.data.data.iter().enumerate() {
+            if *count > 0 {
+                let power = 63 - self.properties.index_low(index).leading_zeros() as usize;
+                if let Some(total) = counts.get_mut(power) {
+                    *total = total.saturating_add(*count);
+                }
+            }
+        }
+
+        let first = counts.iter().position(|count| *count > 0);
+        let last = counts.iter().rposition(|count| *count > 0);
+        match (first, last) {
+            (Some(first), Some(last)) => {
+                counts.iter()
+                      .enumerate()
+                      .take(last + 1)
+                      .skip(first)
+                      .map(|(power, &count)| {
+                          let low = 1_u64 << power;
+                          (low, low + (low - 1), count)
+                      })
+                      .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// render `by_power_of_two()` as a text chart in the style of bpftrace
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(1, 2).unwrap();
+    /// h.record(5_000, 4).unwrap();
+    ///
+    /// let chart = h.power_of_two_chart();
+    /// let lines: Vec<&str> = chart.lines().collect();
+    ///
+    /// assert_eq!(lines.len(), 13);
+    /// assert!(lines[0].starts_with("[1]                    2 |@@@@@@@@@@@@@@@@@@@@@@@@@@ "));
+    /// assert!(lines[12].starts_with("[4K, 8K)               4 |@@@@@@@@@@@@@@@@@@@@@@@@@@@@"));
+    pub fn power_of_two_chart(&self) -> String {
+        const WIDTH: u64 = 52;
+
+        let ranges = self.by_power_of_two();
+        let max = ranges.iter().map(|range| range.2).max().unwrap_or(0);
+
+        let mut chart = String::new();
+        for (low, high, count) in ranges {
+            let label = if low == high {
+                format!("[{}]", power_label(low as u128))
+            } else {
+                format!("[{}, {})", power_label(low as u128), power_label(high as u128 + 1))
+            };
+            let bar = (count as u128 * WIDTH as u128 / max as u128) as usize;
+            chart.push_str(&format!("{:<16}{:>8} |{:<width$}|\n",
+                                    label,
+                                    count,
+                                    "@".repeat(bar),
+                                    width = WIDTH as usize));
+        }
+        chart
+    }
+
+    /// return at most max_buckets bars covering the recorded range, as
+    /// (low, high, count) with high inclusive, for charts with limited space
+    ///
+    /// Adjacent buckets are merged greedily, closing each bar once it holds
+    /// an equal share of the samples, so empty regions fold into their
+    /// neighbours and bars are narrowest where the data is densest. A bucket
+    /// is never split, so one large bucket may hold more than its share.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(1, 2).unwrap();
+    /// h.record(2, 2).unwrap();
+    /// h.record(900, 4).unwrap();
+    ///
+    /// assert_eq!(h.condensed(2), vec![(1, 2, 4), (3, 900, 4)]);
+    pub fn condensed(&self, max_buckets: usize) -> Vec<(u64, u64, u64)> {
+        let (low, high) = match self.data.occupied {
+            Some(occupied) if max_buckets > 0 => occupied,
+            _ => return Vec::new(),
+        };
+
+        let data = &self.data.data;
+        let total = data.iter().take(high + 1).skip(low).map(|count| *count as u128).sum::<u128>();
+        let share = total.div_ceil(max_buckets as u128).max(1);
+
+        let mut bars = Vec::with_capacity(max_buckets);
+        let mut start = low;
+        let mut count = 0_u128;
+        for (index, bucket) in data.iter().enumerate().take(high + 1).skip(low) {
+            count += *bucket as u128;
+            if count >= share || index == high {
+                let value_low = self.index_bounds(start).0;
+                let value_high = self.index_bounds(index).1;
+                bars.push((value_low, value_high, count.min(u64::MAX as u128) as u64));
+                start = index + 1;
+                count = 0;
+            }
+        }
+        bars
+    }
+
+    /// iterate the nominal value of every bucket, in ascending order
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(32).precision(1);
+    ///
+    /// let h = Histogram::configured(c).unwrap();
+    /// let values: Vec<u64> = h.values().collect();
+    ///
+    /// assert_eq!(&values[13..], &[14, 15, 16, 18, 20, 21, 23, 24, 26, 28, 29, 31, 32, 36, 39,
+    ///                             42, 45, 48, 52, 55, 58, 61]);
+    pub fn values(&self) -> HistogramValues {
+        HistogramValues {
+            properties: self.properties,
+            index: 0,
+            end: self.properties.buckets_total as usize,
+        }
+    }
+
+    /// iterate the nominal value of the buckets holding values from low to
+    /// high, inclusive
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(100).precision(1);
+    ///
+    /// let h = Histogram::configured(c).unwrap();
+    /// let values: Vec<u64> = h.values_between(30, 40).collect();
+    ///
+    /// assert_eq!(values, vec![29, 31, 32, 36, 39]);
+    pub fn values_between(&self, low: u64, high: u64) -> HistogramValues {
+        let (index, end) = match self.index_range(low, high) {
+            Some((low, high)) => (low, high + 1),
+            None => (0, 0),
+        };
+        HistogramValues {
+            properties: self.properties,
+            index,
+            end,
+        }
+    }
+
+    /// first Wasserstein (earth mover's) distance between two histograms
+    ///
+    /// Both distributions are normalized by their stored counts, and the
+    /// distance is the area between their cumulative distributions, with
+    /// each bucket's counts placed at its nominal value. The histograms need
+    /// not share a config, and the values of other are converted to this
+    /// histogram's scale, as by `merge()`. The result is in the same units as
+    /// the values of this histogram.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// for value in 100..200 {
+    ///     a.increment(value).unwrap();
+    ///     b.increment(value + 10).unwrap();
+    /// }
+    ///
+    /// assert_eq!(a.emd(&a).unwrap(), 0.0);
+    /// assert!((a.emd(&b).unwrap() - 10.0).abs() < 1e-9);
+    pub fn emd(&self, other: &Histogram) -> Result<f64, HistogramError> {
+        self.check_stored()?;
+        other.check_stored()?;
+
+        let a = self.masses();
+        // a value stored under another scale stands for another value
+        let b: Vec<(u64, u64)> =
+            other.masses()
+                 .into_iter()
+                 .map(|(value, count)| (self.config.rescale(value, &other.config), count))
+                 .collect();
+
+        let a_total = a.iter().map(|m| m.1 as f64).sum::<f64>();
+        let b_total = b.iter().map(|m| m.1 as f64).sum::<f64>();
+        if a_total <= 0.0 || b_total <= 0.0 {
+            return Err(HistogramError::Empty);
+        }
+
+        // sweep the merged support, accumulating |CDF_a - CDF_b| between
+        // consecutive values
+        let mut distance = 0.0_f64;
+        let mut cdf_a = 0.0_f64;
+        let mut cdf_b = 0.0_f64;
+        let mut i = 0;
+        let mut j = 0;
+        let mut previous: Option<u64> = None;
+
+        while i < a.len() || j < b.len() {
+            let value = match (a.get(i), b.get(j)) {
+                (Some(x), Some(y)) => x.0.min(y.0),
+                (Some(x), None) => x.0,
+                (None, Some(y)) => y.0,
+                (None, None) => break,
+            };
+
+            if let Some(previous) = previous {
+                distance += (cdf_a - cdf_b).abs() * (value - previous) as f64;
+            }
+
+            if let Some(&(_, mass)) = a.get(i).filter(|mass| mass.0 == value) {
+                cdf_a += mass as f64 / a_total;
+                i += 1;
+            }
+            if let Some(&(_, mass)) = b.get(j).filter(|mass| mass.0 == value) {
+                cdf_b += mass as f64 / b_total;
+                j += 1;
+            }
+            previous = Some(value);
+        }
+
+        Ok(distance)
+    }
+
+    // the nominal value and count of each non-empty bucket, ascending
+    fn masses(&self) -> Vec<(u64, u64)> {
+        self.data
+            .data
+            .iter()
+            .enumerate()
+            .filter(|&(_, count)| *count > 0)
+            .map(|(index, count)| (self.index_value(index), *count))
+            .collect()
+    }
+
+    /// iterate every bucket, in ascending order, without modifying the
+    /// Histogram
+    ///
+    /// This is also the iterator of `&Histogram` and `&mut Histogram`, so a
+    /// histogram can be iterated any number of times, nested, or left part
+    /// way, and each `for` loop starts from the first bucket.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(100).precision(1);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.increment(5).unwrap();
+    ///
+    /// assert_eq!(h.iter().count() as u64, h.buckets_total());
+    /// assert_eq!(h.iter().map(|b| b.count()).sum::<u64>(), 1);
+    ///
+    /// for bucket in &h {
+    ///     assert_eq!(bucket.count(), h.count_in_bucket(bucket.id() as usize).unwrap());
+    /// }
+    pub fn iter(&self) -> HistogramIter<'_> {
+        HistogramIter::new(self, 0, self.buckets_total() as usize)
+    }
+
+    /// iterate the buckets with a non-zero count, in ascending order
+    ///
+    /// Only the range of occupied buckets is scanned, so this is cheap for a
+    /// sparse histogram, as when exporting.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(5, 2).unwrap();
+    /// h.record(1_000_000, 1).unwrap();
+    ///
+    /// let counts: Vec<u64> = h.iter_recorded().map(|b| b.count()).collect();
+    /// assert_eq!(counts, vec![2, 1]);
+    pub fn iter_recorded(&self) -> HistogramIter<'_> {
+        let (start, end) = match self.data.occupied {
+            Some((low, high)) => (low, high + 1),
+            None => (0, 0),
+        };
+        HistogramIter::new(self, start, end).recorded()
+    }
+
+    /// create a cursor at the first bucket, to walk the buckets a chunk at a
+    /// time and resume the walk later, see `BucketCursor`
+    ///
+    /// The histogram itself keeps no cursor, so a walk left part way leaves
+    /// nothing behind, while a cursor's position is kept explicitly.
+    pub fn cursor(&self) -> BucketCursor<'_> {
+        BucketCursor::new(self)
+    }
+
+    /// iterate the buckets starting from the one holding the given value
+    ///
+    /// Values below the smallest bucket start from the first bucket, and
+    /// values above max_value yield nothing.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(1).unwrap();
+    /// h.increment(100).unwrap();
+    ///
+    /// let bucket = h.iter_from(100).next().unwrap();
+    /// assert_eq!(bucket.value(), 100);
+    /// assert_eq!(bucket.count(), 1);
+    pub fn iter_from(&self, value: u64) -> HistogramIter<'_> {
+        let end = self.buckets_total() as usize;
+        let start = if value < self.config.min_value {
+            0
+        } else if value > self.config.max_value {
+            end
+        } else {
+            self.get_index(value).unwrap_or(end)
+        };
+        HistogramIter::new(self, start, end)
+    }
+
+    /// iterate the buckets holding values from low to high, inclusive
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..100 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let total: u64 = h.iter_range(10, 19).map(|b| b.count()).sum();
+    /// assert_eq!(total, 10);
+    pub fn iter_range(&self, low: u64, high: u64) -> HistogramIter<'_> {
+        match self.index_range(low, high) {
+            Some((low, high)) => HistogramIter::new(self, low, high + 1),
+            None => HistogramIter::new(self, 0, 0),
+        }
+    }
+
+    /// iterate ranges of values of a fixed width from min_value, the last
+    /// ending at max_value, with the stored count in each
+    ///
+    /// The ranges cover every value from min_value to max_value once. The
+    /// count of a bucket is never split: it goes to the range holding the
+    /// lowest value of the bucket, so a step narrower than the buckets
+    /// leaves some ranges empty. Missed samples are left out, so the counts
+    /// sum to the stored samples. A step of 0 is taken as 1.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(100);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let ranges: Vec<_> = h.iter_linear(25).collect();
+    ///
+    /// assert_eq!(ranges.len(), 4);
+    /// assert_eq!((ranges[1].low(), ranges[1].high(), ranges[1].count()), (26, 50, 25));
+    /// assert_eq!(ranges[3].cumulative(), 100);
+    pub fn iter_linear(&self, step: u64) -> LinearIter<'_> {
+        LinearIter::new(self, step)
+    }
+
+    /// iterate ranges of values growing by the base from min_value, the first
+    /// ending at start and the last at max_value, with the stored count in
+    /// each
+    ///
+    /// Each range ends at the end of the one before multiplied by the base,
+    /// rounded up, and is at least one value wide, so a base of 1.0 or less
+    /// gives ranges of single values. Counts are assigned to the ranges as by
+    /// `iter_linear()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// for value in 1..1001 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let bounds: Vec<_> = h.iter_log(10.0, 1).map(|r| (r.low(), r.high(), r.count())).collect();
+    ///
+    /// assert_eq!(bounds, vec![(1, 1, 1), (2, 10, 9), (11, 100, 90), (101, 1_000, 900)]);
+    pub fn iter_log(&self, base: f64, start: u64) -> LogIter<'_> {
+        LogIter::new(self, base, start)
+    }
+
+    /// iterate quantiles closing in on the highest stored sample, with the
+    /// value at each, for a percentile plot with a logarithmic axis, see
+    /// `QuantileIter`
+    ///
+    /// Each halving of the distance to 1.0 is split into the given number of
+    /// ticks, at least 1. Missed samples are left out, as they are by
+    /// `percentile()` unless the config ranks them.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let steps: Vec<_> = h.iter_quantiles(1).map(|s| (s.quantile(), s.value())).collect();
+    ///
+    /// assert_eq!(&steps[..4], &[(0.0, 1), (0.5, 50), (0.75, 75), (0.875, 88)]);
+    /// assert_eq!(steps.last(), Some(&(1.0, 100)));
+    pub fn iter_quantiles(&self, ticks_per_half_distance: u32) -> QuantileIter<'_> {
+        QuantileIter::new(self, ticks_per_half_distance)
+    }
+
+    /// freeze a copy of the Histogram for repeated queries
+    ///
+    /// The returned FrozenHistogram answers percentile and rank queries with
+    /// a binary search over cumulative counts instead of scanning buckets.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..1000 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let f = h.freeze();
+    ///
+    /// assert_eq!(f.percentile(50.0), h.percentile(50.0));
+    /// assert_eq!(f.percentile(99.9), h.percentile(99.9));
+    pub fn freeze(&self) -> FrozenHistogram {
+        FrozenHistogram::new(self)
+    }
+
+    /// build a text report of the Histogram statistics
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(100).unwrap();
+    ///
+    /// let report = h.report().percentiles(&[50.0]).to_string();
+    /// assert!(report.contains("p50"));
+    pub fn report(&self) -> ReportBuilder<'_> {
+        ReportBuilder::new(self)
+    }
+
+    /// render a report of the default statistics and the given percentiles
+    /// in the style, as `report()`
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, ReportStyle};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(100, 2).unwrap();
+    ///
+    /// assert_eq!(h.report_with(&[50.0], ReportStyle::Csv),
+    ///            "count,min,max,mean,stddev,p50\n2,100,100,100,0,100\n");
+    pub fn report_with(&self, percentiles: &[f64], style: ReportStyle) -> String {
+        self.report().percentiles(percentiles).style(style).to_string()
+    }
+
+    /// return the number of entries in the Histogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// assert_eq!(h.entries(), 0);
+    /// h.increment(1);
+    /// assert_eq!(h.entries(), 1);
+    pub fn entries(&self) -> u64 {
+        self.data.counters.entries_total
+    }
+
+    /// return the number of entries stored in the buckets, which are those
+    /// ranked by `percentile()`
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000);
+    ///
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    /// h.record(0, 1).unwrap_err();
+    /// h.record(2_000, 2).unwrap_err();
+    ///
+    /// assert_eq!(h.entries(), 6);
+    /// assert_eq!(h.recorded(), 3);
+    /// assert_eq!(h.entries_missed(), 3);
+    /// assert_eq!((h.missed_small(), h.missed_large(), h.missed_unknown()), (1, 2, 0));
+    pub fn recorded(&self) -> u64 {
+        self.entries().saturating_sub(self.entries_missed())
+    }
+
+    /// return the number of entries which were not stored, being too small,
+    /// too large, or failing to index
+    pub fn entries_missed(&self) -> u64 {
+        self.data.counters.missed()
+    }
+
+    /// return the number of entries below the smallest storable value
+    pub fn missed_small(&self) -> u64 {
+        self.data.counters.missed_small
+    }
+
+    /// return the number of entries above max_value rejected by the overflow
+    /// policy
+    pub fn missed_large(&self) -> u64 {
+        self.data.counters.missed_large
+    }
+
+    /// return the number of entries which failed to index
+    pub fn missed_unknown(&self) -> u64 {
+        self.data.counters.missed_unknown
+    }
+
+    /// return a copy of the entry and missed counters
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(1).unwrap();
+    /// h.increment(0).unwrap_err();
+    ///
+    /// assert_eq!(h.counters().entries(), 2);
+    /// assert_eq!(h.counters().missed_small(), 1);
+    pub fn counters(&self) -> HistogramCounters {
+        self.data.counters
+    }
+
+    /// add a counters delta, as sent by a heartbeat, to the counters
+    ///
+    /// This keeps the totals current between less frequent syncs of the full
+    /// histogram. The delta's samples are not in any bucket, so percentiles
+    /// may fail until the buckets are brought up to date, eg by `reset_to()`
+    /// a full histogram which has passed `check_counters()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut source = Histogram::new().unwrap();
+    /// let mut central = Histogram::new().unwrap();
+    ///
+    /// let earlier = source.counters();
+    /// source.record(10, 5).unwrap();
+    /// central.apply_counter_delta(&source.counters().since(&earlier));
+    ///
+    /// assert_eq!(central.entries(), 5);
+    pub fn apply_counter_delta(&mut self, delta: &HistogramCounters) {
+        self.data.invalidate();
+        self.data.counters.add(delta);
+    }
+
+    /// check a full histogram against counters accumulated from deltas
+    ///
+    /// The full histogram must account for at least every sample counted
+    /// by the deltas, as it may be newer than the last delta but never older.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// let mut full = Histogram::new().unwrap();
+    /// let mut central = Histogram::new().unwrap();
+    ///
+    /// full.record(10, 5).unwrap();
+    /// central.apply_counter_delta(&full.counters());
+    /// assert_eq!(full.check_counters(&central.counters()), Ok(()));
+    ///
+    /// central.apply_counter_delta(&full.counters());
+    /// assert_eq!(full.check_counters(&central.counters()),
+    ///            Err(HistogramError::CountersMismatch));
+    pub fn check_counters(&self, expected: &HistogramCounters) -> Result<(), HistogramError> {
+        let counters = &self.data.counters;
+        if counters.entries_total < expected.entries_total ||
+           counters.missed_unknown < expected.missed_unknown ||
+           counters.missed_small < expected.missed_small ||
+           counters.missed_large < expected.missed_large {
+            return Err(HistogramError::CountersMismatch);
+        }
+        Ok(())
+    }
+
+    /// return the time since the Histogram was created or last cleared
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let h = Histogram::new().unwrap();
+    ///
+    /// let elapsed = h.elapsed();
+    pub fn elapsed(&self) -> Duration {
+        let now = self.clock.now();
+        if now > self.data.started {
+            now - self.data.started
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+
+    /// return the entries recorded per second since the Histogram was created
+    /// or last cleared, 0.0 if no time has elapsed
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig, ManualClock};
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// let clock = Arc::new(ManualClock::new());
+    /// let mut h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.increment(value).unwrap();
+    /// }
+    /// clock.advance(Duration::from_secs(4));
+    ///
+    /// assert_eq!(h.rate(), 25.0);
+    pub fn rate(&self) -> f64 {
+        let seconds = self.elapsed();
+        let seconds = seconds.as_secs() as f64 + seconds.subsec_nanos() as f64 / 1e9;
+        if seconds > 0.0 {
+            self.entries() as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// verify the internal consistency of the Histogram
+    ///
+    /// This is meant for tests and fuzzing, and describes the first invariant
+    /// found to be broken.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(1).unwrap();
+    /// h.increment(0).unwrap_err();
+    ///
+    /// assert!(h.check_invariants().is_ok());
+    pub fn check_invariants(&self) -> Result<(), &'static str> {
+        let properties = match HistogramProperties::new(&self.config) {
+            Ok(properties) => properties,
+            Err(_) => return Err("config does not produce a bucket layout"),
+        };
+        if properties.buckets_total != self.properties.buckets_total {
+            return Err("bucket layout does not match config");
+        }
+
+        // released buckets are all zero
+        let data = &self.data.data;
+        if !data.is_empty() && data.len() != self.properties.buckets_total as usize {
+            return Err("bucket count does not match layout");
+        }
+
+        // saturated buckets drop counts, so stored may fall short of entries
+        let counters = &self.data.counters;
+        let accounted = data.iter()
+                            .fold(0_u64, |total, count| total.saturating_add(*count))
+                            .saturating_add(counters.missed_small)
+                            .saturating_add(counters.missed_large)
+                            .saturating_add(counters.missed_unknown);
+        if accounted > counters.entries_total {
+            return Err("more samples accounted for than recorded");
+        }
+
+        let mut found = self.data.clone();
+        found.find_occupied();
+        if found.occupied != self.data.occupied {
+            return Err("occupied range does not match buckets");
+        }
+
+        Ok(())
+    }
+
+    /// return the most recent value successfully recorded since the Histogram
+    /// was created or last cleared
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// assert_eq!(h.last_value(), None);
+    ///
+    /// h.increment(5).unwrap();
+    /// h.increment(0).unwrap_err();
+    ///
+    /// assert_eq!(h.last_value(), Some(5));
+    pub fn last_value(&self) -> Option<u64> {
+        self.data.last.map(|last| last.0)
+    }
+
+    /// return when the most recent value was successfully recorded, according
+    /// to the Histogram clock
+    pub fn last_recorded_at(&self) -> Option<Instant> {
+        self.data.last.map(|last| last.1)
+    }
+
+    /// return the number of buckets in the Histogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    ///
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// assert_eq!(h.buckets_total(), 27023);
+    ///
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000_000_000);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// assert_eq!(h.buckets_total(), 21023);
+    ///
+    /// c.precision(4);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// assert_eq!(h.buckets_total(), 176383);
+    ///
+    /// c.precision(2);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// assert_eq!(h.buckets_total(), 2427);
+    pub fn buckets_total(&self) -> u64 {
+        self.properties.buckets_total
+    }
+
+    /// return the number of buckets in each power of two above the linear
+    /// buckets, the radix to the power of the precision
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let h = Histogram::new().unwrap();
+    ///
+    /// assert_eq!(h.buckets_inner(), 1_000);
+    /// assert_eq!(h.buckets_outer(), 26);
+    pub fn buckets_inner(&self) -> u64 {
+        self.properties.buckets_inner
+    }
+
+    /// return the number of powers of two from the linear buckets up to
+    /// max_value, each split into `buckets_inner()` buckets
+    pub fn buckets_outer(&self) -> u64 {
+        self.properties.buckets_outer
+    }
+
+    /// return the precision of the config, the number of significant digits
+    /// stored of each value
+    pub fn precision(&self) -> u32 {
+        self.config.precision
+    }
+
+    /// return the max_value of the config
+    pub fn max_value(&self) -> u64 {
+        self.config.max_value
+    }
+
+    /// return a copy of the config
+    ///
+    /// Histograms whose configs are equal share a bucket layout, so merge
+    /// without loss. Equality also compares settings which do not change
+    /// the layout, such as max_memory and the percentile method.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000_000);
+    ///
+    /// let a = Histogram::configured(c).unwrap();
+    /// let b = Histogram::configured(c).unwrap();
+    ///
+    /// assert_eq!(a.config(), b.config());
+    /// assert!(a.config() != Histogram::new().unwrap().config());
+    pub fn config(&self) -> HistogramConfig {
+        self.config
+    }
+
+    /// return the memory used in bytes: the bucket counts as allocated, the
+    /// peak timestamps if tracked, the running totals of the counts while
+    /// kept, and the fixed size of the Histogram and the shared allocation
+    /// holding its counts
+    ///
+    /// The max_memory limit applies to the bucket counts alone, 8 bytes
+    /// each. The running totals take 8 bytes for each bucket from the lowest
+    /// to the highest holding samples, built by the first percentile query
+    /// after the counts change and dropped by the next change. Clones
+    /// sharing the counts or totals until written each count them.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let h = Histogram::new().unwrap();
+    ///
+    /// assert!(h.memory_used() > h.buckets_total() * 8);
+    /// assert!(h.memory_used() < h.buckets_total() * 8 + 1_024);
+    pub fn memory_used(&self) -> u64 {
+        let buckets = self.data.data.capacity() * mem::size_of::<u64>();
+        let peaks = self.data.peaks.len() * mem::size_of::<Option<Instant>>();
+        let totals = self.data.totals.get().map_or(0, RunningTotals::memory_used);
+        (mem::size_of::<Histogram>() + shared_overhead() + buckets + peaks + totals) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{Clock, Histogram, HistogramConfig, HistogramCounters, HistogramError,
+                HistogramProperties, HistogramStats, ManualClock, MergedView, OverflowPolicy,
+                PercentileMethod, RecordOutcome};
+    use super::test_rng::Rng;
+
+    #[test]
+    fn test_new_0() {
+        // this histogram has only a linear region which runs 1-15
+
+        let mut c = HistogramConfig::new();
+        c.max_value(10).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.properties.buckets_inner, 10); // 10 ^ precision
+        assert_eq!(h.properties.buckets_outer, 0); // max <= 2 * buckets_inner
+        assert_eq!(h.properties.buckets_total, 15); // only linear region
+    }
+
+    #[test]
+    fn test_new_1() {
+        // this histogram has linear and log regios
+
+        let mut c = HistogramConfig::new();
+        c.max_value(31).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.properties.buckets_inner, 10); // 10 ^ precision
+        assert_eq!(h.properties.buckets_outer, 1); // max <= 2 * buckets_inner
+        assert_eq!(h.properties.buckets_total, 25); // only linear region
+    }
+
+    #[test]
+    fn test_new_2() {
+        let mut c = HistogramConfig::new();
+        c.max_value(32).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.properties.buckets_inner, 10); // 10 ^ precision
+        assert_eq!(h.properties.buckets_outer, 2); // max <= 2 * buckets_inner
+        assert_eq!(h.properties.buckets_total, 35); // only linear region
+    }
+
+    #[test]
+    fn test_new_3() {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000).precision(3);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.properties.buckets_inner, 1000); // 10 ^ precision
+        assert_eq!(h.properties.buckets_outer, 4); // max <= 2 * buckets_inner
+        assert_eq!(h.properties.buckets_total, 5023); // only linear region
+    }
+
+    #[test]
+    fn test_increment_0() {
+        let mut h = Histogram::new().unwrap();
+
+        for op in 1..1000000 {
+            h.increment(1).unwrap();
+            assert_eq!(h.entries(), op);
+        }
+    }
+
+    #[test]
+    fn test_increment_1() {
+        let mut c = HistogramConfig::new();
+        c.max_value(10).precision(3);
+        let mut h = Histogram::configured(c).unwrap();
+
+        // increment values across the entire range
+        // including 0 and > max_value
+        for v in 1..11 {
+            h.increment(v).unwrap();
+            assert_eq!(h.entries(), v);
+        }
+    }
+
+    #[test]
+    fn test_collect_extend() {
+        let h: Histogram = (1..1_000).collect();
+        assert_eq!(h.entries(), 999);
+        assert_eq!(h.percentile(50.0).unwrap(), 500);
+
+        // out of range values are tallied and the rest still recorded
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+        h.extend(vec![5, 2_000, 10, u64::MAX, 0, 1_000]);
+        assert_eq!(h.entries(), 6);
+        assert_eq!((h.missed_small(), h.missed_large()), (1, 2));
+        assert_eq!(h.maximum(), Ok(1_000));
+
+        h.extend(vec![(10, 4), (3_000, 2), (20, 0)]);
+        assert_eq!(h.get(10), Some(5));
+        assert_eq!(h.missed_large(), 4);
+    }
+
+    #[test]
+    fn test_duration() {
+        let mut h = Histogram::new().unwrap();
+        h.increment_duration(Duration::from_micros(1_500)).unwrap();
+        let (low, high) = h.bucket_bounds(1_500_000).unwrap();
+        let p50 = h.percentile(50.0).unwrap();
+        assert!(low <= p50 && p50 <= high, "p50: {}", p50);
+        assert!((p50 as f64 - 1_500_000.0).abs() / 1_500_000.0 < 0.001);
+
+        // too long for the range, or for u64 nanoseconds
+        let long = Duration::from_secs(100);
+        assert_eq!(h.increment_duration(long), Err(HistogramError::TooLarge));
+        assert_eq!(h.increment_duration(Duration::MAX), Err(HistogramError::TooLarge));
+        assert_eq!(h.missed_large(), 2);
+
+        let mut c = HistogramConfig::new();
+        c.max_value(u64::MAX);
+        let mut h = Histogram::configured(c).unwrap();
+        h.increment_duration(Duration::MAX).unwrap();
+        assert_eq!(h.stats().unwrap().max(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_get() {
+        let mut h = Histogram::new().unwrap();
+
+        h.increment(1).unwrap();
+        assert_eq!(h.get(1), Some(1));
+
+        h.increment(1).unwrap();
+        assert_eq!(h.get(1), Some(2));
+
+        h.increment(2).unwrap();
+        assert_eq!(h.get(2), Some(1));
+
+        assert_eq!(h.get(3), Some(0));
+    }
+
+    #[test]
+    fn test_count_for_equivalent() {
+        let mut rng = Rng(0x3c6e_f372_fe94_f82b);
+        for &(precision, max_value, min_value) in &[(1, 100, 1),
+                                                    (2, 1_000_000, 1),
+                                                    (3, 60_000_000_000, 1_000)] {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).max_value(max_value).min_value(min_value);
+            let mut h = Histogram::configured(c).unwrap();
+            let values: Vec<u64> = (0..1_000).map(|_| rng.next() % (max_value + 2)).collect();
+            for value in values.iter() {
+                let _ = h.increment(*value);
+            }
+
+            for value in values.iter().copied().chain([0, 1, min_value, max_value]) {
+                let equivalent = h.count_for_equivalent(value);
+                assert_eq!(equivalent.map(|(low, high, _)| (low, high)),
+                           h.bucket_bounds(value),
+                           "value: {}",
+                           value);
+                assert_eq!(equivalent.map(|(_, _, count)| count), h.get(value));
+                if let Some((low, high, count)) = equivalent {
+                    assert!(low <= value && value <= high);
+                    let held = values.iter().filter(|v| low <= **v && **v <= high).count();
+                    assert_eq!(count, held as u64, "value: {}", value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_out_of_range() {
+        for &(max, precision) in &[(10, 1), (31, 1), (32, 1), (1_000, 2), (10_000, 3),
+                                   (60_000_000_000, 3), (u64::MAX, 2)] {
+            let mut c = HistogramConfig::new();
+            c.max_value(max).precision(precision);
+            let mut h = Histogram::configured(c).unwrap();
+
+            if max < u64::MAX {
+                h.increment(max).unwrap();
+                assert_eq!(h.get(max), Some(1), "max: {}", max);
+                assert_eq!(h.get(max + 1), None);
+                assert_eq!(h.get(u64::MAX), None);
+            } else {
+                let _ = h.increment(max);
+                let _ = h.get(max);
+            }
+            assert_eq!(h.get(0), None);
+        }
+    }
+
+    #[test]
+    fn test_get_index_0() {
+        let mut c = HistogramConfig::new();
+        c.max_value(32).precision(3);
+        let h = Histogram::configured(c).unwrap();
+
+        // all values should index directly to (value - 1)
+        // no estimated buckets are needed given the precision and max
+
+        assert_eq!(h.get_index(1), Some(0));
+        assert_eq!(h.index_value(0), 1);
+
+        assert_eq!(h.get_index(2), Some(1));
+        assert_eq!(h.index_value(1), 2);
+
+        assert_eq!(h.get_index(3), Some(2));
+        assert_eq!(h.index_value(2), 3);
+
+        assert_eq!(h.get_index(4), Some(3));
+        assert_eq!(h.index_value(3), 4);
+
+        assert_eq!(h.get_index(5), Some(4));
+        assert_eq!(h.index_value(4), 5);
+
+        assert_eq!(h.get_index(16), Some(15));
+        assert_eq!(h.index_value(15), 16);
+
+        assert_eq!(h.get_index(32), Some(31));
+        assert_eq!(h.index_value(31), 32);
+    }
+
+    #[test]
+    fn test_get_index_1() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.get_index(1), Some(0));
+        assert_eq!(h.get_index(2), Some(1));
+        assert_eq!(h.get_index(15), Some(14));
+
+        // powers of two are 10 apart from value = 16 and up
+        assert_eq!(h.get_index(16), Some(15));
+        assert_eq!(h.get_index(32), Some(25));
+        assert_eq!(h.get_index(64), Some(35));
+
+        // this tests that rounding up within inner bucket works
+        assert_eq!(h.get_index(16), Some(15));
+        assert_eq!(h.get_index(17), Some(15));
+        assert_eq!(h.get_index(18), Some(16));
+        assert_eq!(h.get_index(19), Some(16));
+
+        // these values prove roll-up into next outer bucket works
+        assert_eq!(h.get_index(62), Some(34));
+        assert_eq!(h.get_index(63), Some(34));
+        assert_eq!(h.get_index(64), Some(35));
+
+        assert_eq!(h.get_index(65), Some(35));
+    }
+
+    #[test]
+    fn test_bucket_bounds_round_trip() {
+        let mut configs = Vec::new();
+        for &(precision, max, min) in &[(1, 100, 1),
+                                        (1, 1_000_000, 1),
+                                        (2, 1_000_000, 1),
+                                        (3, 10_000_000, 1),
+                                        (2, 1_000_000, 5_000),
+                                        (1, u64::MAX, 1)] {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).max_value(max).min_value(min);
+            configs.push(c);
+        }
+
+        for c in configs {
+            let h = Histogram::configured(c).unwrap();
+            let max = c.max_value;
+            let mut previous_high = c.min_value;
+            for bucket in h.iter() {
+                let id = bucket.id() as usize;
+                let (low, high) = (bucket.low(), bucket.high());
+                assert_eq!(bucket.width(), high - low);
+                if bucket.width() == 0 {
+                    // beyond max_value, where no value maps
+                    assert!(low > max, "bucket {}", id);
+                    continue;
+                }
+                assert_eq!(low, previous_high, "bucket {}", id);
+                previous_high = high;
+                assert!(low <= bucket.value() && bucket.value() < high);
+
+                assert_eq!(h.get_index(low), Some(id));
+                assert_eq!(h.get_index(high - 1), Some(id));
+                if high == u64::MAX {
+                    // the range saturates, so u64::MAX is in the top bucket
+                    assert_eq!(h.get_index(high), Some(id));
+                } else if high <= max {
+                    assert_eq!(h.get_index(high), Some(id + 1));
+                } else {
+                    // the bucket holding max_value ends just above it
+                    assert_eq!(high - 1, max);
+                }
+            }
+            assert_eq!(previous_high, max.saturating_add(1));
+        }
+    }
+
+    #[test]
+    fn test_get_index_2() {
+        // extensive test from precomputed table
+        let mut c = HistogramConfig::new();
+        c.max_value(100).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        let v = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 21, 23, 24,
+                     26, 28, 29, 31, 32, 36, 39, 42, 45, 48, 52, 55, 58, 61, 64, 71, 77, 84, 90,
+                     96, 103, 109, 116, 122];
+
+        for (index, value) in v.iter().enumerate() {
+            let got = h.get_index(*value).unwrap();
+            assert!(got == index,
+                    "Value: {} Got: {} Want: {}",
+                    value,
+                    got,
+                    index);
+        }
+
+        for (index, value) in v.iter().enumerate() {
+            let got = h.index_value(index);
+            assert!(got == *value,
+                    "Index: {} Got: {} Want: {}",
+                    index,
+                    got,
+                    value);
+        }
+    }
+
+    #[test]
+    fn test_get_index_3() {
+        // extensive test from precomputed table
+        let mut c = HistogramConfig::new();
+        c.max_value(250).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        let v = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 21, 23, 24,
+                     26, 28, 29, 31, 32, 36, 39, 42, 45, 48, 52, 55, 58, 61, 64, 71, 77, 84, 90,
+                     96, 103, 109, 116, 122, 128, 141, 154, 167, 180, 192, 205, 218, 231, 244];
+
+        for (index, value) in v.iter().enumerate() {
+            let got = h.get_index(*value).unwrap();
+            assert!(got == index,
+                    "Value: {} Got: {} Want: {}",
+                    value,
+                    got,
+                    index);
+        }
+
+        for (index, value) in v.iter().enumerate() {
+            let got = h.index_value(index);
+            assert!(got == *value,
+                    "Index: {} Got: {} Want: {}",
+                    index,
+                    got,
+                    value);
+        }
+    }
+
+    #[test]
+    fn test_get_index_offset() {
+        // the precomputed table of test_get_index_3, starting at min_value
+        let mut c = HistogramConfig::new();
+        c.max_value(250).precision(1).min_value(16);
+        let h = Histogram::configured(c).unwrap();
+
+        let v = vec![16, 18, 20, 21, 23, 24, 26, 28, 29, 31, 32, 36, 39, 42, 45, 48, 52, 55, 58,
+                     61, 64, 71, 77, 84, 90, 96, 103, 109, 116, 122, 128, 141, 154, 167, 180, 192,
+                     205, 218, 231, 244];
+        assert_eq!(h.buckets_total(), v.len() as u64);
+
+        for (index, value) in v.iter().enumerate() {
+            let got = h.get_index(*value).unwrap();
+            assert!(got == index,
+                    "Value: {} Got: {} Want: {}",
+                    value,
+                    got,
+                    index);
+        }
+
+        for (index, value) in v.iter().enumerate() {
+            let got = h.index_value(index);
+            assert!(got == *value,
+                    "Index: {} Got: {} Want: {}",
+                    index,
+                    got,
+                    value);
+        }
+
+        assert_eq!(h.get_index(15), None);
+        assert_eq!(h.bucket_bounds(15), None);
+        assert_eq!(h.bucket_bounds(16), Some((16, 17)));
+
+        // a min_value within a bucket narrows the first bucket
+        c.min_value(17);
+        let h = Histogram::configured(c).unwrap();
+        assert_eq!(h.buckets_total(), v.len() as u64);
+        assert_eq!(h.get_index(16), None);
+        assert_eq!(h.get_index(17), Some(0));
+        assert_eq!(h.index_value(0), 17);
+        assert_eq!(h.bucket_bounds(17), Some((17, 17)));
+        assert_eq!(h.get_index(18), Some(1));
+    }
+
+    #[test]
+    fn test_bucket_fixtures_offset() {
+        for &(radix, precision, max_value, value, index) in BUCKET_FIXTURES {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).max_value(max_value);
+            c.radix = radix;
+            let full = HistogramProperties::new(&c).unwrap();
+
+            for &min_value in &[2, 1_000, value, value.saturating_add(1)] {
+                if min_value > max_value {
+                    continue;
+                }
+                c.min_value(min_value);
+                let properties = HistogramProperties::new(&c).unwrap();
+                let offset = full.get_index(min_value).unwrap();
+                assert_eq!(properties.buckets_total as usize, full.buckets_total as usize - offset);
+
+                if value < min_value {
+                    assert_eq!(properties.get_index(value), None);
+                    continue;
+                }
+                assert_eq!(properties.get_index(value),
+                           Some(index - offset),
+                           "fixture: {:?}",
+                           (c, value));
+                let low = properties.index_low(index - offset);
+                assert_eq!(low, full.index_low(index).max(min_value));
+                assert_eq!(properties.get_index(low), Some(index - offset));
+                let nominal = properties.index_value(index - offset);
+                assert_eq!(nominal, full.index_value(index).max(min_value));
+                assert_eq!(properties.get_index(nominal), Some(index - offset));
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_value() {
+        let mut c = HistogramConfig::new();
+        c.max_value(60_000_000_000);
+        let full = Histogram::configured(c).unwrap();
+        c.min_value(4096);
+        let mut h = Histogram::configured(c).unwrap();
+
+        // the buckets below 4096 are over a tenth of the layout
+        assert_eq!(full.buckets_total(), 27_023);
+        assert_eq!(h.buckets_total(), 24_000);
+        assert!(h.memory_used() * 10 < full.memory_used() * 9);
+
+        // statistics match a histogram without the offset
+        let mut expected = full.clone();
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let value = 4_096 + rng.next() % 10_000_000;
+            h.increment(value).unwrap();
+            expected.increment(value).unwrap();
+        }
+        for p in &[1.0, 10.0, 50.0, 90.0, 99.9, 100.0] {
+            assert_eq!(h.percentile(*p), expected.percentile(*p), "percentile: {}", p);
+        }
+        assert_eq!(h.mean(), expected.mean());
+        assert_eq!(h.stddev(), expected.stddev());
+        assert_eq!(h.minimum(), expected.minimum());
+        assert_eq!(h.percentile_below(10_000), expected.percentile_below(10_000));
+        assert_eq!(h.count_between(0, 20_000), expected.count_between(0, 20_000));
+
+        // values below min_value are missed as too small
+        assert_eq!(h.increment(4_095), Err(HistogramError::TooSmall));
+        assert_eq!(h.increment(0), Err(HistogramError::TooSmall));
+        assert_eq!(h.counters().missed_small(), 2);
+        assert_eq!(h.get(4_095), None);
+        h.check_invariants().unwrap();
+
+        // a min_value above max_value leaves no buckets
+        c.max_value(1_000).min_value(1_001);
+        assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::InvalidConfig);
+        c.min_value(0);
+        assert_eq!(Histogram::configured(c).unwrap().buckets_total(), 1_023);
+    }
+
+    #[test]
+    fn test_values() {
+        let mut c = HistogramConfig::new();
+        c.max_value(250).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        let values: Vec<u64> = h.values().collect();
+        assert_eq!(values.len() as u64, h.buckets_total());
+        for (index, value) in values.iter().enumerate() {
+            assert_eq!(*value, h.index_value(index));
+        }
+        assert_eq!(h.values().size_hint(), (values.len(), Some(values.len())));
+
+        let between: Vec<u64> = h.values_between(16, 64).collect();
+        let first = h.get_index(16).unwrap();
+        let last = h.get_index(64).unwrap();
+        assert_eq!(between, &values[first..(last + 1)]);
+
+        assert_eq!(h.values_between(0, 0).count(), 0);
+        assert_eq!(h.values_between(64, 16).count(), 0);
+        assert_eq!(h.values_between(0, u64::MAX).count(), values.len());
+    }
+
+    #[test]
+    fn test_index_value_0() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100).precision(1);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.index_value(0), 1);
+        assert_eq!(h.index_value(1), 2);
+        assert_eq!(h.index_value(14), 15);
+
+        assert_eq!(h.index_value(15), 16);
+        assert_eq!(h.index_value(25), 32);
+        assert_eq!(h.index_value(35), 64);
+    }
+
+    #[test]
+    fn test_index_value_1() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(2);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.index_value(0), 1);
+        assert_eq!(h.index_value(1), 2);
+        assert_eq!(h.index_value(126), 127);
+
+        assert_eq!(h.index_value(127), 128);
+        assert_eq!(h.index_value(227), 256);
+        assert_eq!(h.index_value(327), 512);
+    }
+
+    #[test]
+    fn test_index_value_2() {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000).precision(3);
+        let h = Histogram::configured(c).unwrap();
+
+        assert_eq!(h.index_value(0), 1);
+        assert_eq!(h.index_value(1), 2);
+        assert_eq!(h.index_value(1022), 1023);
+
+        assert_eq!(h.index_value(1023), 1024);
+        assert_eq!(h.index_value(2023), 2048);
+        assert_eq!(h.index_value(3023), 4096);
+        assert_eq!(h.index_value(4023), 8192);
+    }
+
+    #[test]
+    fn test_bucket_bounds() {
+        for &(max, precision) in &[(250, 1), (10_000, 2), (1_000_000, 3)] {
+            let mut c = HistogramConfig::new();
+            c.max_value(max).precision(precision);
+            let h = Histogram::configured(c).unwrap();
+
+            let mut expected_low = 1;
+            for index in 0..(h.buckets_total() as usize) {
+                let (low, high) = h.index_bounds(index);
+                if low > max {
+                    break;
+                }
+                assert_eq!(low, expected_low, "index: {}", index);
+                assert!(low <= h.index_value(index) && h.index_value(index) <= high);
+                assert_eq!(h.get_index(low), Some(index));
+                assert_eq!(h.get_index(high), Some(index));
+                assert_eq!(h.bucket_bounds(low), Some((low, high)));
+                if high == max {
+                    break;
+                }
+                expected_low = high + 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_percentile_bounds() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+
+        // values in both the linear and logarithmic regions
+        for value in 1..1000 {
+            h.increment(value * 97).unwrap();
+            h.increment(value % 100 + 1).unwrap();
+        }
+
+        for p in 0..1001 {
+            let p = p as f64 / 10.0;
+            let value = h.percentile(p).unwrap();
+            let (low, high) = h.percentile_bounds(p).unwrap();
+            assert!(low <= value && value <= high, "percentile: {}", p);
+            assert_eq!(h.bucket_bounds(value), Some((low, high)));
+        }
+
+        assert_eq!(h.clone_empty().percentile_bounds(50.0), Err(HistogramError::Empty));
+    }
+
+    #[test]
+    fn test_emd() {
+        let mut a = Histogram::new().unwrap();
+        let mut b = Histogram::new().unwrap();
+
+        assert_eq!(a.emd(&b), Err(HistogramError::Empty));
+
+        for value in 1..500 {
+            a.increment(value).unwrap();
+            b.increment(value + 250).unwrap();
+        }
+
+        assert_eq!(a.emd(&a.clone()).unwrap(), 0.0);
+        assert!((a.emd(&b).unwrap() - 250.0).abs() < 1e-6);
+        assert!((b.emd(&a).unwrap() - 250.0).abs() < 1e-6);
+
+        // scaling the counts doesn't change the normalized distance
+        let mut c = a.clone();
+        c.merge(&a);
+        assert_eq!(a.emd(&c).unwrap(), 0.0);
+    }
+
+    #[test]
+    fn test_emd_log_region() {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000_000).precision(2);
+        let mut a = Histogram::configured(c).unwrap();
+        let mut b = Histogram::new().unwrap();
+
+        let delta = 50_000;
+        for value in 0..10_000 {
+            a.increment(100_000 + value * 10).unwrap();
+            b.increment(100_000 + value * 10 + delta).unwrap();
+        }
+
+        // different configs and bucket widths, so only approximately delta
+        let distance = a.emd(&b).unwrap();
+        assert!((distance - delta as f64).abs() < delta as f64 * 0.05,
+                "distance: {}",
+                distance);
+    }
+
+    #[test]
+    fn test_merge_report_same_config() {
+        let mut a = Histogram::new().unwrap();
+        let mut b = Histogram::new().unwrap();
+        for value in 1..10_000 {
+            b.increment(value * 3).unwrap();
+        }
+
+        let report = a.merge(&b);
+        assert_eq!(report.transferred(), 9_999);
+        assert_eq!(report.dropped(), 0);
+        assert_eq!(report.max_relative_error(), 0.0);
+        assert_eq!(report.mean_relative_error(), 0.0);
+    }
+
+    #[test]
+    fn test_merge_same_config_exact() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+        // the combined stream recorded into one histogram, and split in two
+        let mut combined = Histogram::configured(c).unwrap();
+        let mut a = Histogram::configured(c).unwrap();
+        let mut b = Histogram::configured(c).unwrap();
+        for _ in 0..20_000 {
+            let value = rng.next() % 1_200_000;
+            let count = rng.next() % 5;
+            let _ = combined.record(value, count);
+            let _ = if rng.next().is_multiple_of(3) { &mut a } else { &mut b }.record(value, count);
+        }
+
+        let report = a.merge(&b);
+        assert!(!report.lossy());
+        assert_eq!(report.dropped(), 0);
+        assert_eq!(report.transferred(), b.recorded());
+        assert_eq!(a.data.data, combined.data.data);
+        assert_eq!(a.data.occupied, combined.data.occupied);
+        assert_eq!(a.counters(), combined.counters());
+        a.check_invariants().unwrap();
+
+        // bucket values at the bucket boundaries stay in their own buckets
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(1);
+        let mut a = Histogram::configured(c).unwrap();
+        let mut b = Histogram::configured(c).unwrap();
+        for value in a.values().filter(|&value| value <= 100_000).collect::<Vec<u64>>() {
+            b.increment(value).unwrap();
+        }
+        a.merge(&b);
+        assert_eq!(a.data.data, b.data.data);
+
+        // a different max_value re-buckets by value
+        c.max_value(200_000);
+        let mut other = Histogram::configured(c).unwrap();
+        assert!(other.merge(&b).lossy());
+        assert_eq!(other.entries(), b.entries());
+    }
+
+    #[test]
+    fn test_merge_report_coarser() {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000).precision(1);
+        let mut a = Histogram::configured(c).unwrap();
+
+        c.max_value(100_000).precision(3);
+        let mut b = Histogram::configured(c).unwrap();
+        for value in 1..50_000 {
+            b.increment(value).unwrap();
+        }
+
+        let report = a.merge(&b);
+        assert_eq!(report.transferred() + report.dropped(), 49_999);
+        assert_eq!(report.transferred(), a.count_between(1, 10_000));
+        let large: u64 = b.iter_from(1).filter(|x| x.value() > 10_000).map(|x| x.count()).sum();
+        assert_eq!(report.dropped_large(), large);
+
+        // precision 1 splits each power of two into 10 buckets
+        assert!(report.max_relative_error() > 0.0);
+        assert!(report.max_relative_error() <= 0.1);
+        assert!(report.mean_relative_error() <= report.max_relative_error());
+
+        // values below the destination min_value are dropped as small
+        let mut c = HistogramConfig::new();
+        c.min_value(4_096);
+        let mut a = Histogram::configured(c).unwrap();
+        let mut b = Histogram::new().unwrap();
+        b.record(10, 5).unwrap();
+        b.record(5_000, 2).unwrap();
+
+        let report = a.merge(&b);
+        assert_eq!(report.transferred(), 2);
+        assert_eq!(report.dropped_small(), 5);
+        assert_eq!(report.dropped_unknown(), 0);
+        assert_eq!(report.dropped(), 5);
+        assert_eq!(a.missed_small(), 5);
+    }
+
+    #[test]
+    fn test_merge_report_finer() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(3);
+        let mut a = Histogram::configured(c).unwrap();
+
+        c.precision(1);
+        let mut b = Histogram::configured(c).unwrap();
+        for value in 1..50_000 {
+            b.increment(value).unwrap();
+        }
+
+        let report = a.merge(&b);
+        assert_eq!(report.transferred(), 49_999);
+        assert!(report.max_relative_error() <= 0.001);
+    }
+
+    #[test]
+    fn test_merge_report_saturated() {
+        let mut a = Histogram::new().unwrap();
+        let mut b = Histogram::new().unwrap();
+        a.record(10, u64::MAX - 1).unwrap();
+        b.record(10, 5).unwrap();
+
+        let report = a.merge(&b);
+        assert_eq!(report.transferred(), 1);
+        assert_eq!(report.dropped_saturated(), 4);
+    }
+
+    #[test]
+    fn test_iterator() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100).precision(1);
+        let mut h = Histogram::configured(c).unwrap();
+
+        // the cursor is dropped between buckets so the histogram is free to
+        // record, and a new one resumes from the position
+        let mut position = 0;
+        while let Some(bucket) = {
+            let mut cursor = h.cursor();
+            cursor.resume(position);
+            cursor.next_chunk(1).pop()
+        } {
+            position += 1;
+            let _ = h.increment(bucket.value);
+        }
+        assert_eq!(position, h.buckets_total());
+        assert_eq!(h.entries(), h.buckets_total());
+    }
+
+    #[test]
+    fn test_percentile() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(4);
+        let mut h = Histogram::configured(c).unwrap();
+
+        for i in 100..200 {
+            h.increment(i).expect("error");
+        }
+
+        assert_eq!(h.percentile(0.0).unwrap(), 100);
+        assert_eq!(h.percentile(10.0).unwrap(), 109);
+        assert_eq!(h.percentile(25.0).unwrap(), 124);
+        assert_eq!(h.percentile(50.0).unwrap(), 149);
+        assert_eq!(h.percentile(75.0).unwrap(), 174);
+        assert_eq!(h.percentile(90.0).unwrap(), 189);
+        assert_eq!(h.percentile(95.0).unwrap(), 194);
+        assert_eq!(h.percentile(100.0).unwrap(), 199);
+    }
+
+    // every percentile of the samples lies in the bucket of the exact
+    // nearest-rank percentile, and agrees with the other percentile paths
+    fn check_distribution(c: HistogramConfig, samples: &mut [u64]) {
+        let mut h = Histogram::configured(c).unwrap();
+        for &sample in samples.iter() {
+            h.increment(sample).unwrap();
+        }
+        samples.sort_unstable();
+        let frozen = h.freeze();
+
+        for p in 0..1001 {
+            let p = p as f64 / 10.0;
+            let rank = ((samples.len() as f64 * (p / 100.0)).ceil() as usize).max(1);
+            let exact = samples[rank - 1];
+
+            let value = h.percentile(p).unwrap();
+            let (low, high) = h.percentile_bounds(p).unwrap();
+            assert!(low <= exact && exact <= high, "percentile: {} exact: {}", p, exact);
+            assert!(value.abs_diff(exact) <= high - low, "percentile: {}", p);
+            assert_eq!(h.percentiles(&[p]).unwrap(), vec![value], "percentile: {}", p);
+            assert_eq!(frozen.percentile(p), Ok(value), "percentile: {}", p);
+        }
+    }
+
+    #[test]
+    fn test_percentile_distributions() {
+        for &precision in &[1, 2, 3] {
+            let mut c = HistogramConfig::new();
+            c.max_value(1_000_000).precision(precision);
+
+            // uniform, including counts on bucket boundaries
+            let mut uniform: Vec<u64> = (1..1_001).collect();
+            check_distribution(c, &mut uniform);
+            let mut uniform: Vec<u64> = (1..10_001).map(|i| i * 97).collect();
+            check_distribution(c, &mut uniform);
+
+            // all identical
+            check_distribution(c, &mut [12_345; 7]);
+            check_distribution(c, &mut [1; 1]);
+
+            // two points, with either weighted more heavily
+            let mut two: Vec<u64> = vec![10; 3];
+            two.extend(vec![900_000; 97]);
+            check_distribution(c, &mut two);
+            let mut two: Vec<u64> = vec![10; 50];
+            two.extend(vec![900_000; 50]);
+            check_distribution(c, &mut two);
+            let mut two: Vec<u64> = vec![1_000; 999];
+            two.push(2_000);
+            check_distribution(c, &mut two);
+        }
+    }
+
+    #[test]
+    fn test_percentile_bad() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(4);
+        let mut h = Histogram::configured(c).unwrap();
+
+        let _ = h.increment(5_000);
+
+        assert!(h.percentile(0.0).is_err());
+        assert!(h.percentile(50.0).is_err());
+        assert!(h.percentile(100.0).is_err());
+
+        let _ = h.increment(1);
+
+        assert!(h.percentile(0.0).is_ok());
+
+        let _ = h.increment(500);
+        let _ = h.increment(500);
+
+        assert!(h.percentile(50.0).is_ok());
+    }
+
+    fn assert_only_missed(h: &Histogram, small: u64, large: u64) {
+        let e = HistogramError::OnlyMissedSamples {
+            small,
+            large,
+            unknown: 0,
+        };
+
+        assert_eq!(h.percentile(0.0), Err(e));
+        assert_eq!(h.percentile(50.0), Err(e));
+        assert_eq!(h.percentile(100.0), Err(e));
+        assert_eq!(h.minimum(), Err(e));
+        assert_eq!(h.maximum(), Err(e));
+        assert_eq!(h.mean(), Err(e));
+        assert_eq!(h.stdvar(), Err(e));
+        assert_eq!(h.stddev(), Err(e));
+        assert_eq!(h.percentile_below(10), Err(e));
+        assert_eq!(h.value_at_count(1), Err(e));
+        assert_eq!(h.freeze().percentile(50.0), Err(e));
+    }
+
+    #[test]
+    fn test_only_missed_small() {
+        let mut h = Histogram::new().unwrap();
+
+        for _ in 0..10 {
+            let _ = h.increment(0);
+        }
+
+        assert_only_missed(&h, 10, 0);
+    }
+
+    #[test]
+    fn test_only_missed_large() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+
+        let _ = h.record(1_001, 3);
+        let _ = h.record(u64::MAX, 4);
+
+        assert_only_missed(&h, 0, 7);
+    }
+
+    #[test]
+    fn test_only_missed_mixed() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+
+        let _ = h.record(0, 2);
+        let _ = h.record(5_000, 5);
+
+        assert_only_missed(&h, 2, 5);
+
+        h.increment(10).unwrap();
+        assert!(h.mean().is_ok());
+    }
+
+    #[test]
+    fn test_clone_copy_on_write() {
+        let mut a = Histogram::new().unwrap();
+        a.increment(1).unwrap();
+
+        let mut b = a.clone();
+        b.increment(1).unwrap();
+        b.increment(2).unwrap();
+
+        assert_eq!(a.get(1), Some(1));
+        assert_eq!(a.get(2), Some(0));
+        assert_eq!(a.entries(), 1);
+        assert_eq!(b.get(1), Some(2));
+        assert_eq!(b.entries(), 3);
+
+        let c = a.clone();
+        a.clear();
+        assert_eq!(a.get(1), Some(0));
+        assert_eq!(c.get(1), Some(1));
+
+        let mut d = c.clone();
+        d.merge(&b);
+        assert_eq!(c.entries(), 1);
+        assert_eq!(d.entries(), 4);
+    }
+
+    #[test]
+    fn test_clone_shares_until_write() {
+        let mut a = Histogram::new().unwrap();
+        for value in 1..100 {
+            a.increment(value).unwrap();
+        }
+
+        let b = a.clone();
+        assert!(Arc::ptr_eq(&a.data.data, &b.data.data));
+
+        // statistics never force a copy
+        let _ = b.percentile(50.0);
+        let _ = b.mean();
+        let _ = b.stddev();
+        let _ = b.get(10);
+        let _ = b.freeze();
+        assert!(Arc::ptr_eq(&a.data.data, &b.data.data));
+
+        a.increment(1).unwrap();
+        assert!(!Arc::ptr_eq(&a.data.data, &b.data.data));
+        assert_eq!(Arc::strong_count(&b.data.data), 1);
+    }
+
+    #[test]
+    fn test_record_n_reject() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100);
+        let mut h = Histogram::configured(c).unwrap();
+
+        let r = h.record_n(0, 3);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (0, 0, 3));
+
+        let r = h.record_n(101, 4);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (0, 0, 4));
+
+        let r = h.record_n(100, 5);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (5, 0, 0));
+
+        assert_eq!(h.entries(), 12);
+        assert_eq!(h.get(100), Some(5));
+    }
+
+    #[test]
+    fn test_record_n_clamp() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+
+        let r = h.record_n(u64::MAX, 2);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (0, 2, 0));
+        assert_eq!(h.record(1_000, 1), Ok(()));
+
+        assert_eq!(h.get(100), Some(3));
+        assert_eq!(h.maximum(), Ok(100));
+
+        // small values are still rejected
+        let r = h.record_n(0, 1);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (0, 0, 1));
+    }
+
+    #[test]
+    fn test_record_n_saturate() {
+        let mut h = Histogram::new().unwrap();
+
+        let r = h.record_n(1, u64::MAX - 5);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (u64::MAX - 5, 0, 0));
+
+        let r = h.record_n(1, 10);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (5, 0, 5));
+        assert_eq!(h.get(1), Some(u64::MAX));
+
+        let r = h.record_n(1, 10);
+        assert_eq!((r.stored(), r.clamped(), r.rejected()), (0, 0, 10));
+        assert_eq!(h.record(1, 1), Ok(()));
+    }
+
+    #[test]
+    fn test_clone_empty() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+
+        for value in 1..1000 {
+            h.increment(value * 7).unwrap();
+        }
+        let _ = h.increment(0);
+
+        let mut e = h.clone_empty();
+        assert_eq!(e.entries(), 0);
+        assert_eq!(e.buckets_total(), h.buckets_total());
+        assert!(e.config.matches(&h.config));
+        assert!(e.data.data.iter().all(|count| *count == 0));
+        assert!(!Arc::ptr_eq(&e.data.data, &h.data.data));
+
+        let mut fresh = Histogram::configured(c).unwrap();
+        for value in 1..1000 {
+            e.increment(value * 7).unwrap();
+            fresh.increment(value * 7).unwrap();
+        }
+        assert_eq!(e.data.data, fresh.data.data);
+        for p in 0..101 {
+            let p = p as f64;
+            assert_eq!(e.percentile(p), fresh.percentile(p));
+        }
+    }
+
+    #[test]
+    fn test_equality() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
+        let mut a = Histogram::configured(c).unwrap();
+        for value in 1..1000 {
+            a.increment(value * 7).unwrap();
+        }
+        let _ = a.increment(0);
+        let mut b = a.clone_empty();
+        b.reset_to(&a).unwrap();
+        assert_eq!(a, b);
+        assert!(a.approx_eq(&b, 0.0));
+
+        // a single count apart
+        b.increment(7).unwrap();
+        assert!(a != b);
+        assert!(!a.approx_eq(&b, 0.0));
+        assert!(a.approx_eq(&b, 0.5));
+
+        // equal counts but not counters
+        let mut missed = a.clone();
+        let _ = missed.increment(0);
+        assert!(a != missed);
+        assert!(a.approx_eq(&missed, 0.0));
+
+        // differing layouts are unequal rather than compared by bucket
+        let mut other = c;
+        other.precision(3);
+        let mut finer = Histogram::configured(other).unwrap();
+        finer.merge(&a);
+        assert!(finer != a);
+        assert!(!finer.approx_eq(&a, 1.0));
+        let empty = Histogram::configured(c).unwrap();
+        assert!(empty != Histogram::configured(other).unwrap());
+
+        // settings which keep the layout still compare equal
+        let mut clamped = c;
+        clamped.overflow(OverflowPolicy::Clamp);
+        assert_eq!(empty, Histogram::configured(clamped).unwrap());
+    }
+
+    #[test]
+    fn test_debug() {
+        let mut h = Histogram::new().unwrap();
+        assert_eq!(format!("{:?}", h),
+                   "Histogram { entries: 0, missed: 0, min: None, max: None, p50: None, \
+                    p99: None }");
+        for value in 1..101 {
+            h.increment(value).unwrap();
+        }
+        assert_eq!(format!("{:?}", h),
+                   "Histogram { entries: 100, missed: 0, min: Some(1), max: Some(100), \
+                    p50: Some(50), p99: Some(99) }");
+
+        // a sample missed as too small is left out, unless ranked
+        let _ = h.increment(0);
+        assert_eq!(format!("{:?}", h),
+                   "Histogram { entries: 101, missed: 1, min: Some(1), max: Some(100), \
+                    p50: Some(50), p99: Some(99) }");
+        h.config.rank_missed = true;
+        assert_eq!(format!("{:?}", h),
+                   "Histogram { entries: 101, missed: 1, min: None, max: Some(100), \
+                    p50: Some(50), p99: Some(99) }");
+    }
+
+    #[test]
+    fn test_reset_to() {
+        let mut source = Histogram::new().unwrap();
+        for value in 1..10_000 {
+            source.increment(value).unwrap();
+        }
+        let _ = source.increment(0);
+
+        let mut h = Histogram::new().unwrap();
+        h.increment(5).unwrap();
+        let before = Arc::as_ptr(&h.data.data);
+
+        h.reset_to(&source).unwrap();
+        assert_eq!(Arc::as_ptr(&h.data.data), before);
+        assert_eq!(h.data.data, source.data.data);
+        assert_eq!(h.entries(), source.entries());
+        assert_eq!(h.percentile(0.0), source.percentile(0.0));
+
+        // the source is unaffected by later writes
+        h.increment(5).unwrap();
+        assert_eq!(source.get(5), Some(1));
+
+        let mut c = HistogramConfig::new();
+        c.precision(2);
+        let mut other = Histogram::configured(c).unwrap();
+        assert_eq!(other.reset_to(&source), Err(HistogramError::IncompatibleConfig));
+    }
+
+    #[test]
+    fn test_mean_missed() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+        let mut stored = Histogram::configured(c).unwrap();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+        // about 2% of the samples above the cap, and some below the range
+        let mut sum = 0;
+        let mut count = 0;
+        for _ in 0..10_000 {
+            let value = rng.next() % 1_020;
+            if h.increment(value).is_ok() {
+                stored.increment(value).unwrap();
+                sum += value;
+                count += 1;
+            }
+        }
+        assert!(h.missed_large() > 100);
+        assert!(h.missed_small() > 0);
+        assert_eq!(h.recorded(), count);
+
+        // every value up to 1_000 has its own bucket, so the mean is exact
+        assert_eq!(h.mean().unwrap(), (sum as f64 / count as f64).ceil() as u64);
+        assert_eq!(h.mean(), stored.mean());
+        assert_eq!(h.stdvar(), stored.stdvar());
+        assert_eq!(h.stddev(), stored.stddev());
+
+        // clamping counts the large samples at max_value instead
+        c.overflow(OverflowPolicy::Clamp);
+        let mut clamped = Histogram::configured(c).unwrap();
+        clamped.record(500, 2).unwrap();
+        clamped.record(5_000, 2).unwrap();
+        assert_eq!(clamped.mean(), Ok(750));
+    }
+
+    #[test]
+    fn test_clear_entries() {
+        let mut c = HistogramConfig::new();
+        c.track_peaks(true);
+        let mut h = Histogram::configured(c).unwrap();
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..1_000_000 {
+            let _ = h.increment(rng.next() % 100_000_000_000);
+        }
+        assert_eq!(h.entries(), 1_000_000);
+        assert!(h.missed_large() > 0);
+        h.percentile(50.0).unwrap();
+
+        for shrink in &[false, true] {
+            let mut h = h.clone();
+            if *shrink {
+                h.clear_shrink();
+            } else {
+                h.clear();
+            }
+            assert_eq!((h.entries(), h.recorded()), (0, 0));
+            assert_eq!(h.counters(), HistogramCounters::new());
+            assert_eq!(h.data.occupied, None);
+            assert!(h.data.data.iter().all(|count| *count == 0));
+            assert_eq!(h.get(1_000), Some(0));
+            assert_eq!(h.count_between(0, u64::MAX), 0);
+            assert_eq!(h.iter_recorded().count(), 0);
+            assert_eq!(h.percentile(50.0), Err(HistogramError::Empty));
+            assert_eq!(h.mean(), Err(HistogramError::Empty));
+            assert_eq!(h.minimum(), Err(HistogramError::Empty));
+            assert_eq!(h.maximum(), Err(HistogramError::Empty));
+            assert_eq!(h.stats(), Some(HistogramStats::default()));
+            assert!(h.peak_times().is_empty());
+            assert_eq!(h, Histogram::configured(c).unwrap());
+            h.check_invariants().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_clear_shrink() {
+        let mut a = Histogram::new().unwrap();
+        a.record(10, 3).unwrap();
+        let full = a.memory_used();
+
+        // the buckets are released, and read as zero
+        a.clear_shrink();
+        assert!(a.memory_used() < full - a.buckets_total() * 4);
+        assert_eq!(a, Histogram::new().unwrap());
+        assert_eq!(a.count_in_bucket(0), Some(0));
+        assert_eq!(a.iter().count() as u64, a.buckets_total());
+        assert_eq!(Histogram::from_bytes(&a.to_bytes()).unwrap(), a);
+        a.check_invariants().unwrap();
+
+        // until the next write allocates them again
+        a.increment(30).unwrap();
+        assert_eq!(a.memory_used(), full);
+        assert_eq!((a.get(30), a.get(10), a.entries()), (Some(1), Some(0), 1));
+        a.check_invariants().unwrap();
+
+        // a clone of released buckets allocates its own
+        a.clear_shrink();
+        let mut b = a.clone();
+        b.increment(1).unwrap();
+        assert_eq!(a.data.data.len(), 0);
+        assert_eq!(b.get(1), Some(1));
+
+        // and a histogram reset to released buckets shares them
+        let mut c = Histogram::new().unwrap();
+        c.increment(1).unwrap();
+        c.reset_to(&a).unwrap();
+        assert!(Arc::ptr_eq(&a.data.data, &c.data.data));
+        assert_eq!(c, a);
+    }
+
+    #[test]
+    fn test_take() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).track_peaks(true);
+        let mut h = Histogram::configured(c).unwrap();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut snapshot = h.clone_empty();
+
+        for interval in 0..10 {
+            for _ in 0..1_000 {
+                let _ = h.increment(rng.next() % 1_100_000);
+            }
+            let expected = h.clone();
+            let buckets = Arc::as_ptr(&h.data.data);
+
+            let taken = if interval % 2 == 0 {
+                h.take()
+            } else {
+                // taking into the same snapshot swaps the two allocations
+                let reused = Arc::as_ptr(&snapshot.data.data);
+                h.take_into(&mut snapshot).unwrap();
+                assert_eq!(Arc::as_ptr(&h.data.data), reused);
+                snapshot.clone()
+            };
+            assert_eq!(Arc::as_ptr(&taken.data.data), buckets);
+
+            assert_eq!(taken.entries(), 1_000);
+            assert_eq!(taken.counters(), expected.counters());
+            for p in &[0.0, 50.0, 90.0, 99.0, 99.9] {
+                assert_eq!(taken.percentile(*p), expected.percentile(*p));
+            }
+            assert_eq!(taken.data.peaks, expected.data.peaks);
+
+            assert_eq!(h.entries(), 0);
+            assert_eq!(h.data.occupied, None);
+            assert!(h.data.data.iter().all(|count| *count == 0));
+            assert!(h.peak_times().is_empty());
+            assert_eq!(h.buckets_total(), taken.buckets_total());
+            // besides the running totals built by the percentiles above
+            let totals = taken.data.totals.get().map_or(0, super::RunningTotals::memory_used);
+            assert!(totals > 0);
+            assert_eq!(h.memory_used() + totals as u64, taken.memory_used());
         }
 
-        if percentile <= 100.0 && percentile >= 0.0 {
+        c.precision(2);
+        let mut other = Histogram::configured(c).unwrap();
+        assert_eq!(h.take_into(&mut other), Err(HistogramError::IncompatibleConfig));
+    }
 
-            let total = self.entries();
+    #[test]
+    fn test_prefault() {
+        let mut c = HistogramConfig::new();
+        c.precision(4).prefault(true);
+        let mut h = Histogram::configured(c).unwrap();
 
-            let mut need = (total as f64 * (percentile / 100.0_f64)).ceil() as u64;
+        assert!(h.data.data.iter().all(|count| *count == 0));
+        h.increment(1_000).unwrap();
+        assert_eq!(h.get(1_000), Some(1));
+        assert_eq!(h.clone_empty().entries(), 0);
+    }
 
-            if need > total {
-                need = total;
+    #[test]
+    #[ignore]
+    fn test_prefault_timing() {
+        use std::time::Instant;
+
+        // record one sample per page, which faults every page unless the
+        // buckets were prefaulted
+        fn time(prefault: bool) -> u128 {
+            let mut c = HistogramConfig::new();
+            c.precision(5).prefault(prefault);
+            let mut h = Histogram::configured(c).unwrap();
+
+            let step = super::PAGE_SIZE / 8;
+            let start = Instant::now();
+            for index in (0..h.buckets_total() as usize).step_by(step) {
+                let value = h.index_value(index);
+                let _ = h.increment(value);
             }
+            start.elapsed().as_nanos()
+        }
 
-            need = total - need;
-
-            let mut index: isize = (self.buckets_total() - 1) as isize;
-            let mut step: isize = -1 as isize;
-            let mut have: u64 = self.data.counters.missed_large;
+        let cold = time(false);
+        let warm = time(true);
+        println!("record without prefault: {} ns with prefault: {} ns", cold, warm);
+        assert!(warm < cold);
+    }
 
-            if percentile < 50.0 {
-                index = 0 as isize;
-                step = 1 as isize;
-                need = total - need;
-                have = self.data.counters.missed_small;
-            }
+    #[test]
+    fn test_rate() {
+        let clock = Arc::new(ManualClock::new());
+        let mut h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+
+        assert_eq!(h.elapsed(), Duration::from_secs(0));
+        assert_eq!(h.rate(), 0.0);
+
+        h.record(10, 50).unwrap();
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(h.elapsed(), Duration::from_millis(500));
+        assert_eq!(h.rate(), 100.0);
+
+        // clearing restarts the clock for both the entries and the elapsed time
+        h.clear();
+        assert_eq!(h.elapsed(), Duration::from_secs(0));
+        h.record(10, 30).unwrap();
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(h.rate(), 10.0);
+
+        // an empty copy starts now, reset_to takes the start of its source
+        let mut e = h.clone_empty();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(e.elapsed(), Duration::from_secs(1));
+        assert_eq!(h.elapsed(), Duration::from_secs(4));
+        e.reset_to(&h).unwrap();
+        assert_eq!(e.elapsed(), Duration::from_secs(4));
+    }
 
-            if need == 0 {
-                need = 1;
+    #[test]
+    fn test_random_operations() {
+        for seed in 1..9 {
+            let mut rng = Rng(seed);
+
+            let mut configs = Vec::new();
+            for _ in 0..2 {
+                let mut c = HistogramConfig::new();
+                c.precision(1 + (rng.next() % 3) as u32)
+                 .max_value(1 + rng.next() % 10_000_000)
+                 .overflow(if rng.next().is_multiple_of(2) {
+                     OverflowPolicy::Reject
+                 } else {
+                     OverflowPolicy::Clamp
+                 });
+                configs.push(c);
             }
-
-            if have >= need {
-                if index == 0 {
-                    return Err("underflow");
+            let mut histograms: Vec<Histogram> = configs.iter()
+                                                        .map(|c| Histogram::configured(*c).unwrap())
+                                                        .collect();
+
+            for _ in 0..2_000 {
+                let i = (rng.next() % 2) as usize;
+                let value = rng.next() % 20_000_000;
+                match rng.next() % 100 {
+                    0 => histograms[i].clear(),
+                    1 => {
+                        let other = histograms[1 - i].clone();
+                        histograms[i].merge(&other);
+                    }
+                    2 => {
+                        let other = histograms[i].clone();
+                        histograms[1 - i] = other.clone_empty();
+                        histograms[i].reset_to(&other).unwrap();
+                    }
+                    3 => {
+                        let _ = histograms[i].iter().nth(value as usize % 8);
+                    }
+                    4 => {
+                        let id = value as usize % histograms[i].buckets_total() as usize;
+                        let count = rng.next() % 3;
+                        histograms[i].set_bucket_count(id, count).unwrap();
+                    }
+                    5 => {
+                        let count = u64::MAX - rng.next() % 4;
+                        let _ = histograms[i].record(value, count);
+                    }
+                    6 => {
+                        let value = histograms[i].last_value().unwrap_or(value);
+                        let _ = histograms[i].subtract(value, rng.next() % 3);
+                    }
+                    7..=40 => {
+                        let _ = histograms[i].record_n(value, rng.next() % 1_000);
+                    }
+                    _ => {
+                        let _ = histograms[i].increment(value);
+                    }
                 }
-                return Err("overflow");
+                histograms[i].check_invariants().unwrap();
+                histograms[1 - i].check_invariants().unwrap();
             }
-            loop {
-                have = have + self.data.data[index as usize];
-
-                if have >= need {
-                    return Ok(self.index_value(index as usize) as u64);
-                }
+        }
+    }
 
-                index += step;
+    #[test]
+    fn test_sliding_window() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(2).overflow(OverflowPolicy::Clamp);
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+
+        let samples: Vec<u64> = (0..5_000).map(|_| rng.next() % 200_000).collect();
+        let mut window = Histogram::configured(c).unwrap();
+        for (i, value) in samples.iter().enumerate() {
+            let _ = window.increment(*value);
+            if i >= 500 {
+                window.decrement(samples[i - 500]).unwrap();
+            }
+            window.check_invariants().unwrap();
 
-                if index >= self.buckets_total() as isize {
-                    break;
+            if i % 250 == 0 {
+                let mut fresh = Histogram::configured(c).unwrap();
+                for value in &samples[i.saturating_sub(499)..(i + 1)] {
+                    let _ = fresh.increment(*value);
                 }
-                if index < 0 {
-                    break;
+                assert_eq!(window.data.data, fresh.data.data);
+                assert_eq!(window.data.occupied, fresh.data.occupied);
+                assert_eq!(window.counters(), fresh.counters());
+                for p in &[0.0, 25.0, 50.0, 90.0, 99.0, 100.0] {
+                    assert_eq!(window.percentile(*p), fresh.percentile(*p));
                 }
             }
         }
-        Err("unknown failure")
     }
 
-    /// convenience function for min
-    ///
-    /// # Example
-    /// # use histogram::*;
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// for value in 1..1000 {
-    ///     h.increment(value);
-    /// }
-    ///
-    /// assert_eq!(h.minimum().unwrap(), 1);
-    pub fn minimum(&self) -> Result<u64, &'static str> {
-        self.percentile(0.0_f64)
-    }
+    #[test]
+    fn test_missed_counters() {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000).precision(2);
+        let mut rng = Rng(0x853c_49e6_748f_ea9b);
+
+        for &overflow in &[OverflowPolicy::Reject, OverflowPolicy::Clamp] {
+            c.overflow(overflow);
+            let mut h = Histogram::configured(c).unwrap();
+            let (mut small, mut large) = (0, 0);
+            for _ in 0..10_000 {
+                let value = rng.next() % 12_000;
+                let count = rng.next() % 4;
+                let _ = h.record(value, count);
+                if value < 1 {
+                    small += count;
+                } else if value > 10_000 && overflow == OverflowPolicy::Reject {
+                    large += count;
+                }
+            }
 
-    /// convenience function for max
-    ///
-    /// # Example
-    /// # use histogram::*;
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// for value in 1..1000 {
-    ///     h.increment(value);
-    /// }
-    ///
-    /// assert_eq!(h.maximum().unwrap(), 999);
-    pub fn maximum(&self) -> Result<u64, &'static str> {
-        self.percentile(100.0_f64)
+            assert_eq!(h.missed_small(), small);
+            assert_eq!(h.missed_large(), large);
+            assert_eq!(h.missed_unknown(), 0);
+            assert_eq!(h.entries_missed(), small + large);
+            assert_eq!(h.recorded() + h.entries_missed(), h.entries());
+            assert_eq!(h.recorded(), h.data.data.iter().sum::<u64>());
+
+            let counters = h.counters();
+            assert_eq!(counters.missed_small(), h.missed_small());
+            assert_eq!(counters.missed_large(), h.missed_large());
+            assert_eq!(counters.entries(), h.entries());
+
+            // the rank of p100 is the last stored sample, unless missed large
+            // samples are ranked
+            let last = h.index_value(h.data.occupied.unwrap().1);
+            assert_eq!(h.percentile(100.0), Ok(last));
+            h.config.rank_missed = true;
+            let maximum = h.percentile(100.0);
+            match overflow {
+                OverflowPolicy::Reject => assert_eq!(maximum, Err(HistogramError::Overflow)),
+                OverflowPolicy::Clamp => {
+                    assert_eq!(maximum, Ok(h.index_value(h.get_index(10_000).unwrap())))
+                }
+            }
+            assert!(h.value_at_count(h.recorded()).is_ok());
+            assert!(h.value_at_count(h.recorded() + 1).is_err());
+        }
     }
 
-    /// arithmetic mean approximation across the histogram
-    ///
-    /// # Example
-    /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// for value in 1..1000 {
-    ///     h.increment(value);
-    /// }
-    ///
-    /// assert_eq!(h.mean().unwrap(), 500);
-    pub fn mean(&self) -> Result<u64, &'static str> {
+    #[test]
+    fn test_percentiles() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        assert_eq!(h.percentiles(&[50.0]), Err(HistogramError::Empty));
 
-        if self.entries() < 1 {
-            return Err("no data");
+        let mut rng = Rng(0xbb67_ae85_84ca_a73b);
+        for _ in 0..5_000 {
+            h.record(rng.next() % 1_000_000 + 1, rng.next() % 20).unwrap();
+        }
+        // a tall bucket near the median
+        h.record(400_000, 30_000).unwrap();
+
+        let targets = [99.9, 0.0, 50.0, 49.9, 50.1, 90.0, 50.0, 100.0, 1.0, 99.0, 25.0];
+        let values = h.percentiles(&targets).unwrap();
+        for (&p, &value) in targets.iter().zip(values.iter()) {
+            let total = h.entries();
+            let need = ((total as f64 * p / 100.0).ceil() as u64).clamp(1, total);
+            let mut have = 0;
+            let expected = (0..h.data.data.len())
+                .find(|&index| {
+                    have += h.data.data[index];
+                    have >= need
+                })
+                .map(|index| h.index_value(index))
+                .unwrap();
+            assert_eq!(value, expected, "percentile {}", p);
+            if p < 50.0 || p == 100.0 {
+                assert_eq!(value, h.percentile(p).unwrap(), "percentile {}", p);
+            }
         }
 
-        let total = self.entries();
-
-        let mut mean = 0.0_f64;
-
-        for index in 0..(self.buckets_total() as usize) {
-            mean += (self.index_value(index) as f64 * self.data.data[index] as f64) as f64 /
-                    total as f64;
+        let mut sorted = targets.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let values = h.percentiles(&sorted).unwrap();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", values);
+        assert_eq!(h.percentiles(&[]), Ok(vec![]));
+        assert_eq!(h.percentiles(&[50.0, 100.1]), Err(HistogramError::InvalidPercentile));
+
+        // one walk builds the running totals, reading each occupied bucket
+        // once, after which each rank reads only its own bucket
+        let reads = ::std::cell::Cell::new(0);
+        let count = |index: usize| {
+            reads.set(reads.get() + 1);
+            h.data.data[index]
+        };
+        let totals = super::RunningTotals::new(h.data.occupied, count);
+        let (low, high) = h.data.occupied.unwrap();
+        assert_eq!(reads.get(), high - low + 1);
+        reads.set(0);
+        for &p in &targets {
+            totals.position(&h.data.counters, h.data.occupied, count, super::Rank::Percent(p))
+                  .unwrap();
         }
-        Ok(mean.ceil() as u64)
+        assert!(reads.get() <= targets.len(), "{} reads", reads.get());
+
+        // missed samples at either end are left out, unless ranked
+        let stored = h.percentiles(&[0.0, 50.0, 100.0]).unwrap();
+        h.record(0, 10).unwrap_err();
+        h.record(2_000_000, 10).unwrap_err();
+        assert_eq!(h.percentiles(&[0.0, 50.0, 100.0]).unwrap(), stored);
+        h.config.rank_missed = true;
+        assert_eq!(h.percentiles(&[50.0, 0.0]), Err(HistogramError::Underflow));
+        assert_eq!(h.percentiles(&[1.0, 99.0]).unwrap()[1], h.percentiles(&[99.0]).unwrap()[0]);
+        assert_eq!(h.percentiles(&[100.0, 99.0]), Err(HistogramError::Overflow));
+        assert_eq!(h.percentiles(&[50.0]).unwrap().len(), 1);
     }
 
-    /// standard variance approximation across the histogram
-    ///
-    /// # Example
-    /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// for value in 1..11 {
-    ///     h.increment(value);
-    /// }
-    ///
-    /// assert_eq!(h.stdvar().unwrap(), 9);
-    pub fn stdvar(&self) -> Result<u64, &'static str> {
-
-        if self.entries() < 1 {
-            return Err("no data");
+    #[test]
+    fn test_shift_values_exact() {
+        let mut micros = HistogramConfig::new();
+        micros.max_value(60_000_000).precision(3).rank_missed(true);
+        let mut nanos = HistogramConfig::new();
+        nanos.max_value(60_000_000_000).precision(3).rank_missed(true);
+
+        let mut h = Histogram::configured(micros).unwrap();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            h.record(rng.next() % 60_000_000 + 1, rng.next() % 100).unwrap();
         }
-
-        let total = self.entries() as f64;
-
-        let m = self.mean().unwrap() as f64;
-
-        let mut stdvar = 0.0_f64;
-
-        for index in 0..(self.buckets_total() as usize) {
-            let v = self.index_value(index) as f64;
-            let c = self.data.data[index] as f64;
-            stdvar += (c * v * v) - (2_f64 * c * m * v) + (c * m * m);
+        h.record(0, 3).unwrap_err();
+        h.record(70_000_000, 2).unwrap_err();
+
+        let shifted = h.shift_values_exact_into(1_000, nanos).unwrap();
+        shifted.check_invariants().unwrap();
+        assert_eq!(shifted.counters(), h.counters());
+        assert_eq!(shifted.recorded(), h.recorded());
+        assert_eq!(shifted.data.data.iter().sum::<u64>(), h.data.data.iter().sum::<u64>());
+
+        // each percentile moves to the bucket of the value times 1000
+        for p in &[1.0, 25.0, 50.0, 75.0, 90.0, 99.0, 99.9] {
+            let value = h.percentile(*p).unwrap();
+            let (low, high) = shifted.bucket_bounds(value * 1_000).unwrap();
+            assert_eq!(shifted.percentile(*p).unwrap(), low);
+            assert!(low <= value * 1_000 && value * 1_000 <= high);
         }
+        assert_eq!(shifted.percentile(0.0), Err(HistogramError::Underflow));
+        assert_eq!(shifted.percentile(100.0), Err(HistogramError::Overflow));
+
+        // values past the destination max_value are rejected
+        assert_eq!(h.shift_values_exact(1_000).unwrap_err(), HistogramError::TooLarge);
+        assert_eq!(h.shift_values_exact_into(2_000, nanos).unwrap_err(),
+                   HistogramError::TooLarge);
+        assert_eq!(h.shift_values_exact(0).unwrap_err(), HistogramError::TooSmall);
+
+        let identity = h.shift_values_exact(1).unwrap();
+        assert_eq!(identity.data.data, h.data.data);
+        assert_eq!(identity.counters(), h.counters());
+    }
 
-        stdvar /= total;
+    #[test]
+    fn test_missed_extremes() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 10..20 {
+            h.increment(value).unwrap();
+        }
+        for _ in 0..5 {
+            h.increment(0).unwrap_err();
+            h.increment(2_000).unwrap_err();
+        }
 
-        Ok(stdvar.ceil() as u64)
+        // the stored data decides the extremes, exactly while stats are known
+        assert_eq!(h.minimum(), Ok(10));
+        assert_eq!(h.maximum(), Ok(19));
+        assert_eq!(h.percentile(0.0), Ok(10));
+        assert_eq!(h.percentile(25.0), Ok(12));
+        assert_eq!(h.percentile_below(11), Ok(20.0));
+
+        // and from the buckets otherwise
+        let decoded = Histogram::from_bytes(&h.to_bytes()).unwrap();
+        assert_eq!(decoded.stats(), None);
+        assert_eq!(decoded.minimum(), Ok(10));
+        let (low, high) = decoded.bucket_bounds(19).unwrap();
+        let maximum = decoded.maximum().unwrap();
+        assert!(low <= maximum && maximum <= high, "{}", maximum);
+        assert_eq!(decoded.freeze().percentile(100.0), Ok(maximum));
+
+        // ranking the missed samples keeps them at the ends
+        c.rank_missed(true);
+        let mut ranked = Histogram::configured(c).unwrap();
+        ranked.merge(&h);
+        assert_eq!(ranked.minimum(), Err(HistogramError::Underflow));
+        assert_eq!(ranked.maximum(), Err(HistogramError::Overflow));
+        assert_eq!(ranked.percentile(25.0), Err(HistogramError::Underflow));
+        assert_eq!(ranked.percentile(50.0), Ok(14));
+        assert_eq!(ranked.percentile_below(11), Ok(35.0));
+        assert!(ranked.config().get_rank_missed());
+
+        // a histogram of only missed samples still says so
+        let mut missed = Histogram::configured(c).unwrap();
+        missed.increment(0).unwrap_err();
+        assert_eq!(missed.minimum(),
+                   Err(HistogramError::OnlyMissedSamples {
+                       small: 1,
+                       large: 0,
+                       unknown: 0,
+                   }));
+        c.rank_missed(false);
+        let mut missed = Histogram::configured(c).unwrap();
+        missed.increment(0).unwrap_err();
+        assert_eq!(missed.maximum(), missed.percentile(100.0));
+        assert!(missed.maximum().is_err());
     }
 
-    /// standard deviation approximation across the histogram
-    ///
-    /// # Example
-    /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// for value in 1..11 {
-    ///     h.increment(value);
-    /// }
-    ///
-    /// assert_eq!(h.stddev().unwrap(), 3);
-    ///
-    /// h.clear();
-    ///
-    /// for value in 1..4 {
-    ///     h.increment(value);
-    /// }
-    /// for _ in 0..26 {
-    ///     h.increment(1);
-    /// }
-    ///
-    /// assert_eq!(h.stddev().unwrap(), 1);
-    pub fn stddev(&self) -> Option<u64> {
-
-        if self.entries() < 1 {
-            return None;
-        }
+    #[test]
+    fn test_extreme_percentiles() {
+        let percentiles = [0.0, 0.0001, 99.9999, 100.0];
+        let check = |h: &Histogram, expected: [u64; 4]| {
+            for (p, value) in percentiles.iter().zip(expected.iter()) {
+                assert_eq!(h.percentile(*p), Ok(*value), "percentile {}", p);
+            }
+            assert_eq!(h.minimum_approx(), Ok(expected[0]));
+            assert_eq!(h.maximum_approx(), Ok(expected[3]));
+            assert_eq!(h.percentile_bp(0), Ok(expected[0]));
+            assert_eq!(h.percentile_bp(10_000), Ok(expected[3]));
+            assert_eq!(h.quantile_ppm(0), Ok(expected[0]));
+            assert_eq!(h.quantile_ppm(1_000_000), Ok(expected[3]));
+            assert_eq!(h.freeze().percentile(0.0), Ok(expected[0]));
+            assert_eq!(h.freeze().percentile(100.0), Ok(expected[3]));
+        };
+
+        // a single value
+        let mut h = Histogram::new().unwrap();
+        h.increment(7).unwrap();
+        check(&h, [7; 4]);
 
-        let stdvar = self.stdvar().unwrap() as f64;
+        // the same value many times, in the first bucket
+        let mut h = Histogram::new().unwrap();
+        h.record(1, 1_000_000_000).unwrap();
+        check(&h, [1; 4]);
+        assert_eq!(h.percentile(0.1), Ok(1));
+        assert_eq!(h.percentile(99.9), Ok(1));
 
-        let stddev = stdvar.sqrt();
+        // two buckets, with one sample in the first
+        let mut h = Histogram::new().unwrap();
+        h.record(10, 1).unwrap();
+        h.record(1_000, 999_999).unwrap();
+        check(&h, [10, 10, 1_000, 1_000]);
 
-        Some(stddev.ceil() as u64)
+        // two buckets at either end, with each holding one sample
+        let mut h = Histogram::new().unwrap();
+        h.increment(1).unwrap();
+        h.increment(60_000_000_000).unwrap();
+        let top = h.percentile(100.0).unwrap();
+        assert_eq!(h.bucket_bounds(60_000_000_000).unwrap().0, top);
+        check(&h, [1, 1, top, top]);
+
+        // the first and last samples may be missed, and are left out unless
+        // ranked
+        h.increment(0).unwrap_err();
+        assert_eq!(h.percentile(0.0), Ok(1));
+        h.config.rank_missed = true;
+        assert_eq!(h.percentile(0.0), Err(HistogramError::Underflow));
+        assert_eq!(h.percentile(100.0), Ok(top));
+        h.increment(u64::MAX).unwrap_err();
+        assert_eq!(h.percentile(100.0), Err(HistogramError::Overflow));
     }
 
-    /// merge one Histogram into another Histogram
-    ///
-    /// # Example
-    /// ```
-    /// # use histogram::{Histogram,HistogramConfig};
-    ///
-    /// let mut a = Histogram::new().unwrap();
-    ///
-    /// let mut b = Histogram::new().unwrap();
-    ///
-    /// assert_eq!(a.entries(), 0);
-    /// assert_eq!(b.entries(), 0);
-    ///
-    /// a.increment(1);
-    /// b.increment(2);
-    ///
-    /// assert_eq!(a.entries(), 1);
-    /// assert_eq!(b.entries(), 1);
-    ///
-    /// a.merge(&mut b);
-    ///
-    /// assert_eq!(a.entries(), 2);
-    /// assert_eq!(a.get(1).unwrap(), 1);
-    /// assert_eq!(a.get(2).unwrap(), 1);
-    pub fn merge(&mut self, other: &mut Histogram) {
-        for bucket in other {
-            let _ = self.record(bucket.value, bucket.count);
-        }
-    }
+    #[test]
+    fn test_error_variants() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
 
-    /// return the number of entries in the Histogram
-    ///
-    /// # Example
-    /// ```
-    /// # use histogram::{Histogram, HistogramConfig};
-    ///
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// assert_eq!(h.entries(), 0);
-    /// h.increment(1);
-    /// assert_eq!(h.entries(), 1);
-    pub fn entries(&self) -> u64 {
-        self.data.counters.entries_total
+        assert_eq!(h.percentile(50.0), Err(HistogramError::Empty));
+        assert_eq!(h.mean(), Err(HistogramError::Empty));
+        assert_eq!(h.stddev(), Err(HistogramError::Empty));
+        assert_eq!(h.increment(0), Err(HistogramError::TooSmall));
+        assert_eq!(h.record(1_001, 2), Err(HistogramError::TooLarge));
+        assert_eq!(h.percentile(50.0),
+                   Err(HistogramError::OnlyMissedSamples {
+                       small: 1,
+                       large: 2,
+                       unknown: 0,
+                   }));
+        h.increment(1_000).unwrap();
+        assert_eq!(h.percentile(101.0), Err(HistogramError::InvalidPercentile));
+        assert_eq!(h.value_at_count(5), Err(HistogramError::OutOfRange));
+        assert_eq!(h.record(1_001, 1).unwrap_err().to_string(), "sample value too large");
+
+        c.overflow(OverflowPolicy::Clamp);
+        assert_eq!(Histogram::configured(c).unwrap().record(1_001, 1), Ok(()));
+
+        c.max_memory(64);
+        assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::MemoryLimitExceeded);
+        c.max_memory(0).precision(10);
+        assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::InvalidPrecision);
+        c.precision(3).max_value(u64::MAX);
+        assert!(Histogram::configured(c).is_ok());
     }
 
-    /// return the number of buckets in the Histogram
-    ///
-    /// # Example
-    /// ```
-    /// # use histogram::{Histogram, HistogramConfig};
-    ///
-    /// let mut h = Histogram::new().unwrap();
-    ///
-    /// assert_eq!(h.buckets_total(), 27023);
-    ///
-    /// let mut c = HistogramConfig::new();
-    /// c.max_value(1_000_000_000);
-    /// let mut h = Histogram::configured(c).unwrap();
-    ///
-    /// assert_eq!(h.buckets_total(), 21023);
-    ///
-    /// c.precision(4);
-    /// let mut h = Histogram::configured(c).unwrap();
-    ///
-    /// assert_eq!(h.buckets_total(), 176383);
-    ///
-    /// c.precision(2);
-    /// let mut h = Histogram::configured(c).unwrap();
-    ///
-    /// assert_eq!(h.buckets_total(), 2427);
-    pub fn buckets_total(&self) -> u64 {
-        self.properties.buckets_total as u64
-    }
-}
+    #[test]
+    fn test_config_validation() {
+        // the radix, precision and max_value of each rejected config, and its error
+        for &(radix, precision, max_value, error) in
+            &[(10, 0, 1_000, HistogramError::InvalidPrecision),
+              (10, 10, 1_000, HistogramError::InvalidPrecision),
+              (2, 32, 1_000, HistogramError::InvalidPrecision),
+              (0, 3, 1_000, HistogramError::InvalidRadix),
+              (1, 1, 1_000, HistogramError::InvalidRadix),
+              (10, 3, 0, HistogramError::InvalidMaxValue),
+              (3, 2, 0, HistogramError::InvalidMaxValue)] {
+            let mut c = HistogramConfig::new();
+            c.radix(radix).precision(precision).max_value(max_value);
+            assert_eq!(Histogram::configured(c).unwrap_err(), error);
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::{Histogram, HistogramConfig};
+        // the bounds themselves are accepted
+        for &(radix, precision) in &[(10, 1), (2, 1), (2, 20), (3, 5), (16, 4)] {
+            let mut c = HistogramConfig::new();
+            c.radix(radix).precision(precision).max_value(1);
+            assert!(Histogram::configured(c).is_ok(), "radix {} precision {}", radix, precision);
+        }
+    }
 
     #[test]
-    fn test_new_0() {
-        // this histogram has only a linear region which runs 1-15
-
+    fn test_radix_2() {
         let mut c = HistogramConfig::new();
-        c.max_value(10).precision(1);
+        c.radix(2).precision(3).max_value(1_000_000);
         let h = Histogram::configured(c).unwrap();
+        assert!(h.buckets_total() < 200);
+
+        for index in 0..h.buckets_total() as usize {
+            let value = h.index_value(index);
+            let (low, high) = h.index_bounds(index);
+            assert_eq!(h.get_index(value), Some(index));
+            assert_eq!(h.get_index(low), Some(index));
+            assert_eq!(h.get_index(high.min(1_000_000)), Some(index));
+            if index > 0 {
+                assert_eq!(h.index_bounds(index - 1).1 + 1, low);
+            }
+        }
 
-        assert_eq!(h.properties.buckets_inner, 10); // 10 ^ precision
-        assert_eq!(h.properties.buckets_outer, 0); // max <= 2 * buckets_inner
-        assert_eq!(h.properties.buckets_total, 15); // only linear region
+        // each value is within an eighth of its bucket value
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..10_000 {
+            let value = 1 + rng.next() % 1_000_000;
+            let bucket = h.index_value(h.get_index(value).unwrap());
+            assert!(bucket <= value && (value - bucket) as f64 <= value as f64 / 8.0);
+        }
     }
 
     #[test]
-    fn test_new_1() {
-        // this histogram has linear and log regios
+    fn test_memory_budget() {
+        let max_value = 60_000_000_000;
+        for &budget in &[16 * 1024, 64 * 1024, 1 << 20, 8 << 20, 64 << 20] {
+            let h = Histogram::with_memory_budget(budget, max_value).unwrap();
+            assert!(h.buckets_total() * 8 <= budget as u64);
+            assert_eq!(h.config.max_value, max_value);
+
+            // the next precision would not fit
+            let mut c = h.config;
+            c.precision(h.precision() + 1);
+            assert!(Histogram::configured(c).is_err(), "budget {}", budget);
+        }
+        assert_eq!(Histogram::with_memory_budget(1 << 20, max_value).unwrap().precision(), 3);
+        assert_eq!(Histogram::with_memory_budget(1 << 20, 1_000).unwrap().precision(), 5);
 
+        // too small for precision 1
         let mut c = HistogramConfig::new();
-        c.max_value(31).precision(1);
-        let h = Histogram::configured(c).unwrap();
-
-        assert_eq!(h.properties.buckets_inner, 10); // 10 ^ precision
-        assert_eq!(h.properties.buckets_outer, 1); // max <= 2 * buckets_inner
-        assert_eq!(h.properties.buckets_total, 25); // only linear region
+        c.max_value(max_value).precision(1);
+        let smallest = Histogram::configured(c).unwrap().buckets_total() as u32 * 8;
+        assert_eq!(Histogram::with_memory_budget(smallest, max_value).unwrap().precision(), 1);
+        for &budget in &[0, 1, smallest - 1] {
+            assert_eq!(Histogram::with_memory_budget(budget, max_value).unwrap_err(),
+                       HistogramError::MemoryLimitExceeded);
+        }
     }
 
     #[test]
-    fn test_new_2() {
+    fn test_layout_limits() {
+        for precision in 6..11 {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).max_value(60_000_000_000);
+
+            // the layout worked out independently, in u128
+            let inner = 10_u128.pow(precision);
+            let linear_power = 128 - inner.leading_zeros();
+            let expected = inner * (36 - linear_power) as u128 + (1 << linear_power) - 1;
+
+            match Histogram::configured(c) {
+                Ok(h) => {
+                    assert!(precision <= 7);
+                    assert_eq!(h.buckets_total() as u128, expected);
+                    assert_eq!(h.data.data.len() as u128, expected);
+                    assert_eq!(h.properties.memory_used as u128, expected * 8);
+                }
+                Err(HistogramError::TooManyBuckets { buckets, limit }) => {
+                    assert!(precision == 8 || precision == 9);
+                    assert_eq!(buckets as u128, expected);
+                    assert_eq!(limit, 1 << 28);
+                }
+                Err(e) => {
+                    assert_eq!((precision, e), (10, HistogramError::InvalidPrecision));
+                }
+            }
+        }
+
+        // the limit is on the buckets kept, above min_value
         let mut c = HistogramConfig::new();
-        c.max_value(32).precision(1);
+        c.precision(8).max_value((1 << 30) - 1).min_value(1 << 29);
         let h = Histogram::configured(c).unwrap();
-
-        assert_eq!(h.properties.buckets_inner, 10); // 10 ^ precision
-        assert_eq!(h.properties.buckets_outer, 2); // max <= 2 * buckets_inner
-        assert_eq!(h.properties.buckets_total, 35); // only linear region
+        assert_eq!(h.buckets_total(), 100_000_000);
+        assert_eq!(h.get_index((1 << 30) - 1), Some(99_999_999));
+        assert_eq!(h.index_value(0), 1 << 29);
+
+        // radix powers beyond u32 are still invalid precisions
+        c.radix(2).precision(32).min_value(1);
+        assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::InvalidPrecision);
+        c.precision(31);
+        assert!(matches!(Histogram::configured(c), Err(HistogramError::TooManyBuckets { .. })));
     }
 
     #[test]
-    fn test_new_3() {
+    fn test_max_memory() {
+        // 1MB holds 131,072 counts of 8 bytes
         let mut c = HistogramConfig::new();
-        c.max_value(10_000).precision(3);
+        c.precision(4);
+        let full = Histogram::configured(c).unwrap();
+        let cut = (full.buckets_total() - 131_072) as usize;
+        c.max_memory(1 << 20).min_value(full.properties.index_low(cut));
         let h = Histogram::configured(c).unwrap();
+        assert_eq!(h.buckets_total(), 131_072);
+        assert_eq!(full.memory_used() - h.memory_used(), cut as u64 * 8);
+        c.min_value(full.properties.index_low(cut - 1));
+        assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::MemoryLimitExceeded);
+
+        for &radix in &[2, 10] {
+            for precision in 1..6 {
+                for &max_value in &[1_000, 1_000_000, 1_000_000_000, 60_000_000_000, u64::MAX] {
+                    let mut c = HistogramConfig::new();
+                    c.radix(radix).precision(precision).max_value(max_value);
+                    let buckets = Histogram::configured(c).unwrap().buckets_total();
+                    c.max_memory(1 << 20);
+                    match Histogram::configured(c) {
+                        Ok(h) => assert!(buckets <= 131_072 && h.buckets_total() == buckets),
+                        Err(e) => {
+                            assert_eq!(e, HistogramError::MemoryLimitExceeded);
+                            assert!(buckets > 131_072);
+                        }
+                    }
+                }
+            }
+        }
+    }
 
-        assert_eq!(h.properties.buckets_inner, 1000); // 10 ^ precision
-        assert_eq!(h.properties.buckets_outer, 4); // max <= 2 * buckets_inner
-        assert_eq!(h.properties.buckets_total, 5023); // only linear region
+    #[test]
+    fn test_introspection() {
+        let overhead = (::std::mem::size_of::<Histogram>() + super::shared_overhead()) as u64;
+        for &(precision, max_value, radix) in &[(3, 60_000_000_000, 10),
+                                                (2, 1_000_000, 10),
+                                                (4, u64::MAX, 2),
+                                                (1, 1_000, 16)] {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).max_value(max_value).radix(radix).min_value(2);
+            let h = Histogram::configured(c).unwrap();
+            assert_eq!(h.precision(), precision);
+            assert_eq!(h.max_value(), max_value);
+            assert_eq!(h.config(), c);
+            assert_eq!(h.buckets_inner(), (radix as u64).pow(precision));
+            assert_eq!(h.memory_used(), h.buckets_total() * 8 + overhead);
+
+            let config = h.config();
+            assert_eq!((config.get_precision(), config.get_max_value(), config.get_radix()),
+                       (precision, max_value, radix));
+            assert_eq!(config.get_min_value(), 2);
+        }
+
+        let h = Histogram::new().unwrap();
+        assert_eq!((h.buckets_inner(), h.buckets_outer()), (1_000, 26));
+
+        // every setter has a getter
+        let mut c = HistogramConfig::new();
+        c.max_memory(1 << 20)
+         .overflow(OverflowPolicy::Clamp)
+         .prefault(true)
+         .percentile_method(PercentileMethod::BucketMidpoint)
+         .track_peaks(true)
+         .scale(1_000.0);
+        assert_eq!(c.get_max_memory(), 1 << 20);
+        assert_eq!(c.get_overflow(), OverflowPolicy::Clamp);
+        assert!(c.get_prefault() && c.get_track_peaks());
+        assert_eq!(c.get_percentile_method(), PercentileMethod::BucketMidpoint);
+        assert_eq!(c.get_scale(), 1_000.0);
+        assert!(c != HistogramConfig::new());
+
+        // peak tracking adds its timestamps
+        let h = Histogram::configured(c).unwrap();
+        assert!(h.memory_used() > h.buckets_total() * 8 + overhead);
+        assert_eq!(h.config(), c);
     }
 
     #[test]
-    fn test_increment_0() {
-        let mut h = Histogram::new().unwrap();
-
-        for op in 1..1000000 {
-            h.increment(1).unwrap();
-            assert_eq!(h.entries(), op);
+    fn test_refine() {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000_000).precision(2).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        let mut rng = Rng(0x6a09_e667_f3bc_c908);
+        for _ in 0..10_000 {
+            let _ = h.record(rng.next() % 12_000_000, rng.next() % 10);
+        }
+        let before = h.clone();
+
+        h.refine(4).unwrap();
+        h.check_invariants().unwrap();
+        assert_eq!(h.counters(), before.counters());
+        assert_eq!(h.data.data.iter().sum::<u64>(), before.data.data.iter().sum::<u64>());
+        assert!(h.buckets_total() > before.buckets_total());
+
+        // each percentile keeps the value of its old bucket
+        for p in &[1.0, 10.0, 50.0, 90.0, 99.0, 100.0] {
+            let old = before.percentile(*p).unwrap();
+            assert_eq!(h.percentile(*p).unwrap(), old, "percentile {}", p);
         }
+
+        // new samples use the finer buckets
+        let (low, high) = before.bucket_bounds(1_234_567).unwrap();
+        let (fine_low, fine_high) = h.bucket_bounds(1_234_567).unwrap();
+        assert!(fine_high - fine_low < (high - low) / 50);
+        h.record(1_234_567, 1_000_000).unwrap();
+        assert_eq!(h.percentile(50.0).unwrap(), fine_low);
+
+        // precision is never lowered, and a failed refine changes nothing
+        assert_eq!(h.refine(3), Err(HistogramError::InvalidConfig));
+        assert_eq!(h.refine(4), Ok(()));
+        let mut limited = before.clone();
+        limited.config.max_memory(before.properties.memory_used as u32 * 2);
+        assert_eq!(limited.refine(4), Err(HistogramError::MemoryLimitExceeded));
+        assert_eq!(limited.data.data, before.data.data);
+        assert_eq!(limited.config.precision, 2);
     }
 
     #[test]
-    fn test_increment_1() {
+    fn test_subtract() {
         let mut c = HistogramConfig::new();
-        c.max_value(10).precision(3);
+        c.max_value(1_000);
         let mut h = Histogram::configured(c).unwrap();
 
-        // increment values across the entire range
-        // including 0 and > max_value
-        for v in 1..11 {
-            h.increment(v).unwrap();
-            assert_eq!(h.entries(), v);
+        h.record(0, 2).unwrap_err();
+        h.record(2_000, 3).unwrap_err();
+        h.record(500, 4).unwrap();
+        assert_eq!(h.entries(), 9);
+
+        // each is taken from where record() counted it
+        h.subtract(0, 1).unwrap();
+        h.subtract(3_000, 2).unwrap();
+        h.subtract(500, 3).unwrap();
+        assert_eq!(h.entries(), 3);
+        assert_eq!(h.data.counters.missed_small, 1);
+        assert_eq!(h.data.counters.missed_large, 1);
+        assert_eq!(h.get(500).unwrap(), 1);
+
+        // and nothing changes when it holds too few
+        for &(value, count) in &[(0, 2), (5_000, 2), (500, 2), (400, 1)] {
+            assert_eq!(h.subtract(value, count), Err(HistogramError::OutOfRange));
         }
+        assert_eq!(h.entries(), 3);
+
+        h.decrement(500).unwrap();
+        assert_eq!(h.data.occupied, None);
+        assert_eq!(h.percentile(50.0), Err(HistogramError::OnlyMissedSamples {
+            small: 1,
+            large: 1,
+            unknown: 0,
+        }));
+
+        // clamped values come from the bucket holding max_value
+        c.overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(1_000, 2).unwrap();
+        h.decrement(5_000).unwrap();
+        assert_eq!(h.get(1_000).unwrap(), 1);
     }
 
     #[test]
-    fn test_get() {
-        let mut h = Histogram::new().unwrap();
+    fn test_percentile_bp() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
 
-        h.increment(1).unwrap();
-        assert_eq!(h.get(1), Some(1));
+        for &total in &[1_u64, 2, 7, 100, 1_024, 4_999] {
+            let mut h = Histogram::configured(c).unwrap();
+            for value in 0..total {
+                h.increment(value * 7_919 % 1_000_000 + 1).unwrap();
+            }
 
-        h.increment(1).unwrap();
-        assert_eq!(h.get(1), Some(2));
+            // percentiles which are exact in binary floating point
+            for &(bp, percentile) in &[(0, 0.0), (625, 6.25), (1_250, 12.5), (2_500, 25.0),
+                                       (5_000, 50.0), (7_500, 75.0), (9_375, 93.75),
+                                       (10_000, 100.0)] {
+                assert_eq!(h.percentile_bp(bp), h.percentile(percentile), "bp: {}", bp);
+                assert_eq!(h.quantile_ppm(bp * 100), h.percentile(percentile), "bp: {}", bp);
+            }
 
-        h.increment(2).unwrap();
-        assert_eq!(h.get(2), Some(1));
+            // and every integer percentile where the float rank is exact
+            for p in 0..101_u32 {
+                let rank = total * p as u64;
+                if rank.is_multiple_of(100) &&
+                   total as f64 * (p as f64 / 100.0) == (rank / 100) as f64 {
+                    assert_eq!(h.percentile_bp(p * 100), h.percentile(p as f64));
+                }
+            }
+        }
 
-        assert_eq!(h.get(3), Some(0));
+        let mut h = Histogram::new().unwrap();
+        assert_eq!(h.percentile_bp(5_000), Err(HistogramError::Empty));
+        h.increment(1).unwrap();
+        assert_eq!(h.percentile_bp(10_001), Err(HistogramError::InvalidPercentile));
+        assert_eq!(h.quantile_ppm(1_000_001), Err(HistogramError::InvalidPercentile));
+        assert_eq!(h.quantile_ppm(1_000_000), Ok(1));
     }
 
     #[test]
-    fn test_get_index_0() {
+    fn test_last_value() {
+        let clock = Arc::new(ManualClock::new());
         let mut c = HistogramConfig::new();
-        c.max_value(32).precision(3);
-        let h = Histogram::configured(c).unwrap();
+        c.max_value(100).overflow(OverflowPolicy::Clamp);
+        let mut a = Histogram::with_clock(c, clock.clone()).unwrap();
+        let start = clock.now();
+
+        assert_eq!(a.last_value(), None);
+        assert_eq!(a.last_recorded_at(), None);
+
+        // only successful records update the value, clamped ones keep the
+        // value as given
+        a.increment(5).unwrap();
+        clock.advance(Duration::from_secs(1));
+        a.increment(0).unwrap_err();
+        assert_eq!(a.last_value(), Some(5));
+        assert_eq!(a.last_recorded_at(), Some(start));
+        a.record(500, 2).unwrap();
+        assert_eq!(a.last_value(), Some(500));
+        assert_eq!(a.last_recorded_at(), Some(start + Duration::from_secs(1)));
+
+        // merge keeps whichever side was recorded more recently
+        let mut b = a.clone_empty();
+        assert_eq!(b.last_value(), None);
+        a.merge(&b);
+        assert_eq!(a.last_value(), Some(500));
+        clock.advance(Duration::from_secs(1));
+        b.increment(7).unwrap();
+        a.merge(&b);
+        assert_eq!(a.last_value(), Some(7));
+        clock.advance(Duration::from_secs(1));
+        a.increment(9).unwrap();
+        b.merge(&a);
+        assert_eq!(b.last_value(), Some(9));
+        assert_eq!(b.last_recorded_at(), a.last_recorded_at());
+
+        let mut r = a.clone_empty();
+        r.reset_to(&b).unwrap();
+        assert_eq!(r.last_value(), Some(9));
+
+        a.clear();
+        assert_eq!(a.last_value(), None);
+        assert_eq!(a.last_recorded_at(), None);
+    }
 
-        // all values should index directly to (value - 1)
-        // no estimated buckets are needed given the precision and max
+    #[test]
+    fn test_bucket_count() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
+        let mut a = Histogram::configured(c).unwrap();
+        let mut b = Histogram::configured(c).unwrap();
+        for value in 1..5_000 {
+            a.record(value * 31 % 1_000_000 + 1, value % 4).unwrap();
+            b.record(value * 97 % 1_000_000 + 1, value % 3).unwrap();
+        }
 
-        assert_eq!(h.get_index(1), Some(0));
-        assert_eq!(h.index_value(0), 1);
+        // the ids yielded by iteration address the same buckets
+        for bucket in a.clone() {
+            assert_eq!(a.count_in_bucket(bucket.id() as usize), Some(bucket.count()));
+        }
+        assert_eq!(a.count_in_bucket(a.buckets_total() as usize), None);
+
+        // applying b as a stream of per-bucket deltas matches a merge
+        let mut merged = a.clone();
+        merged.merge(&b);
+        for bucket in b.iter_from(0).recorded() {
+            let id = bucket.id() as usize;
+            let count = a.count_in_bucket(id).unwrap() + bucket.count();
+            a.set_bucket_count(id, count).unwrap();
+        }
+        assert_eq!(a.entries(), merged.entries());
+        assert_eq!(a.data.data, merged.data.data);
+        a.check_invariants().unwrap();
+
+        // lowering a count lowers the entries
+        let entries = a.entries();
+        let count = a.count_in_bucket(100).unwrap();
+        a.set_bucket_count(100, 0).unwrap();
+        assert_eq!(a.entries(), entries - count);
+        assert_eq!(a.set_bucket_count(a.buckets_total() as usize, 1),
+                   Err(HistogramError::InvalidBucket));
+    }
 
-        assert_eq!(h.get_index(2), Some(1));
-        assert_eq!(h.index_value(1), 2);
+    #[test]
+    fn test_above_percentile() {
+        // values within the linear region are stored exactly
+        let mut values = Vec::new();
+        let mut rng = Rng(7);
+        for _ in 0..1_000 {
+            values.push(1 + rng.next() % 1_000);
+        }
 
-        assert_eq!(h.get_index(3), Some(2));
-        assert_eq!(h.index_value(2), 3);
+        let mut h = Histogram::new().unwrap();
+        for value in &values {
+            h.increment(*value).unwrap();
+        }
+        values.sort();
+
+        for p in 0..1001 {
+            let p = p as f64 / 10.0;
+            let below = (values.len() as f64 * (p / 100.0)).ceil() as usize;
+            let tail = &values[below..];
+            let sum: u128 = tail.iter().map(|value| *value as u128).sum();
+            assert_eq!(h.above_percentile(p).unwrap(),
+                       (tail.len() as u64, sum),
+                       "percentile: {}",
+                       p);
+        }
 
-        assert_eq!(h.get_index(4), Some(3));
-        assert_eq!(h.index_value(3), 4);
+        // missed samples are not part of the ranking
+        h.increment(0).unwrap_err();
+        assert_eq!(h.above_percentile(99.0).unwrap().0, 10);
 
-        assert_eq!(h.get_index(5), Some(4));
-        assert_eq!(h.index_value(4), 5);
+        assert_eq!(h.above_percentile(100.1), Err(HistogramError::InvalidPercentile));
+        assert_eq!(Histogram::new().unwrap().above_percentile(50.0),
+                   Err(HistogramError::Empty));
+    }
 
-        assert_eq!(h.get_index(16), Some(15));
-        assert_eq!(h.index_value(15), 16);
+    #[test]
+    fn test_percentile_method() {
+        let methods = [PercentileMethod::NearestRank,
+                       PercentileMethod::BucketMidpoint,
+                       PercentileMethod::LinearInterpolation];
+        let mut histograms = Vec::new();
+        for method in &methods {
+            let mut c = HistogramConfig::new();
+            c.max_value(100_000).precision(2).percentile_method(*method);
+            let mut h = Histogram::configured(c).unwrap();
+            for value in 1..10_001 {
+                h.increment(value).unwrap();
+            }
+            histograms.push(h);
+        }
+        assert_eq!(HistogramConfig::new().percentile_method, PercentileMethod::NearestRank);
+
+        let mut previous = 0;
+        for p in 0..1001 {
+            let p = p as f64 / 10.0;
+            let (low, high) = histograms[0].percentile_bounds(p).unwrap();
+            let values: Vec<u64> = histograms.iter().map(|h| h.percentile(p).unwrap()).collect();
+            let (nearest, midpoint, interpolated) = (values[0], values[1], values[2]);
+
+            // each method reports a value within the bucket, with the
+            // nearest rank at its low end and the midpoint at its center
+            assert_eq!(nearest, low, "percentile: {}", p);
+            assert_eq!(midpoint, low + (high - low) / 2, "percentile: {}", p);
+            assert!(low <= interpolated && interpolated <= high, "percentile: {}", p);
+
+            // the samples fill each bucket below the last evenly, so there
+            // interpolation finds the sample at the rank
+            let exact = ((10_000.0 * (p / 100.0)).ceil() as u64).max(1);
+            if high <= 10_000 {
+                assert!(interpolated.abs_diff(exact) <= 1, "percentile: {}", p);
+                assert!(interpolated.abs_diff(exact) <= nearest.abs_diff(exact));
+            }
+            assert!(interpolated >= previous);
+            previous = interpolated;
+
+            // and every view of the histogram agrees
+            for (h, value) in histograms.iter().zip(values) {
+                assert_eq!(h.freeze().percentile(p), Ok(value), "percentile: {}", p);
+                let empty = h.clone_empty();
+                let view = MergedView::new(&[h, &empty]).unwrap();
+                assert_eq!(view.percentile(p), Ok(value), "percentile: {}", p);
+            }
+        }
 
-        assert_eq!(h.get_index(32), Some(31));
-        assert_eq!(h.index_value(31), 32);
+        for h in &histograms {
+            let summary = h.summary().unwrap();
+            assert_eq!(summary.p99(), h.percentile(99.0).unwrap());
+            assert_eq!(summary.maximum(), h.maximum().unwrap());
+            assert_eq!(h.percentile_bp(2_500), h.percentile(25.0));
+        }
     }
 
     #[test]
-    fn test_get_index_1() {
-        let mut c = HistogramConfig::new();
-        c.max_value(100).precision(1);
-        let h = Histogram::configured(c).unwrap();
-
-        assert_eq!(h.get_index(1), Some(0));
-        assert_eq!(h.get_index(2), Some(1));
-        assert_eq!(h.get_index(15), Some(14));
+    fn test_percentile_curve() {
+        let mut h = Histogram::new().unwrap();
+        let mut rng = Rng(11);
+        for _ in 0..100_000 {
+            h.increment(1 + rng.next() % 1_000_000).unwrap();
+        }
 
-        // powers of two are 10 apart from value = 16 and up
-        assert_eq!(h.get_index(16), Some(15));
-        assert_eq!(h.get_index(32), Some(25));
-        assert_eq!(h.get_index(64), Some(35));
+        for &points in &[1, 2, 3, 10, 200] {
+            let curve = h.percentile_curve(points).unwrap();
+            assert_eq!(curve.len(), points);
+            assert_eq!(*curve.last().unwrap(), (100.0, h.maximum_approx().unwrap()));
 
-        // this tests that rounding up within inner bucket works
-        assert_eq!(h.get_index(16), Some(15));
-        assert_eq!(h.get_index(17), Some(15));
-        assert_eq!(h.get_index(18), Some(16));
-        assert_eq!(h.get_index(19), Some(16));
+            for pair in curve.windows(2) {
+                assert!(pair[0].0 < pair[1].0, "{:?}", pair);
+                assert!(pair[0].1 <= pair[1].1, "{:?}", pair);
+            }
+            for &(percentile, value) in &curve {
+                assert_eq!(h.percentile(percentile).unwrap(), value);
+            }
+        }
 
-        // these values prove roll-up into next outer bucket works
-        assert_eq!(h.get_index(62), Some(34));
-        assert_eq!(h.get_index(63), Some(34));
-        assert_eq!(h.get_index(64), Some(35));
+        // half the points are in the tail, reaching towards p99.999
+        let curve = h.percentile_curve(200).unwrap();
+        assert_eq!(curve.iter().filter(|pair| pair.0 >= 99.0).count(), 100);
+        assert!(curve[198].0 > 99.998);
 
-        assert_eq!(h.get_index(65), Some(35));
+        assert!(h.percentile_curve(0).unwrap().is_empty());
+        assert_eq!(Histogram::new().unwrap().percentile_curve(10),
+                   Err(HistogramError::Empty));
     }
 
     #[test]
-    fn test_get_index_2() {
-        // extensive test from precomputed table
-        let mut c = HistogramConfig::new();
-        c.max_value(100).precision(1);
-        let h = Histogram::configured(c).unwrap();
-
-        let v = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 21, 23, 24,
-                     26, 28, 29, 31, 32, 36, 39, 42, 45, 48, 52, 55, 58, 61, 64, 71, 77, 84, 90,
-                     96, 103, 109, 116, 122];
-
-        for index in 0..45 {
-            let got = h.get_index(v[index]).unwrap();
-            assert!(got == index,
-                    "Value: {} Got: {} Want: {}",
-                    v[index],
-                    got,
-                    index);
+    fn test_by_power_of_two() {
+        let mut h = Histogram::new().unwrap();
+        let mut expected = [0_u64; 64];
+        let mut rng = Rng(5);
+        for _ in 0..10_000 {
+            let value = 1 + (rng.next() >> (rng.next() % 64)) % 60_000_000_000;
+            h.increment(value).unwrap();
+            expected[63 - value.leading_zeros() as usize] += 1;
         }
 
-        for index in 0..45 {
-            let got = h.index_value(index);
-            assert!(got == v[index],
-                    "Index: {} Got: {} Want: {}",
-                    index,
-                    got,
-                    v[index]);
+        let ranges = h.by_power_of_two();
+        assert!(ranges.len() > 30);
+        for &(low, high, count) in &ranges {
+            let power = 63 - low.leading_zeros() as usize;
+            assert_eq!(low, 1 << power);
+            assert_eq!(high, (1 << (power + 1)) - 1);
+            assert_eq!(count, expected[power], "range: {}", low);
+            assert_eq!(count, h.count_between(low, high));
         }
+        let total: u64 = ranges.iter().map(|range| range.2).sum();
+        assert_eq!(total, 10_000);
+
+        let chart = h.power_of_two_chart();
+        assert_eq!(chart.lines().count(), ranges.len());
+        assert!(chart.lines().all(|line| line.len() == 16 + 8 + 2 + 52 + 1));
+        assert!(chart.contains("[1M, 2M)"));
+
+        assert!(Histogram::new().unwrap().by_power_of_two().is_empty());
+        assert_eq!(Histogram::new().unwrap().power_of_two_chart(), "");
+
+        // the top range ends past u64::MAX
+        let mut c = HistogramConfig::new();
+        c.max_value(u64::MAX).precision(1);
+        let mut h = Histogram::configured(c).unwrap();
+        h.increment(u64::MAX).unwrap();
+        assert!(h.power_of_two_chart().starts_with("[8E, 16E)"));
     }
 
     #[test]
-    fn test_get_index_3() {
-        // extensive test from precomputed table
+    fn test_try_record() {
+        let mut h = Histogram::new().unwrap();
+        let linear_max = h.properties.linear_max;
+        assert_eq!(linear_max, 1023);
+
+        // the linear region stores values exactly, up to and including its end
+        assert_eq!(h.try_record(1, 1), Ok(RecordOutcome::StoredExact));
+        assert_eq!(h.try_record(linear_max, 2), Ok(RecordOutcome::StoredExact));
+        assert_eq!(h.get(linear_max).unwrap(), 2);
+
+        // beyond it values share buckets
+        match h.try_record(linear_max + 1, 1) {
+            Ok(RecordOutcome::StoredApproximate { bucket_error }) => assert!(bucket_error < 2),
+            outcome => panic!("{:?}", outcome),
+        }
+        match h.try_record(123_456_789, 1) {
+            Ok(RecordOutcome::StoredApproximate { bucket_error }) => {
+                assert!(bucket_error > 0);
+                assert!(bucket_error <= 123_456_789 / 1_000);
+            }
+            outcome => panic!("{:?}", outcome),
+        }
+
+        assert_eq!(h.try_record(0, 1), Err(HistogramError::TooSmall));
+        assert_eq!(h.try_record(60_000_000_001, 1), Err(HistogramError::TooLarge));
+        assert_eq!(h.entries(), 7);
+
+        // a full bucket stores what it can
+        assert_eq!(h.try_record(5, 1), Ok(RecordOutcome::StoredExact));
+        assert_eq!(h.try_record(5, u64::MAX), Err(HistogramError::Saturated));
+        assert_eq!(h.get(5).unwrap(), u64::MAX);
+
         let mut c = HistogramConfig::new();
-        c.max_value(250).precision(1);
-        let h = Histogram::configured(c).unwrap();
+        c.max_value(1_000).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        assert_eq!(h.try_record(1_001, 1), Ok(RecordOutcome::Clamped));
+        assert_eq!(h.try_record(1_000, 1), Ok(RecordOutcome::StoredExact));
+        assert_eq!(h.get(1_000).unwrap(), 2);
+    }
 
-        let v = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 21, 23, 24,
-                     26, 28, 29, 31, 32, 36, 39, 42, 45, 48, 52, 55, 58, 61, 64, 71, 77, 84, 90,
-                     96, 103, 109, 116, 122, 128, 141, 154, 167, 180, 192, 205, 218, 231, 244];
+    #[test]
+    fn test_percentile_of_pairs() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for round in 0..50 {
+            let max = if round % 2 == 0 { 1_000 } else { 60_000_000_000 };
+            let pairs: Vec<(u64, u64)> = (0..(round * 3 + 1))
+                .map(|_| (rng.next() % max, rng.next() % 5))
+                .collect();
+
+            let mut c = HistogramConfig::new();
+            c.rank_missed(true);
+            let mut h = Histogram::configured(c).unwrap();
+            for &(value, count) in &pairs {
+                let _ = h.record(value, count);
+            }
 
-        for index in 0..55 {
-            let got = h.get_index(v[index]).unwrap();
-            assert!(got == index,
-                    "Value: {} Got: {} Want: {}",
-                    v[index],
-                    got,
-                    index);
+            for p in 0..201 {
+                let p = p as f64 / 2.0;
+                let exact = Histogram::percentile_of_pairs(&pairs, p);
+                let bucketed = exact.map(|value| h.index_value(h.get_index(value).unwrap()));
+                assert_eq!(bucketed, h.percentile(p), "percentile: {} pairs: {:?}", p, pairs);
+                if max == 1_000 {
+                    assert_eq!(exact, h.percentile(p));
+                }
+            }
         }
 
-        for index in 0..55 {
-            let got = h.index_value(index);
-            assert!(got == v[index],
-                    "Index: {} Got: {} Want: {}",
-                    index,
-                    got,
-                    v[index]);
-        }
+        assert_eq!(Histogram::percentile_of_pairs(&[(0, 3), (5, 1)], 25.0),
+                   Err(HistogramError::Underflow));
+        assert_eq!(Histogram::percentile_of_pairs(&[(0, 3), (5, 1)], 50.0),
+                   Err(HistogramError::Underflow));
+        assert_eq!(Histogram::percentile_of_pairs(&[(0, 3), (5, 1)], 100.0), Ok(5));
+        assert_eq!(Histogram::percentile_of_pairs(&[(5, 1)], 101.0),
+                   Err(HistogramError::InvalidPercentile));
     }
 
-    #[test]
-    fn test_index_value_0() {
-        let mut c = HistogramConfig::new();
-        c.max_value(100).precision(1);
-        let h = Histogram::configured(c).unwrap();
+    // (radix, precision, max_value, value, index) bucket assignments which must
+    // hold on every target and across minor versions, see "Bucket stability"
+    const BUCKET_FIXTURES: &[(u32, u32, u64, u64, usize)] = &[
+        (10, 3, 60_000_000_000, 1, 0),
+        (10, 3, 60_000_000_000, 2, 1),
+        (10, 3, 60_000_000_000, 1000, 999),
+        (10, 3, 60_000_000_000, 1001, 1000),
+        (10, 3, 60_000_000_000, 1023, 1022),
+        (10, 3, 60_000_000_000, 1024, 1023),
+        (10, 3, 60_000_000_000, 1025, 1023),
+        (10, 3, 60_000_000_000, 12_345, 4529),
+        (10, 3, 60_000_000_000, 999_999, 10_930),
+        (10, 3, 60_000_000_000, 1_000_000, 10_930),
+        (10, 3, 60_000_000_000, 60_000_000_000, 26_769),
+        (10, 1, 1000, 1, 0),
+        (10, 1, 1000, 2, 1),
+        (10, 1, 1000, 15, 14),
+        (10, 1, 1000, 16, 15),
+        (10, 1, 1000, 17, 15),
+        (10, 1, 1000, 1000, 74),
+        (10, 2, 1_000_000, 1, 0),
+        (10, 2, 1_000_000, 2, 1),
+        (10, 2, 1_000_000, 127, 126),
+        (10, 2, 1_000_000, 128, 127),
+        (10, 2, 1_000_000, 129, 127),
+        (10, 2, 1_000_000, 1000, 422),
+        (10, 2, 1_000_000, 1001, 422),
+        (10, 2, 1_000_000, 12_345, 777),
+        (10, 2, 1_000_000, 999_999, 1417),
+        (10, 2, 1_000_000, 1_000_000, 1417),
+        (2, 4, u64::MAX, 1, 0),
+        (2, 4, u64::MAX, 2, 1),
+        (2, 4, u64::MAX, 31, 30),
+        (2, 4, u64::MAX, 32, 31),
+        (2, 4, u64::MAX, 33, 31),
+        (2, 4, u64::MAX, 1000, 110),
+        (2, 4, u64::MAX, 1001, 110),
+        (2, 4, u64::MAX, 12_345, 167),
+        (2, 4, u64::MAX, 999_999, 269),
+        (2, 4, u64::MAX, 1_000_000, 269),
+        (2, 4, u64::MAX, 60_000_000_000, 522),
+        (2, 4, u64::MAX, 78_953_730_967_339, 688),
+        (2, 4, u64::MAX, 9_007_199_254_740_993, 799),
+        (2, 4, u64::MAX, 6_148_914_691_236_517_205, 948),
+        (2, 4, u64::MAX, u64::MAX, 974),
+        (16, 2, 1_099_511_627_776, 1, 0),
+        (16, 2, 1_099_511_627_776, 2, 1),
+        (16, 2, 1_099_511_627_776, 511, 510),
+        (16, 2, 1_099_511_627_776, 512, 511),
+        (16, 2, 1_099_511_627_776, 513, 511),
+        (16, 2, 1_099_511_627_776, 1000, 755),
+        (16, 2, 1_099_511_627_776, 1001, 755),
+        (16, 2, 1_099_511_627_776, 12_345, 1664),
+        (16, 2, 1_099_511_627_776, 999_999, 3303),
+        (16, 2, 1_099_511_627_776, 1_000_000, 3303),
+        (16, 2, 1_099_511_627_776, 60_000_000_000, 7358),
+        (10, 5, u64::MAX, 1, 0),
+        (10, 5, u64::MAX, 2, 1),
+        (10, 5, u64::MAX, 1000, 999),
+        (10, 5, u64::MAX, 1001, 1000),
+        (10, 5, u64::MAX, 12_345, 12_344),
+        (10, 5, u64::MAX, 131_071, 131_070),
+        (10, 5, u64::MAX, 131_072, 131_071),
+        (10, 5, u64::MAX, 131_073, 131_071),
+        (10, 5, u64::MAX, 999_999, 421_805),
+        (10, 5, u64::MAX, 1_000_000, 421_805),
+        (10, 5, u64::MAX, 60_000_000_000, 2_005_693),
+        (10, 5, u64::MAX, 78_953_730_967_339, 3_043_270),
+        (10, 5, u64::MAX, 9_007_199_254_740_993, 3_731_071),
+        (10, 5, u64::MAX, 6_148_914_691_236_517_205, 4_664_404),
+        (10, 5, u64::MAX, u64::MAX, 4_831_070),
+    ];
 
-        assert_eq!(h.index_value(0), 1);
-        assert_eq!(h.index_value(1), 2);
-        assert_eq!(h.index_value(14), 15);
+    #[test]
+    fn test_bucket_fixtures() {
+        for &(radix, precision, max_value, value, index) in BUCKET_FIXTURES {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).max_value(max_value);
+            c.radix = radix;
+            let properties = HistogramProperties::new(&c).unwrap();
+
+            assert_eq!(properties.get_index(value), Some(index), "fixture: {:?}", (c, value));
+            let low = properties.index_low(index);
+            assert!(low <= value);
+            assert_eq!(properties.get_index(low), Some(index));
+            assert_eq!(properties.get_index(properties.index_value(index)), Some(index));
+        }
+    }
 
-        assert_eq!(h.index_value(15), 16);
-        assert_eq!(h.index_value(25), 32);
-        assert_eq!(h.index_value(35), 64);
+    #[test]
+    fn test_occupied_range() {
+        let mut h = Histogram::new().unwrap();
+        assert_eq!(h.data.occupied, None);
+
+        h.increment(500).unwrap();
+        h.increment(7).unwrap();
+        h.record(1_000_000, 2).unwrap();
+        let (low, high) = (h.get_index(7).unwrap(), h.get_index(1_000_000).unwrap());
+        assert_eq!(h.data.occupied, Some((low, high)));
+        h.increment(0).unwrap_err();
+        h.record(20, 0).unwrap();
+        assert_eq!(h.data.occupied, Some((low, high)));
+
+        // emptying a bound finds the next occupied bucket
+        let middle = h.get_index(500).unwrap();
+        h.set_bucket_count(high, 0).unwrap();
+        assert_eq!(h.data.occupied, Some((low, middle)));
+        h.set_bucket_count(middle, 0).unwrap();
+        h.set_bucket_count(low, 0).unwrap();
+        assert_eq!(h.data.occupied, None);
+        h.set_bucket_count(high, 3).unwrap();
+        assert_eq!(h.data.occupied, Some((high, high)));
+        h.check_invariants().unwrap();
+
+        // merging, copying, and decoding carry the range
+        let mut other = h.clone_empty();
+        assert_eq!(other.data.occupied, None);
+        other.increment(2).unwrap();
+        h.merge(&other);
+        assert_eq!(h.data.occupied, Some((1, high)));
+
+        let mut copy = other.clone_empty();
+        copy.reset_to(&h).unwrap();
+        assert_eq!(copy.data.occupied, Some((1, high)));
+        let decoded = Histogram::from_bytes(&h.to_bytes()).unwrap();
+        assert_eq!(decoded.data.occupied, Some((1, high)));
+        let merged = MergedView::new(&[&other, &copy]).unwrap().to_histogram();
+        assert_eq!(merged.data.occupied, Some((1, high)));
+        merged.check_invariants().unwrap();
+
+        h.clear();
+        assert_eq!(h.data.occupied, None);
+        assert_eq!(h.percentile(50.0), Err(HistogramError::Empty));
     }
 
     #[test]
-    fn test_index_value_1() {
-        let mut c = HistogramConfig::new();
-        c.max_value(1_000).precision(2);
-        let h = Histogram::configured(c).unwrap();
+    #[ignore]
+    fn test_occupied_timing() {
+        use std::time::Instant;
 
-        assert_eq!(h.index_value(0), 1);
-        assert_eq!(h.index_value(1), 2);
-        assert_eq!(h.index_value(126), 127);
+        let mut h = Histogram::new().unwrap();
+        for &value in &[100, 2_000, 30_000] {
+            h.increment(value).unwrap();
+        }
 
-        assert_eq!(h.index_value(127), 128);
-        assert_eq!(h.index_value(227), 256);
-        assert_eq!(h.index_value(327), 512);
+        // scan the occupied range, as percentile() does, or every bucket
+        let time = |occupied| {
+            let start = Instant::now();
+            for p in 0..10_000 {
+                let rank = super::Rank::Percent(p as f64 / 100.0);
+                super::percentile_index(&h.data.counters, occupied, |i| h.data.data[i], rank)
+                    .unwrap();
+            }
+            start.elapsed().as_nanos()
+        };
+
+        let full = time(Some((0, h.data.data.len() - 1)));
+        let occupied = time(h.data.occupied);
+        println!("percentile over all buckets: {} ns occupied range: {} ns", full, occupied);
+        assert!(occupied < full);
+    }
+
+    // every percentile found by the running totals, as by walking the buckets
+    fn check_running_totals(h: &Histogram) {
+        let counters = h.ranked_counters();
+        for p in 0..1_001 {
+            let rank = super::Rank::Percent(p as f64 / 10.0);
+            let walked = super::percentile_position(&counters,
+                                                    h.data.occupied,
+                                                    |index| h.data.data[index],
+                                                    rank);
+            assert_eq!(h.percentile_position(rank), walked, "percentile: {}", p as f64 / 10.0);
+        }
+        assert_eq!(h.memory_used() > h.clone_empty().memory_used(), h.data.occupied.is_some());
     }
 
     #[test]
-    fn test_index_value_2() {
+    fn test_running_totals() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
         let mut c = HistogramConfig::new();
-        c.max_value(10_000).precision(3);
-        let h = Histogram::configured(c).unwrap();
-
-        assert_eq!(h.index_value(0), 1);
-        assert_eq!(h.index_value(1), 2);
-        assert_eq!(h.index_value(1022), 1023);
+        c.max_value(10_000_000).precision(3);
+
+        // uniform, log uniform, bimodal, and a single bucket
+        let mut uniform = Histogram::configured(c).unwrap();
+        let mut log = Histogram::configured(c).unwrap();
+        let mut bimodal = Histogram::configured(c).unwrap();
+        let mut single = Histogram::configured(c).unwrap();
+        for _ in 0..20_000 {
+            uniform.record(rng.next() % 10_000_000 + 1, 1).unwrap();
+            let exponent = (rng.next() >> 11) as f64 / (1_u64 << 53) as f64 * 23.0;
+            log.increment(exponent.exp2() as u64).unwrap();
+            let mode = if rng.next().is_multiple_of(10) { 5_000_000 } else { 1_000 };
+            bimodal.increment(mode + rng.next() % 100).unwrap();
+        }
+        single.record(42, 1_000).unwrap();
+        for h in [&uniform, &log, &bimodal, &single] {
+            check_running_totals(h);
+        }
 
-        assert_eq!(h.index_value(1023), 1024);
-        assert_eq!(h.index_value(2023), 2048);
-        assert_eq!(h.index_value(3023), 4096);
-        assert_eq!(h.index_value(4023), 8192);
+        // missed samples, ranked or not, and saturated counts
+        let mut h = log.clone();
+        h.record(0, 500).unwrap_err();
+        h.record(20_000_000, 700).unwrap_err();
+        check_running_totals(&h);
+        h.config.rank_missed = true;
+        check_running_totals(&h);
+        let mut saturated = Histogram::configured(c).unwrap();
+        saturated.record(10, u64::MAX).unwrap();
+        saturated.record(1_000, u64::MAX).unwrap();
+        check_running_totals(&saturated);
+
+        // each change to the counts drops the totals built before it
+        let mut h = bimodal.clone();
+        check_running_totals(&h);
+        h.record(9_000_000, 5_000).unwrap();
+        check_running_totals(&h);
+        h.subtract(9_000_000, 4_000).unwrap();
+        check_running_totals(&h);
+        h.decrement(1_050).unwrap();
+        check_running_totals(&h);
+        h.merge(&uniform);
+        check_running_totals(&h);
+        let id = h.get_index(1_000).unwrap();
+        h.set_bucket_count(id, 0).unwrap();
+        check_running_totals(&h);
+        h.reset_to(&single).unwrap();
+        check_running_totals(&h);
+        h.refine(4).unwrap();
+        check_running_totals(&h);
+        h.clear();
+        assert_eq!(h.percentile(50.0), Err(HistogramError::Empty));
+        h.increment(7).unwrap();
+        assert_eq!(h.percentile(50.0), Ok(7));
+
+        // clones share the totals, and drop them apart
+        let mut clone = log.clone();
+        assert_eq!(clone.percentile(99.0), log.percentile(99.0));
+        clone.record(1, 1_000_000).unwrap();
+        check_running_totals(&clone);
+        check_running_totals(&log);
     }
 
     #[test]
-    fn test_iterator() {
+    #[ignore]
+    fn test_running_totals_timing() {
+        use std::time::Instant;
+
         let mut c = HistogramConfig::new();
-        c.max_value(100).precision(1);
+        c.precision(4);
         let mut h = Histogram::configured(c).unwrap();
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..100_000 {
+            let exponent = (rng.next() >> 11) as f64 / (1_u64 << 53) as f64 * 35.0;
+            h.increment(exponent.exp2() as u64).unwrap();
+        }
+        let percentiles: Vec<f64> = (0..20).map(|i| 50.0 + i as f64 * 2.5).collect();
+
+        // 20 percentiles of an unchanged histogram, walking the buckets for
+        // each as before, or searching the running totals
+        let counters = h.ranked_counters();
+        let start = Instant::now();
+        for _ in 0..100 {
+            for &p in &percentiles {
+                super::percentile_position(&counters,
+                                           h.data.occupied,
+                                           |index| h.data.data[index],
+                                           super::Rank::Percent(p))
+                    .unwrap();
+            }
+        }
+        let walked = start.elapsed().as_nanos();
 
-        loop {
-            match h.next() {
-                Some(bucket) => {
-                    match h.increment(bucket.value) {
-                        Ok(_) => {}
-                        Err(_) => {}
-                    }
-                }
-                None => {
-                    break;
-                }
+        let start = Instant::now();
+        for _ in 0..100 {
+            for &p in &percentiles {
+                h.percentile(p).unwrap();
             }
         }
+        let searched = start.elapsed().as_nanos();
+        println!("20 percentiles by walking: {} ns by running totals: {} ns",
+                 walked / 100,
+                 searched / 100);
+        assert!(searched < walked);
     }
 
     #[test]
-    fn test_percentile() {
-        let mut c = HistogramConfig::new();
-        c.max_value(1_000).precision(4);
-        let mut h = Histogram::configured(c).unwrap();
+    fn test_entropy() {
+        let mut h = Histogram::new().unwrap();
+        assert_eq!(h.entropy(), Err(HistogramError::Empty));
+        h.increment(0).unwrap_err();
+        assert!(h.entropy().is_err());
+
+        // a point distribution has no entropy
+        h.record(42, 1_000).unwrap();
+        assert_eq!(h.entropy().unwrap(), 0.0);
+
+        // a uniform distribution over 2^k buckets has k bits
+        for &buckets in &[2_u64, 16, 256] {
+            h.clear();
+            for value in 1..(buckets + 1) {
+                h.record(value, 3).unwrap();
+            }
+            let expected = (buckets as f64).log2();
+            assert!((h.entropy().unwrap() - expected).abs() < 1e-9, "buckets: {}", buckets);
+        }
 
-        for i in 100..200 {
-            h.increment(i).ok().expect("error");
+        // counts of 1, 1, and 2 give 0.5 + 0.5 + 0.5 bits
+        h.clear();
+        h.increment(1).unwrap();
+        h.increment(2).unwrap();
+        h.record(3, 2).unwrap();
+        assert!((h.entropy().unwrap() - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_condensed() {
+        let mut rng = Rng(0x5851_f42d_4c95_7f2d);
+        let mut h = Histogram::new().unwrap();
+        for _ in 0..10_000 {
+            let value = 1 + (rng.next() % 1_000) * (rng.next() % 1_000) * (rng.next() % 50);
+            h.increment(value).unwrap();
+        }
+        let minimum = h.minimum().unwrap();
+        let maximum = h.maximum().unwrap();
+
+        for &max_buckets in &[1_usize, 2, 7, 300, 100_000] {
+            let bars = h.condensed(max_buckets);
+            assert!(bars.len() <= max_buckets);
+            assert!(!bars.is_empty());
+
+            // the bars tile the recorded range without gaps
+            assert!(bars[0].0 <= minimum);
+            assert!(bars[bars.len() - 1].1 >= maximum);
+            for pair in bars.windows(2) {
+                assert_eq!(pair[0].1 + 1, pair[1].0);
+            }
+            for bar in &bars {
+                assert!(bar.0 <= bar.1);
+                assert_eq!(bar.2, h.count_between(bar.0, bar.1));
+            }
+            assert_eq!(bars.iter().map(|bar| bar.2).sum::<u64>(), h.entries());
         }
 
-        assert_eq!(h.percentile(0.0).unwrap(), 100);
-        assert_eq!(h.percentile(10.0).unwrap(), 109);
-        assert_eq!(h.percentile(25.0).unwrap(), 124);
-        assert_eq!(h.percentile(50.0).unwrap(), 150);
-        assert_eq!(h.percentile(75.0).unwrap(), 175);
-        assert_eq!(h.percentile(90.0).unwrap(), 190);
-        assert_eq!(h.percentile(95.0).unwrap(), 195);
-        assert_eq!(h.percentile(100.0).unwrap(), 199);
+        assert_eq!(h.condensed(1), vec![(1, h.index_bounds(h.data.occupied.unwrap().1).1, 10_000)]);
+        assert_eq!(h.condensed(100_000).len(), h.iter_from(0).recorded().count());
+        assert!(h.condensed(0).is_empty());
+        assert!(Histogram::new().unwrap().condensed(10).is_empty());
     }
 
     #[test]
-    fn test_percentile_bad() {
+    fn test_inconsistent_state() {
+        // ranked samples missed at either end are found beyond the buckets
         let mut c = HistogramConfig::new();
-        c.max_value(1_000).precision(4);
+        c.rank_missed(true);
         let mut h = Histogram::configured(c).unwrap();
+        h.record(0, 3).unwrap_err();
+        h.record(10, 4).unwrap();
+        h.record(60_000_000_001, 3).unwrap_err();
+        assert_eq!(h.percentile(20.0), Err(HistogramError::Underflow));
+        assert_eq!(h.percentile(50.0), Ok(10));
+        assert_eq!(h.percentile(80.0), Err(HistogramError::Overflow));
+        assert_eq!(h.freeze().percentile(80.0), Err(HistogramError::Overflow));
+
+        // entries counted without samples in a bucket or missed counter
+        let mut h = Histogram::new().unwrap();
+        h.record(10, 4).unwrap();
+        let mut other = Histogram::new().unwrap();
+        other.record(20, 6).unwrap();
+        h.apply_counter_delta(&other.counters());
+        assert_eq!(h.entries(), 10);
+
+        let error = HistogramError::InconsistentState {
+            needed: 5,
+            available: 4,
+        };
+        assert_eq!(h.percentile(45.0), Err(error));
+        assert_eq!(h.freeze().percentile(45.0), Err(error));
+        assert_eq!(h.percentile(40.0), Ok(10));
+        assert_eq!(h.percentile(60.0),
+                   Err(HistogramError::InconsistentState {
+                       needed: 6,
+                       available: 4,
+                   }));
+        assert_eq!(h.percentile(0.0), Ok(10));
+        assert!(error.to_string().contains("needs 5 samples but 4"));
+
+        // doctored counters in an encoded histogram
+        let mut h = Histogram::new().unwrap();
+        h.record(10, 4).unwrap();
+        let mut bytes = h.to_bytes();
+        bytes[22..30].copy_from_slice(&1_000_u64.to_le_bytes());
+        let h = Histogram::from_bytes(&bytes).unwrap();
+        assert_eq!(h.percentile(50.0),
+                   Err(HistogramError::InconsistentState {
+                       needed: 500,
+                       available: 4,
+                   }));
+    }
 
-        let _ = h.increment(5_000);
-
-        assert!(h.percentile(0.0).is_err());
-        assert!(h.percentile(50.0).is_err());
-        assert!(h.percentile(100.0).is_err());
-
-        let _ = h.increment(1);
+    #[test]
+    fn test_diff_buckets() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(2);
+        let mut base = Histogram::configured(c).unwrap();
+        let mut shifted = base.clone_empty();
+        let mut scaled = base.clone_empty();
+        for value in 1_000..2_000 {
+            base.increment(value).unwrap();
+            shifted.increment(value + 500).unwrap();
+            scaled.record(value, 3).unwrap();
+        }
 
-        assert!(h.percentile(0.0).is_ok());
+        // shifting up leaves the base ahead below the overlap and behind above
+        let diff = base.diff_buckets(&shifted).unwrap();
+        assert!(!diff.saturated());
+        for &(value, difference) in diff.buckets() {
+            assert_ne!(difference, 0);
+            assert_eq!(difference > 0, value < 1_500, "value: {}", value);
+        }
+        assert_eq!(diff.buckets().iter().map(|bucket| bucket.1).sum::<i64>(), 0);
+
+        // scaling the counts by three makes every occupied bucket negative
+        let diff = base.diff_buckets(&scaled).unwrap();
+        let occupied = base.iter_from(0).recorded().count();
+        assert_eq!(diff.buckets().len(), occupied);
+        assert!(diff.buckets().iter().all(|bucket| bucket.1 < 0));
+        assert_eq!(diff.buckets().iter().map(|bucket| bucket.1).sum::<i64>(), -2_000);
+        assert!(diff.buckets().windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        assert!(base.diff_buckets(&base).unwrap().buckets().is_empty());
+        assert!(Histogram::new().unwrap().diff_buckets(&base).is_err());
+
+        let mut huge = base.clone_empty();
+        huge.record(10, u64::MAX).unwrap();
+        let diff = huge.diff_buckets(&base).unwrap();
+        assert!(diff.saturated());
+        assert_eq!(diff.buckets()[0], (10, i64::MAX));
+        assert_eq!(base.diff_buckets(&huge).unwrap().buckets()[0], (10, i64::MIN));
+    }
 
-        let _ = h.increment(500);
-        let _ = h.increment(500);
+    #[test]
+    fn test_empty_errors() {
+        let h = Histogram::new().unwrap();
 
-        assert!(h.percentile(50.0).is_ok());
+        assert_eq!(h.percentile(50.0), Err(HistogramError::Empty));
+        assert_eq!(h.mean(), Err(HistogramError::Empty));
+        assert_eq!(h.stddev(), Err(HistogramError::Empty));
     }
 }
@@ -0,0 +1,78 @@
+use super::{HistogramError, HistogramProperties};
+
+/// limits on the size of a decoded Histogram
+///
+/// An encoding carries its config, which decides how many buckets are
+/// allocated. Decoding input from untrusted sources with limits rejects a
+/// config over the limits with `LimitExceeded` before any buckets are
+/// allocated. Use with `Histogram::from_bytes_with_limits()`,
+/// `Histogram::deserialize_from_with_limits()`, `Histogram::load_with_limits()`,
+/// `Histogram::from_arrow_with_limits()`, or as a serde `DeserializeSeed`.
+///
+/// The decoding calls without limits use the default, which allows 64 MiB
+/// of bucket counts: room for precision 5 at the default max_value, while a
+/// few bytes of input can no longer allocate gigabytes. A max_memory of 0
+/// lifts the limit for trusted input.
+///
+/// # Example
+/// ```
+/// # use histogram::{DeserializeLimits, Histogram, HistogramConfig, HistogramError};
+/// let mut c = HistogramConfig::new();
+/// c.precision(4);
+///
+/// let bytes = Histogram::configured(c).unwrap().to_bytes();
+///
+/// let mut limits = DeserializeLimits::new();
+/// limits.max_buckets(100_000);
+///
+/// assert_eq!(Histogram::from_bytes_with_limits(&bytes, &limits).unwrap_err(),
+///            HistogramError::LimitExceeded);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeserializeLimits {
+    max_buckets: u32,
+    max_memory: u64,
+}
+
+// the memory limit of the default DeserializeLimits
+const DEFAULT_MAX_MEMORY: u64 = 64 << 20;
+
+impl Default for DeserializeLimits {
+    fn default() -> DeserializeLimits {
+        DeserializeLimits {
+            max_buckets: 0,
+            max_memory: DEFAULT_MAX_MEMORY,
+        }
+    }
+}
+
+impl DeserializeLimits {
+    /// create DeserializeLimits with the default limits, no limit on the
+    /// buckets and at most 64 MiB of bucket counts
+    pub fn new() -> DeserializeLimits {
+        Default::default()
+    }
+
+    /// set the most buckets of a decoded Histogram, 0 for no limit
+    pub fn max_buckets(&mut self, buckets: u32) -> &mut Self {
+        self.max_buckets = buckets;
+        self
+    }
+
+    /// set the most memory allocated for the bucket counts of a decoded
+    /// Histogram in bytes, 0 for no limit
+    pub fn max_memory(&mut self, bytes: u64) -> &mut Self {
+        self.max_memory = bytes;
+        self
+    }
+
+    // check the layout derived from a decoded config
+    pub(crate) fn check(&self, properties: &HistogramProperties) -> Result<(), HistogramError> {
+        let buckets = properties.buckets_total;
+        let memory = properties.memory_used;
+        if (self.max_buckets > 0 && buckets > self.max_buckets as u64) ||
+           (self.max_memory > 0 && memory > self.max_memory) {
+            return Err(HistogramError::LimitExceeded);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+use std::sync::Arc;
+
+use super::{mean, newer, percentile_position, stdvar, Histogram, HistogramCounters, HistogramError,
+            HistogramStats, Rank};
+
+/// a read-only view answering statistics over several histograms as if they
+/// had been merged
+///
+/// Each query sums the aligned buckets as it scans, so no combined histogram
+/// is allocated. For many queries over the same data, `to_histogram()`
+/// materializes the merge once instead. The minimum, maximum, mean and
+/// variance are exact while the exact aggregates of every histogram are
+/// known, as they are for a merged `Histogram`.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, MergedView};
+/// let mut a = Histogram::new().unwrap();
+/// let mut b = Histogram::new().unwrap();
+///
+/// for value in 1..51 {
+///     a.increment(value).unwrap();
+///     b.increment(value + 50).unwrap();
+/// }
+///
+/// let view = MergedView::new(&[&a, &b]).unwrap();
+///
+/// assert_eq!(view.entries(), 100);
+/// assert_eq!(view.maximum().unwrap(), 100);
+#[derive(Clone)]
+pub struct MergedView<'a> {
+    first: &'a Histogram,
+    histograms: Vec<&'a Histogram>,
+    counters: HistogramCounters,
+    // the combined exact stats, None unless known for every histogram
+    stats: Option<HistogramStats>,
+}
+
+impl<'a> MergedView<'a> {
+    /// create a view over the histograms, which must share the same config
+    pub fn new(histograms: &[&'a Histogram]) -> Result<MergedView<'a>, HistogramError> {
+        let first = match histograms.first() {
+            Some(first) => *first,
+            None => return Err(HistogramError::Empty),
+        };
+        if histograms.iter().any(|histogram| !first.config.matches(&histogram.config)) {
+            return Err(HistogramError::IncompatibleConfig);
+        }
+        Ok(MergedView::matching(first, histograms))
+    }
+
+    // a view over histograms known to share the config of the first
+    pub(crate) fn matching(first: &'a Histogram, histograms: &[&'a Histogram]) -> MergedView<'a> {
+        let mut counters = HistogramCounters::new();
+        let mut stats = Some(HistogramStats::default());
+        for histogram in histograms {
+            counters.add(&histogram.data.counters);
+            stats = match (stats, histogram.exact_stats()) {
+                (Some(stats), Some(theirs)) => stats.combine(theirs),
+                _ => None,
+            };
+        }
+
+        MergedView {
+            first,
+            histograms: histograms.to_vec(),
+            counters,
+            stats,
+        }
+    }
+
+    // the combined count of the bucket at the index
+    fn count(&self, index: usize) -> u64 {
+        self.histograms
+            .iter()
+            .fold(0_u64, |total, histogram| total.saturating_add(histogram.data.count(index)))
+    }
+
+    fn first(&self) -> &'a Histogram {
+        self.first
+    }
+
+    // the range covering the occupied buckets of every histogram
+    fn occupied(&self) -> Option<(usize, usize)> {
+        self.histograms
+            .iter()
+            .filter_map(|histogram| histogram.data.occupied)
+            .fold(None, |occupied, (low, high)| match occupied {
+                Some((l, h)) => Some((low.min(l), high.max(h))),
+                None => Some((low, high)),
+            })
+    }
+
+    /// return the number of entries across the histograms
+    pub fn entries(&self) -> u64 {
+        self.counters.entries_total
+    }
+
+    /// return the value for the given percentile of the merged data
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, MergedView};
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// a.record(10, 9).unwrap();
+    /// b.record(1000, 1).unwrap();
+    ///
+    /// let view = MergedView::new(&[&a, &b]).unwrap();
+    ///
+    /// assert_eq!(view.percentile(50.0).unwrap(), 10);
+    /// assert_eq!(view.percentile(100.0).unwrap(), 1000);
+    pub fn percentile(&self, percentile: f64) -> Result<u64, HistogramError> {
+        let first = self.first();
+        percentile_position(&self.counters.ranked(first.config.rank_missed),
+                            self.occupied(),
+                            |index| self.count(index),
+                            Rank::Percent(percentile))
+            .map(|position| first.position_value(position))
+    }
+
+    // the combined stats, if they count every stored sample
+    fn exact_stats(&self) -> Option<&HistogramStats> {
+        HistogramStats::exact(self.stats.as_ref(), &self.counters)
+    }
+
+    /// return the smallest value of the merged data
+    pub fn minimum(&self) -> Result<u64, HistogramError> {
+        let rank_missed = self.first().config.rank_missed;
+        let exact = self.exact_stats();
+        match exact.and_then(|stats| stats.exact_minimum(&self.counters, rank_missed)) {
+            Some(minimum) => Ok(minimum),
+            None => self.percentile(0.0),
+        }
+    }
+
+    /// return the largest value of the merged data
+    pub fn maximum(&self) -> Result<u64, HistogramError> {
+        let rank_missed = self.first().config.rank_missed;
+        let exact = self.exact_stats();
+        match exact.and_then(|stats| stats.exact_maximum(&self.counters, rank_missed)) {
+            Some(maximum) => Ok(maximum),
+            None => self.percentile(100.0),
+        }
+    }
+
+    /// arithmetic mean of the merged data
+    pub fn mean(&self) -> Result<u64, HistogramError> {
+        self.counters.check_stored()?;
+        match self.exact_stats().and_then(HistogramStats::exact_mean) {
+            Some(mean) => Ok(mean),
+            None => {
+                mean(&self.first().properties,
+                     &self.counters,
+                     self.occupied(),
+                     |index| self.count(index))
+            }
+        }
+    }
+
+    /// standard variance of the merged data
+    pub fn stdvar(&self) -> Result<u64, HistogramError> {
+        self.counters.check_stored()?;
+        match self.exact_stats().and_then(HistogramStats::exact_stdvar) {
+            Some(stdvar) => Ok(stdvar),
+            None => {
+                stdvar(&self.first().properties,
+                       &self.counters,
+                       self.occupied(),
+                       |index| self.count(index))
+            }
+        }
+    }
+
+    /// standard deviation of the merged data
+    pub fn stddev(&self) -> Result<u64, HistogramError> {
+        let stdvar = self.stdvar()? as f64;
+        Ok(stdvar.sqrt().ceil() as u64)
+    }
+
+    /// return the merged count of samples stored in the buckets covering
+    /// values from low to high, inclusive
+    pub fn count_between(&self, low: u64, high: u64) -> u64 {
+        match self.first().index_range(low, high) {
+            Some((low, high)) => {
+                (low..(high + 1))
+                    .fold(0_u64, |total, index| total.saturating_add(self.count(index)))
+            }
+            None => 0,
+        }
+    }
+
+    /// materialize the merged data as a new Histogram
+    ///
+    /// The counters are summed along with the buckets, so samples missed by
+    /// any of the histograms are preserved.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, MergedView};
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// a.increment(1).unwrap();
+    /// b.increment(1).unwrap();
+    ///
+    /// let h = MergedView::new(&[&a, &b]).unwrap().to_histogram();
+    ///
+    /// assert_eq!(h.get(1).unwrap(), 2);
+    pub fn to_histogram(&self) -> Histogram {
+        let mut histogram = self.first().clone_empty();
+        histogram.data.data = Arc::new((0..histogram.data.data.len())
+                                           .map(|index| self.count(index))
+                                           .collect());
+        histogram.data.counters = self.counters;
+        histogram.data.occupied = self.occupied();
+        histogram.data.last = self.histograms
+                                  .iter()
+                                  .fold(None, |last, histogram| newer(last, histogram.data.last));
+        histogram.data.stats = self.stats;
+        histogram
+    }
+}
+
+/// how far the percentiles of several histograms disagree, from
+/// `Histogram::percentile_spread()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpreadReport {
+    values: Vec<u64>,
+    minimum: u64,
+    maximum: u64,
+    median: u64,
+    merged: u64,
+    exceeded: bool,
+}
+
+impl SpreadReport {
+    /// return the percentile of each histogram, in the order given
+    pub fn values(&self) -> &[u64] {
+        &self.values
+    }
+
+    /// return the smallest of the percentiles
+    pub fn minimum(&self) -> u64 {
+        self.minimum
+    }
+
+    /// return the largest of the percentiles
+    pub fn maximum(&self) -> u64 {
+        self.maximum
+    }
+
+    /// return the median of the percentiles, the lower one for an even count
+    pub fn median(&self) -> u64 {
+        self.median
+    }
+
+    /// return the percentile of the histograms merged together
+    pub fn merged(&self) -> u64 {
+        self.merged
+    }
+
+    /// return the difference between the largest and smallest percentiles
+    pub fn spread(&self) -> u64 {
+        self.maximum - self.minimum
+    }
+
+    /// return the spread relative to the merged percentile
+    pub fn relative_spread(&self) -> f64 {
+        self.spread() as f64 / self.merged as f64
+    }
+
+    /// true if the relative spread is above the tolerance
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl Histogram {
+    /// compare a percentile across histograms sharing the same config, such
+    /// as shards of one fleet, to check they agree before trusting the merge
+    ///
+    /// The report flags the histograms as disagreeing when the relative
+    /// spread, `(maximum - minimum) / merged`, is above the tolerance. Any
+    /// histogram without stored samples fails with its percentile error.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut a = Histogram::new().unwrap();
+    /// let mut b = Histogram::new().unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     a.increment(value).unwrap();
+    ///     b.increment(value * 2).unwrap();
+    /// }
+    ///
+    /// let report = Histogram::percentile_spread(&[&a, &b], 50.0, 0.1).unwrap();
+    ///
+    /// assert_eq!(report.values(), &[50, 100]);
+    /// assert_eq!(report.spread(), 50);
+    /// assert!(report.exceeded());
+    pub fn percentile_spread(histograms: &[&Histogram],
+                             percentile: f64,
+                             tolerance: f64)
+                             -> Result<SpreadReport, HistogramError> {
+        let merged = MergedView::new(histograms)?.percentile(percentile)?;
+
+        let mut values = Vec::with_capacity(histograms.len());
+        for histogram in histograms {
+            values.push(histogram.percentile(percentile)?);
+        }
+
+        let mut sorted = values.clone();
+        sorted.sort_unstable();
+        let (minimum, maximum) = match (sorted.first(), sorted.last()) {
+            (Some(&minimum), Some(&maximum)) => (minimum, maximum),
+            _ => return Err(HistogramError::Empty),
+        };
+        let median = sorted.get((sorted.len() - 1) / 2).copied().unwrap_or(minimum);
+
+        let mut report = SpreadReport {
+            values,
+            minimum,
+            maximum,
+            median,
+            merged,
+            exceeded: false,
+        };
+        report.exceeded = report.relative_spread() > tolerance;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MergedView;
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+
+    fn shards() -> Vec<Histogram> {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).precision(2);
+
+        (0..8)
+            .map(|shard| {
+                let mut h = Histogram::configured(c).unwrap();
+                for value in 1..2_000 {
+                    h.record(value * (shard + 3) * 41 % 1_000_000 + 1, shard + value % 3)
+                     .unwrap();
+                }
+                h
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_merge() {
+        let shards = shards();
+        let refs: Vec<&Histogram> = shards.iter().collect();
+        let view = MergedView::new(&refs).unwrap();
+
+        let mut merged = shards[0].clone_empty();
+        for shard in &shards {
+            merged.merge(shard);
+        }
+
+        assert_eq!(view.entries(), merged.entries());
+        for p in 0..1001 {
+            let p = p as f64 / 10.0;
+            assert_eq!(view.percentile(p), merged.percentile(p), "percentile: {}", p);
+        }
+        assert_eq!(view.minimum(), merged.minimum());
+        assert_eq!(view.maximum(), merged.maximum());
+        assert_eq!(view.mean(), merged.mean());
+        assert_eq!(view.stdvar(), merged.stdvar());
+        assert_eq!(view.stddev(), merged.stddev());
+        for &(low, high) in &[(1, 100), (500, 50_000), (0, u64::MAX)] {
+            assert_eq!(view.count_between(low, high), merged.count_between(low, high));
+        }
+
+        let h = view.to_histogram();
+        assert_eq!(h.data.data, merged.data.data);
+        assert_eq!(h.entries(), merged.entries());
+    }
+
+    #[test]
+    fn test_missed_counters() {
+        let mut a = Histogram::new().unwrap();
+        let mut b = Histogram::new().unwrap();
+
+        a.increment(0).unwrap_err();
+        b.increment(0).unwrap_err();
+        assert_eq!(MergedView::new(&[&a, &b]).unwrap().percentile(50.0),
+                   Err(HistogramError::OnlyMissedSamples {
+                       small: 2,
+                       large: 0,
+                       unknown: 0,
+                   }));
+
+        b.increment(10).unwrap();
+        let view = MergedView::new(&[&a, &b]).unwrap();
+        assert_eq!(view.entries(), 3);
+        assert_eq!(view.percentile(100.0).unwrap(), 10);
+        assert_eq!(view.to_histogram().entries(), 3);
+        assert_eq!(view.to_histogram().last_value(), Some(10));
+    }
+
+    #[test]
+    fn test_spread() {
+        let shards = shards();
+        let identical: Vec<&Histogram> = (0..4).map(|_| &shards[0]).collect();
+
+        let report = Histogram::percentile_spread(&identical, 99.0, 0.0).unwrap();
+        let p99 = shards[0].percentile(99.0).unwrap();
+        assert_eq!(report.values(), &[p99; 4]);
+        assert_eq!(report.spread(), 0);
+        assert_eq!(report.relative_spread(), 0.0);
+        assert_eq!(report.median(), p99);
+        assert_eq!(report.merged(), p99);
+        assert!(!report.exceeded());
+
+        // one shard with a slow tail
+        let mut skewed = shards[0].clone();
+        skewed.record(900_000, skewed.entries()).unwrap();
+        let refs = vec![&shards[0], &shards[0], &skewed];
+
+        let report = Histogram::percentile_spread(&refs, 99.0, 0.1).unwrap();
+        assert_eq!(report.minimum(), p99);
+        assert_eq!(report.median(), p99);
+        assert_eq!(report.maximum(), skewed.percentile(99.0).unwrap());
+        assert!(report.maximum() > 890_000);
+        assert_eq!(report.merged(), MergedView::new(&refs).unwrap().percentile(99.0).unwrap());
+        assert!(report.exceeded());
+        assert!(!Histogram::percentile_spread(&refs, 99.0, 100.0).unwrap().exceeded());
+
+        let empty = shards[0].clone_empty();
+        assert_eq!(Histogram::percentile_spread(&[&shards[0], &empty], 50.0, 0.1),
+                   Err(HistogramError::Empty));
+        assert_eq!(Histogram::percentile_spread(&[], 50.0, 0.1), Err(HistogramError::Empty));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(MergedView::new(&[]).err(), Some(HistogramError::Empty));
+
+        let a = Histogram::new().unwrap();
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let b = Histogram::configured(c).unwrap();
+        assert_eq!(MergedView::new(&[&a, &b]).err(),
+                   Some(HistogramError::IncompatibleConfig));
+    }
+}
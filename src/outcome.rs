@@ -0,0 +1,267 @@
+use std::fmt::Write;
+
+use super::{Histogram, HistogramConfig, HistogramError, MergeReport, MergedView};
+
+// the percentiles shown by OutcomeHistogram::report()
+const REPORT_PERCENTILES: [f64; 4] = [50.0, 90.0, 99.0, 99.9];
+
+/// a pair of histograms of the same config, splitting samples by whether the
+/// operation they measure succeeded or failed
+///
+/// Slow failures, as from timeouts, are easily hidden by the bulk of fast
+/// successes in a single histogram. Keeping them apart shows each outcome's
+/// latency, while `combined()` still answers for every sample.
+///
+/// # Example
+/// ```
+/// # use histogram::OutcomeHistogram;
+/// let mut h = OutcomeHistogram::new().unwrap();
+///
+/// for value in 1..100 {
+///     h.record_ok(value).unwrap();
+/// }
+/// h.record_err(1_000).unwrap();
+///
+/// assert_eq!(h.ok().percentile(99.0).unwrap(), 99);
+/// assert_eq!(h.err().percentile(50.0).unwrap(), 1_000);
+/// assert_eq!(h.error_rate(), 0.01);
+#[derive(Clone, Debug)]
+pub struct OutcomeHistogram {
+    ok: Histogram,
+    err: Histogram,
+}
+
+impl OutcomeHistogram {
+    /// create a new OutcomeHistogram
+    pub fn new() -> Result<OutcomeHistogram, HistogramError> {
+        OutcomeHistogram::configured(HistogramConfig::new())
+    }
+
+    /// create a new OutcomeHistogram with both sides of the config, failing
+    /// as `Histogram::configured()` for an invalid config
+    pub fn configured(config: HistogramConfig) -> Result<OutcomeHistogram, HistogramError> {
+        let ok = Histogram::configured(config)?;
+        let err = ok.clone_empty();
+        Ok(OutcomeHistogram { ok, err })
+    }
+
+    /// record the value of a successful operation, as `Histogram::increment()`
+    pub fn record_ok(&mut self, value: u64) -> Result<(), HistogramError> {
+        self.ok.increment(value)
+    }
+
+    /// record the value of a failed operation, as `Histogram::increment()`
+    pub fn record_err(&mut self, value: u64) -> Result<(), HistogramError> {
+        self.err.increment(value)
+    }
+
+    /// return the histogram of successful operations
+    pub fn ok(&self) -> &Histogram {
+        &self.ok
+    }
+
+    /// return the histogram of failed operations
+    pub fn err(&self) -> &Histogram {
+        &self.err
+    }
+
+    /// return a view of both outcomes together
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::OutcomeHistogram;
+    /// let mut h = OutcomeHistogram::new().unwrap();
+    ///
+    /// h.record_ok(10).unwrap();
+    /// h.record_err(20).unwrap();
+    ///
+    /// let all = h.combined();
+    ///
+    /// assert_eq!(all.entries(), 2);
+    /// assert_eq!(all.maximum().unwrap(), 20);
+    pub fn combined(&self) -> MergedView<'_> {
+        // both sides share the config they were created with
+        MergedView::matching(&self.ok, &[&self.ok, &self.err])
+    }
+
+    /// return the fraction of the entries which were failures, 0.0 if there
+    /// are none
+    pub fn error_rate(&self) -> f64 {
+        let errors = self.err.entries();
+        match self.ok.entries().saturating_add(errors) {
+            0 => 0.0,
+            total => errors as f64 / total as f64,
+        }
+    }
+
+    /// merge both sides of another OutcomeHistogram into this one, returning
+    /// the report of each side, as `Histogram::merge()`
+    pub fn merge(&mut self, other: &OutcomeHistogram) -> (MergeReport, MergeReport) {
+        (self.ok.merge(&other.ok), self.err.merge(&other.err))
+    }
+
+    /// return an OutcomeHistogram holding both sides recorded so far,
+    /// leaving this one cleared, as `Histogram::take()`
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::OutcomeHistogram;
+    /// let mut h = OutcomeHistogram::new().unwrap();
+    ///
+    /// h.record_ok(10).unwrap();
+    /// h.record_err(20).unwrap();
+    ///
+    /// let interval = h.take();
+    ///
+    /// assert_eq!(interval.combined().entries(), 2);
+    /// assert_eq!(h.combined().entries(), 0);
+    pub fn take(&mut self) -> OutcomeHistogram {
+        OutcomeHistogram {
+            ok: self.ok.take(),
+            err: self.err.take(),
+        }
+    }
+
+    /// clear both sides
+    pub fn clear(&mut self) {
+        self.ok.clear();
+        self.err.clear();
+    }
+
+    /// render a table of the count, minimum, maximum, mean and common
+    /// percentiles of each outcome and of both together, followed by the
+    /// error rate
+    ///
+    /// A statistic which cannot be computed, as for a side without samples,
+    /// is shown as `-`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::OutcomeHistogram;
+    /// let mut h = OutcomeHistogram::new().unwrap();
+    ///
+    /// h.record_ok(10).unwrap();
+    /// h.record_ok(20).unwrap();
+    /// h.record_ok(30).unwrap();
+    /// h.record_err(900).unwrap();
+    ///
+    /// assert_eq!(h.report(),
+    ///            "                  ok         err         all\n\
+    ///             count              3           1           4\n\
+    ///             min               10         900          10\n\
+    ///             max               30         900         900\n\
+    ///             mean              20         900         240\n\
+    ///             p50               20         900          20\n\
+    ///             p90               30         900         900\n\
+    ///             p99               30         900         900\n\
+    ///             p99.9             30         900         900\n\
+    ///             error rate    25.00%\n");
+    pub fn report(&self) -> String {
+        let all = self.combined();
+        let mut rows: Vec<(String, [Option<u64>; 3])> = Vec::new();
+
+        let entries = [self.ok.entries(), self.err.entries(), all.entries()];
+        rows.push(("count".to_owned(), [Some(entries[0]), Some(entries[1]), Some(entries[2])]));
+        rows.push(("min".to_owned(),
+                   [self.ok.minimum().ok(), self.err.minimum().ok(), all.minimum().ok()]));
+        rows.push(("max".to_owned(),
+                   [self.ok.maximum().ok(), self.err.maximum().ok(), all.maximum().ok()]));
+        rows.push(("mean".to_owned(),
+                   [self.ok.mean().ok(), self.err.mean().ok(), all.mean().ok()]));
+        for p in REPORT_PERCENTILES.iter() {
+            rows.push((format!("p{}", p),
+                       [self.ok.percentile(*p).ok(),
+                        self.err.percentile(*p).ok(),
+                        all.percentile(*p).ok()]));
+        }
+
+        let mut text = String::new();
+        let _ = writeln!(text, "{:<8}{:>12}{:>12}{:>12}", "", "ok", "err", "all");
+        for (label, values) in rows {
+            let _ = write!(text, "{:<8}", label);
+            for value in values.iter() {
+                match value {
+                    Some(value) => {
+                        let _ = write!(text, "{:>12}", value);
+                    }
+                    None => {
+                        let _ = write!(text, "{:>12}", "-");
+                    }
+                }
+            }
+            text.push('\n');
+        }
+        let _ = writeln!(text, "{:<8}{:>9.2}%", "error rate", self.error_rate() * 100.0);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig};
+    use super::OutcomeHistogram;
+
+    // fast successes with a tail of slow failures, as from timeouts
+    fn skewed() -> OutcomeHistogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000);
+        let mut h = OutcomeHistogram::configured(c).unwrap();
+        for value in 1..1_001 {
+            h.record_ok(value).unwrap();
+            if value % 100 == 0 {
+                h.record_err(30_000 + value).unwrap();
+            }
+        }
+        h
+    }
+
+    #[test]
+    fn test_skewed() {
+        let h = skewed();
+        assert_eq!((h.ok().entries(), h.err().entries()), (1_000, 10));
+        assert!((h.error_rate() - 10.0 / 1_010.0).abs() < 1e-12);
+
+        // the failures only show in the error side and the tail of both
+        assert_eq!(h.ok().percentile(99.0).unwrap(), 990);
+        assert_eq!(h.ok().maximum().unwrap(), 1_000);
+        assert!(h.err().percentile(50.0).unwrap() >= 30_000);
+        let all = h.combined();
+        assert_eq!(all.percentile(50.0).unwrap(), 505);
+        assert!(all.percentile(99.5).unwrap() >= 30_000);
+        assert_eq!(all.entries(), 1_010);
+    }
+
+    #[test]
+    fn test_merge_take() {
+        let mut total = skewed();
+        let interval = skewed();
+        let (ok, err) = total.merge(&interval);
+        assert!(!ok.lossy() && !err.lossy());
+        assert_eq!((total.ok().entries(), total.err().entries()), (2_000, 20));
+
+        let mut h = skewed();
+        let snapshot = h.take();
+        assert_eq!(snapshot.err().entries(), 10);
+        assert_eq!(h.combined().entries(), 0);
+        assert_eq!(h.error_rate(), 0.0);
+        h.record_err(5).unwrap();
+        assert_eq!(h.error_rate(), 1.0);
+        h.clear();
+        assert_eq!(h.err().entries(), 0);
+
+        // both sides keep the config
+        assert_eq!(h.err().buckets_total(), snapshot.ok().buckets_total());
+        assert!(h.ok().buckets_total() < Histogram::new().unwrap().buckets_total());
+    }
+
+    #[test]
+    fn test_report() {
+        let mut h = OutcomeHistogram::new().unwrap();
+        h.record_ok(10).unwrap();
+        let report = h.report();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[1], "count              1           0           1");
+        assert_eq!(lines[2], "min               10           -          10");
+        assert_eq!(lines[9], "error rate     0.00%");
+    }
+}
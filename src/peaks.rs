@@ -0,0 +1,259 @@
+use std::time::{Instant, SystemTime};
+
+use super::{Histogram, HistogramConfig, HistogramProperties};
+
+impl Histogram {
+    /// return the lowest value of each non-empty group of buckets, and when
+    /// a sample was last recorded into the group
+    ///
+    /// Requires `HistogramConfig::track_peaks()`, otherwise this is empty.
+    /// The buckets are grouped by power of two, with the linear buckets at
+    /// the bottom of the range as one group, so tracking costs one timestamp
+    /// per group rather than per bucket. The times are converted from the
+    /// Histogram clock to wall clock time, see `Clock::system_time()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Clock, Histogram, HistogramConfig, ManualClock};
+    /// # use std::sync::Arc;
+    /// # use std::time::Duration;
+    /// let clock = Arc::new(ManualClock::new());
+    /// let mut c = HistogramConfig::new();
+    /// c.track_peaks(true);
+    /// let mut h = Histogram::with_clock(c, clock.clone()).unwrap();
+    ///
+    /// h.increment(5).unwrap();
+    /// clock.advance(Duration::from_secs(60));
+    /// h.increment(50_000).unwrap();
+    ///
+    /// let peaks = h.peak_times();
+    ///
+    /// assert_eq!(peaks.len(), 2);
+    /// assert_eq!(peaks[0].0, 1);
+    /// assert_eq!(peaks[1], (32_768, clock.system_time()));
+    pub fn peak_times(&self) -> Vec<(u64, SystemTime)> {
+        let now = self.clock.now();
+        let wall = self.clock.system_time();
+
+        let mut peaks = Vec::new();
+        for (group, peak) in self.data.peaks.iter().enumerate() {
+            let at = match *peak {
+                Some(at) => at,
+                None => continue,
+            };
+            let (start, end) = self.properties.group_indices(group);
+            let occupied = self.data.data.get(start..end).unwrap_or(&[]);
+            if !occupied.iter().any(|count| *count > 0) {
+                continue;
+            }
+            let time = wall.checked_sub(now.saturating_duration_since(at)).unwrap_or(wall);
+            peaks.push((self.properties.group_low(group), time));
+        }
+        peaks
+    }
+
+    // note a sample recorded into the bucket at the given time
+    pub(crate) fn note_peak(&mut self, index: usize, at: Instant) {
+        let group = self.properties.group(index);
+        if let Some(peak) = self.data.peaks.get_mut(group) {
+            *peak = Some(at);
+        }
+    }
+
+    // keep the newer time of each group from another histogram, whose
+    // groups are mapped by their lowest value where the layouts differ
+    pub(crate) fn merge_peaks(&mut self, other: &Histogram) {
+        self.merge_group_peaks(&other.data.peaks, &other.config, &other.properties);
+    }
+
+    // keep the newer time of each group of peaks laid out by the config and
+    // properties, as merge_peaks() does
+    pub(crate) fn merge_group_peaks(&mut self,
+                                    peaks: &[Option<Instant>],
+                                    config: &HistogramConfig,
+                                    properties: &HistogramProperties) {
+        for (group, peak) in peaks.iter().enumerate() {
+            let theirs = match *peak {
+                Some(at) => at,
+                None => continue,
+            };
+            let value = self.config.rescale(properties.group_low(group), config);
+            let value = value.min(self.config.max_value);
+            let index = match self.get_index(value) {
+                Some(index) => index,
+                None => continue,
+            };
+            let group = self.properties.group(index);
+            if let Some(mine) = self.data.peaks.get_mut(group) {
+                if mine.is_none_or(|mine| theirs > mine) {
+                    *mine = Some(theirs);
+                }
+            }
+        }
+    }
+}
+
+impl HistogramProperties {
+    // the number of bucket groups, the linear buckets and then one for each
+    // power of two above them
+    fn groups(&self) -> usize {
+        self.buckets_outer as usize + 1
+    }
+
+    // the group holding the bucket at the index
+    fn group(&self, index: usize) -> usize {
+        let raw = index + self.offset as usize;
+        match raw.checked_sub(self.linear_max as usize) {
+            Some(log) => 1 + log / self.buckets_inner as usize,
+            None => 0,
+        }
+    }
+
+    // the range of indices in the group, empty if below min_value
+    fn group_indices(&self, group: usize) -> (usize, usize) {
+        let linear_max = self.linear_max as usize;
+        let inner = self.buckets_inner as usize;
+        let (start, end) = match group {
+            0 => (0, linear_max),
+            _ => (linear_max + (group - 1) * inner, linear_max + group * inner),
+        };
+        let offset = self.offset as usize;
+        (start.saturating_sub(offset), end.saturating_sub(offset))
+    }
+
+    // the lowest value stored in the group
+    fn group_low(&self, group: usize) -> u64 {
+        let (start, _) = self.group_indices(group);
+        self.index_low(start)
+    }
+}
+
+// the timestamps of each group when the config tracks peaks, otherwise none
+pub(crate) fn new_peaks(config: &HistogramConfig,
+                        properties: &HistogramProperties)
+                        -> Box<[Option<Instant>]> {
+    if config.track_peaks {
+        vec![None; properties.groups()].into_boxed_slice()
+    } else {
+        Box::new([])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::super::{Clock, Histogram, HistogramConfig, ManualClock};
+
+    fn tracked(clock: &Arc<ManualClock>, max: u64, min: u64) -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(max).min_value(min).precision(2).track_peaks(true);
+        Histogram::with_clock(c, clock.clone()).unwrap()
+    }
+
+    #[test]
+    fn test_regions() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.system_time();
+        let mut h = tracked(&clock, 1_000_000, 1);
+
+        // the first group is the linear buckets below 128
+        h.increment(3).unwrap();
+        h.increment(127).unwrap();
+        clock.advance(Duration::from_secs(10));
+        h.increment(128).unwrap();
+        h.increment(255).unwrap();
+        clock.advance(Duration::from_secs(10));
+        h.increment(700_000).unwrap();
+        clock.advance(Duration::from_secs(10));
+        h.increment(50).unwrap();
+        h.increment(0).unwrap_err();
+        h.increment(2_000_000).unwrap_err();
+
+        let seconds = |secs| start + Duration::from_secs(secs);
+        assert_eq!(h.peak_times(),
+                   vec![(1, seconds(30)), (128, seconds(10)), (524_288, seconds(20))]);
+
+        // an emptied group is left out
+        h.subtract(700_000, 1).unwrap();
+        assert_eq!(h.peak_times(), vec![(1, seconds(30)), (128, seconds(10))]);
+
+        h.clear();
+        assert!(h.peak_times().is_empty());
+        h.increment(200).unwrap();
+        assert_eq!(h.peak_times(), vec![(128, seconds(30))]);
+    }
+
+    #[test]
+    fn test_min_value() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.system_time();
+        let mut h = tracked(&clock, 1_000_000, 5_000);
+
+        h.increment(5_000).unwrap();
+        h.increment(9_000).unwrap();
+        assert_eq!(h.peak_times(), vec![(5_000, start), (8_192, start)]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.system_time();
+        let mut a = tracked(&clock, 1_000_000, 1);
+        let mut b = tracked(&clock, 1_000_000, 1);
+        a.increment(1_000).unwrap();
+        clock.advance(Duration::from_secs(5));
+        b.increment(1_001).unwrap();
+        b.increment(10).unwrap();
+        clock.advance(Duration::from_secs(5));
+        a.increment(20).unwrap();
+
+        let mut merged = a.clone();
+        merged.merge(&b);
+        let seconds = |secs| start + Duration::from_secs(secs);
+        assert_eq!(merged.peak_times(), vec![(1, seconds(10)), (512, seconds(5))]);
+
+        // a different layout maps each group by its lowest value, and the
+        // transfers are not stamped as new
+        clock.advance(Duration::from_secs(5));
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000_000).precision(3).track_peaks(true);
+        let mut wide = Histogram::with_clock(c, clock.clone()).unwrap();
+        wide.increment(5_000).unwrap();
+        wide.merge(&b);
+        assert_eq!(wide.peak_times(), vec![(1, seconds(5)), (4_096, seconds(15))]);
+    }
+
+    #[test]
+    fn test_refine() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.system_time();
+        let mut h = tracked(&clock, 1_000_000, 1);
+        h.increment(5).unwrap();
+        clock.advance(Duration::from_secs(60));
+        h.increment(200).unwrap();
+        let seconds = |secs| start + Duration::from_secs(secs);
+        assert_eq!(h.peak_times(), vec![(1, seconds(0)), (128, seconds(60))]);
+
+        // the finer layout has more linear buckets, which take the newer
+        // time of the groups they absorb
+        h.refine(3).unwrap();
+        assert_eq!(h.peak_times(), vec![(1, seconds(60))]);
+        h.refine(4).unwrap();
+        assert_eq!(h.peak_times(), vec![(1, seconds(60))]);
+
+        clock.advance(Duration::from_secs(10));
+        h.increment(50_000).unwrap();
+        assert_eq!(h.peak_times(), vec![(1, seconds(60)), (32_768, seconds(70))]);
+    }
+
+    #[test]
+    fn test_disabled() {
+        let clock = Arc::new(ManualClock::new());
+        let mut h = Histogram::with_clock(HistogramConfig::new(), clock).unwrap();
+        h.increment(100).unwrap();
+        assert!(h.data.peaks.is_empty());
+        assert!(h.peak_times().is_empty());
+    }
+}
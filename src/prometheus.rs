@@ -0,0 +1,197 @@
+use std::fmt::Write;
+
+use super::{occupied_indices, Histogram};
+
+impl Histogram {
+    /// render the histogram in the Prometheus text exposition format
+    ///
+    /// Emits the cumulative `name_bucket{le="..."}` count for each bound, in
+    /// ascending order, then the `+Inf` bucket, `name_sum` and `name_count`.
+    /// A bucket is counted at or below a bound if its value is, so a bound
+    /// within a bucket is only as precise as the bucket around it. Samples
+    /// missed as too small are counted at or below every bound from one
+    /// below min_value, the most any of them can be, and samples missed as
+    /// too large, or which failed to index, only in the `+Inf` bucket, which
+    /// like `name_count` holds every entry. The sum is approximated from the
+    /// value and count of each bucket, and leaves out missed samples. Bounds
+    /// which are not finite are skipped, and repeated bounds emitted once.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(5, 2).unwrap();
+    /// h.record(50, 1).unwrap();
+    ///
+    /// let text = h.to_prometheus("latency_us", &[10.0, 100.0]);
+    ///
+    /// assert_eq!(text,
+    ///            "# TYPE latency_us histogram\n\
+    ///             latency_us_bucket{le=\"10\"} 2\n\
+    ///             latency_us_bucket{le=\"100\"} 3\n\
+    ///             latency_us_bucket{le=\"+Inf\"} 3\n\
+    ///             latency_us_sum 60\n\
+    ///             latency_us_count 3\n");
+    pub fn to_prometheus(&self, name: &str, le_bounds: &[f64]) -> String {
+        let mut bounds: Vec<f64> = le_bounds.iter().copied().filter(|b| b.is_finite()).collect();
+        bounds.sort_unstable_by(|a, b| a.total_cmp(b));
+        bounds.dedup();
+
+        // the count first at or below each bound, summed into the
+        // cumulative counts below
+        let mut counts = vec![0_u64; bounds.len()];
+        let counters = &self.data.counters;
+        // a sample missed as too small is at most one below min_value
+        let below = self.properties.min_value.saturating_sub(1) as f64;
+        let small = bounds.partition_point(|bound| *bound < below);
+        if let Some(count) = counts.get_mut(small) {
+            *count = counters.missed_small;
+        }
+        let mut sum = 0.0;
+        for index in occupied_indices(self.data.occupied) {
+            let count = self.data.count(index);
+            if count == 0 {
+                continue;
+            }
+            let value = self.properties.index_value(index);
+            sum += value as f64 * count as f64;
+            let first = bounds.partition_point(|bound| *bound < value as f64);
+            if let Some(total) = counts.get_mut(first) {
+                *total = total.saturating_add(count);
+            }
+        }
+
+        let mut text = String::new();
+        let _ = writeln!(text, "# TYPE {} histogram", name);
+        let mut cumulative = 0_u64;
+        for (bound, count) in bounds.iter().zip(counts) {
+            cumulative = cumulative.saturating_add(count);
+            let _ = writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(text, "{}_bucket{{le=\"+Inf\"}} {}", name, self.entries());
+        let _ = writeln!(text, "{}_sum {}", name, sum);
+        let _ = writeln!(text, "{}_count {}", name, self.entries());
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, OverflowPolicy};
+    use super::super::test_rng::Rng;
+
+    // the (le, count) of each bucket line, and the sum and count
+    fn parse(text: &str, name: &str) -> (Vec<(String, u64)>, f64, u64) {
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some(format!("# TYPE {} histogram", name).as_str()));
+
+        let mut buckets = Vec::new();
+        let mut sum = None;
+        let mut count = None;
+        let prefix = format!("{}_bucket{{le=\"", name);
+        for line in lines {
+            let (key, value) = line.rsplit_once(' ').unwrap();
+            if let Some(le) = key.strip_prefix(&prefix) {
+                let le = le.strip_suffix("\"}").unwrap();
+                buckets.push((le.to_owned(), value.parse().unwrap()));
+            } else if key == format!("{}_sum", name) {
+                sum = Some(value.parse().unwrap());
+            } else if key == format!("{}_count", name) {
+                count = Some(value.parse().unwrap());
+            } else {
+                panic!("unexpected line: {}", line);
+            }
+        }
+        (buckets, sum.unwrap(), count.unwrap())
+    }
+
+    #[test]
+    fn test_cumulative() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..10_000 {
+            let _ = h.record(rng.next() % 120_000, 1 + rng.next() % 3);
+        }
+        assert!(h.counters().missed_small() > 0);
+        assert!(h.counters().missed_large() > 0);
+
+        let bounds = [50_000.0, 0.5, 10.0, 1e3, 1e4, 99_999.5, 1e9, f64::NAN, 10.0];
+        let (buckets, sum, count) = parse(&h.to_prometheus("rpc", &bounds), "rpc");
+
+        let les: Vec<&str> = buckets.iter().map(|(le, _)| le.as_str()).collect();
+        assert_eq!(les,
+                   vec!["0.5", "10", "1000", "10000", "50000", "99999.5", "1000000000", "+Inf"]);
+        for pair in buckets.windows(2) {
+            assert!(pair[0].1 <= pair[1].1, "{:?}", pair);
+        }
+
+        // every bucket value is at most max_value, so only the missed large
+        // samples are left above the last bound
+        let (_, inf) = buckets[buckets.len() - 1];
+        assert_eq!(inf, h.entries());
+        assert_eq!(count, h.entries());
+        let (_, last) = buckets[buckets.len() - 2];
+        assert_eq!(inf - last, h.counters().missed_large());
+        assert_eq!(buckets[0].1, h.counters().missed_small());
+
+        // a bound on a bucket value counts the whole bucket
+        let (_, at) = &buckets[2];
+        assert_eq!(*at, h.count_between(0, 1_000) + h.counters().missed_small());
+
+        let expected: f64 = h.iter_recorded()
+                             .map(|bucket| bucket.value() as f64 * bucket.count() as f64)
+                             .sum();
+        assert_eq!(sum, expected);
+    }
+
+    #[test]
+    fn test_clamped() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(5_000, 4).unwrap();
+        h.record(10, 1).unwrap();
+
+        // clamped samples are stored, so fall within the bounds
+        let (buckets, sum, count) = parse(&h.to_prometheus("size", &[999.0, 1_000.0]), "size");
+        assert_eq!(buckets,
+                   vec![("999".to_owned(), 1), ("1000".to_owned(), 5), ("+Inf".to_owned(), 5)]);
+        assert_eq!(sum, 4_010.0);
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_min_value() {
+        let mut c = HistogramConfig::new();
+        c.min_value(100).max_value(10_000).precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(50, 3).unwrap_err();
+        h.record(99, 1).unwrap_err();
+        h.record(500, 2).unwrap();
+
+        // the samples below min_value are only counted from a bound of 99
+        let text = h.to_prometheus("rpc", &[0.0, 10.0, 98.5, 99.0, 1_000.0]);
+        let (buckets, _, count) = parse(&text, "rpc");
+        assert_eq!(buckets,
+                   vec![("0".to_owned(), 0),
+                        ("10".to_owned(), 0),
+                        ("98.5".to_owned(), 0),
+                        ("99".to_owned(), 4),
+                        ("1000".to_owned(), 6),
+                        ("+Inf".to_owned(), 6)]);
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn test_empty() {
+        let h = Histogram::new().unwrap();
+        assert_eq!(h.to_prometheus("empty", &[]),
+                   "# TYPE empty histogram\n\
+                    empty_bucket{le=\"+Inf\"} 0\n\
+                    empty_sum 0\n\
+                    empty_count 0\n");
+    }
+}
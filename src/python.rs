@@ -0,0 +1,139 @@
+//! Python bindings, enabled with the `python` feature
+//!
+//! The module exposes a `Histogram` class wrapping the Rust Histogram, using
+//! the same byte encoding as `Histogram::to_bytes()` so blobs written by Rust
+//! services can be loaded directly. Build the extension with maturin, eg
+//! `maturin develop --features python,pyo3/extension-module`.
+
+use std::fmt;
+
+use numpy::PyArray1;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use super::{Histogram, HistogramConfig};
+
+// raise an error as a Python ValueError
+fn value_error<E: fmt::Display>(error: E) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+/// the Python Histogram class
+#[pyclass(name = "Histogram", module = "histogram")]
+#[derive(Clone)]
+pub struct PyHistogram {
+    inner: Histogram,
+}
+
+#[pymethods]
+impl PyHistogram {
+    #[new]
+    #[pyo3(signature = (precision = 3, max_value = 60_000_000_000))]
+    fn new(precision: u32, max_value: u64) -> PyResult<PyHistogram> {
+        let mut config = HistogramConfig::new();
+        config.precision(precision).max_value(max_value);
+        Histogram::configured(config).map(|inner| PyHistogram { inner }).map_err(value_error)
+    }
+
+    /// decode a histogram written by `to_bytes()` or the Rust `to_bytes()`
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<PyHistogram> {
+        Histogram::from_bytes(bytes).map(|inner| PyHistogram { inner }).map_err(value_error)
+    }
+
+    /// encode the histogram as bytes
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.to_bytes())
+    }
+
+    /// add count samples of the value
+    #[pyo3(signature = (value, count = 1))]
+    fn record(&mut self, value: u64, count: u64) -> PyResult<()> {
+        self.inner.record(value, count).map_err(value_error)
+    }
+
+    /// add the counts of another histogram, returning the number dropped
+    fn merge(slf: &Bound<'_, PyHistogram>, other: &Bound<'_, PyHistogram>) -> u64 {
+        // a cheap copy of the counts lets a histogram merge itself
+        let other = other.borrow().inner.clone();
+        slf.borrow_mut().inner.merge(&other).dropped()
+    }
+
+    /// return the value at the percentile, from 0.0 to 100.0
+    fn percentile(&self, percentile: f64) -> PyResult<u64> {
+        self.inner.percentile(percentile).map_err(value_error)
+    }
+
+    /// return the nominal values and counts of the non-empty buckets as
+    /// uint64 arrays, which hold every count without loss
+    fn to_numpy<'py>(&self,
+                     py: Python<'py>)
+                     -> (Bound<'py, PyArray1<u64>>, Bound<'py, PyArray1<u64>>) {
+        let (values, counts) = self.inner
+                                   .iter_from(0)
+                                   .recorded()
+                                   .map(|bucket| (bucket.value(), bucket.count()))
+                                   .unzip();
+        (PyArray1::from_vec(py, values), PyArray1::from_vec(py, counts))
+    }
+
+    /// the number of samples recorded
+    #[getter]
+    fn entries(&self) -> u64 {
+        self.inner.entries()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Histogram({} total)", self.inner.entries())
+    }
+}
+
+/// the `histogram` Python module
+#[pymodule]
+#[pyo3(name = "histogram")]
+pub fn histogram_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyHistogram>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    use super::PyHistogram;
+
+    #[test]
+    fn test_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let locals = PyDict::new(py);
+            locals.set_item("Histogram", py.get_type::<PyHistogram>()).unwrap();
+
+            let code = CString::new("
+h = Histogram(precision=2, max_value=1000)
+for value in range(1, 101):
+    h.record(value)
+h.record(10, 5)
+
+r = Histogram.from_bytes(h.to_bytes())
+assert r.entries == 105
+assert r.percentile(50.0) == h.percentile(50.0)
+assert r.percentile(100.0) == 100
+
+assert r.merge(r) == 0
+assert r.entries == 210
+
+try:
+    Histogram.from_bytes(b'HIST')
+    assert False
+except ValueError:
+    pass
+")
+                           .unwrap();
+            py.run(&code, None, Some(&locals)).unwrap();
+        });
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::{Arc, OnceLock};
+
+use super::{peaks, Clock, Histogram, HistogramConfig, HistogramCounters, HistogramData,
+            HistogramError, HistogramProperties, HistogramStats, Schedule, SystemClock};
+
+impl Histogram {
+    /// take the Histogram apart into its config, counters and bucket counts,
+    /// for a transport the crate does not know about
+    ///
+    /// The counts are in bucket order, as `count_in_bucket()` numbers them,
+    /// and are moved out without a copy unless shared with a clone. The
+    /// parts go back together with `from_raw_parts()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(100, 3).unwrap();
+    ///
+    /// let (config, counters, counts) = h.into_raw_parts();
+    ///
+    /// assert_eq!(counters.entries(), 3);
+    /// assert_eq!(counts.iter().sum::<u64>(), 3);
+    ///
+    /// let h = Histogram::from_raw_parts(config, counters, counts).unwrap();
+    ///
+    /// assert_eq!(h.get(100), Some(3));
+    pub fn into_raw_parts(self) -> (HistogramConfig, HistogramCounters, Vec<u64>) {
+        let counts = Arc::try_unwrap(self.data.data).unwrap_or_else(|shared| (*shared).clone());
+        (self.config, self.data.counters, counts)
+    }
+
+    /// assemble a Histogram from the parts given by `into_raw_parts()`,
+    /// taking the counts without a copy
+    ///
+    /// Fails as `Histogram::configured()` for an invalid config, with
+    /// `BucketCountMismatch` unless there is one count for each bucket of the
+    /// config, and with `CountersMismatch` if the counts and missed counters
+    /// hold more samples than the entries counter. Fewer is accepted, as
+    /// saturated buckets and `apply_counter_delta()` leave. The exact
+    /// `stats()` are not known, as after decoding.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramError};
+    /// let h = Histogram::new().unwrap();
+    /// let (config, counters, mut counts) = h.into_raw_parts();
+    ///
+    /// counts.pop();
+    ///
+    /// assert!(matches!(Histogram::from_raw_parts(config, counters, counts),
+    ///                  Err(HistogramError::BucketCountMismatch { .. })));
+    pub fn from_raw_parts(config: HistogramConfig,
+                          counters: HistogramCounters,
+                          counts: Vec<u64>)
+                          -> Result<Histogram, HistogramError> {
+        let properties = HistogramProperties::new(&config)?;
+        let expected = properties.buckets_total as usize;
+        if counts.len() != expected {
+            return Err(HistogramError::BucketCountMismatch {
+                expected,
+                found: counts.len(),
+            });
+        }
+
+        let accounted = counts.iter()
+                              .fold(0_u64, |total, count| total.saturating_add(*count))
+                              .saturating_add(counters.missed());
+        if accounted > counters.entries_total {
+            return Err(HistogramError::CountersMismatch);
+        }
+
+        Ok(Histogram::assembled(config, properties, counters, counts))
+    }
+
+    // build a Histogram around counts already laid out for the properties,
+    // without allocating buckets of its own, for the decoders and snapshots
+    pub(crate) fn assembled(config: HistogramConfig,
+                            properties: HistogramProperties,
+                            counters: HistogramCounters,
+                            counts: Vec<u64>)
+                            -> Histogram {
+        let clock = Arc::new(SystemClock);
+        let mut data = HistogramData {
+            data: Arc::new(counts),
+            counters,
+            started: clock.now(),
+            last: None,
+            occupied: None,
+            peaks: peaks::new_peaks(&config, &properties),
+            summary: None,
+            totals: OnceLock::new(),
+            stats: None,
+        };
+        data.find_occupied();
+        if data.occupied.is_none() {
+            // no stored sample, so the exact stats are known to be empty
+            data.stats = Some(HistogramStats::default());
+        }
+
+        Histogram {
+            config,
+            data,
+            properties,
+            clock,
+            schedule: Schedule::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+
+    #[test]
+    fn test_round_trip() {
+        let mut c = HistogramConfig::new();
+        c.precision(2).max_value(1_000_000).min_value(10);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 10..5_000 {
+            h.increment(value * 7).unwrap();
+        }
+        h.increment(1).unwrap_err();
+        h.increment(2_000_000).unwrap_err();
+        let expected = h.clone();
+
+        let (config, counters, counts) = h.into_raw_parts();
+        assert_eq!(counts.len() as u64, expected.buckets_total());
+        let h = Histogram::from_raw_parts(config, counters, counts).unwrap();
+        assert!(h.check_invariants().is_ok());
+        assert_eq!(h.counters(), expected.counters());
+        assert_eq!(h.to_bytes(), expected.to_bytes());
+        for &p in &[0.0, 50.0, 99.0, 100.0] {
+            assert_eq!(h.percentile(p), expected.percentile(p));
+        }
+        assert_eq!(h.stats(), None);
+
+        // a shared buffer is copied, leaving the clone alone
+        let mut shared = expected.clone();
+        let (_, _, mut counts) = expected.into_raw_parts();
+        counts.iter_mut().for_each(|count| *count = 0);
+        shared.increment(70).unwrap();
+        assert_eq!(shared.get(70), Some(2));
+
+        // empty parts
+        let (config, counters, counts) = Histogram::new().unwrap().into_raw_parts();
+        let h = Histogram::from_raw_parts(config, counters, counts).unwrap();
+        assert_eq!(h.percentile(50.0), Err(HistogramError::Empty));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut h = Histogram::new().unwrap();
+        h.record(100, 5).unwrap();
+        let buckets = h.buckets_total() as usize;
+        let (config, counters, counts) = h.into_raw_parts();
+
+        for &len in &[0, 1, buckets - 1, buckets + 1] {
+            let mut wrong = counts.clone();
+            wrong.resize(len, 0);
+            assert_eq!(Histogram::from_raw_parts(config, counters, wrong).unwrap_err(),
+                       HistogramError::BucketCountMismatch {
+                           expected: buckets,
+                           found: len,
+                       });
+        }
+
+        // the counts of a different config
+        let mut other = HistogramConfig::new();
+        other.precision(2);
+        let (_, _, coarse) = Histogram::configured(other).unwrap().into_raw_parts();
+        assert!(Histogram::from_raw_parts(config, counters, coarse).is_err());
+
+        // more samples than the counters recorded
+        let mut extra = counts.clone();
+        extra[0] = 1;
+        assert_eq!(Histogram::from_raw_parts(config, counters, extra).unwrap_err(),
+                   HistogramError::CountersMismatch);
+
+        // fewer are accepted
+        let mut fewer = counts.clone();
+        fewer.iter_mut().for_each(|count| *count = 0);
+        assert!(Histogram::from_raw_parts(config, counters, fewer).is_ok());
+
+        let mut invalid = config;
+        invalid.precision(0);
+        assert_eq!(Histogram::from_raw_parts(invalid, counters, counts).unwrap_err(),
+                   HistogramError::InvalidPrecision);
+    }
+}
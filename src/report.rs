@@ -0,0 +1,454 @@
+use std::fmt;
+
+use super::{Histogram, HistogramError};
+
+// the percentiles shown by the Display of a Histogram
+const DISPLAY_PERCENTILES: [f64; 8] = [50.0, 75.0, 90.0, 95.0, 99.0, 99.9, 99.99, 100.0];
+
+/// the layout used when rendering a report
+///
+/// The JSON and CSV styles are meant for tools, so do not mark percentiles
+/// limited by the resolution. The table reports `no data` for an empty
+/// histogram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReportStyle {
+    /// one statistic per line with aligned columns
+    Table,
+    /// space separated key=value pairs on a single line
+    KeyValue,
+    /// a single JSON object
+    Json,
+    /// a header row of the labels and a row of the values, comma separated,
+    /// with empty fields for values which cannot be computed
+    Csv,
+}
+
+/// a configurable text report of Histogram statistics
+///
+/// Created by `Histogram::report()`. Choose which statistics to include and
+/// how to render them, then call `to_string()` or `write_to()`. A percentile
+/// whose bucket spans several values and also answers another percentile of
+/// the report, so the histogram is too coarse to tell them apart, is marked
+/// with a trailing `*`, except in the JSON and CSV styles. See
+/// `Histogram::resolution_at()`.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, ReportStyle};
+/// let mut h = Histogram::new().unwrap();
+///
+/// for value in 1..101 {
+///     h.increment(value).unwrap();
+/// }
+///
+/// let report = h.report()
+///               .percentiles(&[50.0, 99.0])
+///               .stddev(false)
+///               .style(ReportStyle::KeyValue)
+///               .to_string();
+///
+/// assert_eq!(report, "count=100 min=1 max=100 mean=51 p50=50 p99=99");
+pub struct ReportBuilder<'a> {
+    histogram: &'a Histogram,
+    percentiles: Vec<f64>,
+    count: bool,
+    missed: bool,
+    minimum: bool,
+    maximum: bool,
+    mean: bool,
+    stddev: bool,
+    divisor: u64,
+    label_width: usize,
+    value_width: usize,
+    style: ReportStyle,
+}
+
+impl<'a> ReportBuilder<'a> {
+    /// create a ReportBuilder with the default statistics
+    pub fn new(histogram: &'a Histogram) -> ReportBuilder<'a> {
+        ReportBuilder {
+            histogram,
+            percentiles: vec![50.0, 90.0, 99.0, 99.9],
+            count: true,
+            missed: false,
+            minimum: true,
+            maximum: true,
+            mean: true,
+            stddev: true,
+            divisor: 1,
+            label_width: 8,
+            value_width: 12,
+            style: ReportStyle::Table,
+        }
+    }
+
+    /// set the percentiles to include
+    pub fn percentiles(&mut self, percentiles: &[f64]) -> &mut Self {
+        self.percentiles = percentiles.to_vec();
+        self
+    }
+
+    /// include the number of entries
+    pub fn count(&mut self, enabled: bool) -> &mut Self {
+        self.count = enabled;
+        self
+    }
+
+    /// include the number of missed samples, off by default
+    pub fn missed(&mut self, enabled: bool) -> &mut Self {
+        self.missed = enabled;
+        self
+    }
+
+    /// include the minimum value
+    pub fn minimum(&mut self, enabled: bool) -> &mut Self {
+        self.minimum = enabled;
+        self
+    }
+
+    /// include the maximum value
+    pub fn maximum(&mut self, enabled: bool) -> &mut Self {
+        self.maximum = enabled;
+        self
+    }
+
+    /// include the mean value
+    pub fn mean(&mut self, enabled: bool) -> &mut Self {
+        self.mean = enabled;
+        self
+    }
+
+    /// include the standard deviation
+    pub fn stddev(&mut self, enabled: bool) -> &mut Self {
+        self.stddev = enabled;
+        self
+    }
+
+    /// divide every value by this amount, eg 1000 to show ns as us
+    pub fn divisor(&mut self, divisor: u64) -> &mut Self {
+        self.divisor = if divisor < 1 { 1 } else { divisor };
+        self
+    }
+
+    /// set the label and value column widths used by the table style
+    pub fn widths(&mut self, label: usize, value: usize) -> &mut Self {
+        self.label_width = label;
+        self.value_width = value;
+        self
+    }
+
+    /// set the output style
+    pub fn style(&mut self, style: ReportStyle) -> &mut Self {
+        self.style = style;
+        self
+    }
+
+    /// render the report into the writer
+    pub fn write_to<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        let rows = self.rows();
+
+        match self.style {
+            ReportStyle::Table if self.histogram.entries() == 0 => writeln!(w, "no data")?,
+            ReportStyle::Table => {
+                for (label, value) in rows {
+                    writeln!(w,
+                             "{:<lw$}{:>vw$}",
+                             label,
+                             value.unwrap_or_else(|| "-".to_owned()),
+                             lw = self.label_width,
+                             vw = self.value_width)?;
+                }
+            }
+            ReportStyle::KeyValue => {
+                for (i, (label, value)) in rows.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(w, " ")?;
+                    }
+                    write!(w, "{}={}", label, value.unwrap_or_else(|| "-".to_owned()))?;
+                }
+            }
+            ReportStyle::Json => {
+                write!(w, "{{")?;
+                for (i, (label, value)) in rows.into_iter().enumerate() {
+                    if i > 0 {
+                        write!(w, ",")?;
+                    }
+                    write!(w,
+                           "\"{}\":{}",
+                           label,
+                           value.unwrap_or_else(|| "null".to_owned()))?;
+                }
+                write!(w, "}}")?;
+            }
+            ReportStyle::Csv => {
+                let (labels, values): (Vec<String>, Vec<Option<String>>) =
+                    rows.into_iter().unzip();
+                writeln!(w, "{}", labels.join(","))?;
+                let values: Vec<String> =
+                    values.into_iter().map(Option::unwrap_or_default).collect();
+                writeln!(w, "{}", values.join(","))?;
+            }
+        }
+        Ok(())
+    }
+
+    // the labelled values in output order, None where a statistic failed
+    fn rows(&self) -> Vec<(String, Option<String>)> {
+        let mut rows = Vec::new();
+
+        if self.count {
+            rows.push(("count".to_owned(), Some(self.histogram.entries().to_string())));
+        }
+        if self.missed {
+            rows.push(("missed".to_owned(), Some(self.histogram.entries_missed().to_string())));
+        }
+        if self.minimum {
+            rows.push(("min".to_owned(), self.value(self.histogram.minimum())));
+        }
+        if self.maximum {
+            rows.push(("max".to_owned(), self.value(self.histogram.maximum())));
+        }
+        if self.mean {
+            rows.push(("mean".to_owned(), self.value(self.histogram.mean())));
+        }
+        if self.stddev {
+            rows.push(("stddev".to_owned(), self.value(self.histogram.stddev())));
+        }
+        for percentile in &self.percentiles {
+            let mut value = self.value(self.histogram.percentile(*percentile));
+            let marked = self.style == ReportStyle::Table || self.style == ReportStyle::KeyValue;
+            if marked && self.is_limited(*percentile) {
+                value = value.map(|value| value + "*");
+            }
+            rows.push((format!("p{}", percentile), value));
+        }
+        rows
+    }
+
+    // true if the percentile shares a multi-value bucket with another of
+    // the report percentiles
+    fn is_limited(&self, percentile: f64) -> bool {
+        match self.histogram.resolution_at(percentile) {
+            Ok(resolution) if resolution.values() > 1 => {
+                self.percentiles
+                    .iter()
+                    .any(|other| *other != percentile && resolution.contains(*other))
+            }
+            _ => false,
+        }
+    }
+
+    fn value(&self, value: Result<u64, HistogramError>) -> Option<String> {
+        match value {
+            Ok(value) => {
+                if self.divisor == 1 {
+                    Some(value.to_string())
+                } else {
+                    Some((value as f64 / self.divisor as f64).to_string())
+                }
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+impl<'a> fmt::Display for ReportBuilder<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_to(f)
+    }
+}
+
+/// a table of the entries, missed samples, minimum, maximum, mean, standard
+/// deviation, and percentiles from p50 to p100, or `no data` if empty
+///
+/// # Example
+/// ```
+/// # use histogram::Histogram;
+/// let mut h = Histogram::new().unwrap();
+///
+/// assert_eq!(h.to_string(), "no data\n");
+///
+/// for value in 1..101 {
+///     h.increment(value).unwrap();
+/// }
+///
+/// assert!(h.to_string().starts_with("count            100\nmissed             0\n"));
+/// assert!(h.to_string().ends_with("p99.99           100\np100             100\n"));
+impl fmt::Display for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.report().missed(true).percentiles(&DISPLAY_PERCENTILES).write_to(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReportStyle;
+    use super::super::{Histogram, HistogramConfig};
+
+    fn histogram() -> Histogram {
+        let mut h = Histogram::new().unwrap();
+        for value in 1..11 {
+            h.increment(value * 10).unwrap();
+        }
+        h
+    }
+
+    #[test]
+    fn test_table() {
+        let h = histogram();
+
+        let report = h.report().percentiles(&[50.0, 99.9]).widths(6, 8).to_string();
+
+        assert_eq!(report,
+                   "count       10\n\
+                    min         10\n\
+                    max        100\n\
+                    mean        55\n\
+                    stddev      29\n\
+                    p50         50\n\
+                    p99.9      100\n");
+    }
+
+    #[test]
+    fn test_key_value_divisor() {
+        let h = histogram();
+
+        let report = h.report()
+                      .percentiles(&[90.0])
+                      .mean(false)
+                      .stddev(false)
+                      .divisor(4)
+                      .style(ReportStyle::KeyValue)
+                      .to_string();
+
+        assert_eq!(report, "count=10 min=2.5 max=25 p90=22.5");
+    }
+
+    #[test]
+    fn test_json() {
+        let h = histogram();
+
+        let mut report = String::new();
+        h.report()
+         .percentiles(&[50.0, 99.0])
+         .count(false)
+         .minimum(false)
+         .stddev(false)
+         .style(ReportStyle::Json)
+         .write_to(&mut report)
+         .unwrap();
+
+        assert_eq!(report, "{\"max\":100,\"mean\":55,\"p50\":50,\"p99\":100}");
+    }
+
+    #[test]
+    fn test_display() {
+        let mut h = histogram();
+        h.increment(0).unwrap_err();
+
+        assert_eq!(h.to_string(),
+                   "count             11\n\
+                    missed             1\n\
+                    min               10\n\
+                    max              100\n\
+                    mean              55\n\
+                    stddev            29\n\
+                    p50               50\n\
+                    p75               80\n\
+                    p90               90\n\
+                    p95              100\n\
+                    p99              100\n\
+                    p99.9            100\n\
+                    p99.99           100\n\
+                    p100             100\n");
+        assert_eq!(Histogram::new().unwrap().to_string(), "no data\n");
+    }
+
+    #[test]
+    fn test_csv() {
+        let h = histogram();
+        let percentiles = [50.0, 90.0, 99.0, 99.9];
+
+        let report = h.report_with(&percentiles, ReportStyle::Csv);
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "count,min,max,mean,stddev,p50,p90,p99,p99.9");
+        assert_eq!(lines[1], "10,10,100,55,29,50,90,100,100");
+        assert_eq!(lines.len(), 2);
+
+        // the header and values line up, with empty fields for missing values
+        let empty = Histogram::new().unwrap().report_with(&percentiles, ReportStyle::Csv);
+        let lines: Vec<&str> = empty.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "count,min,max,mean,stddev,p50,p90,p99,p99.9");
+        assert_eq!(lines[1].split(',').count(), 9);
+        assert_eq!(lines[1], "0,,,,,,,,");
+    }
+
+    #[test]
+    fn test_json_keys() {
+        // a ranked sample missed as too small leaves the minimum unknown
+        let mut c = HistogramConfig::new();
+        c.rank_missed(true);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 1..11 {
+            h.increment(value * 10).unwrap();
+        }
+        h.increment(0).unwrap_err();
+
+        let report = h.report()
+                      .missed(true)
+                      .percentiles(&[75.0, 99.99])
+                      .style(ReportStyle::Json)
+                      .to_string();
+        assert!(report.starts_with('{') && report.ends_with('}'));
+        for key in ["count", "missed", "min", "max", "mean", "stddev", "p75", "p99.99"] {
+            assert!(report.contains(&format!("\"{}\":", key)), "{}: {}", key, report);
+        }
+        assert!(report.contains("\"min\":null"));
+    }
+
+    #[test]
+    fn test_resolution() {
+        let mut c = HistogramConfig::new();
+        c.precision(1);
+        let mut coarse = Histogram::configured(c).unwrap();
+        let mut fine = Histogram::new().unwrap();
+        for value in 1..1_001 {
+            coarse.increment(value).unwrap();
+            fine.increment(value).unwrap();
+        }
+
+        // p99 and p99.9 share the top bucket of the coarse histogram
+        let report = |h: &Histogram, style| {
+            h.report()
+             .count(false)
+             .minimum(false)
+             .maximum(false)
+             .mean(false)
+             .stddev(false)
+             .style(style)
+             .to_string()
+        };
+        assert_eq!(report(&coarse, ReportStyle::KeyValue),
+                   "p50=487 p90=871 p99=973* p99.9=973*");
+        assert_eq!(report(&coarse, ReportStyle::Json),
+                   "{\"p50\":487,\"p90\":871,\"p99\":973,\"p99.9\":973}");
+        assert_eq!(report(&fine, ReportStyle::KeyValue), "p50=500 p90=900 p99=990 p99.9=1000");
+
+        // a shared bucket of a single value is exact, so is not marked
+        let mut h = Histogram::new().unwrap();
+        h.record(7, 100).unwrap();
+        assert_eq!(report(&h, ReportStyle::KeyValue), "p50=7 p90=7 p99=7 p99.9=7");
+    }
+
+    #[test]
+    fn test_empty() {
+        let h = Histogram::new().unwrap();
+
+        let report = h.report().percentiles(&[50.0]).style(ReportStyle::Json).to_string();
+
+        assert_eq!(report,
+                   "{\"count\":0,\"min\":null,\"max\":null,\"mean\":null,\"stddev\":null,\
+                    \"p50\":null}");
+    }
+}
@@ -0,0 +1,186 @@
+//! periodic reporting on a tokio runtime, enabled with the `tokio` feature
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use tokio::time::{self, Instant};
+
+use super::Histogram;
+
+/// a task emitting a snapshot of a shared Histogram every interval
+///
+/// Each report passes a copy of the histogram to the callback, which may
+/// format it or forward it on a channel. In interval mode, set by `reset()`,
+/// the histogram is cleared after each snapshot so reports hold only the
+/// samples since the previous one; otherwise they are cumulative. Calling
+/// `ReporterHandle::shutdown()` emits a final report and ends `run()`.
+///
+/// # Example
+/// ```
+/// # extern crate histogram;
+/// # extern crate tokio;
+/// # use histogram::{Histogram, Reporter};
+/// # use std::sync::{Arc, Mutex};
+/// # use std::time::Duration;
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let shared = Arc::new(Mutex::new(Histogram::new().unwrap()));
+/// let (reporter, handle) = Reporter::new(shared.clone(),
+///                                        Duration::from_secs(10),
+///                                        |snapshot: Histogram| {
+///                                            println!("p99: {:?}", snapshot.percentile(99.0));
+///                                        });
+/// let task = tokio::spawn(reporter.reset(true).run());
+///
+/// shared.lock().unwrap().increment(100).unwrap();
+///
+/// handle.shutdown();
+/// task.await.unwrap();
+/// # }
+pub struct Reporter<F> {
+    histogram: Arc<Mutex<Histogram>>,
+    interval: Duration,
+    reset: bool,
+    callback: F,
+    shutdown: Arc<Notify>,
+}
+
+/// stops a running Reporter after a final report
+#[derive(Clone)]
+pub struct ReporterHandle {
+    shutdown: Arc<Notify>,
+}
+
+impl ReporterHandle {
+    /// ask the Reporter to emit a final report and stop, which also applies
+    /// if the Reporter has not started running yet
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+}
+
+impl<F> Reporter<F>
+    where F: FnMut(Histogram)
+{
+    /// create a Reporter of the shared histogram calling the callback every
+    /// interval, and the handle to stop it
+    pub fn new(histogram: Arc<Mutex<Histogram>>,
+               interval: Duration,
+               callback: F)
+               -> (Reporter<F>, ReporterHandle) {
+        let shutdown = Arc::new(Notify::new());
+        let reporter = Reporter {
+            histogram,
+            interval,
+            reset: false,
+            callback,
+            shutdown: shutdown.clone(),
+        };
+        (reporter, ReporterHandle { shutdown })
+    }
+
+    /// clear the histogram after each report, so each covers one interval
+    pub fn reset(mut self, enabled: bool) -> Reporter<F> {
+        self.reset = enabled;
+        self
+    }
+
+    /// report every interval until shut down, then report a final time
+    pub async fn run(mut self) {
+        let mut ticks = time::interval_at(Instant::now() + self.interval, self.interval);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => self.report(),
+                _ = self.shutdown.notified() => {
+                    self.report();
+                    return;
+                }
+            }
+        }
+    }
+
+    fn report(&mut self) {
+        let snapshot = {
+            let mut histogram = match self.histogram.lock() {
+                Ok(histogram) => histogram,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let snapshot = histogram.clone();
+            if self.reset {
+                histogram.clear();
+            }
+            snapshot
+        };
+        (self.callback)(snapshot);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::time::{self, Instant};
+
+    use super::Reporter;
+    use super::super::Histogram;
+
+    // run a reporter for 3.5 intervals, recording a sample every quarter
+    // interval, and return the (seconds, entries) of each report
+    async fn reports(reset: bool) -> Vec<(u64, u64)> {
+        let shared = Arc::new(Mutex::new(Histogram::new().unwrap()));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+
+        let start = Instant::now();
+        let record = seen.clone();
+        let (reporter, handle) = Reporter::new(shared.clone(),
+                                               Duration::from_secs(1),
+                                               move |snapshot: Histogram| {
+                                                   let elapsed = start.elapsed().as_secs();
+                                                   record.lock()
+                                                         .unwrap()
+                                                         .push((elapsed, snapshot.entries()));
+                                               });
+        let task = tokio::spawn(reporter.reset(reset).run());
+
+        time::sleep(Duration::from_millis(125)).await;
+        for _ in 0..14 {
+            shared.lock().unwrap().increment(10).unwrap();
+            time::sleep(Duration::from_millis(250)).await;
+        }
+        handle.shutdown();
+        task.await.unwrap();
+
+        let reports = seen.lock().unwrap().clone();
+        reports
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cumulative() {
+        assert_eq!(reports(false).await, vec![(1, 4), (2, 8), (3, 12), (3, 14)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interval() {
+        assert_eq!(reports(true).await, vec![(1, 4), (2, 4), (3, 4), (3, 2)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_before_run() {
+        let shared = Arc::new(Mutex::new(Histogram::new().unwrap()));
+        shared.lock().unwrap().increment(1).unwrap();
+
+        let reported = Arc::new(Mutex::new(0));
+        let count = reported.clone();
+        let (reporter, handle) = Reporter::new(shared,
+                                               Duration::from_secs(60),
+                                               move |snapshot: Histogram| {
+                                                   *count.lock().unwrap() += snapshot.entries();
+                                               });
+        handle.shutdown();
+        reporter.run().await;
+
+        assert_eq!(*reported.lock().unwrap(), 1);
+    }
+}
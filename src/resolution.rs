@@ -0,0 +1,181 @@
+use super::{Histogram, HistogramError, Rank};
+
+/// how finely a Histogram resolves a percentile, see
+/// `Histogram::resolution_at()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Resolution {
+    low: u64,
+    high: u64,
+    // the span of basis point percentiles which land in the bucket
+    first: u64,
+    last: u64,
+}
+
+impl Resolution {
+    /// return the lowest and highest values held by the bucket
+    pub fn bounds(&self) -> (u64, u64) {
+        (self.low, self.high)
+    }
+
+    /// return the number of distinct values the bucket spans
+    pub fn values(&self) -> u64 {
+        (self.high - self.low).saturating_add(1)
+    }
+
+    /// return the lowest and highest percentiles, in steps of 0.01, which
+    /// return the same value
+    pub fn percentiles(&self) -> (f64, f64) {
+        (self.first as f64 / 100.0, self.last as f64 / 100.0)
+    }
+
+    /// return the number of percentiles, in steps of 0.01 from p0 to p100,
+    /// which return the same value, 0 if the bucket holds too few samples
+    /// for any
+    pub fn shared(&self) -> u64 {
+        (self.last + 1).saturating_sub(self.first)
+    }
+
+    /// true if a query for the percentile returns the same value
+    pub fn contains(&self, percentile: f64) -> bool {
+        let (first, last) = self.percentiles();
+        first <= percentile && percentile <= last
+    }
+}
+
+impl Histogram {
+    /// return the resolution of the bucket holding the percentile
+    ///
+    /// A coarse histogram answers every percentile within a bucket with the
+    /// same value, so tail percentiles such as p99 and p99.9 may be equal.
+    /// This reports the values spanned by the bucket and the range of
+    /// neighboring percentiles which share the answer. Fails as
+    /// `percentile()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.precision(1);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// for value in 1..1001 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let resolution = h.resolution_at(99.0).unwrap();
+    ///
+    /// assert_eq!(resolution.bounds(), (973, 1023));
+    /// assert_eq!(resolution.values(), 51);
+    /// assert!(resolution.contains(99.9));
+    pub fn resolution_at(&self, percentile: f64) -> Result<Resolution, HistogramError> {
+        let index = self.percentile_index(Rank::Percent(percentile))?;
+        let (low, high) = self.index_bounds(index);
+
+        // the samples ranked below the bucket, and up to its end
+        let counters = self.ranked_counters();
+        let below = (0..index).fold(counters.missed_small, |total, index| {
+            total.saturating_add(self.data.count(index))
+        });
+        let through = below.saturating_add(self.data.count(index));
+
+        // a basis point q ranks the ceil(q * total / 10_000)th sample, at
+        // least the first, which lies in the bucket from just above below
+        // up to through
+        let total = counters.entries_total as u128;
+        let first = match below {
+            0 => 0,
+            below => (below as u128 * 10_000 / total + 1) as u64,
+        };
+        let last = match through as u128 {
+            through if through >= total => 10_000,
+            through => (through * 10_000 / total) as u64,
+        };
+
+        Ok(Resolution {
+            low,
+            high,
+            first,
+            last,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+
+    #[test]
+    fn test_shared() {
+        let mut c = HistogramConfig::new();
+        c.precision(1).max_value(10_000);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 1..10_001 {
+            h.increment(value).unwrap();
+        }
+
+        // each percentile in the span returns the same value, and those
+        // just outside it do not
+        for &p in &[1.0, 25.0, 50.0, 90.0, 99.0, 99.9, 100.0] {
+            let resolution = h.resolution_at(p).unwrap();
+            let value = h.percentile(p).unwrap();
+            assert!(resolution.contains(p));
+
+            // as basis points, which are exact
+            let (first, last) = resolution.percentiles();
+            let (first, last) = ((first * 100.0).round() as u32, (last * 100.0).round() as u32);
+            assert_eq!(resolution.shared(), (last - first + 1) as u64);
+            assert_eq!(h.percentile_bp(first), Ok(value), "percentile: {}", p);
+            assert_eq!(h.percentile_bp(last), Ok(value), "percentile: {}", p);
+            if first > 0 {
+                assert!(h.percentile_bp(first - 1).unwrap() < value);
+            }
+            if last < 10_000 {
+                assert!(h.percentile_bp(last + 1).unwrap() > value);
+            }
+
+            let (low, high) = resolution.bounds();
+            assert_eq!(resolution.values(), high - low + 1);
+            assert_eq!(h.count_between(low, high), resolution.values());
+        }
+    }
+
+    #[test]
+    fn test_fine() {
+        let mut h = Histogram::new().unwrap();
+        for value in 1..1_001 {
+            h.increment(value).unwrap();
+        }
+
+        let resolution = h.resolution_at(50.0).unwrap();
+        assert_eq!(resolution.bounds(), (500, 500));
+        assert_eq!(resolution.values(), 1);
+        assert_eq!(resolution.percentiles(), (49.91, 50.0));
+        assert_eq!(resolution.shared(), 10);
+        assert!(!resolution.contains(50.01));
+    }
+
+    #[test]
+    fn test_missed() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(0, 2).unwrap_err();
+        h.record(10, 6).unwrap();
+        h.record(2_000, 2).unwrap_err();
+
+        // only the stored samples are ranked by default
+        assert_eq!(h.resolution_at(10.0).unwrap().percentiles(), (0.0, 100.0));
+
+        c.rank_missed(true);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(0, 2).unwrap_err();
+        h.record(10, 6).unwrap();
+        h.record(2_000, 2).unwrap_err();
+
+        let resolution = h.resolution_at(50.0).unwrap();
+        assert_eq!(resolution.percentiles(), (20.01, 80.0));
+        assert_eq!(h.resolution_at(10.0), Err(HistogramError::Underflow));
+        assert_eq!(h.resolution_at(90.0), Err(HistogramError::Overflow));
+        assert_eq!(Histogram::new().unwrap().resolution_at(50.0), Err(HistogramError::Empty));
+    }
+}
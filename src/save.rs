@@ -0,0 +1,322 @@
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{DeserializeLimits, Histogram, HistogramError};
+
+// starts each line of the preamble
+const COMMENT: u8 = b'#';
+
+/// options for saving a Histogram to a file
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, SaveOptions};
+/// let h = Histogram::new().unwrap();
+///
+/// let mut bytes = Vec::new();
+/// h.save_to(&mut bytes, SaveOptions::new().preamble(false)).unwrap();
+///
+/// assert_eq!(bytes, h.to_bytes());
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SaveOptions {
+    preamble: bool,
+}
+
+impl Default for SaveOptions {
+    fn default() -> SaveOptions {
+        SaveOptions { preamble: true }
+    }
+}
+
+impl SaveOptions {
+    /// create SaveOptions with defaults, writing the preamble
+    pub fn new() -> SaveOptions {
+        Default::default()
+    }
+
+    /// set whether the text of `Histogram::describe()` is written before
+    /// the encoding, each line commented with `#`
+    pub fn preamble(&mut self, enabled: bool) -> &mut Self {
+        self.preamble = enabled;
+        self
+    }
+}
+
+impl Histogram {
+    /// return a human readable description of the crate version, config,
+    /// counters, and the current time, one `key: value` per line
+    ///
+    /// This is the text of the preamble written by `save()`.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    ///
+    /// let description = h.describe();
+    ///
+    /// assert!(description.contains("precision: 3\n"));
+    /// assert!(description.contains("entries: 3\n"));
+    pub fn describe(&self) -> String {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let config = &self.config;
+        let counters = &self.data.counters;
+
+        let mut description = String::new();
+        let _ = writeln!(description, "histogram: {}", env!("CARGO_PKG_VERSION"));
+        let _ = writeln!(description, "precision: {}", config.precision);
+        let _ = writeln!(description, "radix: {}", config.radix);
+        let _ = writeln!(description, "max_value: {}", config.max_value);
+        let _ = writeln!(description, "min_value: {}", config.min_value);
+        let _ = writeln!(description, "scale: {}", config.scale);
+        let _ = writeln!(description, "max_memory: {}", config.max_memory);
+        let _ = writeln!(description, "overflow: {:?}", config.overflow);
+        let _ = writeln!(description, "prefault: {}", config.prefault);
+        let _ = writeln!(description, "percentile_method: {:?}", config.percentile_method);
+        let _ = writeln!(description, "track_peaks: {}", config.track_peaks);
+        let _ = writeln!(description, "rank_missed: {}", config.rank_missed);
+        let _ = writeln!(description, "buckets: {}", self.properties.buckets_total);
+        let _ = writeln!(description, "entries: {}", counters.entries_total);
+        let _ = writeln!(description, "missed_small: {}", counters.missed_small);
+        let _ = writeln!(description, "missed_large: {}", counters.missed_large);
+        let _ = writeln!(description, "missed_unknown: {}", counters.missed_unknown);
+        let _ = writeln!(description, "created: {}", created);
+        description
+    }
+
+    /// save to a file, as the encoding of `to_bytes()` optionally preceded
+    /// by a commented preamble describing the histogram
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use histogram::{Histogram, SaveOptions};
+    /// let h = Histogram::new().unwrap();
+    ///
+    /// h.save("latency.hist", &SaveOptions::new()).unwrap();
+    ///
+    /// let r = Histogram::load("latency.hist").unwrap();
+    pub fn save<P: AsRef<Path>>(&self, path: P, options: &SaveOptions) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save_to(&mut writer, options)?;
+        writer.flush()
+    }
+
+    /// write the contents of a file saved by `save()` to the writer,
+    /// returning the number of bytes written
+    pub fn save_to<W: Write>(&self, mut writer: W, options: &SaveOptions) -> io::Result<usize> {
+        let mut preamble = String::new();
+        if options.preamble {
+            for line in self.describe().lines() {
+                preamble.push(COMMENT as char);
+                preamble.push(' ');
+                preamble.push_str(line);
+                preamble.push('\n');
+            }
+            writer.write_all(preamble.as_bytes())?;
+        }
+        Ok(preamble.len() + self.serialize_into(writer)?)
+    }
+
+    /// load a file saved by `save()`, with or without the preamble
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Histogram, HistogramError> {
+        Histogram::load_with_limits(path, &DeserializeLimits::new())
+    }
+
+    /// load a file as `load()`, rejecting a config over the limits before
+    /// any buckets are allocated
+    pub fn load_with_limits<P: AsRef<Path>>(path: P,
+                                            limits: &DeserializeLimits)
+                                            -> Result<Histogram, HistogramError> {
+        let file = File::open(path).map_err(|e| HistogramError::Io(e.kind()))?;
+        Histogram::load_from_with_limits(BufReader::new(file), limits)
+    }
+
+    /// read the contents of a file saved by `save()` from the reader,
+    /// skipping any preamble
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, SaveOptions};
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(42).unwrap();
+    ///
+    /// let mut bytes = Vec::new();
+    /// h.save_to(&mut bytes, &SaveOptions::new()).unwrap();
+    ///
+    /// assert!(bytes.starts_with(b"# histogram: "));
+    /// assert_eq!(Histogram::load_from(&bytes[..]).unwrap().get(42).unwrap(), 1);
+    pub fn load_from<R: Read>(reader: R) -> Result<Histogram, HistogramError> {
+        Histogram::load_from_with_limits(reader, &DeserializeLimits::new())
+    }
+
+    /// read the contents of a file as `load_from()`, rejecting a config over
+    /// the limits before any buckets are allocated
+    pub fn load_from_with_limits<R: Read>(mut reader: R,
+                                          limits: &DeserializeLimits)
+                                          -> Result<Histogram, HistogramError> {
+        let mut byte = [0_u8];
+        loop {
+            read_byte(&mut reader, &mut byte)?;
+            if byte[0] != COMMENT {
+                break;
+            }
+            while byte[0] != b'\n' {
+                read_byte(&mut reader, &mut byte)?;
+            }
+        }
+        Histogram::deserialize_from_with_limits((&byte[..]).chain(reader), limits)
+    }
+}
+
+// read one byte, where ending early means the file was truncated
+fn read_byte<R: Read>(reader: &mut R, byte: &mut [u8; 1]) -> Result<(), HistogramError> {
+    reader.read_exact(byte).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => HistogramError::InvalidEncoding,
+        kind => HistogramError::Io(kind),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::io;
+
+    use super::SaveOptions;
+    use super::super::{DeserializeLimits, Histogram, HistogramConfig, HistogramError,
+                       OverflowPolicy, PercentileMethod};
+
+    fn populated() -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000)
+         .precision(2)
+         .max_memory(1 << 20)
+         .overflow(OverflowPolicy::Clamp)
+         .prefault(true)
+         .percentile_method(PercentileMethod::BucketMidpoint);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 0..1_000 {
+            let _ = h.record(value * 4_099, value % 7);
+        }
+        h
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let h = populated();
+        for &preamble in &[true, false] {
+            let mut bytes = Vec::new();
+            let written = h.save_to(&mut bytes, SaveOptions::new().preamble(preamble)).unwrap();
+            assert_eq!(written, bytes.len());
+            assert_eq!(bytes.starts_with(b"# "), preamble);
+            assert!(bytes.ends_with(&h.to_bytes()));
+
+            let r = Histogram::load_from(&bytes[..]).unwrap();
+            assert_eq!(r.data.data, h.data.data);
+            assert_eq!(r.counters(), h.counters());
+
+            // every truncation, including within the preamble
+            for len in 0..bytes.len() {
+                assert_eq!(Histogram::load_from(&bytes[..len]).unwrap_err(),
+                           HistogramError::InvalidEncoding);
+            }
+        }
+
+        let path = env::temp_dir().join(format!("histogram-save-{}.hist", std::process::id()));
+        h.save(&path, &SaveOptions::new()).unwrap();
+        let text = fs::read(&path).unwrap();
+        let r = Histogram::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(text.starts_with(b"# histogram: "));
+        assert_eq!(r.to_bytes(), h.to_bytes());
+        assert_eq!(Histogram::load(&path).unwrap_err(),
+                   HistogramError::Io(io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn test_limits() {
+        let h = populated();
+        let mut bytes = Vec::new();
+        h.save_to(&mut bytes, &SaveOptions::new()).unwrap();
+
+        let mut limits = DeserializeLimits::new();
+        limits.max_buckets(h.buckets_total() as u32);
+        let r = Histogram::load_from_with_limits(&bytes[..], &limits).unwrap();
+        assert_eq!(r.to_bytes(), h.to_bytes());
+
+        limits.max_buckets(h.buckets_total() as u32 - 1);
+        assert_eq!(Histogram::load_from_with_limits(&bytes[..], &limits).unwrap_err(),
+                   HistogramError::LimitExceeded);
+
+        let path = env::temp_dir().join(format!("histogram-limits-{}.hist", std::process::id()));
+        h.save(&path, &SaveOptions::new()).unwrap();
+        let loaded = Histogram::load_with_limits(&path, &limits);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap_err(), HistogramError::LimitExceeded);
+    }
+
+    #[test]
+    fn test_describe() {
+        let h = populated();
+        let description = h.describe();
+
+        let keys: Vec<&str> = description.lines()
+                                         .map(|line| line.split(": ").next().unwrap())
+                                         .collect();
+        assert_eq!(keys,
+                   vec!["histogram",
+                        "precision",
+                        "radix",
+                        "max_value",
+                        "min_value",
+                        "scale",
+                        "max_memory",
+                        "overflow",
+                        "prefault",
+                        "percentile_method",
+                        "track_peaks",
+                        "rank_missed",
+                        "buckets",
+                        "entries",
+                        "missed_small",
+                        "missed_large",
+                        "missed_unknown",
+                        "created"]);
+        for line in &["precision: 2",
+                      "radix: 10",
+                      "max_value: 1000000",
+                      "min_value: 1",
+                      "scale: 1",
+                      "max_memory: 1048576",
+                      "overflow: Clamp",
+                      "prefault: true",
+                      "percentile_method: BucketMidpoint",
+                      "track_peaks: false",
+                      "rank_missed: false",
+                      "entries: 2997",
+                      "missed_small: 0"] {
+            assert!(description.lines().any(|l| l == *line), "{}", line);
+        }
+        assert!(description.contains(&format!("buckets: {}\n", h.buckets_total())));
+
+        // the preamble is the description, commented
+        let mut bytes = Vec::new();
+        h.save_to(&mut bytes, &SaveOptions::new()).unwrap();
+        let preamble_len = description.len() + 2 * description.lines().count();
+        let preamble = String::from_utf8_lossy(&bytes[..preamble_len]);
+        for (saved, described) in preamble.lines().zip(description.lines()) {
+            if !described.starts_with("created") {
+                assert_eq!(saved, format!("# {}", described));
+            }
+        }
+    }
+}
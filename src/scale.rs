@@ -0,0 +1,274 @@
+use super::{Histogram, HistogramConfig, HistogramError};
+
+impl Histogram {
+    /// record the f64 value, multiplied by the config scale, count times
+    ///
+    /// The scaled value is rounded to the nearest integer, ties to even, so
+    /// with a scale of 1.0 both 2.5 and 1.5 are stored as 2. A value which
+    /// is NaN, infinite, or negative is rejected with `InvalidValue` and not
+    /// counted. Otherwise this records as `record()`, so a scaled value
+    /// outside the range is counted as missed, or clamped.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig,HistogramError};
+    /// let mut c = HistogramConfig::new();
+    /// c.scale(1_000.0);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.record_f64(0.25, 2).unwrap();
+    ///
+    /// assert_eq!(h.get(250), Some(2));
+    /// assert_eq!(h.record_f64(-1.0, 1), Err(HistogramError::InvalidValue));
+    pub fn record_f64(&mut self, value: f64, count: u64) -> Result<(), HistogramError> {
+        let value = self.scale_value(value)?;
+        self.record(value, count)
+    }
+
+    /// return the percentile as a f64, divided by the config scale
+    ///
+    /// This is `percentile()`, so is only as precise as the bucket holding
+    /// the percentile, and fails as it does.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.scale(100.0);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.record_f64(value as f64 / 100.0, 1).unwrap();
+    /// }
+    ///
+    /// assert_eq!(h.percentile_f64(50.0).unwrap(), 0.5);
+    pub fn percentile_f64(&self, percentile: f64) -> Result<f64, HistogramError> {
+        self.percentile(percentile).map(|value| self.unscale_value(value))
+    }
+
+    /// return the mean as a f64, divided by the config scale, failing as
+    /// `mean()`
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.scale(10.0);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.record_f64(1.5, 1).unwrap();
+    /// h.record_f64(2.5, 1).unwrap();
+    ///
+    /// assert_eq!(h.mean_f64().unwrap(), 2.0);
+    pub fn mean_f64(&self) -> Result<f64, HistogramError> {
+        self.mean().map(|value| self.unscale_value(value))
+    }
+
+    /// return the minimum as a f64, divided by the config scale, failing as
+    /// `minimum()`
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.scale(10.0);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.record_f64(1.5, 1).unwrap();
+    /// h.record_f64(2.5, 1).unwrap();
+    ///
+    /// assert_eq!(h.minimum_f64().unwrap(), 1.5);
+    pub fn minimum_f64(&self) -> Result<f64, HistogramError> {
+        self.minimum().map(|value| self.unscale_value(value))
+    }
+
+    /// return the maximum as a f64, divided by the config scale, failing as
+    /// `maximum()`
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram,HistogramConfig};
+    /// let mut c = HistogramConfig::new();
+    /// c.scale(10.0);
+    /// let mut h = Histogram::configured(c).unwrap();
+    ///
+    /// h.record_f64(1.5, 1).unwrap();
+    /// h.record_f64(2.5, 1).unwrap();
+    ///
+    /// assert_eq!(h.maximum_f64().unwrap(), 2.5);
+    pub fn maximum_f64(&self) -> Result<f64, HistogramError> {
+        self.maximum().map(|value| self.unscale_value(value))
+    }
+
+    // the integer value stored for the f64 value, saturating at u64::MAX
+    fn scale_value(&self, value: f64) -> Result<u64, HistogramError> {
+        if !value.is_finite() || value < 0.0 {
+            return Err(HistogramError::InvalidValue);
+        }
+        Ok((value * self.config.scale).round_ties_even() as u64)
+    }
+
+    // the f64 value of the stored integer value
+    fn unscale_value(&self, value: u64) -> f64 {
+        value as f64 / self.config.scale
+    }
+}
+
+impl HistogramConfig {
+    // the value under this scale of a value stored under the other config's
+    // scale, saturating at u64::MAX
+    pub(crate) fn rescale(&self, value: u64, from: &HistogramConfig) -> u64 {
+        if self.scale == from.scale {
+            return value;
+        }
+        (value as f64 / from.scale * self.scale).round_ties_even() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError, OverflowPolicy};
+
+    fn scaled(scale: f64) -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.scale(scale);
+        Histogram::configured(c).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut h = scaled(1e6);
+        h.record_f64(0.25, 1).unwrap();
+
+        // within the bucket holding the scaled value
+        let (low, high) = h.bucket_bounds(250_000).unwrap();
+        let width = (high - low + 1) as f64 / 1e6;
+        let p50 = h.percentile_f64(50.0).unwrap();
+        assert!((p50 - 0.25).abs() <= width, "p50: {}", p50);
+        assert!((h.mean_f64().unwrap() - 0.25).abs() <= width);
+        assert_eq!(h.minimum_f64(), Ok(0.25));
+        assert_eq!(h.maximum_f64(), Ok(0.25));
+
+        // a scale below 1 stores coarser values
+        let mut h = scaled(0.001);
+        h.record_f64(2_000_000.0, 1).unwrap();
+        assert_eq!(h.get(2_000), Some(1));
+        assert_eq!(h.percentile_f64(50.0).unwrap(), 2_000_000.0);
+    }
+
+    #[test]
+    fn test_encodings() {
+        let mut h = scaled(1_000.0);
+        h.record_f64(0.25, 3).unwrap();
+        let p50 = h.percentile_f64(50.0).unwrap();
+
+        // the scale is kept, with or without a min_value
+        let mut c = HistogramConfig::new();
+        c.scale(1_000.0).min_value(100);
+        let mut bounded = Histogram::configured(c).unwrap();
+        bounded.record_f64(0.25, 3).unwrap();
+        for h in &[h, bounded] {
+            for r in &[Histogram::from_bytes(&h.to_bytes()).unwrap(),
+                       Histogram::deserialize(&h.serialize()).unwrap()] {
+                assert_eq!(r.config().get_scale(), 1_000.0);
+                assert_eq!(r.config().get_min_value(), h.config().get_min_value());
+                assert_eq!(r.percentile_f64(50.0), Ok(p50));
+                assert_eq!(r, h);
+            }
+            assert!(h.describe().contains("scale: 1000\n"));
+        }
+
+        // the default scale is written as before
+        let plain = scaled(1.0);
+        assert_eq!(plain.to_bytes()[4], 1);
+        assert_eq!(plain.serialize()[4], 2);
+
+        // a scale which is not finite and above 0 is rejected
+        let mut bytes = scaled(1_000.0).to_bytes();
+        let at = 4 + 1 + 1 + 4 + 4 + 8 + 8;
+        bytes[at..at + 8].copy_from_slice(&(-1.0_f64).to_bits().to_le_bytes());
+        assert_eq!(Histogram::from_bytes(&bytes).unwrap_err(), HistogramError::InvalidEncoding);
+    }
+
+    #[test]
+    fn test_incompatible() {
+        let mut a = scaled(1_000.0);
+        let mut b = scaled(1.0);
+        a.record_f64(0.25, 1).unwrap();
+        b.record(250, 1).unwrap();
+
+        // the same counts stand for other values under another scale
+        assert_eq!(a.get(250), b.get(250));
+        assert_ne!(a, b);
+        assert_eq!(b.clone().reset_to(&a), Err(HistogramError::IncompatibleConfig));
+
+        // a merge moves the counts to the values they stand for
+        let report = a.merge(&b);
+        assert!(report.lossy());
+        assert_eq!(report.transferred(), 1);
+        assert_eq!(a.get(250), Some(1));
+        assert_eq!(a.get(250_000), Some(1));
+        assert!((a.maximum_f64().unwrap() - 250.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_emd() {
+        let mut ms = scaled(1_000.0);
+        let mut us = scaled(1_000_000.0);
+        for i in 0..100 {
+            let seconds = (100 + i) as f64 / 1_000.0;
+            ms.record_f64(seconds, 1).unwrap();
+            us.record_f64(seconds, 1).unwrap();
+        }
+
+        // the same samples are close in the units of either histogram
+        let in_ms = ms.emd(&us).unwrap();
+        let in_us = us.emd(&ms).unwrap();
+        assert!(in_ms < 0.5, "{}", in_ms);
+        assert!(in_us < 200.0, "{}", in_us);
+        assert!((in_us / 1_000.0 - in_ms).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_rounding() {
+        let mut h = scaled(1.0);
+        for &(value, stored) in &[(0.5, 0), (1.5, 2), (2.5, 2), (3.5, 4), (2.49, 2), (2.51, 3)] {
+            let mut single = scaled(1.0);
+            let _ = single.record_f64(value, 1);
+            assert_eq!(single.counters().entries(), 1);
+            if stored > 0 {
+                assert_eq!(single.get(stored), Some(1), "value: {}", value);
+            } else {
+                assert_eq!(single.missed_small(), 1);
+            }
+            let _ = h.record_f64(value, 1);
+        }
+        assert_eq!(h.entries(), 6);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut h = scaled(1_000.0);
+        for &value in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.5, -1e-300] {
+            assert_eq!(h.record_f64(value, 1), Err(HistogramError::InvalidValue));
+        }
+        assert_eq!(h.entries(), 0);
+
+        // -0.0 is zero, which is too small
+        assert_eq!(h.record_f64(-0.0, 1), Err(HistogramError::TooSmall));
+
+        // scaled values beyond the range follow the overflow policy
+        assert_eq!(h.record_f64(1e300, 1), Err(HistogramError::TooLarge));
+        let mut c = HistogramConfig::new();
+        c.scale(1_000.0).max_value(1_000).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record_f64(5.0, 1).unwrap();
+        assert_eq!(h.maximum_f64().unwrap(), 1.0);
+
+        for &scale in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let mut c = HistogramConfig::new();
+            c.scale(scale);
+            assert_eq!(Histogram::configured(c).unwrap_err(), HistogramError::InvalidConfig);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::Histogram;
+
+// the auto clear schedule of a Histogram, which clones and snapshots do not
+// inherit, as the callback cannot be shared
+#[derive(Default)]
+pub(crate) struct Schedule(Option<Box<AutoClear>>);
+
+struct AutoClear {
+    interval: Duration,
+    // the end of the current interval
+    next: Instant,
+    // locked only to make the histogram Sync, as it is called through &mut
+    callback: Mutex<Box<dyn FnMut(Histogram) + Send>>,
+}
+
+impl Clone for Schedule {
+    fn clone(&self) -> Schedule {
+        Schedule(None)
+    }
+}
+
+impl Schedule {
+    pub(crate) fn is_active(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl Histogram {
+    /// clear the histogram at the end of every interval, first handing the
+    /// samples recorded in the interval to the callback
+    ///
+    /// The schedule is checked as each sample is recorded, before storing
+    /// it, and once before each `merge()`, on the histogram's clock, so the
+    /// snapshot and clear happen on the recording thread and no sample can
+    /// be recorded between them. The
+    /// first interval ends the interval after this call. The snapshot is
+    /// taken as by `take()`. Intervals in which nothing was recorded give no
+    /// snapshot of their own: the samples held are handed over at the next
+    /// record, and later intervals stay aligned to the first.
+    ///
+    /// A later call replaces the schedule, and an interval of zero stops it.
+    /// Clones and snapshots do not auto clear.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, HistogramConfig, ManualClock};
+    /// # use std::sync::{Arc, Mutex};
+    /// # use std::time::Duration;
+    /// let clock = Arc::new(ManualClock::new());
+    /// let mut h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+    ///
+    /// let reported = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = reported.clone();
+    /// h.auto_clear(Duration::from_secs(10), move |snapshot: Histogram| {
+    ///     sink.lock().unwrap().push(snapshot.entries());
+    /// });
+    ///
+    /// h.record(5, 3).unwrap();
+    /// clock.advance(Duration::from_secs(10));
+    /// h.record(5, 1).unwrap();
+    ///
+    /// assert_eq!(*reported.lock().unwrap(), vec![3]);
+    /// assert_eq!(h.entries(), 1);
+    pub fn auto_clear<F>(&mut self, interval: Duration, on_snapshot: F)
+        where F: FnMut(Histogram) + Send + 'static
+    {
+        self.schedule = Schedule(match interval {
+            Duration::ZERO => None,
+            _ => {
+                self.clock.now().checked_add(interval).map(|next| {
+                    Box::new(AutoClear {
+                        interval,
+                        next,
+                        callback: Mutex::new(Box::new(on_snapshot)),
+                    })
+                })
+            }
+        });
+    }
+
+    // hand over and clear the samples if the current interval has ended
+    pub(crate) fn run_schedule(&mut self) {
+        let now = self.clock.now();
+        let mut schedule = match self.schedule.0.take() {
+            Some(schedule) if now >= schedule.next => schedule,
+            schedule => {
+                self.schedule.0 = schedule;
+                return;
+            }
+        };
+
+        // the start of the interval holding now, skipping idle intervals
+        let interval = schedule.interval.as_nanos();
+        let skipped = (now - schedule.next).as_nanos() / interval * interval;
+        let start = schedule.next + Duration::from_nanos(skipped.min(u64::MAX as u128) as u64);
+
+        let snapshot = self.take();
+        self.data.started = start;
+        let callback = match schedule.callback.get_mut() {
+            Ok(callback) => callback,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        callback(snapshot);
+
+        // an interval past the clock's range ends the schedule
+        if let Some(next) = start.checked_add(schedule.interval) {
+            schedule.next = next;
+            self.schedule.0 = Some(schedule);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::super::{Histogram, HistogramConfig, ManualClock};
+
+    // a histogram on a manual clock, auto clearing every second into the
+    // returned list of snapshots
+    fn scheduled() -> (Histogram, Arc<ManualClock>, Arc<Mutex<Vec<Histogram>>>) {
+        let clock = Arc::new(ManualClock::new());
+        let mut h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+        let snapshots = Arc::new(Mutex::new(Vec::new()));
+        let sink = snapshots.clone();
+        h.auto_clear(Duration::from_secs(1), move |snapshot| {
+            sink.lock().unwrap().push(snapshot);
+        });
+        (h, clock, snapshots)
+    }
+
+    #[test]
+    fn test_intervals() {
+        let (mut h, clock, snapshots) = scheduled();
+
+        // ten records each interval, the first at its very start
+        let mut recorded = 0;
+        for i in 0..100 {
+            h.increment(1 + i).unwrap();
+            recorded += 1;
+            clock.advance(Duration::from_millis(100));
+            assert_eq!(snapshots.lock().unwrap().len() as u64, i / 10);
+        }
+        assert_eq!(h.entries(), 10);
+
+        let snapshots = snapshots.lock().unwrap();
+        assert_eq!(snapshots.len(), 9);
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            let first = 1 + i as u64 * 10;
+            assert_eq!(snapshot.entries(), 10);
+            assert_eq!(snapshot.minimum().unwrap(), first);
+            assert_eq!(snapshot.maximum().unwrap(), first + 9);
+        }
+
+        // no samples lost across the boundaries
+        let handed = snapshots.iter().map(|snapshot| snapshot.entries()).sum::<u64>();
+        assert_eq!(handed + h.entries(), recorded);
+    }
+
+    #[test]
+    fn test_idle() {
+        let (mut h, clock, snapshots) = scheduled();
+        h.record(5, 2).unwrap();
+
+        // nothing happens until the next record, then one snapshot
+        clock.advance(Duration::from_millis(3_500));
+        assert_eq!(snapshots.lock().unwrap().len(), 0);
+        h.increment(7).unwrap();
+        assert_eq!(snapshots.lock().unwrap().len(), 1);
+        assert_eq!(h.entries(), 1);
+
+        // the intervals stay aligned to the first, so the next ends at 4s
+        clock.advance(Duration::from_millis(499));
+        h.increment(7).unwrap();
+        assert_eq!(snapshots.lock().unwrap().len(), 1);
+        clock.advance(Duration::from_millis(1));
+        h.increment(7).unwrap();
+        assert_eq!(snapshots.lock().unwrap().len(), 2);
+        assert_eq!(snapshots.lock().unwrap()[1].entries(), 2);
+
+        // a rejected sample still checks the schedule, and counts after it
+        clock.advance(Duration::from_secs(1));
+        h.increment(0).unwrap_err();
+        assert_eq!(snapshots.lock().unwrap().len(), 3);
+        assert_eq!(h.missed_small(), 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let (mut h, clock, snapshots) = scheduled();
+        h.record(5, 2).unwrap();
+
+        // a lossy merge past the end of the interval hands over only the
+        // earlier samples, and lands whole in the new interval
+        let mut c = HistogramConfig::new();
+        c.precision(2);
+        let mut other = Histogram::configured(c).unwrap();
+        for value in 1..1_000 {
+            other.increment(value * 1_000).unwrap();
+        }
+        clock.advance(Duration::from_secs(1));
+        let report = h.merge(&other);
+        assert!(report.lossy());
+        assert_eq!(report.transferred(), 999);
+
+        let snapshots = snapshots.lock().unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].entries(), 2);
+        assert_eq!(snapshots[0].maximum().unwrap(), 5);
+        assert_eq!(h.entries(), 999);
+        assert_eq!(h.get(5), Some(0));
+        assert!(h.minimum().unwrap() >= 1_000);
+    }
+
+    #[test]
+    fn test_stop() {
+        let (mut h, clock, snapshots) = scheduled();
+
+        // clones and snapshots do not inherit the schedule
+        let mut copy = h.clone();
+        let mut taken = h.take();
+        clock.advance(Duration::from_secs(2));
+        copy.increment(1).unwrap();
+        taken.increment(1).unwrap();
+        assert_eq!(snapshots.lock().unwrap().len(), 0);
+        h.increment(1).unwrap();
+        assert_eq!(snapshots.lock().unwrap().len(), 1);
+
+        h.auto_clear(Duration::ZERO, |_| panic!("stopped"));
+        clock.advance(Duration::from_secs(2));
+        h.increment(1).unwrap();
+        assert_eq!(h.entries(), 2);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<Histogram>();
+    }
+}
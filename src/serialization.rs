@@ -0,0 +1,335 @@
+//! serde support, enabled with the `serde` feature
+//!
+//! A Histogram serializes as its config, counters, and buckets. The buckets
+//! are written densely, as every count, or sparsely, as `(index, count)` pairs
+//! of the non-zero buckets, whichever is smaller. The chosen form is tagged in
+//! the output so deserialization accepts either.
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use super::{DeserializeLimits, Histogram, HistogramConfig, HistogramCounters, HistogramProperties,
+            OverflowPolicy};
+
+// a sparse pair takes about twice the space of a dense count, so sparse is
+// used when under half the buckets are occupied
+const SPARSE_DENSITY: usize = 2;
+
+#[derive(Serialize)]
+#[serde(rename = "Histogram")]
+struct EncodeHistogram<'a> {
+    precision: u32,
+    radix: u32,
+    max_value: u64,
+    min_value: u64,
+    scale: f64,
+    overflow: OverflowPolicy,
+    entries: u64,
+    missed_small: u64,
+    missed_large: u64,
+    missed_unknown: u64,
+    buckets: EncodeBuckets<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Buckets")]
+enum EncodeBuckets<'a> {
+    Dense(&'a [u64]),
+    Sparse(Vec<(u32, u64)>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Histogram")]
+struct DecodeHistogram {
+    precision: u32,
+    radix: u32,
+    max_value: u64,
+    // absent from histograms serialized before min_value was added
+    #[serde(default = "default_min_value")]
+    min_value: u64,
+    // absent from histograms serialized before the scale was added
+    #[serde(default = "default_scale")]
+    scale: f64,
+    overflow: OverflowPolicy,
+    entries: u64,
+    missed_small: u64,
+    missed_large: u64,
+    missed_unknown: u64,
+    buckets: DecodeBuckets,
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Buckets")]
+enum DecodeBuckets {
+    Dense(Vec<u64>),
+    Sparse(Vec<(u32, u64)>),
+}
+
+fn default_min_value() -> u64 {
+    1
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+impl Serialize for Histogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = &self.data.data;
+        let occupied = data.iter().filter(|count| **count > 0).count();
+
+        let buckets = if occupied * SPARSE_DENSITY < self.properties.buckets_total as usize {
+            EncodeBuckets::Sparse(data.iter()
+                                      .enumerate()
+                                      .filter(|&(_, count)| *count > 0)
+                                      .map(|(index, count)| (index as u32, *count))
+                                      .collect())
+        } else {
+            EncodeBuckets::Dense(data)
+        };
+
+        let counters = &self.data.counters;
+        let encoded = EncodeHistogram {
+            precision: self.config.precision,
+            radix: self.config.radix,
+            max_value: self.config.max_value,
+            min_value: self.config.min_value,
+            scale: self.config.scale,
+            overflow: self.config.overflow,
+            entries: counters.entries_total,
+            missed_small: counters.missed_small,
+            missed_large: counters.missed_large,
+            missed_unknown: counters.missed_unknown,
+            buckets,
+        };
+        encoded.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Histogram {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Histogram, D::Error> {
+        DeserializeLimits::new().deserialize(deserializer)
+    }
+}
+
+/// deserializes a Histogram, rejecting a config over the limits before its
+/// buckets are allocated
+///
+/// # Example
+/// ```
+/// # extern crate histogram;
+/// # extern crate serde;
+/// # extern crate serde_json;
+/// # use histogram::{DeserializeLimits, Histogram};
+/// # use serde::de::DeserializeSeed;
+/// let json = serde_json::to_string(&Histogram::new().unwrap()).unwrap();
+///
+/// let mut limits = DeserializeLimits::new();
+/// limits.max_buckets(1_000);
+///
+/// let mut deserializer = serde_json::Deserializer::from_str(&json);
+/// assert!(limits.deserialize(&mut deserializer).is_err());
+impl<'de> DeserializeSeed<'de> for DeserializeLimits {
+    type Value = Histogram;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Histogram, D::Error> {
+        let decoded = DecodeHistogram::deserialize(deserializer)?;
+
+        let mut config = HistogramConfig::new();
+        config.precision(decoded.precision)
+              .max_value(decoded.max_value)
+              .min_value(decoded.min_value)
+              .scale(decoded.scale)
+              .overflow(decoded.overflow);
+        config.radix = decoded.radix;
+        if config.radix < 2 {
+            return Err(de::Error::custom("invalid radix"));
+        }
+
+        let properties = match HistogramProperties::new(&config) {
+            Ok(properties) => properties,
+            Err(e) => return Err(de::Error::custom(e)),
+        };
+        if let Err(e) = self.check(&properties) {
+            return Err(de::Error::custom(e));
+        }
+        let buckets = properties.buckets_total as usize;
+
+        let data = match decoded.buckets {
+            DecodeBuckets::Dense(data) => {
+                if data.len() != buckets {
+                    return Err(de::Error::invalid_length(data.len(), &"buckets_total counts"));
+                }
+                data
+            }
+            DecodeBuckets::Sparse(pairs) => {
+                let mut data = vec![0; buckets];
+                let mut next = 0;
+                for (index, count) in pairs {
+                    let index = index as usize;
+                    if index < next || index >= buckets {
+                        return Err(de::Error::custom("bucket index out of order or range"));
+                    }
+                    if let Some(bucket) = data.get_mut(index) {
+                        *bucket = count;
+                    }
+                    next = index + 1;
+                }
+                data
+            }
+        };
+
+        let counters = HistogramCounters {
+            entries_total: decoded.entries,
+            missed_unknown: decoded.missed_unknown,
+            missed_small: decoded.missed_small,
+            missed_large: decoded.missed_large,
+        };
+        Ok(Histogram::assembled(config, properties, counters, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::de::DeserializeSeed;
+
+    use super::super::{DeserializeLimits, Histogram, HistogramConfig, HistogramCounters,
+                       OverflowPolicy};
+
+    fn round_trip(h: &Histogram) -> (String, Histogram) {
+        let json = serde_json::to_string(h).unwrap();
+        let r: Histogram = serde_json::from_str(&json).unwrap();
+        r.check_invariants().unwrap();
+
+        assert_eq!(r.data.data, h.data.data);
+        assert_eq!(r.entries(), h.entries());
+        assert_eq!(r.config.overflow, h.config.overflow);
+        for p in 0..101 {
+            assert_eq!(r.percentile(p as f64), h.percentile(p as f64));
+        }
+        (json, r)
+    }
+
+    #[test]
+    fn test_sparse() {
+        let mut h = Histogram::new().unwrap();
+        h.increment(1).unwrap();
+        h.record(1_000_000, 3).unwrap();
+        h.increment(0).unwrap_err();
+
+        let (json, _) = round_trip(&h);
+        assert!(json.contains("\"Sparse\""));
+        assert!(json.len() < 400, "{}", json.len());
+    }
+
+    #[test]
+    fn test_dense() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).precision(1).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 1..2_000 {
+            h.increment(value).unwrap();
+        }
+
+        let (json, _) = round_trip(&h);
+        assert!(json.contains("\"Dense\""));
+    }
+
+    #[test]
+    fn test_empty() {
+        let h = Histogram::new().unwrap();
+
+        let (json, r) = round_trip(&h);
+        assert!(json.contains("\"Sparse\":[]"));
+        assert_eq!(r.buckets_total(), h.buckets_total());
+    }
+
+    #[test]
+    fn test_min_value() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000_000).min_value(1_000);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(1_234, 3).unwrap();
+        h.increment(999).unwrap_err();
+
+        let (json, r) = round_trip(&h);
+        assert!(json.contains("\"min_value\":1000"), "{}", json);
+        assert!(r.config.matches(&h.config));
+
+        // histograms serialized without a min_value start at 1
+        let json = serde_json::to_string(&Histogram::new().unwrap()).unwrap();
+        let json = json.replace("\"min_value\":1,", "");
+        let r: Histogram = serde_json::from_str(&json).unwrap();
+        assert_eq!(r.config.min_value, 1);
+    }
+
+    #[test]
+    fn test_scale() {
+        let mut c = HistogramConfig::new();
+        c.scale(1_000.0);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record_f64(0.25, 3).unwrap();
+
+        let (json, r) = round_trip(&h);
+        assert!(json.contains("\"scale\":1000.0"), "{}", json);
+        assert_eq!(r.percentile_f64(50.0), h.percentile_f64(50.0));
+
+        // histograms serialized without a scale have a scale of 1
+        let json = serde_json::to_string(&Histogram::new().unwrap()).unwrap();
+        let json = json.replace("\"scale\":1.0,", "");
+        let r: Histogram = serde_json::from_str(&json).unwrap();
+        assert_eq!(r.config.scale, 1.0);
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut h = Histogram::new().unwrap();
+        h.record(10, 4).unwrap();
+        h.increment(0).unwrap_err();
+
+        let json = serde_json::to_string(&h.counters()).unwrap();
+        assert_eq!(json,
+                   "{\"entries_total\":5,\"missed_unknown\":0,\"missed_small\":1,\
+                    \"missed_large\":0}");
+        let counters: HistogramCounters = serde_json::from_str(&json).unwrap();
+        assert_eq!(counters, h.counters());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100).precision(1);
+        let mut h = Histogram::configured(c).unwrap();
+        h.increment(50).unwrap();
+
+        let sparse = serde_json::to_string(&h).unwrap();
+        assert!(sparse.contains("\"Sparse\":[[30,1]]"), "{}", sparse);
+        for bad in &["[[30,1],[30,1]]", "[[31,1],[30,1]]", "[[4000,1]]"] {
+            let json = sparse.replace("[[30,1]]", bad);
+            assert!(serde_json::from_str::<Histogram>(&json).is_err(), "{}", bad);
+        }
+
+        let json = sparse.replace("{\"Sparse\":[[30,1]]}", "{\"Dense\":[0,1]}");
+        assert!(serde_json::from_str::<Histogram>(&json).is_err());
+
+        let json = sparse.replace("\"precision\":1", "\"precision\":40");
+        assert!(serde_json::from_str::<Histogram>(&json).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        // a few bytes claiming a config of about 45 million buckets
+        let json = "{\"precision\":6,\"radix\":10,\"max_value\":18446744073709551615,\
+                    \"overflow\":\"Reject\",\"entries\":0,\"missed_small\":0,\
+                    \"missed_large\":0,\"missed_unknown\":0,\"buckets\":{\"Sparse\":[]}}";
+
+        let mut limits = DeserializeLimits::new();
+        limits.max_buckets(1_000_000);
+        let error = limits.deserialize(&mut serde_json::Deserializer::from_str(json)).unwrap_err();
+        assert_eq!(error.to_string(), "encoding exceeds deserialize limits");
+
+        let json = serde_json::to_string(&Histogram::new().unwrap()).unwrap();
+        let decoded = limits.deserialize(&mut serde_json::Deserializer::from_str(&json)).unwrap();
+        assert_eq!(decoded.buckets_total(), Histogram::new().unwrap().buckets_total());
+    }
+}
@@ -0,0 +1,286 @@
+use std::sync::{Mutex, MutexGuard};
+
+use super::{Histogram, HistogramError};
+
+// the closures recording into and querying the reference state, and the
+// hook told of each discrepancy
+type Record<S> = Box<dyn FnMut(&mut S, u64, u64) + Send>;
+type Query<S> = Box<dyn Fn(&S, f64) -> Option<u64> + Send>;
+type Hook = Box<dyn FnMut(&Discrepancy) + Send>;
+
+/// a percentile on which a ShadowHistogram and its reference disagreed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Discrepancy {
+    percentile: f64,
+    primary: Option<u64>,
+    reference: Option<u64>,
+}
+
+impl Discrepancy {
+    /// return the percentile queried
+    pub fn percentile(&self) -> f64 {
+        self.percentile
+    }
+
+    /// return the answer of the Histogram, None if it failed
+    pub fn primary(&self) -> Option<u64> {
+        self.primary
+    }
+
+    /// return the answer of the reference, None if it had none
+    pub fn reference(&self) -> Option<u64> {
+        self.reference
+    }
+}
+
+// the disagreements found so far, and the hook told of each
+struct Audit {
+    checks: u64,
+    discrepancies: Vec<Discrepancy>,
+    hook: Option<Hook>,
+}
+
+/// a Histogram run side by side with a reference implementation, such as
+/// the histogram library being migrated from, checking that their
+/// percentiles agree
+///
+/// Every sample is recorded into both, the reference through a closure over
+/// its state. Each `percentile()` query asks both, and returns the
+/// Histogram's answer, counting a discrepancy when the answers differ by
+/// more than the tolerance relative to the reference's, or only one of them
+/// has an answer. The default tolerance is the documented error of the
+/// Histogram's buckets, one part in `buckets_inner()`, so against an exact
+/// reference using the same nearest rank, a discrepancy means a bug rather
+/// than expected imprecision.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, ShadowHistogram};
+/// // an exact reference keeping every sample
+/// let mut h = ShadowHistogram::new(Histogram::new().unwrap(),
+///                                  Vec::new(),
+///                                  |samples: &mut Vec<u64>, value, count| {
+///                                      samples.extend((0..count).map(|_| value))
+///                                  },
+///                                  |samples: &Vec<u64>, percentile| {
+///                                      let mut sorted = samples.clone();
+///                                      sorted.sort_unstable();
+///                                      let rank = (percentile / 100.0 * sorted.len() as f64)
+///                                          .ceil() as usize;
+///                                      sorted.get(rank.max(1) - 1).copied()
+///                                  });
+///
+/// for value in 1..1_001 {
+///     h.increment(value).unwrap();
+/// }
+///
+/// assert_eq!(h.percentile(99.0).unwrap(), 990);
+/// assert!(h.discrepancies().is_empty());
+pub struct ShadowHistogram<S> {
+    primary: Histogram,
+    reference: S,
+    record: Record<S>,
+    query: Query<S>,
+    tolerance: f64,
+    audit: Mutex<Audit>,
+}
+
+impl<S> ShadowHistogram<S> {
+    /// create a ShadowHistogram checking the Histogram against the reference
+    /// state, recorded into by `record` with each value and count, and
+    /// asked for a percentile by `query`
+    pub fn new<R, Q>(primary: Histogram, reference: S, record: R, query: Q) -> ShadowHistogram<S>
+        where R: FnMut(&mut S, u64, u64) + Send + 'static,
+              Q: Fn(&S, f64) -> Option<u64> + Send + 'static
+    {
+        let tolerance = 1.0 / primary.buckets_inner() as f64;
+        ShadowHistogram {
+            primary,
+            reference,
+            record: Box::new(record),
+            query: Box::new(query),
+            tolerance,
+            audit: Mutex::new(Audit {
+                checks: 0,
+                discrepancies: Vec::new(),
+                hook: None,
+            }),
+        }
+    }
+
+    /// set the largest difference of the answers, relative to the
+    /// reference's, which is not a discrepancy
+    pub fn tolerance(&mut self, tolerance: f64) -> &mut Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// call the hook with each discrepancy as it is found, as to log it
+    pub fn on_discrepancy<F>(&mut self, hook: F) -> &mut Self
+        where F: FnMut(&Discrepancy) + Send + 'static
+    {
+        self.lock_audit().hook = Some(Box::new(hook));
+        self
+    }
+
+    /// record a single sample into both
+    pub fn increment(&mut self, value: u64) -> Result<(), HistogramError> {
+        self.record(value, 1)
+    }
+
+    /// record counts of a value into both, returning the result of
+    /// `Histogram::record()`
+    ///
+    /// The reference is given every sample, including those the Histogram
+    /// misses.
+    pub fn record(&mut self, value: u64, count: u64) -> Result<(), HistogramError> {
+        (self.record)(&mut self.reference, value, count);
+        self.primary.record(value, count)
+    }
+
+    /// return the percentile of the Histogram, as `Histogram::percentile()`,
+    /// after checking it against the reference
+    pub fn percentile(&self, percentile: f64) -> Result<u64, HistogramError> {
+        let primary = self.primary.percentile(percentile);
+        let reference = (self.query)(&self.reference, percentile);
+        let agree = match (primary.as_ref().ok(), reference) {
+            (Some(&primary), Some(reference)) => {
+                primary.abs_diff(reference) as f64 <= self.tolerance * reference as f64
+            }
+            (primary, reference) => primary.is_none() && reference.is_none(),
+        };
+
+        let mut audit = self.lock_audit();
+        audit.checks += 1;
+        if !agree {
+            let discrepancy = Discrepancy {
+                percentile,
+                primary: primary.as_ref().ok().copied(),
+                reference,
+            };
+            if let Some(hook) = audit.hook.as_mut() {
+                hook(&discrepancy);
+            }
+            audit.discrepancies.push(discrepancy);
+        }
+        primary
+    }
+
+    /// return the discrepancies found so far, in the order found
+    pub fn discrepancies(&self) -> Vec<Discrepancy> {
+        self.lock_audit().discrepancies.clone()
+    }
+
+    /// return the number of percentile queries checked so far
+    pub fn checks(&self) -> u64 {
+        self.lock_audit().checks
+    }
+
+    /// return the Histogram
+    pub fn primary(&self) -> &Histogram {
+        &self.primary
+    }
+
+    /// return the reference state
+    pub fn reference(&self) -> &S {
+        &self.reference
+    }
+
+    /// return the Histogram and the reference state
+    pub fn into_inner(self) -> (Histogram, S) {
+        (self.primary, self.reference)
+    }
+
+    // a poisoned lock is taken anyway, as the audit is only appended to
+    fn lock_audit(&self) -> MutexGuard<'_, Audit> {
+        match self.audit.lock() {
+            Ok(audit) => audit,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+    use super::ShadowHistogram;
+    use super::super::test_rng::Rng;
+
+    // the histogram shadowed by every raw sample, queried by the exact
+    // nearest rank over the samples, scaled to simulate a buggy reference
+    fn exact(primary: Histogram, scale: u64) -> ShadowHistogram<Vec<u64>> {
+        ShadowHistogram::new(primary,
+                             Vec::new(),
+                             |samples: &mut Vec<u64>, value, count| {
+                                 samples.extend((0..count).map(|_| value))
+                             },
+                             move |samples: &Vec<u64>, percentile| {
+                                 let mut sorted = samples.clone();
+                                 sorted.sort_unstable();
+                                 let rank = (percentile / 100.0 * sorted.len() as f64).ceil();
+                                 sorted.get((rank as usize).max(1) - 1).map(|v| v * scale)
+                             })
+    }
+
+    #[test]
+    fn test_exact_reference() {
+        for &precision in &[1, 2, 3] {
+            let mut c = HistogramConfig::new();
+            c.precision(precision).max_value(100_000_000);
+            let mut h = exact(Histogram::configured(c).unwrap(), 1);
+            let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+            for _ in 0..5_000 {
+                let value = 1 + rng.next() % 10_000_000 / (1 + rng.next() % 1_000);
+                h.record(value, 1 + rng.next() % 3).unwrap();
+            }
+
+            for p in 0..201 {
+                let percentile = p as f64 / 2.0;
+                assert_eq!(h.percentile(percentile), h.primary().percentile(percentile));
+            }
+            assert_eq!(h.checks(), 201);
+            assert_eq!(h.discrepancies(), vec![], "precision {}", precision);
+        }
+    }
+
+    #[test]
+    fn test_disagreement() {
+        let mut h = exact(Histogram::new().unwrap(), 2);
+        let found = Arc::new(Mutex::new(Vec::new()));
+        let sink = found.clone();
+        h.on_discrepancy(move |discrepancy| sink.lock().unwrap().push(discrepancy.percentile()));
+
+        for value in 1..1_001 {
+            h.increment(value).unwrap();
+        }
+        assert_eq!(h.percentile(50.0), Ok(500));
+        assert_eq!(h.percentile(90.0), Ok(900));
+        let discrepancies = h.discrepancies();
+        assert_eq!(discrepancies.len(), 2);
+        assert_eq!((discrepancies[0].primary(), discrepancies[0].reference()),
+                   (Some(500), Some(1_000)));
+        assert_eq!(*found.lock().unwrap(), vec![50.0, 90.0]);
+
+        // a wide enough tolerance accepts the difference
+        h.tolerance(0.5);
+        h.percentile(50.0).unwrap();
+        assert_eq!(h.discrepancies().len(), 2);
+
+        // the reference sees samples the histogram misses
+        h.increment(0).unwrap_err();
+        assert_eq!(h.reference().len(), 1_001);
+        assert_eq!(h.percentile(0.0), Ok(1));
+        let last = h.discrepancies()[2];
+        assert_eq!((last.primary(), last.reference()), (Some(1), Some(0)));
+
+        // neither answering is agreement
+        assert_eq!(h.percentile(101.0), Err(HistogramError::InvalidPercentile));
+        assert_eq!(h.discrepancies().len(), 3);
+        assert_eq!(h.checks(), 5);
+
+        let (primary, reference) = h.into_inner();
+        assert_eq!((primary.entries(), reference.len()), (1_001, 1_001));
+    }
+}
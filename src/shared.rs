@@ -0,0 +1,151 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{Histogram, HistogramError};
+
+/// a Histogram recorded into from many threads
+///
+/// Clones share the same histogram. `snapshot_all()` captures several shared
+/// histograms at a single point, so statistics derived across them agree.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, SharedHistogram};
+/// # use std::thread;
+/// let h = SharedHistogram::new(Histogram::new().unwrap());
+///
+/// let writer = h.clone();
+/// thread::spawn(move || writer.increment(100).unwrap()).join().unwrap();
+///
+/// assert_eq!(h.snapshot().entries(), 1);
+#[derive(Clone)]
+pub struct SharedHistogram {
+    histogram: Arc<Mutex<Histogram>>,
+}
+
+impl SharedHistogram {
+    /// create a SharedHistogram holding the histogram
+    pub fn new(histogram: Histogram) -> SharedHistogram {
+        SharedHistogram { histogram: Arc::new(Mutex::new(histogram)) }
+    }
+
+    /// record a single sample, as `Histogram::increment()`
+    pub fn increment(&self, value: u64) -> Result<(), HistogramError> {
+        self.lock().increment(value)
+    }
+
+    /// record additional counts for a value, as `Histogram::record()`
+    pub fn record(&self, value: u64, count: u64) -> Result<(), HistogramError> {
+        self.lock().record(value, count)
+    }
+
+    /// return a copy of the histogram as it is now
+    pub fn snapshot(&self) -> Histogram {
+        self.lock().clone()
+    }
+
+    /// return copies of the histograms all taken at the same point, in the
+    /// order given
+    ///
+    /// Writers to every histogram in the set are blocked while the copies are
+    /// made, so no copy holds a sample recorded after another was taken. The
+    /// locks are taken in a fixed order, so concurrent calls over overlapping
+    /// sets do not deadlock.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{Histogram, SharedHistogram};
+    /// let latency = SharedHistogram::new(Histogram::new().unwrap());
+    /// let size = SharedHistogram::new(Histogram::new().unwrap());
+    ///
+    /// latency.increment(250).unwrap();
+    /// size.increment(4096).unwrap();
+    ///
+    /// let snapshots = SharedHistogram::snapshot_all(&[&latency, &size]);
+    ///
+    /// assert_eq!(snapshots[0].get(250).unwrap(), 1);
+    /// assert_eq!(snapshots[1].get(4096).unwrap(), 1);
+    pub fn snapshot_all(histograms: &[&SharedHistogram]) -> Vec<Histogram> {
+        let mut order = histograms.to_vec();
+        order.sort_by_key(|histogram| Arc::as_ptr(&histogram.histogram));
+
+        // lock each distinct histogram once, in address order
+        let mut guards: Vec<(*const Mutex<Histogram>, MutexGuard<'_, Histogram>)> = Vec::new();
+        for histogram in order {
+            let address = Arc::as_ptr(&histogram.histogram);
+            if guards.last().map(|guard| guard.0) != Some(address) {
+                guards.push((address, histogram.lock()));
+            }
+        }
+
+        histograms.iter()
+                  .filter_map(|histogram| {
+                      let address = Arc::as_ptr(&histogram.histogram);
+                      let position = guards.binary_search_by_key(&address, |guard| guard.0).ok()?;
+                      guards.get(position).map(|guard| guard.1.clone())
+                  })
+                  .collect()
+    }
+
+    // a poisoned lock is taken anyway, as a panicking writer leaves the
+    // histogram valid
+    fn lock(&self) -> MutexGuard<'_, Histogram> {
+        match self.histogram.lock() {
+            Ok(histogram) => histogram,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::SharedHistogram;
+    use super::super::Histogram;
+
+    #[test]
+    fn test_snapshot_all() {
+        let latency = SharedHistogram::new(Histogram::new().unwrap());
+        let queue = SharedHistogram::new(Histogram::new().unwrap());
+        let size = SharedHistogram::new(Histogram::new().unwrap());
+        let done = Arc::new(AtomicBool::new(false));
+
+        // each request records its latency, then queue delay, then size
+        let writers: Vec<_> = (0..4)
+            .map(|_| {
+                let (latency, queue, size) = (latency.clone(), queue.clone(), size.clone());
+                let done = done.clone();
+                thread::spawn(move || {
+                    let mut requests = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        latency.increment(100).unwrap();
+                        queue.increment(10).unwrap();
+                        size.increment(1_000).unwrap();
+                        requests += 1;
+                    }
+                    requests
+                })
+            })
+            .collect();
+
+        for _ in 0..1_000 {
+            let snapshots = SharedHistogram::snapshot_all(&[&size, &latency, &queue, &size]);
+            let (size, latency, queue) =
+                (snapshots[0].entries(), snapshots[1].entries(), snapshots[2].entries());
+
+            // each writer is part way through at most one request
+            assert!(latency >= queue && latency - queue <= 4);
+            assert!(queue >= size && queue - size <= 4);
+            assert_eq!(snapshots[3].entries(), size);
+        }
+
+        done.store(true, Ordering::Relaxed);
+        let requests: u64 = writers.into_iter().map(|writer| writer.join().unwrap()).sum();
+        let snapshots = SharedHistogram::snapshot_all(&[&latency, &queue, &size]);
+        for snapshot in snapshots {
+            assert_eq!(snapshot.entries(), requests);
+        }
+    }
+}
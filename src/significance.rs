@@ -0,0 +1,277 @@
+use std::fmt;
+
+use super::{Histogram, HistogramError, Rank};
+
+// the normal quantile for a two sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// whether a percentile differs between two histograms by more than their
+/// precision and sampling noise explain, see `DeltaAssessment`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeltaVerdict {
+    /// the delta is no larger than the quantization error, so the buckets
+    /// cannot tell the percentiles apart
+    NotDistinguishable,
+    /// the delta exceeds the quantization error, but the confidence
+    /// interval of the delta includes zero
+    WithinNoise,
+    /// the confidence interval of the delta excludes zero
+    Significant,
+}
+
+impl fmt::Display for DeltaVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeltaVerdict::NotDistinguishable => write!(f, "not distinguishable"),
+            DeltaVerdict::WithinNoise => write!(f, "within noise"),
+            DeltaVerdict::Significant => write!(f, "significant"),
+        }
+    }
+}
+
+/// the difference of a percentile between a baseline and a candidate
+/// histogram, with the error it could be due to, see
+/// `Histogram::percentile_delta_significance()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeltaAssessment {
+    baseline: u64,
+    candidate: u64,
+    quantization_error: u64,
+    low: i64,
+    high: i64,
+}
+
+impl DeltaAssessment {
+    /// return the percentile of the baseline
+    pub fn baseline(&self) -> u64 {
+        self.baseline
+    }
+
+    /// return the percentile of the candidate
+    pub fn candidate(&self) -> u64 {
+        self.candidate
+    }
+
+    /// return the candidate's percentile less the baseline's, saturating at
+    /// the range of i64
+    pub fn delta(&self) -> i64 {
+        clamp(self.candidate as i128 - self.baseline as i128)
+    }
+
+    /// return the combined quantization error: the widths of the buckets
+    /// holding the percentile in both histograms, less one each, which the
+    /// delta may be off by from the buckets alone
+    pub fn quantization_error(&self) -> u64 {
+        self.quantization_error
+    }
+
+    /// return the lowest and highest delta of the 95% confidence interval
+    pub fn interval(&self) -> (i64, i64) {
+        (self.low, self.high)
+    }
+
+    /// return the verdict, `NotDistinguishable` if the delta is no larger
+    /// than the quantization error, otherwise `Significant` if the interval
+    /// excludes zero, and `WithinNoise` if not
+    pub fn verdict(&self) -> DeltaVerdict {
+        if self.delta().unsigned_abs() <= self.quantization_error {
+            DeltaVerdict::NotDistinguishable
+        } else if self.low > 0 || self.high < 0 {
+            DeltaVerdict::Significant
+        } else {
+            DeltaVerdict::WithinNoise
+        }
+    }
+}
+
+impl Histogram {
+    /// assess whether the percentile of the other histogram, the candidate,
+    /// differs from this one, the baseline, by more than bucket quantization
+    /// and sample noise explain
+    ///
+    /// The interval is rank based, needing neither resampling nor any
+    /// assumption about the distribution. Of n samples, the number at or
+    /// below the true percentile p is binomial, with mean n * p and variance
+    /// n * p * (1 - p), so the samples of ranks n * p +/- 1.96 *
+    /// sqrt(n * p * (1 - p)) bracket the true percentile about 95% of the
+    /// time. Each histogram's interval runs from the lowest value of the
+    /// bucket holding its lower rank to the highest of the bucket holding
+    /// its upper rank, so covers the quantization too, or to 0 or u64::MAX
+    /// where a rank falls among missed samples the config ranks. The
+    /// delta's interval runs from the candidate's lowest less the baseline's
+    /// highest to the candidate's highest less the baseline's lowest, which
+    /// is conservative. The normal approximation to the binomial is rough
+    /// for extreme percentiles of few samples, where np or n(1 - p) is below
+    /// about 10.
+    ///
+    /// Fails as `percentile()` for either histogram. The histograms need not
+    /// share a config.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{DeltaVerdict, Histogram};
+    /// let mut baseline = Histogram::new().unwrap();
+    /// let mut candidate = Histogram::new().unwrap();
+    ///
+    /// for value in 1..10_001 {
+    ///     baseline.increment(value).unwrap();
+    ///     candidate.increment(value + value / 10).unwrap();
+    /// }
+    ///
+    /// let same = baseline.percentile_delta_significance(&baseline, 99.0).unwrap();
+    ///
+    /// assert_eq!(same.delta(), 0);
+    /// assert_eq!(same.verdict(), DeltaVerdict::NotDistinguishable);
+    ///
+    /// let slower = baseline.percentile_delta_significance(&candidate, 99.0).unwrap();
+    ///
+    /// assert_eq!(slower.delta(), 992);
+    /// assert_eq!(slower.verdict(), DeltaVerdict::Significant);
+    /// assert_eq!(slower.verdict().to_string(), "significant");
+    pub fn percentile_delta_significance(&self,
+                                         other: &Histogram,
+                                         percentile: f64)
+                                         -> Result<DeltaAssessment, HistogramError> {
+        let baseline = self.percentile(percentile)?;
+        let candidate = other.percentile(percentile)?;
+        let (low, high) = self.percentile_bounds(percentile)?;
+        let (other_low, other_high) = other.percentile_bounds(percentile)?;
+        let (interval_low, interval_high) = self.rank_interval(percentile)?;
+        let (other_interval_low, other_interval_high) = other.rank_interval(percentile)?;
+
+        Ok(DeltaAssessment {
+            baseline,
+            candidate,
+            quantization_error: (high - low).saturating_add(other_high - other_low),
+            low: clamp(other_interval_low as i128 - interval_high as i128),
+            high: clamp(other_interval_high as i128 - interval_low as i128),
+        })
+    }
+
+    // the lowest and highest values of the 95% confidence interval of the
+    // percentile, from the buckets holding the ranks bracketing it
+    fn rank_interval(&self, percentile: f64) -> Result<(u64, u64), HistogramError> {
+        let total = self.ranked_counters().entries_total;
+        let n = total as f64;
+        let p = percentile / 100.0;
+        let spread = Z_95 * (n * p * (1.0 - p)).sqrt();
+        let lower = ((n * p - spread).floor().max(1.0) as u64).min(total);
+        let upper = ((n * p + spread).ceil().max(1.0) as u64).min(total);
+        Ok((self.rank_bounds(Rank::Fraction(lower, total))?.0,
+            self.rank_bounds(Rank::Fraction(upper, total))?.1))
+    }
+
+    // the bounds of the bucket holding the rank, or of the values missed
+    // where the rank falls among missed samples
+    fn rank_bounds(&self, rank: Rank) -> Result<(u64, u64), HistogramError> {
+        let (min, max) = (self.config.min_value, self.config.max_value);
+        match self.percentile_index(rank) {
+            Ok(index) => Ok(self.index_bounds(index)),
+            Err(HistogramError::Underflow) => Ok((0, min.saturating_sub(1))),
+            Err(HistogramError::Overflow) => Ok((max.saturating_add(1), u64::MAX)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+// narrow a difference to i64, saturating
+fn clamp(value: i128) -> i64 {
+    value.clamp(i64::MIN as i128, i64::MAX as i128) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+    use super::DeltaVerdict;
+    use super::super::test_rng::Rng;
+
+    // samples of a latency distribution, with a tail shifted by the offset
+    fn latencies(seed: u64, samples: usize, tail_offset: u64) -> Histogram {
+        let mut rng = Rng(seed);
+        let mut h = Histogram::new().unwrap();
+        for _ in 0..samples {
+            let value = 1_000 + rng.next() % 1_000;
+            let value = if value > 1_950 { value + tail_offset } else { value };
+            h.increment(value).unwrap();
+        }
+        h
+    }
+
+    #[test]
+    fn test_identical() {
+        let h = latencies(1, 10_000, 0);
+        for &p in &[0.0, 1.0, 50.0, 99.0, 99.9, 100.0] {
+            let assessment = h.percentile_delta_significance(&h, p).unwrap();
+            assert_eq!(assessment.delta(), 0);
+            assert_eq!(assessment.verdict(), DeltaVerdict::NotDistinguishable, "p{}", p);
+            let (low, high) = assessment.interval();
+            assert!(low <= 0 && high >= 0);
+        }
+
+        // a fresh sample of the same distribution is noise
+        let other = latencies(2, 10_000, 0);
+        for &p in &[50.0, 90.0, 99.0] {
+            let assessment = h.percentile_delta_significance(&other, p).unwrap();
+            assert!(assessment.verdict() != DeltaVerdict::Significant, "p{}", p);
+        }
+    }
+
+    #[test]
+    fn test_shifted_tail() {
+        let baseline = latencies(1, 10_000, 0);
+        let candidate = latencies(2, 10_000, 500);
+        let assessment = baseline.percentile_delta_significance(&candidate, 99.0).unwrap();
+        assert!(assessment.delta() > 400, "{:?}", assessment);
+        assert_eq!(assessment.verdict(), DeltaVerdict::Significant);
+        assert!(assessment.interval().0 > 0);
+        let reversed = candidate.percentile_delta_significance(&baseline, 99.0).unwrap();
+        assert_eq!(reversed.delta(), -assessment.delta());
+        assert_eq!(reversed.verdict(), DeltaVerdict::Significant);
+
+        // the body is unchanged
+        let assessment = baseline.percentile_delta_significance(&candidate, 50.0).unwrap();
+        assert!(assessment.verdict() != DeltaVerdict::Significant);
+
+        // fewer samples give a wider interval
+        let width = |assessment: super::DeltaAssessment| {
+            let (low, high) = assessment.interval();
+            high - low
+        };
+        let (small, shifted) = (latencies(1, 500, 0), latencies(2, 500, 500));
+        let few = small.percentile_delta_significance(&shifted, 90.0).unwrap();
+        let many = baseline.percentile_delta_significance(&candidate, 90.0).unwrap();
+        assert!(width(few) > width(many), "{:?} {:?}", few, many);
+    }
+
+    #[test]
+    fn test_quantization() {
+        // coarse buckets hide a small shift
+        let mut c = HistogramConfig::new();
+        c.precision(1);
+        let mut coarse = Histogram::configured(c).unwrap();
+        let mut shifted = Histogram::configured(c).unwrap();
+        let (low, high) = coarse.bucket_bounds(1_000).unwrap();
+        assert!(high - low >= 10);
+        coarse.record(low, 1_000).unwrap();
+        shifted.record(high, 1_000).unwrap();
+        let assessment = coarse.percentile_delta_significance(&shifted, 50.0).unwrap();
+        assert_eq!(assessment.delta(), 0);
+        assert_eq!(assessment.quantization_error(), 2 * (high - low));
+        assert_eq!(assessment.verdict(), DeltaVerdict::NotDistinguishable);
+        assert_eq!(assessment.verdict().to_string(), "not distinguishable");
+
+        // the configs may differ
+        let mut fine = Histogram::new().unwrap();
+        fine.record(high, 1_000).unwrap();
+        let assessment = coarse.percentile_delta_significance(&fine, 50.0).unwrap();
+        assert_eq!(assessment.delta(), (high - low) as i64);
+        assert_eq!(assessment.quantization_error(), high - low);
+        assert_eq!(assessment.verdict(), DeltaVerdict::NotDistinguishable);
+
+        let empty = Histogram::new().unwrap();
+        assert_eq!(coarse.percentile_delta_significance(&empty, 50.0).unwrap_err(),
+                   HistogramError::Empty);
+        assert_eq!(coarse.percentile_delta_significance(&fine, 101.0).unwrap_err(),
+                   HistogramError::InvalidPercentile);
+    }
+}
@@ -0,0 +1,321 @@
+use std::f64::consts::PI;
+
+use super::{occupied_indices, Histogram, HistogramError};
+
+/// a compact summary of a distribution as weighted centroids, for combining
+/// histograms whose configs differ
+///
+/// Created by `Histogram::to_sketch()`. Like a t-digest, the centroids are
+/// small at the tails and large in the middle, so the extreme percentiles
+/// keep the most precision. A sketch taken from a histogram places each
+/// sample at the midpoint of its bucket, so is first off by up to half the
+/// bucket width, as the histogram itself is. Compressing to n centroids then
+/// merges samples at percentile q into centroids of about q * (1 - q) / n
+/// of the total, so a percentile is further off by at most the spread of
+/// values within such a centroid: negligible at p99.9 for a few hundred
+/// centroids, and largest around the median. Merging sketches recompresses
+/// their centroids, which adds error of the same kind, but never more than
+/// recompressing the combined samples would.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, HistogramConfig};
+/// let mut coarse = HistogramConfig::new();
+/// coarse.precision(2);
+/// let mut a = Histogram::configured(coarse).unwrap();
+/// let mut b = Histogram::new().unwrap();
+///
+/// for value in 1..1001 {
+///     a.increment(value).unwrap();
+///     b.increment(value + 1_000).unwrap();
+/// }
+///
+/// let mut sketch = a.to_sketch(100);
+/// sketch.merge(&b.to_sketch(100));
+///
+/// assert_eq!(sketch.count(), 2_000);
+/// assert!((sketch.percentile(50.0).unwrap() - 1_000.0).abs() < 10.0);
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuantileSketch {
+    // (mean, weight), ordered by mean
+    centroids: Vec<(f64, u64)>,
+    max_centroids: usize,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl QuantileSketch {
+    /// return the centroids as (mean, weight) pairs, in ascending order
+    pub fn centroids(&self) -> &[(f64, u64)] {
+        &self.centroids
+    }
+
+    /// return the number of samples summarized
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// merge another sketch into this one, keeping at most this sketch's
+    /// number of centroids
+    pub fn merge(&mut self, other: &QuantileSketch) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            self.min = other.min;
+            self.max = other.max;
+        } else {
+            self.min = self.min.min(other.min);
+            self.max = self.max.max(other.max);
+        }
+        self.count = self.count.saturating_add(other.count);
+
+        let mut points = self.centroids.clone();
+        points.extend_from_slice(&other.centroids);
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        self.centroids = compress(&points, self.max_centroids);
+    }
+
+    /// return the value at the percentile, interpolated between the
+    /// centroids, and between the extreme centroids and the lowest and
+    /// highest values
+    ///
+    /// Fails with `InvalidPercentile` outside 0.0 to 100.0, or `Empty` for a
+    /// sketch without samples.
+    pub fn percentile(&self, percentile: f64) -> Result<f64, HistogramError> {
+        if !(0.0..=100.0).contains(&percentile) {
+            return Err(HistogramError::InvalidPercentile);
+        }
+        let total: f64 = self.centroids.iter().map(|c| c.1 as f64).sum();
+        if total <= 0.0 {
+            return Err(HistogramError::Empty);
+        }
+
+        // each centroid is taken to sit at the middle of the samples it holds
+        let rank = percentile / 100.0 * total;
+        let mut previous = (0.0, self.min);
+        let mut before = 0.0;
+        for &(mean, weight) in &self.centroids {
+            let center = before + weight as f64 / 2.0;
+            if rank <= center {
+                return Ok(interpolate(previous, (center, mean), rank));
+            }
+            previous = (center, mean);
+            before += weight as f64;
+        }
+        Ok(interpolate(previous, (total, self.max), rank))
+    }
+}
+
+impl Histogram {
+    /// summarize the stored samples as a QuantileSketch of at most the given
+    /// number of centroids, at least 1
+    ///
+    /// Missed samples are left out. The lowest and highest values are exact
+    /// while `stats()` is known, otherwise the bounds of the extreme buckets.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..10_001 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let sketch = h.to_sketch(50);
+    ///
+    /// assert!(sketch.centroids().len() <= 50);
+    /// assert!((sketch.percentile(99.0).unwrap() - 9_900.0).abs() < 20.0);
+    pub fn to_sketch(&self, centroids: usize) -> QuantileSketch {
+        let max_centroids = centroids.max(1);
+        let mut points = Vec::new();
+        for index in occupied_indices(self.data.occupied) {
+            let count = self.data.count(index);
+            if count > 0 {
+                let (low, high) = self.index_bounds(index);
+                points.push((low as f64 + (high - low) as f64 / 2.0, count));
+            }
+        }
+
+        let count = points.iter().fold(0_u64, |total, point| total.saturating_add(point.1));
+        let stats = self.stats();
+        let first = self.data.occupied.map(|(low, _)| self.index_bounds(low).0);
+        let last = self.data.occupied.map(|(_, high)| self.index_bounds(high).1);
+        let min = stats.and_then(|stats| stats.min()).or(first).unwrap_or(0);
+        let max = stats.and_then(|stats| stats.max()).or(last).unwrap_or(0);
+
+        QuantileSketch {
+            centroids: compress(&points, max_centroids),
+            max_centroids,
+            count,
+            min: min as f64,
+            max: max as f64,
+        }
+    }
+}
+
+// the value at the rank, on the line between two (rank, value) points
+fn interpolate(left: (f64, f64), right: (f64, f64), rank: f64) -> f64 {
+    if right.0 <= left.0 {
+        return right.1;
+    }
+    let fraction = ((rank - left.0) / (right.0 - left.0)).clamp(0.0, 1.0);
+    left.1 + (right.1 - left.1) * fraction
+}
+
+// merge the ordered points into at most max centroids, each spanning at
+// most one unit of the t-digest scale k(q) = delta / 2pi * asin(2q - 1),
+// which shrinks the centroids towards the tails
+fn compress(points: &[(f64, u64)], max: usize) -> Vec<(f64, u64)> {
+    let total: f64 = points.iter().map(|point| point.1 as f64).sum();
+    if total <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut delta = max as f64;
+    loop {
+        let scale = |q: f64| delta / (2.0 * PI) * (2.0 * q - 1.0).clamp(-1.0, 1.0).asin();
+        let mut centroids: Vec<(f64, u64)> = Vec::new();
+        let mut before = 0.0;
+        let mut left = scale(0.0);
+        for &(mean, weight) in points {
+            match centroids.last_mut() {
+                Some(current) if scale((before + current.1 as f64 + weight as f64) / total) -
+                                 left <= 1.0 => {
+                    // weigh the means in f64, as the counts may saturate
+                    let merged = current.1 as f64 + weight as f64;
+                    current.0 += (mean - current.0) * weight as f64 / merged;
+                    current.1 = current.1.saturating_add(weight);
+                }
+                _ => {
+                    if let Some(current) = centroids.last() {
+                        before += current.1 as f64;
+                        left = scale(before / total);
+                    }
+                    centroids.push((mean, weight));
+                }
+            }
+        }
+        if centroids.len() <= max {
+            return centroids;
+        }
+        delta *= 0.9;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, HistogramError};
+    use super::super::test_rng::Rng;
+
+    // the exact nearest-rank percentile of sorted samples
+    fn exact(sorted: &[u64], percentile: f64) -> f64 {
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil().max(1.0) as usize;
+        sorted[rank - 1] as f64
+    }
+
+    #[test]
+    fn test_cross_config() {
+        let mut coarse = HistogramConfig::new();
+        coarse.precision(2).max_value(10_000_000);
+        let mut fine = HistogramConfig::new();
+        fine.precision(4).max_value(100_000_000);
+        let mut a = Histogram::configured(coarse).unwrap();
+        let mut b = Histogram::configured(fine).unwrap();
+        let mut c = Histogram::configured(coarse).unwrap();
+
+        // log uniform from 1,000 to about 4,000,000, a long tail
+        let mut rng = Rng(0x1234_5678_9abc_def1);
+        let mut samples = Vec::new();
+        for i in 0..60_000 {
+            let exponent = (rng.next() >> 11) as f64 / (1_u64 << 53) as f64 * 12.0;
+            let value = (1_000.0 * exponent.exp2()) as u64;
+            samples.push(value);
+            match i % 3 {
+                0 => a.increment(value).unwrap(),
+                1 => b.increment(value).unwrap(),
+                _ => c.increment(value).unwrap(),
+            }
+        }
+        samples.sort_unstable();
+
+        let mut sketch = a.to_sketch(200);
+        sketch.merge(&b.to_sketch(200));
+        sketch.merge(&c.to_sketch(200));
+        assert!(sketch.centroids().len() <= 200);
+        assert_eq!(sketch.count(), 60_000);
+
+        // within the coarse bucket error plus a little compression error
+        for &p in &[1.0, 10.0, 50.0, 90.0, 95.0, 99.0, 99.9] {
+            let expected = exact(&samples, p);
+            let value = sketch.percentile(p).unwrap();
+            let error = (value - expected).abs() / expected;
+            assert!(error < 0.03, "p{}: {} vs {} ({})", p, value, expected, error);
+        }
+        assert_eq!(sketch.percentile(0.0).unwrap(), samples[0] as f64);
+        assert_eq!(sketch.percentile(100.0).unwrap(), samples[samples.len() - 1] as f64);
+
+        // merging in any order gives the same count and close percentiles
+        let mut reversed = c.to_sketch(200);
+        reversed.merge(&b.to_sketch(200));
+        reversed.merge(&a.to_sketch(200));
+        let (x, y) = (reversed.percentile(99.0).unwrap(), sketch.percentile(99.0).unwrap());
+        assert!((x - y).abs() / y < 0.01);
+    }
+
+    #[test]
+    fn test_compression() {
+        let mut h = Histogram::new().unwrap();
+        for value in 1..100_001 {
+            h.increment(value).unwrap();
+        }
+        for &n in &[1, 2, 10, 100, 1_000] {
+            let sketch = h.to_sketch(n);
+            assert!(sketch.centroids().len() <= n, "{}", n);
+            assert_eq!(sketch.centroids().iter().map(|c| c.1).sum::<u64>(), 100_000);
+            for pair in sketch.centroids().windows(2) {
+                assert!(pair[0].0 <= pair[1].0);
+            }
+        }
+        assert_eq!(h.to_sketch(0).centroids().len(), 1);
+
+        // the tail centroids are smaller than the middle ones
+        let sketch = h.to_sketch(100);
+        let centroids = sketch.centroids();
+        assert!(centroids[0].1 * 10 < centroids[centroids.len() / 2].1);
+    }
+
+    #[test]
+    fn test_saturated_counts() {
+        let mut h = Histogram::new().unwrap();
+        h.record(10, u64::MAX).unwrap();
+        h.record(11, u64::MAX).unwrap();
+
+        let sketch = h.to_sketch(1);
+        assert_eq!(sketch.centroids().len(), 1);
+        assert_eq!(sketch.centroids()[0].1, u64::MAX);
+        assert!((sketch.centroids()[0].0 - 10.5).abs() < 0.01);
+
+        let mut merged = sketch.clone();
+        merged.merge(&sketch);
+        assert_eq!(merged.count(), u64::MAX);
+        assert_eq!(merged.centroids()[0].1, u64::MAX);
+    }
+
+    #[test]
+    fn test_empty() {
+        let h = Histogram::new().unwrap();
+        let mut sketch = h.to_sketch(10);
+        assert_eq!(sketch.percentile(50.0), Err(HistogramError::Empty));
+        assert_eq!(sketch.percentile(101.0), Err(HistogramError::InvalidPercentile));
+
+        let mut other = Histogram::new().unwrap();
+        other.record(100, 5).unwrap();
+        other.record(0, 5).unwrap_err();
+        sketch.merge(&other.to_sketch(10));
+        assert_eq!(sketch.count(), 5);
+        assert_eq!(sketch.percentile(50.0), Ok(100.0));
+    }
+}
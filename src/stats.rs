@@ -0,0 +1,357 @@
+use super::{Histogram, HistogramCounters};
+
+/// exact aggregates of the samples stored by a Histogram, see
+/// `Histogram::stats()`
+///
+/// These are kept as each sample is recorded, from its value rather than the
+/// nominal value of its bucket, so are exact where the buckets approximate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HistogramStats {
+    count: u64,
+    sum: u128,
+    sum_squares: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Default for HistogramStats {
+    fn default() -> HistogramStats {
+        HistogramStats {
+            count: 0,
+            sum: 0,
+            sum_squares: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+}
+
+impl HistogramStats {
+    /// return the number of samples
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// return the sum of the sample values
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+
+    /// return the sum of the squares of the sample values
+    pub fn sum_squares(&self) -> u128 {
+        self.sum_squares
+    }
+
+    /// return the lowest sample value, None without samples
+    pub fn min(&self) -> Option<u64> {
+        match self.count {
+            0 => None,
+            _ => Some(self.min),
+        }
+    }
+
+    /// return the highest sample value, None without samples
+    pub fn max(&self) -> Option<u64> {
+        match self.count {
+            0 => None,
+            _ => Some(self.max),
+        }
+    }
+
+    // the stats with count samples of the value added, None if a sum would
+    // overflow
+    pub(crate) fn add(&self, value: u64, count: u64) -> Option<HistogramStats> {
+        if count == 0 {
+            return Some(*self);
+        }
+        let value = value as u128;
+        let count_wide = count as u128;
+        Some(HistogramStats {
+            count: self.count.checked_add(count)?,
+            sum: self.sum.checked_add(value * count_wide)?,
+            sum_squares: self.sum_squares
+                             .checked_add((value * value).checked_mul(count_wide)?)?,
+            min: self.min.min(value as u64),
+            max: self.max.max(value as u64),
+        })
+    }
+
+    // the stats of the samples of both, None if a sum would overflow
+    pub(crate) fn combine(&self, other: &HistogramStats) -> Option<HistogramStats> {
+        Some(HistogramStats {
+            count: self.count.checked_add(other.count)?,
+            sum: self.sum.checked_add(other.sum)?,
+            sum_squares: self.sum_squares.checked_add(other.sum_squares)?,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        })
+    }
+
+    // the stats, if they count every sample stored under the counters
+    pub(crate) fn exact<'a>(stats: Option<&'a HistogramStats>,
+                            counters: &HistogramCounters)
+                            -> Option<&'a HistogramStats> {
+        let stored = counters.entries_total.saturating_sub(counters.missed());
+        stats.filter(|stats| stats.count == stored)
+    }
+
+    // the exact mean, if there are samples
+    pub(crate) fn exact_mean(&self) -> Option<u64> {
+        Some(self).filter(|stats| stats.count > 0).map(HistogramStats::mean)
+    }
+
+    // the exact variance, if there are samples
+    pub(crate) fn exact_stdvar(&self) -> Option<u64> {
+        Some(self).filter(|stats| stats.count > 0).map(HistogramStats::stdvar)
+    }
+
+    // the exact minimum, unless samples missed as smaller are ranked
+    pub(crate) fn exact_minimum(&self,
+                                counters: &HistogramCounters,
+                                rank_missed: bool)
+                                -> Option<u64> {
+        if rank_missed && counters.missed_small > 0 {
+            return None;
+        }
+        self.min()
+    }
+
+    // the exact maximum, unless samples missed as larger or unindexed are
+    // ranked
+    pub(crate) fn exact_maximum(&self,
+                                counters: &HistogramCounters,
+                                rank_missed: bool)
+                                -> Option<u64> {
+        if rank_missed && (counters.missed_large > 0 || counters.missed_unknown > 0) {
+            return None;
+        }
+        self.max()
+    }
+
+    // the mean, rounded up as the bucket approximation is
+    fn mean(&self) -> u64 {
+        match self.count {
+            0 => 0,
+            count => self.sum.div_ceil(count as u128) as u64,
+        }
+    }
+
+    // the population variance, rounded up, exactly and without overflow:
+    // with sum = q * n + r, n * variance is sum_squares - sum * q - q * r
+    // less r^2 / n, which is under 1 and so cannot change the rounding
+    fn stdvar(&self) -> u64 {
+        let count = self.count as u128;
+        if count == 0 {
+            return 0;
+        }
+        let (q, r) = (self.sum / count, self.sum % count);
+        let spread = self.sum_squares
+                         .saturating_sub(self.sum.saturating_mul(q))
+                         .saturating_sub(q * r)
+                         .saturating_sub(r * r / count);
+        spread.div_ceil(count).min(u64::MAX as u128) as u64
+    }
+}
+
+impl Histogram {
+    /// return the exact aggregates of the stored samples, if known
+    ///
+    /// These are kept by `record()` and its variants, cleared by `clear()`,
+    /// and combined by `merge()` when every sample of the other histogram is
+    /// stored. Clamped samples count as max_value, as they are stored. They
+    /// are lost, so this is None, once counts are removed or set directly,
+    /// once a sum overflows, or once the counters no longer match, as after
+    /// decoding or `apply_counter_delta()`. `mean()`, `stdvar()`,
+    /// `minimum()` and `maximum()` use them when known.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(1_234_567, 2).unwrap();
+    /// h.record(7, 1).unwrap();
+    ///
+    /// let stats = h.stats().unwrap();
+    ///
+    /// assert_eq!(stats.count(), 3);
+    /// assert_eq!(stats.sum(), 2_469_141);
+    /// assert_eq!(stats.max(), Some(1_234_567));
+    ///
+    /// h.decrement(7).unwrap();
+    ///
+    /// assert_eq!(h.stats(), None);
+    pub fn stats(&self) -> Option<HistogramStats> {
+        self.exact_stats().copied()
+    }
+
+    // the stats, if they still count every stored sample
+    pub(crate) fn exact_stats(&self) -> Option<&HistogramStats> {
+        HistogramStats::exact(self.data.stats.as_ref(), &self.data.counters)
+    }
+
+    // the exact mean, if known
+    pub(crate) fn exact_mean(&self) -> Option<u64> {
+        self.exact_stats().and_then(HistogramStats::exact_mean)
+    }
+
+    // the exact variance, if known
+    pub(crate) fn exact_stdvar(&self) -> Option<u64> {
+        self.exact_stats().and_then(HistogramStats::exact_stdvar)
+    }
+
+    // the exact minimum, if known and no samples were missed as smaller
+    // while the config ranks them
+    pub(crate) fn exact_minimum(&self) -> Option<u64> {
+        self.exact_stats()
+            .and_then(|stats| stats.exact_minimum(&self.data.counters, self.config.rank_missed))
+    }
+
+    // the exact maximum, if known and no samples were missed as larger or
+    // unindexed while the config ranks them
+    pub(crate) fn exact_maximum(&self) -> Option<u64> {
+        self.exact_stats()
+            .and_then(|stats| stats.exact_maximum(&self.data.counters, self.config.rank_missed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Histogram, HistogramConfig, OverflowPolicy};
+    use super::HistogramStats;
+
+    // the exact population mean and variance of the samples
+    fn moments(samples: &[u64]) -> (f64, f64) {
+        let n = samples.len() as f64;
+        let mean = samples.iter().map(|v| *v as f64).sum::<f64>() / n;
+        let var = samples.iter().map(|v| (*v as f64 - mean).powi(2)).sum::<f64>() / n;
+        (mean, var)
+    }
+
+    #[test]
+    fn test_exact() {
+        let mut c = HistogramConfig::new();
+        c.precision(2);
+        let mut h = Histogram::configured(c).unwrap();
+        let samples = [1_234, 5_678, 9_999, 10_001, 123_457, 987_653, 3_333];
+        for value in samples.iter() {
+            h.increment(*value).unwrap();
+        }
+
+        let (mean, var) = moments(&samples);
+        assert_eq!(h.mean().unwrap(), mean.ceil() as u64);
+        assert_eq!(h.stdvar().unwrap(), var.ceil() as u64);
+        assert_eq!(h.stddev().unwrap(), var.ceil().sqrt().ceil() as u64);
+        assert_eq!(h.minimum().unwrap(), 1_234);
+        assert_eq!(h.maximum().unwrap(), 987_653);
+
+        // the coarse buckets give different answers
+        assert!(h.mean_approx().unwrap() != h.mean().unwrap());
+        assert!(h.stdvar_approx().unwrap() != h.stdvar().unwrap());
+        assert_eq!(h.minimum_approx().unwrap(), 1_229);
+        assert_eq!(h.maximum_approx().unwrap(), 985_662);
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut h = Histogram::new().unwrap();
+        assert_eq!(h.stats(), Some(HistogramStats::default()));
+        h.record(100, 3).unwrap();
+        h.record(0, 2).unwrap_err();
+        assert_eq!(h.stats().unwrap().count(), 3);
+
+        // missed samples at either end leave the extremes to the buckets
+        assert_eq!(h.minimum(), h.minimum_approx());
+        assert_eq!(h.maximum().unwrap(), 100);
+        assert_eq!(h.mean().unwrap(), 100);
+
+        // removing counts loses the stats until cleared
+        h.subtract(100, 1).unwrap();
+        assert_eq!(h.stats(), None);
+        assert_eq!(h.mean(), h.mean_approx());
+        h.increment(100).unwrap();
+        assert_eq!(h.stats(), None);
+        h.clear();
+        assert_eq!(h.stats(), Some(HistogramStats::default()));
+
+        h.increment(100).unwrap();
+        h.set_bucket_count(0, 1).unwrap();
+        assert_eq!(h.stats(), None);
+
+        // decoded histograms have no stats
+        h.clear();
+        h.increment(100).unwrap();
+        let decoded = Histogram::from_bytes(&h.to_bytes()).unwrap();
+        assert_eq!(decoded.stats(), None);
+        let mut copy = Histogram::new().unwrap();
+        copy.reset_to(&h).unwrap();
+        assert_eq!(copy.stats(), h.stats());
+        assert_eq!(h.take().stats().unwrap().count(), 1);
+        assert_eq!(h.stats(), Some(HistogramStats::default()));
+    }
+
+    #[test]
+    fn test_clamped() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000).overflow(OverflowPolicy::Clamp);
+        let mut h = Histogram::configured(c).unwrap();
+        h.record(5_000, 1).unwrap();
+        h.record(10, 1).unwrap();
+        let stats = h.stats().unwrap();
+        assert_eq!((stats.sum(), stats.max()), (1_010, Some(1_000)));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = Histogram::new().unwrap();
+        let mut b = Histogram::new().unwrap();
+        a.record(1_001, 2).unwrap();
+        b.record(99_999, 1).unwrap();
+        b.record(3, 1).unwrap();
+
+        let mut merged = a.clone();
+        merged.merge(&b);
+        let stats = merged.stats().unwrap();
+        assert_eq!(stats.count(), 4);
+        assert_eq!(stats.sum(), 2_002 + 99_999 + 3);
+        assert_eq!(stats.sum_squares(), 2 * 1_001 * 1_001 + 99_999 * 99_999 + 9);
+        assert_eq!((stats.min(), stats.max()), (Some(3), Some(99_999)));
+
+        // a different layout keeps the values rather than the bucket values
+        let mut c = HistogramConfig::new();
+        c.precision(1);
+        let mut coarse = Histogram::configured(c).unwrap();
+        coarse.merge(&b);
+        assert_eq!(coarse.stats(), b.stats());
+        assert_eq!(coarse.maximum().unwrap(), 99_999);
+
+        // unless some are not stored
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000);
+        let mut narrow = Histogram::configured(c).unwrap();
+        narrow.merge(&b);
+        assert_eq!(narrow.stats(), None);
+
+        // and a histogram without stats spoils them
+        let decoded = Histogram::from_bytes(&b.to_bytes()).unwrap();
+        merged.merge(&decoded);
+        assert_eq!(merged.stats(), None);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut h = Histogram::new().unwrap();
+        h.record(60_000_000_000, u64::MAX / 2).unwrap();
+        assert_eq!(h.stats(), None);
+        assert_eq!(h.mean(), h.mean_approx());
+
+        // large values keep an exact variance
+        let stats = HistogramStats::default();
+        let stats = stats.add(1 << 62, 4).unwrap().add((1 << 62) + (1 << 32), 4).unwrap();
+        assert_eq!(stats.stdvar(), 1 << 62);
+        let stats = HistogramStats::default().add(0, 1).unwrap().add(u64::MAX, 1).unwrap();
+        assert_eq!(stats.stdvar(), u64::MAX);
+        let stats = HistogramStats::default().add(1, 2).unwrap().add(2, 1).unwrap();
+        assert_eq!(stats.stdvar(), 1);
+    }
+}
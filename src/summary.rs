@@ -0,0 +1,419 @@
+use std::time::Duration;
+
+use super::{mean, percentile_position, stdvar, Histogram, HistogramCounters, HistogramError,
+            HistogramStats, OverflowPolicy, Rank};
+
+/// the common statistics of a Histogram, computed together
+///
+/// Created by `Histogram::summary()` or `Histogram::stats_with_pending()`.
+/// The minimum, maximum, mean and stddev are exact while the exact
+/// aggregates of `Histogram::stats()` are known, as for the `Histogram`
+/// methods, otherwise taken from the buckets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Summary {
+    entries: u64,
+    minimum: u64,
+    maximum: u64,
+    mean: u64,
+    stddev: u64,
+    p50: u64,
+    p90: u64,
+    p99: u64,
+    p999: u64,
+}
+
+impl Summary {
+    /// return the number of entries, including missed samples
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    /// return the smallest value
+    pub fn minimum(&self) -> u64 {
+        self.minimum
+    }
+
+    /// return the largest value
+    pub fn maximum(&self) -> u64 {
+        self.maximum
+    }
+
+    /// return the arithmetic mean
+    pub fn mean(&self) -> u64 {
+        self.mean
+    }
+
+    /// return the standard deviation
+    pub fn stddev(&self) -> u64 {
+        self.stddev
+    }
+
+    /// return the 50th percentile
+    pub fn p50(&self) -> u64 {
+        self.p50
+    }
+
+    /// return the 90th percentile
+    pub fn p90(&self) -> u64 {
+        self.p90
+    }
+
+    /// return the 99th percentile
+    pub fn p99(&self) -> u64 {
+        self.p99
+    }
+
+    /// return the 99.9th percentile
+    pub fn p999(&self) -> u64 {
+        self.p999
+    }
+}
+
+// raw samples bucketed as a sparse layer over a histogram, so statistics can
+// include them without modifying or copying the histogram
+struct Overlay<'a> {
+    histogram: &'a Histogram,
+    counters: HistogramCounters,
+    // (index, count) sorted by index
+    counts: Vec<(usize, u64)>,
+    occupied: Option<(usize, usize)>,
+    // the exact stats of the histogram with the stored samples added
+    stats: Option<HistogramStats>,
+}
+
+impl<'a> Overlay<'a> {
+    // bucket the samples as record() would
+    fn new(histogram: &'a Histogram, pending: &[u64]) -> Overlay<'a> {
+        let mut counters = histogram.data.counters;
+        let mut stats = histogram.exact_stats().copied();
+        let mut indices = Vec::with_capacity(pending.len());
+
+        for &value in pending {
+            counters.entries_total = counters.entries_total.saturating_add(1);
+            if value < histogram.config.min_value {
+                counters.missed_small = counters.missed_small.saturating_add(1);
+                continue;
+            }
+            let value = if value <= histogram.config.max_value {
+                value
+            } else if histogram.config.overflow == OverflowPolicy::Clamp {
+                histogram.config.max_value
+            } else {
+                counters.missed_large = counters.missed_large.saturating_add(1);
+                continue;
+            };
+            match histogram.get_index(value) {
+                Some(index) if index < histogram.data.data.len() => {
+                    indices.push(index);
+                    stats = stats.and_then(|stats| stats.add(value, 1));
+                }
+                _ => counters.missed_unknown = counters.missed_unknown.saturating_add(1),
+            }
+        }
+
+        indices.sort_unstable();
+        let mut counts: Vec<(usize, u64)> = Vec::new();
+        for index in indices {
+            if let Some(last) = counts.last_mut() {
+                if last.0 == index {
+                    last.1 += 1;
+                    continue;
+                }
+            }
+            counts.push((index, 1));
+        }
+
+        let mut occupied = histogram.data.occupied;
+        if let (Some(first), Some(last)) = (counts.first(), counts.last()) {
+            occupied = match occupied {
+                Some((low, high)) => Some((low.min(first.0), high.max(last.0))),
+                None => Some((first.0, last.0)),
+            };
+        }
+
+        Overlay {
+            histogram,
+            counters,
+            counts,
+            occupied,
+            stats,
+        }
+    }
+
+    // the combined count of the bucket at the index
+    fn count(&self, index: usize) -> u64 {
+        let count = self.histogram.data.count(index);
+        match self.counts.binary_search_by_key(&index, |pending| pending.0) {
+            Ok(position) => count.saturating_add(self.counts.get(position).map_or(0, |p| p.1)),
+            Err(_) => count,
+        }
+    }
+
+    fn percentile(&self, percentile: f64) -> Result<u64, HistogramError> {
+        percentile_position(&self.counters.ranked(self.histogram.config.rank_missed),
+                            self.occupied,
+                            |index| self.count(index),
+                            Rank::Percent(percentile))
+            .map(|position| self.histogram.position_value(position))
+    }
+
+    fn summary(&self) -> Result<Summary, HistogramError> {
+        let properties = &self.histogram.properties;
+        let rank_missed = self.histogram.config.rank_missed;
+        let count = |index| self.count(index);
+        let exact = HistogramStats::exact(self.stats.as_ref(), &self.counters);
+
+        let stdvar = stdvar(properties, &self.counters, self.occupied, count)?;
+        let stdvar = exact.and_then(HistogramStats::exact_stdvar).unwrap_or(stdvar);
+        let mean = mean(properties, &self.counters, self.occupied, count)?;
+        let minimum = exact.and_then(|stats| stats.exact_minimum(&self.counters, rank_missed));
+        let minimum = match minimum {
+            Some(minimum) => minimum,
+            None => self.percentile(0.0)?,
+        };
+        let maximum = exact.and_then(|stats| stats.exact_maximum(&self.counters, rank_missed));
+        let maximum = match maximum {
+            Some(maximum) => maximum,
+            None => self.percentile(100.0)?,
+        };
+
+        Ok(Summary {
+            entries: self.counters.entries_total,
+            minimum,
+            maximum,
+            mean: exact.and_then(HistogramStats::exact_mean).unwrap_or(mean),
+            stddev: (stdvar as f64).sqrt().ceil() as u64,
+            p50: self.percentile(50.0)?,
+            p90: self.percentile(90.0)?,
+            p99: self.percentile(99.0)?,
+            p999: self.percentile(99.9)?,
+        })
+    }
+}
+
+impl Histogram {
+    /// return the common statistics together
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// for value in 1..101 {
+    ///     h.increment(value).unwrap();
+    /// }
+    ///
+    /// let summary = h.summary().unwrap();
+    ///
+    /// assert_eq!(summary.entries(), 100);
+    /// assert_eq!(summary.p50(), h.percentile(50.0).unwrap());
+    /// assert_eq!(summary.maximum(), 100);
+    pub fn summary(&self) -> Result<Summary, HistogramError> {
+        Overlay::new(self, &[]).summary()
+    }
+
+    /// return the common statistics, reusing the previous result if the
+    /// histogram is unchanged since and it is no older than max_age
+    ///
+    /// The age is measured by the clock of the histogram. This suits many
+    /// readers polling a large histogram, as only the first after a change
+    /// pays for the computation.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// # use std::time::Duration;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.increment(100).unwrap();
+    /// assert_eq!(h.cached_summary(Duration::from_secs(1)).unwrap().entries(), 1);
+    ///
+    /// h.increment(200).unwrap();
+    /// assert_eq!(h.cached_summary(Duration::from_secs(1)).unwrap().entries(), 2);
+    pub fn cached_summary(&mut self, max_age: Duration) -> Result<&Summary, HistogramError> {
+        let now = self.clock.now();
+        let fresh = match self.data.summary {
+            Some(ref cached) => now.saturating_duration_since(cached.1) <= max_age,
+            None => false,
+        };
+        let cached = match self.data.summary.take() {
+            Some(cached) if fresh => cached,
+            _ => Box::new((self.summary()?, now)),
+        };
+        Ok(&self.data.summary.get_or_insert(cached).0)
+    }
+
+    /// return the common statistics as if the pending values had also been
+    /// recorded, without modifying the histogram
+    ///
+    /// The pending values are bucketed into a small temporary layer, so this
+    /// suits a short buffer of samples awaiting a flush.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    ///
+    /// let summary = h.stats_with_pending(&[20, 30]).unwrap();
+    ///
+    /// assert_eq!(summary.entries(), 5);
+    /// assert_eq!(summary.maximum(), 30);
+    /// assert_eq!(h.entries(), 3);
+    pub fn stats_with_pending(&self, pending: &[u64]) -> Result<Summary, HistogramError> {
+        Overlay::new(self, pending).summary()
+    }
+
+    /// return the value for the given percentile as if the pending values had
+    /// also been recorded, without modifying the histogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::Histogram;
+    /// let mut h = Histogram::new().unwrap();
+    ///
+    /// h.record(10, 3).unwrap();
+    ///
+    /// assert_eq!(h.percentile_with_pending(&[20, 20, 20, 20], 50.0).unwrap(), 20);
+    /// assert_eq!(h.percentile(50.0).unwrap(), 10);
+    pub fn percentile_with_pending(&self,
+                                   pending: &[u64],
+                                   percentile: f64)
+                                   -> Result<u64, HistogramError> {
+        Overlay::new(self, pending).percentile(percentile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::super::{Clock, Histogram, HistogramConfig, HistogramError, ManualClock,
+                       OverflowPolicy};
+    use super::super::test_rng::Rng;
+
+    #[test]
+    fn test_matches_recording() {
+        let mut rng = Rng(0x1234_5678_9abc_def1);
+        for &overflow in &[OverflowPolicy::Reject, OverflowPolicy::Clamp] {
+            let mut c = HistogramConfig::new();
+            c.max_value(1_000_000).precision(2).overflow(overflow);
+
+            for &flushed in &[0, 1, 500] {
+                let mut h = Histogram::configured(c).unwrap();
+                for _ in 0..flushed {
+                    h.increment(1 + rng.next() % 100_000).unwrap();
+                }
+                let pending: Vec<u64> = (0..64).map(|_| rng.next() % 2_000_000).collect();
+
+                let mut recorded = h.clone();
+                for &value in &pending {
+                    let _ = recorded.increment(value);
+                }
+
+                assert_eq!(h.stats_with_pending(&pending), recorded.summary());
+                for p in 0..201 {
+                    let p = p as f64 / 2.0;
+                    assert_eq!(h.percentile_with_pending(&pending, p),
+                               recorded.percentile(p),
+                               "percentile: {}",
+                               p);
+                }
+                assert_eq!(h.entries(), flushed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let mut h = Histogram::new().unwrap();
+        assert_eq!(h.summary(), Err(HistogramError::Empty));
+        assert_eq!(h.stats_with_pending(&[0]),
+                   Err(HistogramError::OnlyMissedSamples {
+                       small: 1,
+                       large: 0,
+                       unknown: 0,
+                   }));
+
+        for value in 1..1001 {
+            h.increment(value).unwrap();
+        }
+        let summary = h.summary().unwrap();
+        assert_eq!(summary.entries(), 1_000);
+        assert_eq!(summary.minimum(), h.minimum().unwrap());
+        assert_eq!(summary.maximum(), h.maximum().unwrap());
+        assert_eq!(summary.mean(), h.mean().unwrap());
+        assert_eq!(summary.stddev(), h.stddev().unwrap());
+        assert_eq!(summary.p50(), h.percentile(50.0).unwrap());
+        assert_eq!(summary.p90(), h.percentile(90.0).unwrap());
+        assert_eq!(summary.p99(), h.percentile(99.0).unwrap());
+        assert_eq!(summary.p999(), h.percentile(99.9).unwrap());
+        assert_eq!(h.stats_with_pending(&[]), Ok(summary));
+
+        // the extremes and mean are exact where the buckets are coarse, and
+        // from the buckets once the exact stats are lost
+        let mut c = HistogramConfig::new();
+        c.precision(1);
+        let mut h = Histogram::configured(c).unwrap();
+        h.increment(1_000).unwrap();
+        h.increment(2_000).unwrap();
+        let summary = h.summary().unwrap();
+        assert_eq!((summary.minimum(), summary.maximum(), summary.mean()), (1_000, 2_000, 1_500));
+        let summary = h.stats_with_pending(&[3_000]).unwrap();
+        assert_eq!((summary.maximum(), summary.mean()), (3_000, 2_000));
+        h.set_bucket_count(h.get_index(2_000).unwrap(), 3).unwrap();
+        let summary = h.summary().unwrap();
+        assert_eq!(summary.minimum(), h.minimum_approx().unwrap());
+        assert_eq!(summary.maximum(), h.maximum_approx().unwrap());
+        assert_eq!(summary.mean(), h.mean_approx().unwrap());
+    }
+
+    #[test]
+    fn test_cached_summary() {
+        let clock = Arc::new(ManualClock::new());
+        let mut h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+        let max_age = Duration::from_secs(5);
+        let computed = |h: &Histogram| h.data.summary.as_ref().map(|cached| cached.1);
+
+        assert_eq!(h.cached_summary(max_age), Err(HistogramError::Empty));
+        assert_eq!(computed(&h), None);
+
+        h.increment(100).unwrap();
+        let start = clock.now();
+        assert_eq!(h.cached_summary(max_age).unwrap().maximum(), 100);
+        assert_eq!(computed(&h), Some(start));
+
+        // reused while unchanged and young enough
+        clock.advance(max_age);
+        let summary = h.summary();
+        assert_eq!(h.cached_summary(max_age).copied(), summary);
+        assert_eq!(computed(&h), Some(start));
+
+        // recomputed once too old
+        clock.advance(Duration::from_millis(1));
+        h.cached_summary(max_age).unwrap();
+        assert_eq!(computed(&h), Some(clock.now()));
+
+        // and after every kind of change
+        let changes: Vec<fn(&mut Histogram)> =
+            vec![|h| h.increment(200).unwrap(),
+                 |h| assert!(h.increment(0).is_err()),
+                 |h| h.set_bucket_count(0, 3).unwrap(),
+                 |h| h.apply_counter_delta(&h.counters()),
+                 |h| h.reset_to(&Histogram::new().unwrap()).unwrap(),
+                 |h| h.clear()];
+        for change in changes {
+            h.clear();
+            h.increment(100).unwrap();
+            h.cached_summary(max_age).unwrap();
+            clock.advance(Duration::from_secs(1));
+
+            change(&mut h);
+            assert_eq!(computed(&h), None);
+            let summary = h.summary();
+            assert_eq!(h.cached_summary(max_age).copied(), summary);
+        }
+    }
+}
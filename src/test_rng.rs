@@ -0,0 +1,17 @@
+// deterministic xorshift so the tests are reproducible
+pub(crate) struct Rng(pub(crate) u64);
+
+impl Rng {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // a pareto distributed value with a minimum of 1000
+    pub(crate) fn pareto(&mut self) -> u64 {
+        let uniform = (self.next() >> 11) as f64 / (1_u64 << 53) as f64;
+        (1_000.0 / (1.0 - uniform).powf(1.0 / 1.2)).min(1e15) as u64
+    }
+}
@@ -0,0 +1,215 @@
+//! assertions for tests of code recording into a Histogram
+//!
+//! A percentile is reported as the value of its bucket, so asserting it
+//! equals an exact value fails whenever that bucket holds more than one
+//! value. These helpers instead pass when the expected value lies within the
+//! bucket holding the percentile, and otherwise fail with the bucket bounds
+//! and the counts of the buckets around the percentile.
+
+use std::fmt::Write;
+
+use super::{Histogram, Rank};
+
+// the non-empty buckets shown either side of the percentile's bucket
+const CONTEXT: usize = 3;
+
+/// assert the value for a percentile of a Histogram is equivalent to the
+/// expected value, with an optional message as for `assert_eq!`
+///
+/// # Example
+/// ```
+/// # use histogram::{assert_percentile_eq, Histogram, HistogramConfig};
+/// let mut c = HistogramConfig::new();
+/// c.max_value(10_000).precision(2);
+///
+/// let mut h = Histogram::configured(c).unwrap();
+///
+/// h.record(1_504, 100).unwrap();
+///
+/// assert_eq!(h.percentile(99.0).unwrap(), 1_496);
+/// assert_percentile_eq!(h, 99.0, 1_500);
+/// assert_percentile_eq!(h, 99.0, 1_500, "after {} samples", 100);
+#[macro_export]
+macro_rules! assert_percentile_eq {
+    ($histogram:expr, $percentile:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_percentile_eq(&$histogram, $percentile, $expected)
+    };
+    ($histogram:expr, $percentile:expr, $expected:expr, $($message:tt)+) => {
+        if let Err(failure) = $crate::testing::check_percentile(&$histogram,
+                                                                $percentile,
+                                                                $expected) {
+            panic!("{}\n{}", format_args!($($message)+), failure);
+        }
+    };
+}
+
+/// panic unless the value for the percentile lies in the same bucket as the
+/// expected value
+///
+/// # Example
+/// ```
+/// # use histogram::{testing, Histogram};
+/// let mut h = Histogram::new().unwrap();
+///
+/// for value in 1..1001 {
+///     h.increment(value).unwrap();
+/// }
+///
+/// testing::assert_percentile_eq(&h, 50.0, 500);
+#[track_caller]
+pub fn assert_percentile_eq(histogram: &Histogram, percentile: f64, expected: u64) {
+    if let Err(failure) = check_percentile(histogram, percentile, expected) {
+        panic!("{}", failure);
+    }
+}
+
+/// return Ok if the value for the percentile lies in the same bucket as the
+/// expected value, or else the failure message of `assert_percentile_eq()`
+///
+/// # Example
+/// ```
+/// # use histogram::{testing, Histogram};
+/// let mut h = Histogram::new().unwrap();
+///
+/// h.increment(10).unwrap();
+///
+/// assert!(testing::check_percentile(&h, 50.0, 10).is_ok());
+/// assert!(testing::check_percentile(&h, 50.0, 11).is_err());
+pub fn check_percentile(histogram: &Histogram,
+                        percentile: f64,
+                        expected: u64)
+                        -> Result<(), String> {
+    let position = match histogram.percentile_position(Rank::Percent(percentile)) {
+        Ok(position) => position,
+        Err(e) => {
+            return Err(format!("percentile {} failed: {} (expected {})", percentile, e, expected));
+        }
+    };
+    let index = position.index;
+    let (low, high) = histogram.index_bounds(index);
+    if low <= expected && expected <= high {
+        return Ok(());
+    }
+
+    let mut failure = format!("percentile {} is {}, not equivalent to {}\n",
+                              percentile,
+                              histogram.position_value(position),
+                              expected);
+    let _ = writeln!(failure, "  percentile bucket: {}..={}", low, high);
+    let _ = match histogram.bucket_bounds(expected) {
+        Some((low, high)) => writeln!(failure, "    expected bucket: {}..={}", low, high),
+        None => {
+            writeln!(failure,
+                     "    expected bucket: none, outside 1..={}",
+                     histogram.config.max_value)
+        }
+    };
+    let counters = &histogram.data.counters;
+    let _ = writeln!(failure,
+                     "            entries: {} (missed small: {} large: {} unknown: {})",
+                     counters.entries_total,
+                     counters.missed_small,
+                     counters.missed_large,
+                     counters.missed_unknown);
+    write_context(&mut failure, histogram, index);
+    Err(failure)
+}
+
+// write a table of the buckets around the index with their counts and the
+// cumulative count of samples up to and including each
+fn write_context(failure: &mut String, histogram: &Histogram, index: usize) {
+    let data = &histogram.data;
+    let buckets = histogram.properties.buckets_total as usize;
+    let below = (0..index).rev().filter(|&i| data.count(i) > 0).take(CONTEXT).last();
+    let above = ((index + 1)..buckets).filter(|&i| data.count(i) > 0).take(CONTEXT).last();
+
+    let _ = writeln!(failure,
+                     "  {:>12} {:>12} {:>12} {:>12}",
+                     "low",
+                     "high",
+                     "count",
+                     "cumulative");
+    let mut cumulative = histogram.data.counters.missed_small;
+    for (i, count) in histogram.counts().enumerate().take(above.unwrap_or(index) + 1) {
+        cumulative = cumulative.saturating_add(count);
+        if i < below.unwrap_or(index) || (count == 0 && i != index) {
+            continue;
+        }
+        let (low, high) = histogram.index_bounds(i);
+        let _ = writeln!(failure,
+                         "{} {:>12} {:>12} {:>12} {:>12}",
+                         if i == index { ">" } else { " " },
+                         low,
+                         high,
+                         count,
+                         cumulative);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_percentile;
+    use super::super::{Histogram, HistogramConfig};
+
+    const FAILURE: &str = "percentile 50 is 1496, not equivalent to 1495
+  percentile bucket: 1496..=1505
+    expected bucket: 1485..=1495
+            entries: 100 (missed small: 0 large: 0 unknown: 0)
+           low         high        count   cumulative
+          1485         1495           30           30
+>         1496         1505           50           80
+          1506         1515           20          100
+";
+
+    fn quantized() -> Histogram {
+        let mut c = HistogramConfig::new();
+        c.max_value(10_000).precision(2);
+
+        let mut h = Histogram::configured(c).unwrap();
+        for value in 1_490..1_510 {
+            h.record(value, 5).unwrap();
+        }
+        h
+    }
+
+    #[test]
+    fn test_passing() {
+        let h = quantized();
+        assert_eq!(h.percentile(50.0).unwrap(), 1_496);
+
+        for expected in 1_496..1_506 {
+            assert_percentile_eq!(h, 50.0, expected);
+            assert_percentile_eq!(h, 50.0, expected, "expected {}", expected);
+        }
+        assert_eq!(check_percentile(&h, 50.0, 1_495), Err(FAILURE.to_owned()));
+        assert!(check_percentile(&h, 50.0, 1_506).is_err());
+    }
+
+    #[test]
+    fn test_context() {
+        let mut h = Histogram::new().unwrap();
+        for value in 1..101 {
+            h.increment(value).unwrap();
+        }
+
+        let failure = check_percentile(&h, 50.0, 60).unwrap_err();
+        let rows: Vec<&str> = failure.lines().skip(5).collect();
+        assert_eq!(rows.len(), 2 * super::CONTEXT + 1);
+        assert!(rows[0].starts_with("            47 "));
+        assert!(rows[3].starts_with(">           50 "));
+        assert!(rows[6].ends_with("           53"));
+    }
+
+    #[test]
+    #[should_panic(expected = "not equivalent to 2000")]
+    fn test_failing() {
+        assert_percentile_eq!(quantized(), 50.0, 2_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "while testing\npercentile 50 failed: no data (expected 1)")]
+    fn test_failing_message() {
+        let h = Histogram::new().unwrap();
+        assert_percentile_eq!(h, 50.0, 1, "while {}", "testing");
+    }
+}
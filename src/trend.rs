@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use super::{Histogram, HistogramError};
+
+/// the value of a percentile over the most recent intervals
+///
+/// Each interval histogram passed to `push()` or `rotate()` adds one point,
+/// the configured percentile timestamped by the histogram clock. Only the
+/// latest `capacity` points are kept, the oldest dropped first, which suits
+/// charting a percentile such as p99 over the last N intervals.
+///
+/// # Example
+/// ```
+/// # use histogram::{Histogram, PercentileTrend};
+/// let mut trend = PercentileTrend::new(99.0, 60);
+/// let mut h = Histogram::new().unwrap();
+///
+/// for interval in 1..4 {
+///     h.record(interval * 100, 10).unwrap();
+///     trend.rotate(&mut h).unwrap();
+/// }
+///
+/// let values: Vec<u64> = trend.points().iter().map(|&(_, value)| value).collect();
+/// assert_eq!(values, vec![100, 200, 300]);
+/// assert_eq!(trend.current(), Some(300));
+/// assert_eq!(trend.max(), Some(300));
+/// assert_eq!(trend.mean(), Some(200.0));
+#[derive(Clone, Debug)]
+pub struct PercentileTrend {
+    percentile: f64,
+    capacity: usize,
+    points: VecDeque<(Instant, u64)>,
+}
+
+impl PercentileTrend {
+    /// create a PercentileTrend keeping the given percentile of the latest
+    /// `capacity` intervals, a capacity of 0 being treated as 1
+    pub fn new(percentile: f64, capacity: usize) -> PercentileTrend {
+        let capacity = capacity.max(1);
+        PercentileTrend {
+            percentile,
+            capacity,
+            points: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// add the percentile of an interval histogram, returning its value
+    ///
+    /// An interval without a value for the percentile, such as an empty one,
+    /// adds no point and returns the error of `Histogram::percentile()`.
+    pub fn push(&mut self, interval: &Histogram) -> Result<u64, HistogramError> {
+        let value = interval.percentile(self.percentile)?;
+        if self.points.len() == self.capacity {
+            self.points.pop_front();
+        }
+        self.points.push_back((interval.clock.now(), value));
+        Ok(value)
+    }
+
+    /// add the percentile of the histogram as `push()`, then clear it for
+    /// the next interval
+    pub fn rotate(&mut self, histogram: &mut Histogram) -> Result<u64, HistogramError> {
+        let pushed = self.push(histogram);
+        histogram.clear();
+        pushed
+    }
+
+    /// return the points of the window as (timestamp, value), oldest first
+    pub fn points(&self) -> Vec<(Instant, u64)> {
+        self.points.iter().copied().collect()
+    }
+
+    /// return the value of the latest point
+    pub fn current(&self) -> Option<u64> {
+        self.points.back().map(|&(_, value)| value)
+    }
+
+    /// return the mean value of the points in the window
+    pub fn mean(&self) -> Option<f64> {
+        if self.points.is_empty() {
+            return None;
+        }
+        let total: f64 = self.points.iter().map(|&(_, value)| value as f64).sum();
+        Some(total / self.points.len() as f64)
+    }
+
+    /// return the largest value of the points in the window
+    pub fn max(&self) -> Option<u64> {
+        self.points.iter().map(|&(_, value)| value).max()
+    }
+
+    /// return the percentile evaluated at each interval
+    pub fn percentile(&self) -> f64 {
+        self.percentile
+    }
+
+    /// return the most points kept
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::PercentileTrend;
+    use super::super::{Clock, Histogram, HistogramConfig, HistogramError, ManualClock};
+
+    #[test]
+    fn test_rotation() {
+        let clock = Arc::new(ManualClock::new());
+        let start = clock.now();
+        let mut h = Histogram::with_clock(HistogramConfig::new(), clock.clone()).unwrap();
+        let mut trend = PercentileTrend::new(90.0, 3);
+
+        for interval in 1..6 {
+            clock.advance(Duration::from_secs(10));
+            h.record(interval, 95).unwrap();
+            h.record(interval * 1_000, 5).unwrap();
+            assert_eq!(trend.rotate(&mut h), Ok(interval));
+            assert_eq!(h.entries(), 0);
+        }
+
+        let seconds: Vec<(u64, u64)> = trend.points()
+                                            .iter()
+                                            .map(|&(at, value)| ((at - start).as_secs(), value))
+                                            .collect();
+        assert_eq!(seconds, vec![(30, 3), (40, 4), (50, 5)]);
+        assert_eq!(trend.current(), Some(5));
+        assert_eq!(trend.max(), Some(5));
+        assert_eq!(trend.mean(), Some(4.0));
+
+        // an empty interval adds no point, but is still cleared
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(trend.rotate(&mut h), Err(HistogramError::Empty));
+        assert_eq!(trend.points().len(), 3);
+
+        // a spike is the current value and the max, until it leaves the window
+        h.record(1, 1).unwrap();
+        h.record(900, 99).unwrap();
+        assert_eq!(trend.push(&h), Ok(900));
+        assert_eq!(trend.max(), Some(900));
+        assert_eq!(trend.mean(), Some(303.0));
+        assert_eq!(h.entries(), 100);
+        for _ in 0..3 {
+            h.clear();
+            h.increment(7).unwrap();
+            trend.rotate(&mut h).unwrap();
+        }
+        assert_eq!(trend.points().iter().map(|&(_, value)| value).collect::<Vec<_>>(),
+                   vec![7, 7, 7]);
+        assert_eq!(trend.max(), Some(7));
+    }
+
+    #[test]
+    fn test_empty() {
+        let trend = PercentileTrend::new(99.0, 0);
+        assert_eq!(trend.capacity(), 1);
+        assert_eq!(trend.points(), vec![]);
+        assert_eq!((trend.current(), trend.mean(), trend.max()), (None, None, None));
+    }
+}
@@ -0,0 +1,356 @@
+use super::{HistogramConfig, HistogramError, HistogramProperties};
+
+/// a histogram whose buckets accumulate f64 weights instead of counts
+///
+/// This is useful when samples carry non-integer weights, as with importance
+/// sampling, where rounding each weight to an integer count would bias the
+/// resulting percentiles. Percentiles are computed as a fraction of the total
+/// stored weight.
+#[derive(Clone)]
+pub struct WeightedHistogram {
+    config: HistogramConfig,
+    properties: HistogramProperties,
+    data: Vec<f64>,
+    weight_total: f64,
+    missed_small: f64,
+    missed_large: f64,
+}
+
+impl WeightedHistogram {
+    /// create a new WeightedHistogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::WeightedHistogram;
+    ///
+    /// let mut h = WeightedHistogram::new().unwrap();
+    pub fn new() -> Result<WeightedHistogram, HistogramError> {
+        let config = HistogramConfig::new();
+        WeightedHistogram::configured(config)
+    }
+
+    /// create a new WeightedHistogram, failing as `Histogram::configured()`
+    /// for an invalid config
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::{HistogramConfig, WeightedHistogram};
+    ///
+    /// let mut c = HistogramConfig::new();
+    /// c.max_value(1_000_000);
+    ///
+    /// let mut h = WeightedHistogram::configured(c).unwrap();
+    pub fn configured(config: HistogramConfig) -> Result<WeightedHistogram, HistogramError> {
+        let properties = HistogramProperties::new(&config)?;
+
+        Ok(WeightedHistogram {
+            config,
+            properties,
+            data: vec![0.0; properties.buckets_total as usize],
+            weight_total: 0.0,
+            missed_small: 0.0,
+            missed_large: 0.0,
+        })
+    }
+
+    /// clear the histogram data
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::WeightedHistogram;
+    ///
+    /// let mut h = WeightedHistogram::new().unwrap();
+    ///
+    /// h.record_weighted(1, 0.5).unwrap();
+    /// h.clear();
+    /// assert_eq!(h.weight(), 0.0);
+    pub fn clear(&mut self) {
+        for weight in &mut self.data {
+            *weight = 0.0;
+        }
+        self.weight_total = 0.0;
+        self.missed_small = 0.0;
+        self.missed_large = 0.0;
+    }
+
+    /// add weight for a value
+    ///
+    /// Weights which are NaN, infinite, or negative are rejected.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::WeightedHistogram;
+    ///
+    /// let mut h = WeightedHistogram::new().unwrap();
+    ///
+    /// h.record_weighted(10, 0.25).unwrap();
+    /// h.record_weighted(10, 0.5).unwrap();
+    /// assert_eq!(h.get(10).unwrap(), 0.75);
+    ///
+    /// assert!(h.record_weighted(10, -1.0).is_err());
+    pub fn record_weighted(&mut self, value: u64, weight: f64) -> Result<(), HistogramError> {
+        if !weight.is_finite() || weight < 0.0 {
+            return Err(HistogramError::InvalidWeight);
+        }
+
+        if value < self.config.min_value {
+            self.missed_small += weight;
+            Err(HistogramError::TooSmall)
+        } else if value > self.config.max_value {
+            self.missed_large += weight;
+            Err(HistogramError::TooLarge)
+        } else {
+            match self.properties.get_index(value).and_then(|index| self.data.get_mut(index)) {
+                Some(bucket) => {
+                    *bucket += weight;
+                    self.weight_total += weight;
+                    Ok(())
+                }
+                None => Err(HistogramError::Unknown),
+            }
+        }
+    }
+
+    /// get the weight stored for a value
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::WeightedHistogram;
+    ///
+    /// let mut h = WeightedHistogram::new().unwrap();
+    ///
+    /// assert_eq!(h.get(1).unwrap(), 0.0);
+    pub fn get(&self, value: u64) -> Option<f64> {
+        if value > self.config.max_value {
+            return None;
+        }
+        self.properties.get_index(value).and_then(|index| self.data.get(index).copied())
+    }
+
+    /// return the total weight stored in the buckets
+    pub fn weight(&self) -> f64 {
+        self.weight_total
+    }
+
+    /// return the weight rejected as too small
+    pub fn missed_small(&self) -> f64 {
+        self.missed_small
+    }
+
+    /// return the weight rejected as too large
+    pub fn missed_large(&self) -> f64 {
+        self.missed_large
+    }
+
+    /// return the value for the given percentile of the stored weight
+    ///
+    /// The result is the lowest bucket whose cumulative weight reaches the
+    /// requested fraction of the total weight.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::WeightedHistogram;
+    /// let mut h = WeightedHistogram::new().unwrap();
+    ///
+    /// h.record_weighted(1, 3.0).unwrap();
+    /// h.record_weighted(100, 1.0).unwrap();
+    ///
+    /// assert_eq!(h.percentile(50.0).unwrap(), 1);
+    /// assert_eq!(h.percentile(75.0).unwrap(), 1);
+    /// assert_eq!(h.percentile(90.0).unwrap(), 100);
+    pub fn percentile(&self, percentile: f64) -> Result<u64, HistogramError> {
+        if self.weight_total <= 0.0 {
+            return Err(HistogramError::Empty);
+        }
+
+        if !(0.0..=100.0).contains(&percentile) {
+            return Err(HistogramError::InvalidPercentile);
+        }
+
+        let need = self.weight_total * percentile / 100.0;
+
+        let mut have = 0.0_f64;
+        let mut last = None;
+        for (index, weight) in self.data.iter().enumerate() {
+            if *weight > 0.0 {
+                have += *weight;
+                last = Some(index);
+                if have >= need {
+                    return Ok(self.properties.index_value(index));
+                }
+            }
+        }
+
+        // floating point drift may leave the total just short of need
+        match last {
+            Some(index) => Ok(self.properties.index_value(index)),
+            None => Err(HistogramError::Empty),
+        }
+    }
+
+    /// weighted arithmetic mean approximation across the histogram
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::WeightedHistogram;
+    /// let mut h = WeightedHistogram::new().unwrap();
+    ///
+    /// h.record_weighted(10, 1.0).unwrap();
+    /// h.record_weighted(20, 3.0).unwrap();
+    ///
+    /// assert_eq!(h.mean().unwrap(), 17.5);
+    pub fn mean(&self) -> Result<f64, HistogramError> {
+        if self.weight_total <= 0.0 {
+            return Err(HistogramError::Empty);
+        }
+
+        let mut sum = 0.0_f64;
+        for (index, weight) in self.data.iter().enumerate() {
+            if *weight > 0.0 {
+                sum += self.properties.index_value(index) as f64 * *weight;
+            }
+        }
+        Ok(sum / self.weight_total)
+    }
+
+    /// merge another WeightedHistogram into this one
+    ///
+    /// Both histograms must share the same config.
+    ///
+    /// # Example
+    /// ```
+    /// # use histogram::WeightedHistogram;
+    ///
+    /// let mut a = WeightedHistogram::new().unwrap();
+    /// let mut b = WeightedHistogram::new().unwrap();
+    ///
+    /// a.record_weighted(1, 0.5).unwrap();
+    /// b.record_weighted(1, 0.25).unwrap();
+    ///
+    /// a.merge(&b).unwrap();
+    /// assert_eq!(a.get(1).unwrap(), 0.75);
+    pub fn merge(&mut self, other: &WeightedHistogram) -> Result<(), HistogramError> {
+        if !self.config.matches(&other.config) {
+            return Err(HistogramError::IncompatibleConfig);
+        }
+
+        for (weight, add) in self.data.iter_mut().zip(other.data.iter()) {
+            *weight += *add;
+        }
+        self.weight_total += other.weight_total;
+        self.missed_small += other.missed_small;
+        self.missed_large += other.missed_large;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeightedHistogram;
+    use super::super::HistogramConfig;
+    use super::super::test_rng::Rng;
+
+    // lowest value whose cumulative weight reaches the percentile
+    fn brute_force(pairs: &[(u64, f64)], percentile: f64) -> u64 {
+        let mut sorted = pairs.to_vec();
+        sorted.sort_by_key(|pair| pair.0);
+
+        let total: f64 = sorted.iter().map(|pair| pair.1).sum();
+        let need = total * percentile / 100.0;
+
+        let mut have = 0.0;
+        for &(value, weight) in &sorted {
+            if weight > 0.0 {
+                have += weight;
+                if have >= need {
+                    return value;
+                }
+            }
+        }
+        sorted.last().unwrap().0
+    }
+
+    #[test]
+    fn test_invalid_weights() {
+        let mut h = WeightedHistogram::new().unwrap();
+
+        assert!(h.record_weighted(1, f64::NAN).is_err());
+        assert!(h.record_weighted(1, f64::INFINITY).is_err());
+        assert!(h.record_weighted(1, -0.5).is_err());
+        assert_eq!(h.weight(), 0.0);
+        assert!(h.percentile(50.0).is_err());
+    }
+
+    #[test]
+    fn test_missed() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100);
+        let mut h = WeightedHistogram::configured(c).unwrap();
+
+        assert!(h.record_weighted(0, 1.5).is_err());
+        assert!(h.record_weighted(101, 2.5).is_err());
+        assert_eq!(h.missed_small(), 1.5);
+        assert_eq!(h.missed_large(), 2.5);
+        assert_eq!(h.weight(), 0.0);
+    }
+
+    #[test]
+    fn test_percentile_brute_force() {
+        let mut c = HistogramConfig::new();
+        c.max_value(100_000).precision(2);
+
+        for seed in 1..6 {
+            let mut h = WeightedHistogram::configured(c).unwrap();
+            let mut rng = Rng(seed);
+            let mut pairs = Vec::new();
+
+            for _ in 0..500 {
+                let value = 1 + rng.next() % 100_000;
+                // multiples of 1/8 keep the sums exact
+                let weight = (rng.next() % 64) as f64 / 8.0;
+                h.record_weighted(value, weight).unwrap();
+                pairs.push((value, weight));
+            }
+
+            for p in 0..1001 {
+                let p = p as f64 / 10.0;
+                let expected = brute_force(&pairs, p);
+                let index = h.properties.get_index(expected).unwrap();
+                assert_eq!(h.percentile(p).unwrap(),
+                           h.properties.index_value(index),
+                           "percentile: {}",
+                           p);
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = WeightedHistogram::new().unwrap();
+        let mut b = WeightedHistogram::new().unwrap();
+        let mut both = WeightedHistogram::new().unwrap();
+
+        for value in 1..100 {
+            let weight = value as f64 / 4.0;
+            if value % 2 == 0 {
+                a.record_weighted(value, weight).unwrap();
+            } else {
+                b.record_weighted(value, weight).unwrap();
+            }
+            both.record_weighted(value, weight).unwrap();
+        }
+
+        a.merge(&b).unwrap();
+        assert_eq!(a.weight(), both.weight());
+        for p in 0..101 {
+            let p = p as f64;
+            assert_eq!(a.percentile(p), both.percentile(p));
+        }
+
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let other = WeightedHistogram::configured(c).unwrap();
+        assert!(a.merge(&other).is_err());
+    }
+}
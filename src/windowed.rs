@@ -0,0 +1,213 @@
+use std::mem;
+
+use super::{Histogram, HistogramConfig, HistogramError};
+
+/// configures a WindowedHistogram
+///
+/// # Example
+/// ```
+/// # use histogram::{HistogramConfig, WindowedBuilder};
+/// let mut c = HistogramConfig::new();
+/// c.max_value(1_000_000);
+///
+/// let w = WindowedBuilder::new().slots(60).config(c).build().unwrap();
+///
+/// assert_eq!(w.slots(), 60);
+#[derive(Clone, Copy, Debug)]
+pub struct WindowedBuilder {
+    slots: usize,
+    config: HistogramConfig,
+}
+
+impl Default for WindowedBuilder {
+    fn default() -> WindowedBuilder {
+        WindowedBuilder {
+            slots: 60,
+            config: HistogramConfig::new(),
+        }
+    }
+}
+
+impl WindowedBuilder {
+    /// create a WindowedBuilder of 60 slots of the default config
+    pub fn new() -> WindowedBuilder {
+        Default::default()
+    }
+
+    /// set the number of slots, the ticks a sample stays in the window, 0
+    /// being treated as 1
+    pub fn slots(&mut self, slots: usize) -> &mut Self {
+        self.slots = slots.max(1);
+        self
+    }
+
+    /// set the config shared by every slot
+    pub fn config(&mut self, config: HistogramConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    /// create the WindowedHistogram, failing as `Histogram::configured()`
+    /// for an invalid config
+    pub fn build(&self) -> Result<WindowedHistogram, HistogramError> {
+        let current = Histogram::configured(self.config)?;
+        let previous = (1..self.slots).map(|_| current.clone_empty()).collect();
+        Ok(WindowedHistogram { current,
+                               previous,
+                               oldest: 0 })
+    }
+}
+
+/// a ring of histogram slots sharing one config, covering a sliding window
+/// of the last N ticks
+///
+/// Samples are recorded into the current slot. Each `tick()`, as once a
+/// second, moves to the next slot and clears it, so a sample ages out of the
+/// window N ticks after it was recorded, and `merged()` answers for the
+/// samples of the last N - 1 ticks and the current one. A slot is cleared in
+/// place, reusing its buckets.
+///
+/// # Example
+/// ```
+/// # use histogram::WindowedBuilder;
+/// let mut w = WindowedBuilder::new().slots(3).build().unwrap();
+///
+/// w.record(1_000, 10).unwrap();
+/// w.tick();
+/// w.record(10, 10).unwrap();
+///
+/// assert_eq!(w.merged().percentile(99.0).unwrap(), 1_000);
+///
+/// w.tick();
+/// w.tick();
+///
+/// assert_eq!(w.merged().percentile(99.0).unwrap(), 10);
+#[derive(Clone, Debug)]
+pub struct WindowedHistogram {
+    current: Histogram,
+    // the other slots, a ring starting at the oldest
+    previous: Vec<Histogram>,
+    oldest: usize,
+}
+
+impl WindowedHistogram {
+    /// record a single sample into the current slot, as
+    /// `Histogram::increment()`
+    pub fn increment(&mut self, value: u64) -> Result<(), HistogramError> {
+        self.record(value, 1)
+    }
+
+    /// record counts for a value into the current slot, as
+    /// `Histogram::record()`
+    pub fn record(&mut self, value: u64, count: u64) -> Result<(), HistogramError> {
+        self.current.record(value, count)
+    }
+
+    /// advance to the next slot, clearing the samples it held, which were
+    /// recorded N ticks ago
+    pub fn tick(&mut self) {
+        // the current slot takes the place of the oldest, which is reused
+        if let Some(oldest) = self.previous.get_mut(self.oldest) {
+            mem::swap(&mut self.current, oldest);
+            self.oldest = (self.oldest + 1) % self.previous.len();
+        }
+        self.current.clear();
+    }
+
+    /// return a histogram of the samples of every slot, those recorded since
+    /// N - 1 ticks ago
+    pub fn merged(&self) -> Histogram {
+        let mut merged = self.current.clone_empty();
+        merged.merge(&self.current);
+        for slot in &self.previous {
+            merged.merge(slot);
+        }
+        merged
+    }
+
+    /// return the slot being recorded into
+    pub fn current(&self) -> &Histogram {
+        &self.current
+    }
+
+    /// return the number of slots
+    pub fn slots(&self) -> usize {
+        self.previous.len() + 1
+    }
+
+    /// clear every slot
+    pub fn clear(&mut self) {
+        self.current.clear();
+        for slot in &mut self.previous {
+            slot.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::super::{HistogramConfig, HistogramError};
+    use super::WindowedBuilder;
+
+    #[test]
+    fn test_aging() {
+        let mut w = WindowedBuilder::new().slots(5).build().unwrap();
+
+        // a slow tick, then fast ones until it has aged out
+        w.record(50_000, 100).unwrap();
+        for tick in 1..=10 {
+            w.tick();
+            w.record(100, 1_000).unwrap();
+            let merged = w.merged();
+            assert_eq!(merged.entries(), 100 * (tick < 5) as u64 + 1_000 * tick.min(5));
+            let p99 = merged.percentile(99.0).unwrap();
+            if tick < 5 {
+                assert!(p99 > 10_000, "tick {}: {}", tick, p99);
+            } else {
+                assert_eq!(p99, 100, "tick {}", tick);
+                assert_eq!(merged.maximum().unwrap(), 100);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reuse() {
+        let mut w = WindowedBuilder::new().slots(2).build().unwrap();
+        w.record(10, 1).unwrap();
+        let buckets = Arc::as_ptr(&w.current().data.data);
+        w.tick();
+        w.tick();
+        assert_eq!(w.current().entries(), 0);
+        assert_eq!(Arc::as_ptr(&w.current().data.data), buckets);
+
+        // a merged copy does not keep the slots shared
+        w.record(10, 1).unwrap();
+        let merged = w.merged();
+        w.tick();
+        w.tick();
+        assert_eq!(Arc::as_ptr(&w.current().data.data), buckets);
+        assert_eq!(merged.entries(), 1);
+
+        w.record(10, 1).unwrap();
+        w.clear();
+        assert_eq!(w.merged().entries(), 0);
+    }
+
+    #[test]
+    fn test_builder() {
+        let mut c = HistogramConfig::new();
+        c.max_value(1_000);
+        let mut w = WindowedBuilder::new().slots(0).config(c).build().unwrap();
+        assert_eq!(w.slots(), 1);
+        assert_eq!(w.record(5_000, 1), Err(HistogramError::TooLarge));
+        w.record(500, 1).unwrap();
+        w.tick();
+        assert_eq!(w.merged().entries(), 0);
+
+        c.precision(0);
+        assert_eq!(WindowedBuilder::new().config(c).build().unwrap_err(),
+                   HistogramError::InvalidPrecision);
+    }
+}